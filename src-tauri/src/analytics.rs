@@ -0,0 +1,204 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbState;
+use crate::{backup, read_only, settings};
+
+/// Setting key for the opt-in switch; analytics are off unless this is "true"
+pub const ANALYTICS_SETTING: &str = "analytics_enabled";
+
+/// Events older than this are pruned as new ones are recorded
+const RETENTION_DAYS: i64 = 90;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    /// Everything still kept, i.e. the retention window
+    #[serde(alias = "all")]
+    Retained,
+}
+
+impl Period {
+    fn days(self) -> i64 {
+        match self {
+            Period::Day => 1,
+            Period::Week => 7,
+            Period::Month => 30,
+            Period::Retained => RETENTION_DAYS,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EventCount {
+    event_name: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+pub struct DailyEventCount {
+    date: String,
+    event_name: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+pub struct AnalyticsSummary {
+    enabled: bool,
+    total_events: i64,
+    by_event: Vec<EventCount>,
+    by_day: Vec<DailyEventCount>,
+}
+
+/// Create the local-only events table and drop anything past the retention window, in
+/// case nothing was recorded for a while
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS analytics_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_name TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            payload TEXT NOT NULL DEFAULT '{}'
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_analytics_created ON analytics_events(created_at)",
+        [],
+    )?;
+
+    prune(conn)
+}
+
+/// Drop events past the retention window
+fn prune(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "DELETE FROM analytics_events WHERE created_at < unixepoch() - ? * 86400",
+        params![RETENTION_DAYS],
+    )?;
+    Ok(())
+}
+
+/// Record a usage event if the user opted in.
+///
+/// Payloads must only carry counts, flags and ids - never definition or note text.
+/// Failures are swallowed so analytics can never break the command that called it.
+pub fn record_event(conn: &Connection, event_name: &str, payload: serde_json::Value) {
+    if !settings::get_bool(conn, ANALYTICS_SETTING, false) || read_only::is_read_only(conn) {
+        return;
+    }
+
+    let _ = conn
+        .execute(
+            "INSERT INTO analytics_events (event_name, created_at, payload) VALUES (?, unixepoch(), ?)",
+            params![event_name, payload.to_string()],
+        )
+        .and_then(|_| prune(conn));
+}
+
+fn summarize(conn: &Connection, period: Period) -> Result<AnalyticsSummary> {
+    let days = period.days();
+
+    let mut stmt = conn.prepare(
+        "SELECT event_name, COUNT(*) FROM analytics_events
+         WHERE created_at >= unixepoch() - ? * 86400
+         GROUP BY event_name ORDER BY COUNT(*) DESC",
+    )?;
+    let by_event = stmt
+        .query_map(params![days], |row| {
+            Ok(EventCount {
+                event_name: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT date(created_at, 'unixepoch', 'localtime') AS day, event_name, COUNT(*)
+         FROM analytics_events
+         WHERE created_at >= unixepoch() - ? * 86400
+         GROUP BY day, event_name ORDER BY day",
+    )?;
+    let by_day = stmt
+        .query_map(params![days], |row| {
+            Ok(DailyEventCount {
+                date: row.get(0)?,
+                event_name: row.get(1)?,
+                count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(AnalyticsSummary {
+        enabled: settings::get_bool(conn, ANALYTICS_SETTING, false),
+        total_events: by_event.iter().map(|e| e.count).sum(),
+        by_event,
+        by_day,
+    })
+}
+
+#[tauri::command]
+pub fn get_analytics_summary(
+    period: Period,
+    state: tauri::State<DbState>,
+) -> Result<AnalyticsSummary, String> {
+    let conn = state.user_data()?;
+    summarize(&conn, period).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn clear_analytics(app: tauri::AppHandle, state: tauri::State<DbState>) -> Result<(), String> {
+    let conn = state.user_data()?;
+    conn.execute("DELETE FROM analytics_events", [])
+        .map_err(|e| e.to_string())?;
+    backup::schedule(&app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM analytics_events", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn nothing_is_recorded_without_opt_in() {
+        let conn = db::init_db(None).unwrap();
+        record_event(&conn, "search", serde_json::json!({}));
+        assert_eq!(count(&conn), 0);
+    }
+
+    #[test]
+    fn recording_prunes_events_past_retention() {
+        let conn = db::init_db(None).unwrap();
+        settings::set(&conn, ANALYTICS_SETTING, "true").unwrap();
+        conn.execute(
+            "INSERT INTO analytics_events (event_name, created_at)
+             VALUES ('old', unixepoch() - ? * 86400), ('recent', unixepoch() - 2 * 86400)",
+            params![RETENTION_DAYS + 1],
+        )
+        .unwrap();
+
+        record_event(&conn, "search", serde_json::json!({ "results": 3 }));
+
+        let summary = summarize(&conn, Period::Retained).unwrap();
+        let names: Vec<&str> = summary
+            .by_event
+            .iter()
+            .map(|e| e.event_name.as_str())
+            .collect();
+        assert_eq!(count(&conn), 2);
+        assert!(!names.contains(&"old"));
+        assert_eq!(summarize(&conn, Period::Day).unwrap().total_events, 1);
+    }
+}
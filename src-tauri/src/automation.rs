@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{Manager, Runtime};
+
+use crate::db::{self, DbState, SearchOptions};
+use crate::permissions::{self, EntryPoint, Tier};
+use crate::session_lookups::SessionLookups;
+use crate::source_stats::SourceCounters;
+use crate::{books, selection, senses, settings};
+
+/// When "true", a local socket accepts automation requests from other programs run by the
+/// same user. Read at startup.
+pub const AUTOMATION_SETTING: &str = "automation_enabled";
+/// Stands in for a window label where state is kept per window, e.g. session lookups
+const LABEL: &str = "automation";
+
+/// The commands open to automation, as `{"id": ..., "method": ..., "params": {...}}`. The
+/// params are the command's own arguments.
+#[derive(Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+enum Call {
+    Search {
+        word: String,
+        #[serde(default)]
+        options: Option<SearchOptions>,
+    },
+    Define {
+        selection: String,
+        #[serde(default)]
+        context: String,
+        #[serde(default)]
+        book_id: Option<i64>,
+    },
+    GetSenses {
+        word: String,
+    },
+    ListBooks,
+}
+
+impl Call {
+    fn tier(&self) -> Tier {
+        match self {
+            Call::Search { .. } | Call::Define { .. } | Call::GetSenses { .. } => Tier::ReadOnly,
+            Call::ListBooks => Tier::UserData,
+        }
+    }
+}
+
+/// One line of reply: the id from the request with either the command's result or its
+/// error, serialized as the frontend would receive them
+#[derive(Serialize)]
+struct Reply {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+fn reply<T: Serialize, E: Serialize>(id: Value, outcome: Result<T, E>) -> Reply {
+    let (result, error) = match outcome {
+        Ok(result) => (Some(serde_json::to_value(result).unwrap_or_default()), None),
+        Err(error) => (None, Some(serde_json::to_value(error).unwrap_or_default())),
+    };
+    Reply { id, result, error }
+}
+
+/// Run one request line through the same command functions the frontend invokes, against
+/// the app's shared state, if the permission policy allows its tier
+#[cfg_attr(not(unix), allow(dead_code))]
+fn handle<R: Runtime>(app: &tauri::AppHandle<R>, line: &str) -> Reply {
+    let mut request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return reply::<(), _>(Value::Null, Err(e.to_string())),
+    };
+    let id = request
+        .get_mut("id")
+        .map(Value::take)
+        .unwrap_or(Value::Null);
+    let call: Call = match serde_json::from_value(request) {
+        Ok(call) => call,
+        Err(e) => return reply::<(), _>(id, Err(e.to_string())),
+    };
+    let allowed = {
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        permissions::check(&conn, EntryPoint::Automation, call.tier())
+    };
+    if let Err(denied) = allowed {
+        return reply::<(), _>(id, Err(denied));
+    }
+
+    match call {
+        Call::Search { word, options } => reply(
+            id,
+            db::search_from(
+                LABEL,
+                &word,
+                options,
+                &app.state::<DbState>(),
+                &app.state::<SessionLookups>(),
+                &app.state::<SourceCounters>(),
+            ),
+        ),
+        Call::Define {
+            selection,
+            context,
+            book_id,
+        } => {
+            let state = app.state::<DbState>();
+            let defined = state
+                .user_data()
+                .map_err(selection::SelectionError::from)
+                .and_then(|conn| selection::define(&conn, &selection, &context, book_id));
+            reply(id, defined)
+        }
+        Call::GetSenses { word } => reply(id, senses::get_senses(&word, app.state())),
+        Call::ListBooks => reply(id, books::list_books(app.state())),
+    }
+}
+
+/// Start the automation socket in the background if the setting is on
+pub fn start<R: Runtime>(app: &tauri::AppHandle<R>) {
+    let enabled = {
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        settings::get_bool(&conn, AUTOMATION_SETTING, false)
+    };
+    if !enabled {
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        if let Err(e) = socket::listen(app) {
+            eprintln!("Failed to start the automation socket: {}", e);
+        }
+    }
+    #[cfg(not(unix))]
+    eprintln!("Automation is only available on Unix platforms for now");
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::fs::{self, DirBuilder};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use tauri::{Manager, Runtime};
+
+    fn socket_path<R: Runtime>(app: &tauri::AppHandle<R>) -> io::Result<PathBuf> {
+        let dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.to_string()))?
+            .join("automation");
+        DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+        // An existing directory keeps its mode, so tighten it in case it was created looser
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        Ok(dir.join("openread.sock"))
+    }
+
+    /// Bind the socket in a directory only the current user can enter, and serve each
+    /// connection on its own thread
+    pub fn listen<R: Runtime>(app: &tauri::AppHandle<R>) -> io::Result<()> {
+        bind(app, &socket_path(app)?)
+    }
+
+    pub fn bind<R: Runtime>(app: &tauri::AppHandle<R>, path: &Path) -> io::Result<()> {
+        // Left behind by a previous run that didn't exit cleanly
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        println!("Automation socket listening at {}", path.display());
+
+        let app = app.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let app = app.clone();
+                        std::thread::spawn(move || {
+                            if let Err(e) = serve(&app, stream) {
+                                eprintln!("Automation connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => eprintln!("Automation socket accept failed: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Answer line-delimited JSON requests until the client hangs up
+    fn serve<R: Runtime>(app: &tauri::AppHandle<R>, stream: UnixStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = super::handle(app, &line);
+            serde_json::to_writer(&mut writer, &reply)?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    fn app() -> tauri::App<tauri::test::MockRuntime> {
+        let app = tauri::test::mock_app();
+        let conn = db::init_db(None).unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition, source) VALUES (?, ?, 'test')",
+            ["run", "To move swiftly on foot."],
+        )
+        .unwrap();
+        app.manage(DbState::new(conn, false));
+        app.manage(SessionLookups::default());
+        app.manage(SourceCounters::default());
+        app
+    }
+
+    fn call(stream: &mut UnixStream, reader: &mut impl BufRead, request: &str) -> Value {
+        writeln!(stream, "{}", request).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn a_client_connects_and_searches() {
+        let app = app();
+        let dir = std::env::temp_dir().join(format!("open-read-automation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("openread.sock");
+        socket::bind(app.handle(), &path).unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        let found = call(
+            &mut stream,
+            &mut reader,
+            r#"{"id": 1, "method": "search", "params": {"word": "Run"}}"#,
+        );
+        assert_eq!(found["id"], 1);
+        assert!(found.get("error").is_none(), "{}", found);
+        let results = found["result"]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["word"], "run");
+        assert_eq!(results[0]["definition"], "To move swiftly on foot.");
+        assert_eq!(found["result"]["status"], "ok");
+
+        // Personal data stays off until the setting allows it
+        let denied = call(
+            &mut stream,
+            &mut reader,
+            r#"{"id": "books", "method": "list_books"}"#,
+        );
+        assert_eq!(denied["id"], "books");
+        assert_eq!(denied["error"]["kind"], "permission_denied");
+        assert_eq!(denied["error"]["setting"], "automation_allow_user_data");
+
+        let malformed = call(&mut stream, &mut reader, r#"{"id": 2, "method": "drop"}"#);
+        assert_eq!(malformed["id"], 2);
+        assert!(malformed["error"].is_string());
+
+        drop(stream);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{Emitter, Manager};
+
+use crate::db::{self, DbState};
+use crate::{encryption, migrations, settings};
+
+/// "off" (the default), "daily" or "weekly"
+pub const AUTO_BACKUP_SETTING: &str = "auto_backup";
+/// How many automatic backups to keep; the oldest are deleted past this
+pub const BACKUP_ROTATIONS_SETTING: &str = "backup_rotations";
+const DEFAULT_ROTATIONS: f64 = 5.0;
+
+pub const LAST_BACKUP_KEY: &str = "last_backup_at";
+
+/// Free space a backup must leave behind on top of its own size
+const SPACE_MARGIN: u64 = 50 * 1024 * 1024;
+
+const BACKUP_PREFIX: &str = "dictionary-";
+
+/// Set while a backup thread is running so checks don't start a second one
+#[derive(Default)]
+pub struct BackupJob(AtomicBool);
+
+impl BackupJob {
+    pub fn is_running(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct BackupCompleted {
+    path: String,
+    created_at: i64,
+}
+
+#[derive(Serialize, Clone)]
+struct BackupSkipped {
+    available_bytes: u64,
+    required_bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct BackupFailed {
+    message: String,
+}
+
+fn interval_seconds(setting: Option<&str>) -> Option<i64> {
+    match setting {
+        Some("daily") => Some(24 * 60 * 60),
+        Some("weekly") => Some(7 * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+/// Whether a backup taken at `last_backup_at` is stale at `now`; no backup yet counts as stale
+pub fn backup_due(last_backup_at: Option<i64>, interval: i64, now: i64) -> bool {
+    last_backup_at.is_none_or(|last| now - last >= interval)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+pub fn last_backup_at(conn: &Connection) -> rusqlite::Result<Option<i64>> {
+    Ok(migrations::get_meta(conn, LAST_BACKUP_KEY)?.and_then(|v| v.parse().ok()))
+}
+
+/// Automatic backups in `dir`, newest first
+fn existing_backups(dir: &Path) -> Vec<(i64, PathBuf)> {
+    let mut backups: Vec<(i64, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let taken_at = name
+                .strip_prefix(BACKUP_PREFIX)?
+                .strip_suffix(".db")?
+                .parse()
+                .ok()?;
+            Some((taken_at, entry.path()))
+        })
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.0));
+    backups
+}
+
+fn rotate(dir: &Path, keep: usize) {
+    for (_, path) in existing_backups(dir).into_iter().skip(keep) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Copy the database to `target` without holding the app's connection where possible
+fn copy_database(state: &DbState, db_path: &Path, target: &Path) -> Result<(), String> {
+    if encryption::is_encrypted(db_path) {
+        // A second connection would need the passphrase; the file is already encrypted,
+        // so copy it while the app's connection can't write
+        let _conn = state.0.lock().unwrap();
+        fs::copy(db_path, target).map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    let source = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    source
+        .busy_timeout(Duration::from_secs(10))
+        .map_err(|e| e.to_string())?;
+    source
+        .execute("VACUUM INTO ?", params![target.to_string_lossy()])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// How many backups to keep if one is due at `now` under the settings, `None` if not
+fn rotations_if_due(conn: &Connection, now: i64) -> rusqlite::Result<Option<usize>> {
+    let interval = interval_seconds(settings::get(conn, AUTO_BACKUP_SETTING)?.as_deref());
+    let last = last_backup_at(conn)?;
+    if !interval.is_some_and(|interval| backup_due(last, interval, now)) {
+        return Ok(None);
+    }
+    let keep = settings::get_f64(conn, BACKUP_ROTATIONS_SETTING, DEFAULT_ROTATIONS).max(1.0);
+    Ok(Some(keep as usize))
+}
+
+fn run_if_due(app: &tauri::AppHandle, now: i64) -> Result<(), String> {
+    let state = app.state::<DbState>();
+    if state.is_locked() {
+        return Ok(());
+    }
+    let Some(db_path) = db::database_path(app) else {
+        return Ok(());
+    };
+
+    let keep = {
+        let conn = state.0.lock().unwrap();
+        rotations_if_due(&conn, now).map_err(|e| e.to_string())?
+    };
+    let Some(keep) = keep else {
+        return Ok(());
+    };
+
+    let dir = db_path.with_file_name("backups");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let size = fs::metadata(&db_path).map_err(|e| e.to_string())?.len();
+    let available = fs2::available_space(&dir).map_err(|e| e.to_string())?;
+    let required = size + SPACE_MARGIN;
+    if available < required {
+        let _ = app.emit(
+            "backup-skipped",
+            BackupSkipped {
+                available_bytes: available,
+                required_bytes: required,
+            },
+        );
+        return Ok(());
+    }
+
+    let target = dir.join(format!("{}{}.db", BACKUP_PREFIX, now));
+    copy_database(&state, &db_path, &target)?;
+
+    {
+        let conn = state.0.lock().unwrap();
+        migrations::set_meta(&conn, LAST_BACKUP_KEY, &now.to_string())
+            .map_err(|e| e.to_string())?;
+    }
+    rotate(&dir, keep);
+
+    let _ = app.emit(
+        "backup-completed",
+        BackupCompleted {
+            path: target.to_string_lossy().into_owned(),
+            created_at: now,
+        },
+    );
+    Ok(())
+}
+
+/// Take an automatic backup in the background if the configured interval has passed.
+/// Called once setup has finished and after large imports and deletes.
+pub fn schedule(app: &tauri::AppHandle) {
+    if app.state::<BackupJob>().0.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if let Err(message) = run_if_due(&app, unix_now()) {
+            eprintln!("Automatic backup failed: {}", message);
+            let _ = app.emit("backup-failed", BackupFailed { message });
+        }
+        app.state::<BackupJob>().0.store(false, Ordering::SeqCst);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+    const NOW: i64 = 1_760_000_000;
+
+    #[test]
+    fn backups_fall_due_after_their_interval() {
+        let conn = db::init_db(None).unwrap();
+        // Off by default
+        assert_eq!(rotations_if_due(&conn, NOW).unwrap(), None);
+
+        settings::set(&conn, AUTO_BACKUP_SETTING, "daily").unwrap();
+        assert_eq!(rotations_if_due(&conn, NOW).unwrap(), Some(5));
+
+        migrations::set_meta(&conn, LAST_BACKUP_KEY, &NOW.to_string()).unwrap();
+        assert_eq!(rotations_if_due(&conn, NOW + DAY - 1).unwrap(), None);
+        assert_eq!(rotations_if_due(&conn, NOW + DAY).unwrap(), Some(5));
+
+        settings::set(&conn, AUTO_BACKUP_SETTING, "weekly").unwrap();
+        settings::set(&conn, BACKUP_ROTATIONS_SETTING, "0").unwrap();
+        assert_eq!(rotations_if_due(&conn, NOW + 6 * DAY).unwrap(), None);
+        // At least one backup is always kept
+        assert_eq!(rotations_if_due(&conn, NOW + 7 * DAY).unwrap(), Some(1));
+
+        // A clock that went backwards doesn't trigger one
+        assert!(!backup_due(Some(NOW), DAY, NOW - 3 * DAY));
+        assert!(backup_due(None, DAY, 0));
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_backups() {
+        let dir = std::env::temp_dir().join(format!("open-read-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for taken_at in [NOW - 3 * DAY, NOW, NOW - DAY, NOW - 2 * DAY] {
+            fs::write(dir.join(format!("{}{}.db", BACKUP_PREFIX, taken_at)), b"").unwrap();
+        }
+        // Files that aren't automatic backups are never touched
+        for other in ["dictionary.db", "dictionary-manual.db", "dictionary-1.txt"] {
+            fs::write(dir.join(other), b"").unwrap();
+        }
+
+        let taken: Vec<i64> = existing_backups(&dir).into_iter().map(|(t, _)| t).collect();
+        assert_eq!(taken, [NOW, NOW - DAY, NOW - 2 * DAY, NOW - 3 * DAY]);
+
+        rotate(&dir, 2);
+        let taken: Vec<i64> = existing_backups(&dir).into_iter().map(|(t, _)| t).collect();
+        assert_eq!(taken, [NOW, NOW - DAY]);
+        for other in ["dictionary.db", "dictionary-manual.db", "dictionary-1.txt"] {
+            assert!(dir.join(other).exists());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
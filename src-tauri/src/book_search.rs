@@ -0,0 +1,490 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+
+use crate::books::{self, Book};
+use crate::db::DbState;
+use crate::locators::{self, ChapterGroup};
+use crate::memory::{DeferredIndexes, MemoryMode};
+use crate::pdf_text::{self, PageRange, MAX_PAGES_PER_CALL};
+use crate::query_budget::{self, QueryError};
+use crate::{epub, text_book};
+
+/// Paragraphs are merged into chunks of about this many bytes
+const CHUNK_BYTES: usize = 1200;
+const DEFAULT_LIMIT: i64 = 20;
+/// Most matches listed for a search within one book
+const MAX_BOOK_HITS: i64 = 1000;
+
+/// Cancellation flags for index builds in flight, keyed by book id
+#[derive(Default)]
+pub struct BookIndexJobs(pub Mutex<HashMap<i64, Arc<AtomicBool>>>);
+
+/// Indexed span of a book. Locators are `txt:<byte offset>`, `epub:<spine index>:<char offset>`
+/// or `pdf:<page>`, and point at the start of the chunk.
+struct Chunk {
+    locator: String,
+    text: String,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexStatus {
+    Running,
+    Completed,
+    UpToDate,
+    /// Held back by low-memory mode until `build_indexes_now`
+    Deferred,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+struct IndexProgress {
+    book_id: i64,
+    status: IndexStatus,
+    done: usize,
+    /// Zero when the total isn't known up front (PDFs)
+    total: usize,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BookSearchHit {
+    book_id: i64,
+    title: String,
+    locator: String,
+    /// Matching text with hits wrapped in `<mark>`
+    snippet: String,
+    rank: f64,
+}
+
+#[derive(Serialize)]
+pub struct BookIndexStats {
+    indexed_books: i64,
+    chunks: i64,
+    text_bytes: i64,
+}
+
+/// Create the FTS5 index and the per-book bookkeeping used to skip up-to-date books
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS book_search USING fts5(
+            text,
+            book_id UNINDEXED,
+            locator UNINDEXED,
+            tokenize = 'porter unicode61'
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_search_state (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            content_hash TEXT NOT NULL,
+            chunks INTEGER NOT NULL,
+            indexed_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Drop everything indexed for a book
+pub fn remove_book_index(conn: &Connection, book_id: i64) -> Result<()> {
+    conn.execute(
+        "DELETE FROM book_search WHERE book_id = ?",
+        params![book_id],
+    )?;
+    conn.execute(
+        "DELETE FROM book_search_state WHERE book_id = ?",
+        params![book_id],
+    )?;
+    Ok(())
+}
+
+pub fn index_stats(conn: &Connection) -> Result<BookIndexStats> {
+    let (indexed_books, chunks) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(chunks), 0) FROM book_search_state",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let text_bytes = conn.query_row(
+        "SELECT COALESCE(SUM(length(text)), 0) FROM book_search",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(BookIndexStats {
+        indexed_books,
+        chunks,
+        text_bytes,
+    })
+}
+
+/// Turn free text into an FTS5 query of quoted terms, so user input is never parsed as syntax
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("\"{}\"", t))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Paragraphs of a plain-text book with the byte offset each starts at
+pub fn text_paragraphs(text: &str) -> Vec<(usize, String)> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            paragraphs.extend(current.take());
+        } else {
+            match current.as_mut() {
+                Some((_, paragraph)) => {
+                    paragraph.push(' ');
+                    paragraph.push_str(trimmed);
+                }
+                None => current = Some((offset, trimmed.to_string())),
+            }
+        }
+        offset += line.len();
+    }
+    paragraphs.extend(current);
+    paragraphs
+}
+
+/// Merge consecutive paragraphs into chunks, each located at its first paragraph
+fn chunk_paragraphs(paragraphs: impl IntoIterator<Item = (String, String)>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut current: Option<Chunk> = None;
+
+    for (locator, text) in paragraphs {
+        match current.as_mut() {
+            Some(chunk) if chunk.text.len() + text.len() < CHUNK_BYTES => {
+                chunk.text.push('\n');
+                chunk.text.push_str(&text);
+            }
+            _ => {
+                chunks.extend(current.take());
+                current = Some(Chunk { locator, text });
+            }
+        }
+    }
+    chunks.extend(current);
+    chunks
+}
+
+fn emit_progress(app: &tauri::AppHandle, progress: IndexProgress) {
+    let _ = app.emit("book-index-progress", progress);
+}
+
+/// Extract and chunk a book's text. `Ok(None)` means the build was cancelled.
+fn collect_chunks(
+    app: &tauri::AppHandle,
+    book: &Book,
+    cancel: &AtomicBool,
+) -> Result<Option<Vec<Chunk>>, String> {
+    let running = |done: usize, total: usize| {
+        emit_progress(
+            app,
+            IndexProgress {
+                book_id: book.id,
+                status: IndexStatus::Running,
+                done,
+                total,
+                error: None,
+            },
+        )
+    };
+
+    match book.format.as_str() {
+        "txt" | "md" => {
+            let (text, _) = text_book::read_text(book)?;
+            let paragraphs = text_paragraphs(&text)
+                .into_iter()
+                .map(|(offset, p)| (format!("txt:{}", offset), p));
+            running(1, 1);
+            Ok(Some(chunk_paragraphs(paragraphs)))
+        }
+        "epub" => {
+            let (bytes, hash) = epub::read_epub(book)?;
+            let structure = {
+                let state = app.state::<DbState>();
+                let conn = state.0.lock().unwrap();
+                epub::structure_for(&conn, bytes, &hash)?
+            };
+
+            let total = structure.spine.len();
+            let mut chunks = Vec::new();
+            for (index, item) in structure.spine.iter().enumerate() {
+                if cancel.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                if item.media_type.contains("html") {
+                    let bytes = epub::read_resource(book, &item.href)?;
+                    let mut char_offset = 0;
+                    let paragraphs: Vec<(String, String)> =
+                        epub::html_paragraphs(&String::from_utf8_lossy(&bytes))
+                            .into_iter()
+                            .map(|p| {
+                                let locator = format!("epub:{}:{}", index, char_offset);
+                                char_offset += p.chars().count() + 1;
+                                (locator, p)
+                            })
+                            .collect();
+                    chunks.extend(chunk_paragraphs(paragraphs));
+                }
+                running(index + 1, total);
+            }
+            Ok(Some(chunks))
+        }
+        "pdf" => {
+            let mut chunks = Vec::new();
+            let mut first = 1;
+            loop {
+                if cancel.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                let range = PageRange {
+                    first,
+                    last: first + MAX_PAGES_PER_CALL - 1,
+                };
+                let pages = pdf_text::page_texts(app, book, &range)?;
+                let batch = pages.len() as u32;
+
+                for page in pages {
+                    if let Some(text) = page.text {
+                        let paragraphs = text_paragraphs(&text)
+                            .into_iter()
+                            .map(|(_, p)| (format!("pdf:{}", page.page), p));
+                        chunks.extend(chunk_paragraphs(paragraphs));
+                    }
+                }
+                running((first + batch - 1) as usize, 0);
+
+                if batch < MAX_PAGES_PER_CALL {
+                    break;
+                }
+                first += MAX_PAGES_PER_CALL;
+            }
+            Ok(Some(chunks))
+        }
+        other => Err(format!(
+            "Search indexing is not supported for {} books",
+            other
+        )),
+    }
+}
+
+pub fn run_build(
+    app: &tauri::AppHandle,
+    book: &Book,
+    cancel: &AtomicBool,
+) -> Result<IndexStatus, String> {
+    let hash = books::content_hash(&fs::read(&book.path).map_err(|e| e.to_string())?);
+    let state = app.state::<DbState>();
+
+    {
+        let conn = state.0.lock().unwrap();
+        let indexed: Option<String> = conn
+            .query_row(
+                "SELECT content_hash FROM book_search_state WHERE book_id = ?",
+                params![book.id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if indexed.as_deref() == Some(hash.as_str()) {
+            return Ok(IndexStatus::UpToDate);
+        }
+    }
+
+    // Extraction runs without the database lock; only the final write takes it
+    let Some(chunks) = collect_chunks(app, book, cancel)? else {
+        return Ok(IndexStatus::Cancelled);
+    };
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(IndexStatus::Cancelled);
+    }
+
+    let conn = state.0.lock().unwrap();
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    remove_book_index(&tx, book.id).map_err(|e| e.to_string())?;
+    {
+        let mut insert = tx
+            .prepare("INSERT INTO book_search (text, book_id, locator) VALUES (?, ?, ?)")
+            .map_err(|e| e.to_string())?;
+        for chunk in &chunks {
+            insert
+                .execute(params![chunk.text, book.id, chunk.locator])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.execute(
+        "INSERT INTO book_search_state (book_id, content_hash, chunks, indexed_at)
+         VALUES (?, ?, ?, unixepoch())",
+        params![book.id, hash, chunks.len() as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(IndexStatus::Completed)
+}
+
+/// Start (re)indexing a book in the background. Progress and the outcome arrive as
+/// "book-index-progress" events; a book whose content hasn't changed is skipped.
+#[tauri::command]
+pub fn build_book_search_index(
+    book_id: i64,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    deferred: tauri::State<DeferredIndexes>,
+) -> Result<(), String> {
+    let book = {
+        let conn = state.0.lock().unwrap();
+        books::load_book(&conn, book_id)?
+    };
+
+    if app.state::<MemoryMode>().is_low() {
+        deferred.0.lock().unwrap().insert(book_id);
+        emit_progress(
+            &app,
+            IndexProgress {
+                book_id,
+                status: IndexStatus::Deferred,
+                done: 0,
+                total: 0,
+                error: None,
+            },
+        );
+        return Ok(());
+    }
+
+    start_build(&app, book)
+}
+
+/// Index a book on a background thread, unless a build for it is already running
+pub fn start_build(app: &tauri::AppHandle, book: Book) -> Result<(), String> {
+    let book_id = book.id;
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let jobs = app.state::<BookIndexJobs>();
+        let mut jobs = jobs.0.lock().unwrap();
+        if jobs.contains_key(&book_id) {
+            return Ok(());
+        }
+        jobs.insert(book_id, cancel.clone());
+    }
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let result = run_build(&app, &book, &cancel);
+        app.state::<BookIndexJobs>()
+            .0
+            .lock()
+            .unwrap()
+            .remove(&book_id);
+
+        let (status, error) = match result {
+            Ok(status) => (status, None),
+            Err(e) => (IndexStatus::Failed, Some(e)),
+        };
+        emit_progress(
+            &app,
+            IndexProgress {
+                book_id,
+                status,
+                done: 0,
+                total: 0,
+                error,
+            },
+        );
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cancel_book_index(book_id: i64, jobs: tauri::State<BookIndexJobs>) {
+    if let Some(flag) = jobs.0.lock().unwrap().get(&book_id) {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Indexed chunks matching an FTS query, best match first, optionally within some books
+fn find_hits(
+    conn: &Connection,
+    query: &str,
+    book_ids: Option<&[i64]>,
+    limit: i64,
+) -> Result<Vec<BookSearchHit>> {
+    let filter = book_ids.map(|ids| serde_json::to_string(ids).unwrap_or_default());
+    let mut stmt = conn.prepare(
+        "SELECT book_search.book_id, b.title, book_search.locator,
+                snippet(book_search, 0, '<mark>', '</mark>', '…', 16),
+                bm25(book_search)
+         FROM book_search
+         JOIN books b ON b.id = book_search.book_id
+         WHERE book_search MATCH ?1
+           AND (?2 IS NULL OR book_search.book_id IN (SELECT value FROM json_each(?2)))
+         ORDER BY bm25(book_search)
+         LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(params![query, filter, limit], |row| {
+        Ok(BookSearchHit {
+            book_id: row.get(0)?,
+            title: row.get(1)?,
+            locator: row.get(2)?,
+            snippet: row.get(3)?,
+            rank: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[tauri::command]
+pub fn search_books(
+    query: &str,
+    book_filter: Option<Vec<i64>>,
+    limit: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<BookSearchHit>, QueryError> {
+    let Some(query) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+    let conn = state.0.lock().unwrap();
+    query_budget::run(&conn, |conn| {
+        find_hits(
+            conn,
+            &query,
+            book_filter.as_deref(),
+            limit.unwrap_or(DEFAULT_LIMIT),
+        )
+    })
+}
+
+/// Every match in one book, in reading order and grouped under its chapter headings
+#[tauri::command]
+pub fn search_book_in_order(
+    book_id: i64,
+    query: &str,
+    state: tauri::State<DbState>,
+) -> Result<Vec<ChapterGroup<BookSearchHit>>, QueryError> {
+    let conn = state.0.lock().unwrap();
+    let book = books::load_book(&conn, book_id)?;
+    let hits = match fts_query(query) {
+        Some(query) => query_budget::run(&conn, |conn| {
+            find_hits(conn, &query, Some(&[book_id][..]), MAX_BOOK_HITS)
+        })?,
+        None => Vec::new(),
+    };
+    locators::group_by_chapter(&conn, &book, hits, |hit| &hit.locator).map_err(QueryError::from)
+}
@@ -0,0 +1,180 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::books;
+use crate::db::DbState;
+use crate::read_only;
+use crate::tokens::Tokenizer;
+
+const DEFAULT_TOP_WORDS: usize = 50;
+
+/// Word counts of one book, cached until the book or the tokenizer settings change
+#[derive(Serialize, Deserialize)]
+pub struct WordCounts {
+    pub total: usize,
+    /// Every distinct word, most frequent first
+    pub words: Vec<WordStat>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WordStat {
+    pub word: String,
+    pub count: usize,
+    /// Number of counted words before its first occurrence, for listing in reading order
+    pub first: usize,
+}
+
+#[derive(Serialize)]
+pub struct WordCount {
+    word: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+pub struct BookVocabulary {
+    book_id: i64,
+    /// Words counted, stopwords left out
+    total_words: usize,
+    distinct_words: usize,
+    top_words: Vec<WordCount>,
+    /// Distinct words with no dictionary entry under that exact form
+    not_in_dictionary: usize,
+    /// Distinct words the active profile has marked known
+    known: usize,
+}
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_word_stats (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            content_hash TEXT NOT NULL,
+            tokenizer TEXT NOT NULL,
+            counts TEXT NOT NULL,
+            built_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Count the words of a book's indexed text, reusing the cached counts when they were
+/// made from the same index and tokenizer settings
+pub fn counts_for(
+    conn: &Connection,
+    book_id: i64,
+    tokenizer: &Tokenizer,
+) -> Result<WordCounts, String> {
+    let content_hash: String = conn
+        .query_row(
+            "SELECT content_hash FROM book_search_state WHERE book_id = ?",
+            params![book_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Index the book for search before analysing its vocabulary".to_string())?;
+
+    let cached: Option<String> = conn
+        .query_row(
+            "SELECT counts FROM book_word_stats
+             WHERE book_id = ? AND content_hash = ? AND tokenizer = ?",
+            params![book_id, content_hash, tokenizer.fingerprint],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(counts) = cached.and_then(|json| serde_json::from_str(&json).ok()) {
+        return Ok(counts);
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT text FROM book_search WHERE book_id = ? ORDER BY rowid")
+        .map_err(|e| e.to_string())?;
+    let chunks = stmt
+        .query_map(params![book_id], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    // Chunks were indexed in reading order
+    let mut by_word: HashMap<String, WordStat> = HashMap::new();
+    let mut total = 0;
+    for chunk in chunks {
+        for token in tokenizer.tokens(&chunk.map_err(|e| e.to_string())?) {
+            by_word
+                .entry(token)
+                .or_insert_with_key(|word| WordStat {
+                    word: word.clone(),
+                    count: 0,
+                    first: total,
+                })
+                .count += 1;
+            total += 1;
+        }
+    }
+    let mut words: Vec<WordStat> = by_word.into_values().collect();
+    words.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+    let counts = WordCounts { total, words };
+    if read_only::is_read_only(conn) {
+        return Ok(counts);
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO book_word_stats (book_id, content_hash, tokenizer, counts, built_at)
+         VALUES (?, ?, ?, ?, unixepoch())",
+        params![
+            book_id,
+            content_hash,
+            tokenizer.fingerprint,
+            serde_json::to_string(&counts).map_err(|e| e.to_string())?
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(counts)
+}
+
+/// Word counts of a book indexed for search, with its stopwords (by the book's language)
+/// left out, and how many of its words the dictionary has and the active profile knows
+#[tauri::command]
+pub fn get_book_vocabulary(
+    book_id: i64,
+    top: Option<usize>,
+    state: tauri::State<DbState>,
+) -> Result<BookVocabulary, String> {
+    let conn = state.user_data()?;
+    let book = books::load_book(&conn, book_id)?;
+    let tokenizer =
+        Tokenizer::for_language(&conn, book.language.as_deref()).map_err(|e| e.to_string())?;
+    let counts = counts_for(&conn, book_id, &tokenizer)?;
+
+    let distinct: Vec<&str> = counts.words.iter().map(|w| w.word.as_str()).collect();
+    let distinct_json = serde_json::to_string(&distinct).unwrap_or_default();
+    let (not_in_dictionary, known): (usize, usize) = conn
+        .query_row(
+            "SELECT
+                (SELECT COUNT(*) FROM json_each(?1) j
+                 WHERE NOT EXISTS (SELECT 1 FROM dictionary d WHERE d.word = j.value)),
+                (SELECT COUNT(*) FROM json_each(?1) j
+                 WHERE EXISTS (SELECT 1 FROM known_words k
+                               WHERE k.profile_id = (SELECT id FROM temp.current_profile)
+                                 AND k.word = j.value))",
+            params![distinct_json],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    Ok(BookVocabulary {
+        book_id,
+        total_words: counts.total,
+        distinct_words: counts.words.len(),
+        top_words: counts
+            .words
+            .iter()
+            .take(top.unwrap_or(DEFAULT_TOP_WORDS))
+            .map(|w| WordCount {
+                word: w.word.clone(),
+                count: w.count,
+            })
+            .collect(),
+        not_in_dictionary,
+        known,
+    })
+}
@@ -0,0 +1,332 @@
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::db::{self, DbState};
+use crate::{analytics, book_search, queue, settings};
+
+/// Percentage at which a book counts as finished, overridable via settings
+pub const FINISHED_THRESHOLD_SETTING: &str = "finished_threshold";
+pub const DEFAULT_FINISHED_THRESHOLD: f64 = 98.0;
+
+/// Columns selected for a `Book`, in the order `book_from_row` reads them. Select them
+/// `FROM` `BOOK_SOURCE`.
+pub const BOOK_COLUMNS: &str = "b.id, b.path, b.title, b.author, b.language, b.format,
+    p.locator, COALESCE(p.progress, 0.0), b.added_at, p.last_opened_at, p.finished_at,
+    EXISTS(SELECT 1 FROM reading_queue rq
+           WHERE rq.book_id = b.id AND rq.profile_id = (SELECT id FROM temp.current_profile))";
+
+/// Books joined with the active profile's reading position in each
+pub const BOOK_SOURCE: &str = "books b LEFT JOIN book_progress p
+    ON p.book_id = b.id AND p.profile_id = (SELECT id FROM temp.current_profile)";
+
+/// Reading-position columns that lived on `books` before profiles
+const LEGACY_POSITION_COLUMNS: [&str; 4] = ["locator", "progress", "last_opened_at", "finished_at"];
+
+#[derive(Serialize, Clone)]
+pub struct Book {
+    pub id: i64,
+    pub path: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub format: String,
+    pub locator: Option<String>,
+    pub progress: f64,
+    pub added_at: i64,
+    pub last_opened_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub queued: bool,
+}
+
+pub fn book_from_row(row: &Row) -> Result<Book> {
+    book_from_row_at(row, 0)
+}
+
+/// Read a `Book` whose columns start at `offset`, for queries that select extra leading columns
+pub fn book_from_row_at(row: &Row, offset: usize) -> Result<Book> {
+    Ok(Book {
+        id: row.get(offset)?,
+        path: row.get(offset + 1)?,
+        title: row.get(offset + 2)?,
+        author: row.get(offset + 3)?,
+        language: row.get(offset + 4)?,
+        format: row.get(offset + 5)?,
+        locator: row.get(offset + 6)?,
+        progress: row.get(offset + 7)?,
+        added_at: row.get(offset + 8)?,
+        last_opened_at: row.get(offset + 9)?,
+        finished_at: row.get(offset + 10)?,
+        queued: row.get(offset + 11)?,
+    })
+}
+
+/// Create the book library table
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS books (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            title TEXT NOT NULL,
+            author TEXT,
+            language TEXT,
+            format TEXT NOT NULL,
+            added_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_progress (
+            profile_id INTEGER NOT NULL REFERENCES profiles(id) ON DELETE CASCADE,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            locator TEXT,
+            progress REAL NOT NULL DEFAULT 0,
+            last_opened_at INTEGER,
+            finished_at INTEGER,
+            PRIMARY KEY (profile_id, book_id)
+        )",
+        [],
+    )?;
+
+    if db::column_exists(conn, "books", "locator")? {
+        move_positions_to_default_profile(conn)?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_tags (
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            tag TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (book_id, tag)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Libraries from before profiles kept one reading position per book on `books` itself;
+/// it becomes the Default profile's
+fn move_positions_to_default_profile(conn: &Connection) -> Result<()> {
+    // Libraries created before finished-book tracking
+    db::add_column_if_missing(conn, "books", "finished_at", "INTEGER")?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT OR IGNORE INTO book_progress
+            (profile_id, book_id, locator, progress, last_opened_at, finished_at)
+         SELECT 1, id, locator, progress, last_opened_at, finished_at FROM books
+         WHERE locator IS NOT NULL OR progress > 0
+            OR last_opened_at IS NOT NULL OR finished_at IS NOT NULL",
+        [],
+    )?;
+    for column in LEGACY_POSITION_COLUMNS {
+        tx.execute(&format!("ALTER TABLE books DROP COLUMN {}", column), [])?;
+    }
+    tx.commit()
+}
+
+/// Record that the active profile opened a book now
+pub fn touch_book(conn: &Connection, book_id: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO book_progress (profile_id, book_id, last_opened_at)
+         VALUES ((SELECT id FROM temp.current_profile), ?, unixepoch())
+         ON CONFLICT(profile_id, book_id) DO UPDATE SET last_opened_at = excluded.last_opened_at",
+        params![book_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_book(conn: &Connection, book_id: i64) -> Result<Option<Book>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM {} WHERE b.id = ?",
+            BOOK_COLUMNS, BOOK_SOURCE
+        ),
+        params![book_id],
+        book_from_row,
+    )
+    .optional()
+}
+
+/// Like `get_book`, but a missing book is an error for commands to return as-is
+pub fn load_book(conn: &Connection, book_id: i64) -> Result<Book, String> {
+    get_book(conn, book_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Book {} not found", book_id))
+}
+
+/// Hex SHA-256 of a book file's bytes, used to key caches of derived data
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn finished_threshold(conn: &Connection) -> f64 {
+    settings::get_f64(conn, FINISHED_THRESHOLD_SETTING, DEFAULT_FINISHED_THRESHOLD)
+}
+
+/// Register a book file, returning the existing entry if the path is already in the library
+#[tauri::command]
+pub fn add_book(
+    path: String,
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<Book, String> {
+    let conn = state.user_data()?;
+    insert_book(&conn, &path, title, author, language).map_err(|e| e.to_string())
+}
+
+pub fn insert_book(
+    conn: &Connection,
+    path: &str,
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+) -> Result<Book> {
+    let file = Path::new(path);
+    let format = file
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let title = title.unwrap_or_else(|| {
+        file.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string())
+    });
+
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO books (path, title, author, language, format, added_at)
+         VALUES (?, ?, ?, ?, ?, unixepoch())",
+        params![path, title, author, language, format],
+    )?;
+
+    if inserted > 0 {
+        analytics::record_event(conn, "add_book", serde_json::json!({ "format": format }));
+    }
+
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM {} WHERE b.path = ?",
+            BOOK_COLUMNS, BOOK_SOURCE
+        ),
+        params![path],
+        book_from_row,
+    )
+}
+
+#[tauri::command]
+pub fn list_books(state: tauri::State<DbState>) -> Result<Vec<Book>, String> {
+    let conn = state.user_data()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM {} ORDER BY COALESCE(p.last_opened_at, b.added_at) DESC",
+            BOOK_COLUMNS, BOOK_SOURCE
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map([], book_from_row)
+        .map_err(|e| e.to_string())?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(books)
+}
+
+#[tauri::command]
+pub fn remove_book(book_id: i64, state: tauri::State<DbState>) -> Result<(), String> {
+    let conn = state.user_data()?;
+    queue::remove_from_queue(&conn, book_id).map_err(|e| e.to_string())?;
+    book_search::remove_book_index(&conn, book_id).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM books WHERE id = ?", params![book_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn tags_for(conn: &Connection, book_id: i64) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT tag FROM book_tags WHERE book_id = ? ORDER BY tag COLLATE NOCASE")?;
+    let rows = stmt.query_map(params![book_id], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Replace a book's tags with the given set
+#[tauri::command]
+pub fn set_book_tags(
+    book_id: i64,
+    tags: Vec<String>,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    tx.execute("DELETE FROM book_tags WHERE book_id = ?", params![book_id])
+        .map_err(|e| e.to_string())?;
+    for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        tx.execute(
+            "INSERT OR IGNORE INTO book_tags (book_id, tag) VALUES (?, ?)",
+            params![book_id, tag],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    tx.commit().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_book_tags(book_id: i64, state: tauri::State<DbState>) -> Result<Vec<String>, String> {
+    let conn = state.user_data()?;
+    tags_for(&conn, book_id).map_err(|e| e.to_string())
+}
+
+/// Store the active profile's current locator and percentage read for a book
+#[tauri::command]
+pub fn save_book_position(
+    book_id: i64,
+    locator: String,
+    progress: f64,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+    let progress = progress.clamp(0.0, 100.0);
+
+    conn.execute(
+        "INSERT INTO book_progress (profile_id, book_id, locator, progress, last_opened_at)
+         VALUES ((SELECT id FROM temp.current_profile), ?, ?, ?, unixepoch())
+         ON CONFLICT(profile_id, book_id) DO UPDATE SET
+            locator = excluded.locator,
+            progress = excluded.progress,
+            last_opened_at = excluded.last_opened_at",
+        params![book_id, locator, progress],
+    )
+    .map_err(|e| e.to_string())?;
+
+    if progress >= finished_threshold(&conn)
+        && settings::get_bool(&conn, queue::AUTO_REMOVE_SETTING, false)
+    {
+        queue::remove_from_queue(&conn, book_id).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Start a finished book over: clears the finished mark and the saved position
+#[tauri::command]
+pub fn restart_book(book_id: i64, state: tauri::State<DbState>) -> Result<(), String> {
+    let conn = state.user_data()?;
+    conn.execute(
+        "UPDATE book_progress SET finished_at = NULL, locator = NULL, progress = 0
+         WHERE book_id = ? AND profile_id = (SELECT id FROM temp.current_profile)",
+        params![book_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, DbState, DictionaryQuery, SearchOptions};
+use crate::difficulty;
+
+/// Most entries one bulk edit may touch
+const MAX_BATCH: usize = 5000;
+
+/// Edits applied to every selected entry. Every field is optional; tag edits run in the
+/// order set, add, remove.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct EntryChanges {
+    /// Replace the entries' tags with these
+    set_tags: Option<Vec<String>>,
+    add_tags: Vec<String>,
+    remove_tags: Vec<String>,
+    language: Option<String>,
+    /// A CEFR level ("B2") or its rank
+    difficulty: Option<String>,
+    /// A usage label ("archaic", "slang") to add where it's missing
+    add_label: Option<String>,
+}
+
+/// Rows each kind of change actually altered; entries that already matched aren't counted
+#[derive(Serialize, Default)]
+pub struct ChangeCounts {
+    tags_added: usize,
+    tags_removed: usize,
+    language: usize,
+    difficulty: usize,
+    labels: usize,
+}
+
+/// Tags the user puts on dictionary entries. They go with the entry, so re-importing the
+/// bundled dictionary drops the tags on bundled entries.
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS entry_tags (
+            entry_id INTEGER NOT NULL REFERENCES dictionary(id) ON DELETE CASCADE,
+            tag TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (entry_id, tag)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_entry_tags_tag ON entry_tags(tag)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn clean_tags(tags: &[String]) -> Vec<&str> {
+    tags.iter()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Add each tag to the selected entries that don't have it yet
+fn add_tags(conn: &Connection, ids: &str, tags: &[&str]) -> Result<usize> {
+    let mut added = 0;
+    for tag in tags {
+        added += conn.execute(
+            "INSERT OR IGNORE INTO entry_tags (entry_id, tag)
+             SELECT id, ?2 FROM dictionary WHERE id IN (SELECT value FROM json_each(?1))",
+            params![ids, tag],
+        )?;
+    }
+    Ok(added)
+}
+
+/// Apply the changes to the entries in `ids` (a JSON array), in the caller's transaction
+fn apply(conn: &Connection, ids: &str, changes: &EntryChanges) -> Result<ChangeCounts, String> {
+    let mut counts = ChangeCounts::default();
+    let sql_err = |e: rusqlite::Error| e.to_string();
+
+    if let Some(tags) = &changes.set_tags {
+        let tags = clean_tags(tags);
+        counts.tags_removed += conn
+            .execute(
+                "DELETE FROM entry_tags
+                 WHERE entry_id IN (SELECT value FROM json_each(?1))
+                   AND tag NOT IN (SELECT value FROM json_each(?2))",
+                params![ids, serde_json::to_string(&tags).unwrap_or_default()],
+            )
+            .map_err(sql_err)?;
+        counts.tags_added += add_tags(conn, ids, &tags).map_err(sql_err)?;
+    }
+    counts.tags_added += add_tags(conn, ids, &clean_tags(&changes.add_tags)).map_err(sql_err)?;
+    for tag in clean_tags(&changes.remove_tags) {
+        counts.tags_removed += conn
+            .execute(
+                "DELETE FROM entry_tags
+                 WHERE entry_id IN (SELECT value FROM json_each(?1)) AND tag = ?2",
+                params![ids, tag],
+            )
+            .map_err(sql_err)?;
+    }
+
+    if let Some(language) = changes.language.as_deref().map(str::trim) {
+        if language.is_empty() {
+            return Err("The language can't be empty".to_string());
+        }
+        counts.language = conn
+            .execute(
+                "UPDATE dictionary SET language = ?2
+                 WHERE id IN (SELECT value FROM json_each(?1)) AND language IS NOT ?2",
+                params![ids, language],
+            )
+            .map_err(sql_err)?;
+    }
+
+    if let Some(level) = &changes.difficulty {
+        let rank = difficulty::parse_level(level)
+            .ok_or_else(|| format!("Unknown difficulty level: {}", level))?;
+        counts.difficulty = conn
+            .execute(
+                "UPDATE dictionary SET difficulty = ?2
+                 WHERE id IN (SELECT value FROM json_each(?1)) AND difficulty IS NOT ?2",
+                params![ids, rank],
+            )
+            .map_err(sql_err)?;
+    }
+
+    if let Some(label) = changes
+        .add_label
+        .as_deref()
+        .map(|l| l.trim().to_lowercase())
+    {
+        if label.is_empty() {
+            return Err("The label can't be empty".to_string());
+        }
+        counts.labels = conn
+            .execute(
+                "UPDATE dictionary SET labels = json_insert(COALESCE(labels, '[]'), '$[#]', ?2)
+                 WHERE id IN (SELECT value FROM json_each(?1))
+                   AND NOT EXISTS (SELECT 1 FROM json_each(dictionary.labels)
+                                   WHERE value = ?2 COLLATE NOCASE)",
+                params![ids, label],
+            )
+            .map_err(sql_err)?;
+    }
+
+    Ok(counts)
+}
+
+/// Change tags, language, difficulty or labels across many entries at once. All of it
+/// happens in one transaction, so a bad value leaves every entry as it was.
+#[tauri::command]
+pub fn bulk_update_entries(
+    entry_ids: Vec<i64>,
+    changes: EntryChanges,
+    state: tauri::State<DbState>,
+) -> Result<ChangeCounts, String> {
+    if entry_ids.len() > MAX_BATCH {
+        return Err(format!(
+            "At most {} entries can be edited at once",
+            MAX_BATCH
+        ));
+    }
+    let ids = serde_json::to_string(&entry_ids).map_err(|e| e.to_string())?;
+
+    let conn = state.user_data()?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let counts = apply(&tx, &ids, &changes)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(counts)
+}
+
+/// Ids of every entry matching the search filters, and the word prefix when there is one,
+/// for selecting all results before a bulk edit. Unlike a search, nothing is capped.
+#[tauri::command]
+pub fn select_entry_ids(
+    word: Option<String>,
+    filter: Option<SearchOptions>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<i64>, String> {
+    let filter = filter.unwrap_or_default().effective();
+    let prefix = db::like_prefix(&db::normalize(word.as_deref().unwrap_or_default()));
+
+    let conn = state.0.lock().unwrap();
+    filter
+        .apply(DictionaryQuery::matching(
+            "d.word LIKE ? ESCAPE '\\'",
+            prefix,
+        ))
+        .fetch_ids(&conn)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_entry_tags(entry_id: i64, state: tauri::State<DbState>) -> Result<Vec<String>, String> {
+    let conn = state.user_data()?;
+    let mut stmt = conn
+        .prepare("SELECT tag FROM entry_tags WHERE entry_id = ? ORDER BY tag COLLATE NOCASE")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![entry_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>>>().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tauri::Manager;
+
+    /// Entries 1–3 are the selection; 4 is left out and has to come through untouched
+    fn dictionary() -> Connection {
+        let conn = db::init_db(None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO dictionary (id, word, definition, language, difficulty, labels) VALUES
+                 (1, 'run', 'To move swiftly.', 'en', NULL, NULL),
+                 (2, 'runs', 'Plural of run.', 'en', 4, '[\"informal\"]'),
+                 (3, 'baleine', 'A whale.', 'fr', NULL, '[\"Archaic\"]'),
+                 (4, 'harpoon', 'A barbed spear.', 'en', NULL, NULL);
+             INSERT INTO entry_tags (entry_id, tag) VALUES
+                 (1, 'verb'), (2, 'verb'), (2, 'sport'), (4, 'verb');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn counts(counts: &ChangeCounts) -> [usize; 5] {
+        [
+            counts.tags_added,
+            counts.tags_removed,
+            counts.language,
+            counts.difficulty,
+            counts.labels,
+        ]
+    }
+
+    fn untouched(conn: &Connection) -> (String, Option<i64>, Option<String>, Vec<String>) {
+        let (language, difficulty, labels) = conn
+            .query_row(
+                "SELECT language, difficulty, labels FROM dictionary WHERE id = 4",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        let tags = conn
+            .prepare("SELECT tag FROM entry_tags WHERE entry_id = 4")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        (language, difficulty, labels, tags)
+    }
+
+    #[test]
+    fn counts_only_rows_that_changed() {
+        let ids = "[1, 2, 3]";
+        // [tags added, tags removed, language, difficulty, labels]
+        let cases = [
+            // 'sport' goes from 2; 'noun' goes on all three and 'verb' on 3
+            (json!({ "set_tags": ["verb", "noun"] }), [4, 1, 0, 0, 0]),
+            // Blank tags are dropped, leaving an empty set
+            (json!({ "set_tags": [" ", ""] }), [0, 3, 0, 0, 0]),
+            // Tags compare without case
+            (json!({ "add_tags": ["Verb", "noun"] }), [4, 0, 0, 0, 0]),
+            (
+                json!({ "remove_tags": ["VERB", "missing"] }),
+                [0, 2, 0, 0, 0],
+            ),
+            // Set, then add, then remove
+            (
+                json!({ "set_tags": ["verb"], "add_tags": ["noun"], "remove_tags": ["noun"] }),
+                [4, 4, 0, 0, 0],
+            ),
+            (json!({ "language": " en " }), [0, 0, 1, 0, 0]),
+            (json!({ "difficulty": "B2" }), [0, 0, 0, 2, 0]),
+            (json!({ "difficulty": "4" }), [0, 0, 0, 2, 0]),
+            // Lowercased, and not added again where it's there in another case
+            (json!({ "add_label": "ARCHAIC " }), [0, 0, 0, 0, 2]),
+            (json!({}), [0, 0, 0, 0, 0]),
+        ];
+
+        for (changes, expected) in cases {
+            let conn = dictionary();
+            let before = untouched(&conn);
+            let parsed: EntryChanges = serde_json::from_value(changes.clone()).unwrap();
+            let result = apply(&conn, ids, &parsed).unwrap();
+            assert_eq!(counts(&result), expected, "{}", changes);
+            assert_eq!(untouched(&conn), before, "{}", changes);
+        }
+    }
+
+    #[test]
+    fn running_the_same_edit_again_changes_nothing() {
+        let conn = dictionary();
+        let changes: EntryChanges = serde_json::from_value(json!({
+            "set_tags": ["verb", "noun"],
+            "language": "en",
+            "difficulty": "B2",
+            "add_label": "archaic",
+        }))
+        .unwrap();
+
+        let first = apply(&conn, "[1, 2, 3]", &changes).unwrap();
+        assert_eq!(counts(&first), [4, 1, 1, 2, 2]);
+        let second = apply(&conn, "[1, 2, 3]", &changes).unwrap();
+        assert_eq!(counts(&second), [0, 0, 0, 0, 0]);
+
+        let labels: String = conn
+            .query_row("SELECT labels FROM dictionary WHERE id = 2", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(labels, r#"["informal","archaic"]"#);
+    }
+
+    #[test]
+    fn a_bad_level_rolls_back_the_whole_batch() {
+        let app = tauri::test::mock_app();
+        app.manage(DbState::new(dictionary(), false));
+        let changes: EntryChanges = serde_json::from_value(json!({
+            "set_tags": ["noun"],
+            "language": "de",
+            "difficulty": "Z9",
+        }))
+        .unwrap();
+
+        let result = bulk_update_entries(vec![1, 2, 3], changes, app.state());
+        assert_eq!(
+            result.err().as_deref(),
+            Some("Unknown difficulty level: Z9")
+        );
+
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        let languages: String = conn
+            .query_row(
+                "SELECT group_concat(language, ',') FROM (SELECT language FROM dictionary ORDER BY id)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(languages, "en,en,fr,en");
+        let tags: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM entry_tags WHERE tag = 'noun'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tags, 0);
+        drop(conn);
+        assert_eq!(get_entry_tags(2, app.state()).unwrap(), ["sport", "verb"]);
+    }
+
+    #[test]
+    fn tags_are_unavailable_while_locked() {
+        let app = tauri::test::mock_app();
+        app.manage(DbState::new(dictionary(), true));
+        assert!(get_entry_tags(1, app.state()).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+
+use crate::db::{self, DbState};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WordStatus {
+    /// Marked known by the active profile, itself or by its base form
+    Known,
+    Unknown,
+}
+
+/// What the reader needs to know about one word on the page
+#[derive(Serialize)]
+pub struct WordCoverage {
+    /// The word normalized the way lookups normalize it
+    word: String,
+    /// Whether a lookup would find an entry, directly or through its base form, e.g. "run"
+    /// for "running". Prefix matches don't count.
+    in_dictionary: bool,
+    status: WordStatus,
+}
+
+/// Flag a batch of words in one query: they go into a temp table, keyed by word with the
+/// base forms `db::lookup` would fall back to, which is then joined against the dictionary
+/// and the known words
+fn check(conn: &Connection, words: &[String]) -> Result<Vec<WordCoverage>> {
+    conn.execute(
+        "CREATE TEMP TABLE IF NOT EXISTS checked_words (
+            word TEXT PRIMARY KEY COLLATE NOCASE,
+            position INTEGER NOT NULL,
+            base TEXT,
+            base_e TEXT
+        )",
+        [],
+    )?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM temp.checked_words", [])?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT OR IGNORE INTO temp.checked_words (word, position, base, base_e)
+             VALUES (?, ?, ?, ?)",
+        )?;
+        for (position, word) in words.iter().enumerate() {
+            let word = db::normalize(word);
+            if word.is_empty() {
+                continue;
+            }
+            let base = db::stem(&word);
+            let base_e = format!("{}e", base);
+            let other = |form: String| (form != word).then_some(form);
+            insert.execute(params![word, position, other(base), other(base_e)])?;
+        }
+    }
+
+    let coverage = {
+        let mut stmt = tx.prepare(
+            "SELECT c.word,
+                    EXISTS(SELECT 1 FROM dictionary d WHERE d.word = c.word)
+                    OR EXISTS(SELECT 1 FROM dictionary d WHERE d.word IN (c.base, c.base_e)),
+                    EXISTS(SELECT 1 FROM known_words k
+                           WHERE k.profile_id = (SELECT id FROM temp.current_profile)
+                             AND k.word IN (c.word, c.base, c.base_e))
+             FROM temp.checked_words c
+             ORDER BY c.position",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(WordCoverage {
+                word: row.get(0)?,
+                in_dictionary: row.get(1)?,
+                status: if row.get::<_, bool>(2)? {
+                    WordStatus::Known
+                } else {
+                    WordStatus::Unknown
+                },
+            })
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    tx.commit()?;
+    Ok(coverage)
+}
+
+/// Whether each of a page's words is in the dictionary and known, for underlining the
+/// unknown ones. Answered in one query however many words there are; duplicates and
+/// blanks are dropped and the rest come back in the order given. While the database is
+/// locked every word is unknown.
+#[tauri::command]
+pub fn check_words(
+    words: Vec<String>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<WordCoverage>, String> {
+    let conn = state.0.lock().unwrap();
+    check(&conn, &words).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn page(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn a_page_is_flagged_in_order() {
+        let conn = db::init_db(None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition) VALUES
+                 ('run', 'To move swiftly.'),
+                 ('whale', 'A large sea mammal.'),
+                 ('ishmael', 'A narrator.');
+             INSERT INTO known_words (profile_id, word, marked_at) VALUES
+                 (1, 'whale', 0),
+                 (1, 'harpoon', 0),
+                 (2, 'run', 0);",
+        )
+        .unwrap();
+
+        let words = page(&[
+            "Running", "  ", "whales", "Ishmael", "running", "", "harpoon", "xyzzy", "Whales",
+        ]);
+        let flags: Vec<_> = check(&conn, &words)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.word, c.in_dictionary, c.status == WordStatus::Known))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                // Known by another profile only
+                ("running".to_string(), true, false),
+                // Known and found through its base form
+                ("whales".to_string(), true, true),
+                ("ishmael".to_string(), true, false),
+                // Known without an entry
+                ("harpoon".to_string(), false, true),
+                ("xyzzy".to_string(), false, false),
+            ]
+        );
+
+        // The temp table is emptied for the next page
+        let next = check(&conn, &page(&["whale"])).unwrap();
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].status, WordStatus::Known);
+    }
+
+    #[test]
+    fn a_page_is_checked_quickly_on_a_large_dictionary() {
+        let conn = db::init_db(None).unwrap();
+        conn.execute_batch(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000)
+             INSERT INTO dictionary (word, definition) SELECT 'word' || i, 'A word.' FROM n;
+             INSERT INTO known_words (profile_id, word, marked_at)
+             SELECT 1, word, 0 FROM dictionary WHERE id % 7 = 0;",
+        )
+        .unwrap();
+        let words: Vec<String> = (0..500)
+            .map(|i| match i % 3 {
+                0 => format!("word{}", i * 199 + 1),
+                1 => format!("word{}ing", i * 199 + 1),
+                _ => format!("missing{}", i),
+            })
+            .collect();
+
+        let started = Instant::now();
+        let coverage = check(&conn, &words).unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(coverage.len(), 500);
+        assert!(coverage[0].in_dictionary && coverage[1].in_dictionary);
+        assert!(!coverage[2].in_dictionary);
+        // Well under the release target of 20ms even unoptimized
+        assert!(elapsed < Duration::from_millis(200), "took {:?}", elapsed);
+    }
+}
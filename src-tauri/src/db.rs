@@ -1,36 +1,98 @@
-use rusqlite::{params, Connection, Result};
-use serde::Deserialize;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Result};
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::sync::Mutex;
-use tauri::Manager;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{Manager, Runtime};
 
-pub struct DbState(pub Mutex<Connection>);
+use crate::licenses::{self, License};
+use crate::query_budget::{self, QueryError};
+use crate::session_lookups::SessionLookups;
+use crate::source_stats::{self, SourceCounters};
+use crate::{
+    analytics, book_search, book_words, books, bulk_edit, difficulty, epub, etymology, glossary,
+    history, hyphenation, memory, migrations, onboarding, pack_updates, pdf_text, profiles, queue,
+    review, senses, sessions, settings, snippet, source_reports, summary, text_book, tokens,
+    vocabulary, word_family, word_of_day,
+};
 
-#[derive(Deserialize)]
+/// The open database, and whether it's the dictionary-only stand-in used while an
+/// encrypted database is locked
+pub struct DbState(pub Mutex<Connection>, pub AtomicBool);
+
+pub const LOCKED_ERROR: &str = "The database is locked; unlock it to use personal data";
+
+impl DbState {
+    pub fn new(conn: Connection, locked: bool) -> Self {
+        DbState(Mutex::new(conn), AtomicBool::new(locked))
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.1.load(Ordering::SeqCst)
+    }
+
+    /// Lock the connection for a command that reads or writes personal data, which isn't
+    /// there while the database is locked
+    pub fn user_data(&self) -> Result<MutexGuard<'_, Connection>, String> {
+        if self.is_locked() {
+            return Err(LOCKED_ERROR.to_string());
+        }
+        Ok(self.0.lock().unwrap())
+    }
+}
+
+#[derive(Deserialize, Default)]
 struct DictionaryEntry {
     word: String,
     definition: String,
+    #[serde(default)]
+    pos: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
+    /// Usage labels such as "archaic" or "slang"
+    #[serde(default)]
+    labels: Vec<String>,
+    /// Corpus frequency rank or count; higher is more common
+    #[serde(default)]
+    frequency: Option<i64>,
 }
 
 #[derive(Deserialize)]
 struct DictionaryData {
     words: Vec<DictionaryEntry>,
+    #[serde(default)]
+    license: Option<License>,
+    /// Release of the file, compared against the pack catalog to offer updates
+    #[serde(default)]
+    version: Option<String>,
+}
+
+/// Location of the persistent database, if the app data directory is available
+pub fn database_path<R: Runtime>(handle: &tauri::AppHandle<R>) -> Option<PathBuf> {
+    let app_dir = handle.path().app_data_dir().ok()?;
+    let _ = fs::create_dir_all(&app_dir);
+    Some(app_dir.join("dictionary.db"))
 }
 
-/// Initialize the database - loads from bundled dictionary.json
+/// Initialize the database. The dictionary itself is filled in later, by
+/// `populate_initial_dictionary` once the user has picked one.
 pub fn init_db(app_handle: Option<&tauri::AppHandle>) -> Result<Connection> {
     // Use persistent database in app data directory if available, otherwise in-memory
-    let conn = if let Some(handle) = app_handle {
-        if let Some(app_dir) = handle.path().app_data_dir().ok() {
-            let _ = fs::create_dir_all(&app_dir);
-            let db_path = app_dir.join("dictionary.db");
-            Connection::open(&db_path)?
-        } else {
-            Connection::open_in_memory()?
-        }
-    } else {
-        Connection::open_in_memory()?
+    let conn = match app_handle.and_then(database_path) {
+        Some(db_path) => Connection::open(db_path)?,
+        None => Connection::open_in_memory()?,
     };
+    prepare(conn)
+}
+
+/// Bring an open connection up to the current schema. An empty dictionary stays empty.
+pub fn prepare(conn: Connection) -> Result<Connection> {
+    conn.execute_batch("PRAGMA foreign_keys = ON")?;
+
+    // Must run before any schema change so the snapshot is of the untouched file
+    let legacy = migrations::detect_legacy(&conn)?;
 
     // Create tables if they don't exist
     conn.execute(
@@ -42,42 +104,112 @@ pub fn init_db(app_handle: Option<&tauri::AppHandle>) -> Result<Connection> {
         [],
     )?;
 
+    add_column_if_missing(&conn, "dictionary", "pos", "TEXT")?;
+    add_column_if_missing(
+        &conn,
+        "dictionary",
+        "source",
+        "TEXT NOT NULL DEFAULT 'bundled'",
+    )?;
+    add_column_if_missing(
+        &conn,
+        "dictionary",
+        "language",
+        "TEXT NOT NULL DEFAULT 'en'",
+    )?;
+    add_column_if_missing(&conn, "dictionary", "labels", "TEXT")?;
+    add_column_if_missing(&conn, "dictionary", "frequency", "INTEGER")?;
+
     // Create index for faster lookups
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_word ON dictionary(word COLLATE NOCASE)",
         [],
     )?;
 
-    // Check if dictionary is already populated
-    let count: i64 = conn.query_row("SELECT COUNT(*) FROM dictionary", [], |row| row.get(0))?;
+    settings::create_table(&conn)?;
+    profiles::create_table(&conn)?;
+    analytics::create_table(&conn)?;
+    history::create_table(&conn)?;
+    books::create_table(&conn)?;
+    queue::create_table(&conn)?;
+    sessions::create_table(&conn)?;
+    review::create_table(&conn)?;
+    text_book::create_table(&conn)?;
+    epub::create_table(&conn)?;
+    pdf_text::create_table(&conn)?;
+    book_search::create_table(&conn)?;
+    book_words::create_table(&conn)?;
+    glossary::create_table(&conn)?;
+    tokens::create_table(&conn)?;
+    hyphenation::create_table(&conn)?;
+    senses::create_table(&conn)?;
+    bulk_edit::create_table(&conn)?;
+    source_stats::create_table(&conn)?;
+    source_reports::create_table(&conn)?;
+    licenses::create_table(&conn)?;
+    pack_updates::create_table(&conn)?;
+    summary::create_table(&conn)?;
+    vocabulary::create_table(&conn)?;
+    word_family::create_table(&conn)?;
+    word_of_day::create_table(&conn)?;
+    etymology::create_table(&conn)?;
+    difficulty::create_table(&conn)?;
+    migrations::create_table(&conn)?;
+    migrations::finish(&conn, legacy)?;
+    profiles::activate(&conn)?;
+    memory::apply(&conn)?;
+    onboarding::settle(&conn)?;
+
+    Ok(conn)
+}
+
+pub fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    conn.query_row(
+        &format!(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('{}') WHERE name = ?)",
+            table
+        ),
+        params![column],
+        |row| row.get(0),
+    )
+}
 
-    if count == 0 {
-        // Load dictionary from bundled resource or embedded data
-        load_dictionary_data(&conn, app_handle)?;
+/// Add a column to an existing table, for upgrading databases created by older versions
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    if !column_exists(conn, table, column)? {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
     }
 
-    Ok(conn)
+    Ok(())
 }
 
 /// Load dictionary data from JSON file or use embedded fallback
-fn load_dictionary_data(conn: &Connection, app_handle: Option<&tauri::AppHandle>) -> Result<()> {
+pub fn load_dictionary_data(
+    conn: &Connection,
+    app_handle: Option<&tauri::AppHandle>,
+) -> Result<()> {
     let mut loaded = false;
 
     // Try to load from bundled resource
-    if let Some(handle) = app_handle {
-        if let Ok(resource_path) = handle.path().resolve(
-            "resources/dictionary.json",
-            tauri::path::BaseDirectory::Resource,
-        ) {
-            if let Ok(json_content) = fs::read_to_string(&resource_path) {
-                if let Ok(data) = serde_json::from_str::<DictionaryData>(&json_content) {
-                    insert_entries(conn, &data.words)?;
-                    loaded = true;
-                    println!(
-                        "Loaded {} dictionary entries from bundled file",
-                        data.words.len()
-                    );
-                }
+    if let Some(resource_path) = app_handle.and_then(bundled_dictionary_path) {
+        if let Ok(json_content) = fs::read_to_string(&resource_path) {
+            if let Ok(data) = serde_json::from_str::<DictionaryData>(&json_content) {
+                let tx = conn.unchecked_transaction()?;
+                import_bundled(&tx, &data, &json_content)?;
+                tx.commit()?;
+                loaded = true;
+                println!(
+                    "Loaded {} dictionary entries from bundled file",
+                    data.words.len()
+                );
             }
         }
     }
@@ -85,7 +217,7 @@ fn load_dictionary_data(conn: &Connection, app_handle: Option<&tauri::AppHandle>
     // Fallback to embedded data if bundled file not found
     if !loaded {
         let fallback_entries = get_fallback_entries();
-        insert_entries(conn, &fallback_entries)?;
+        insert_entries(conn, &fallback_entries, "fallback")?;
         println!(
             "Loaded {} fallback dictionary entries",
             fallback_entries.len()
@@ -95,137 +227,951 @@ fn load_dictionary_data(conn: &Connection, app_handle: Option<&tauri::AppHandle>
     Ok(())
 }
 
-fn insert_entries(conn: &Connection, entries: &[DictionaryEntry]) -> Result<()> {
+/// Resolved path of the bundled dictionary.json
+pub fn bundled_dictionary_path<R: Runtime>(handle: &tauri::AppHandle<R>) -> Option<PathBuf> {
+    handle
+        .path()
+        .resolve(
+            "resources/dictionary.json",
+            tauri::path::BaseDirectory::Resource,
+        )
+        .ok()
+}
+
+fn import_bundled(conn: &Connection, data: &DictionaryData, json: &str) -> Result<()> {
+    insert_entries(conn, &data.words, "bundled")?;
+    licenses::record(conn, "bundled", data.license.as_ref())?;
+    pack_updates::record_version(conn, "bundled", data.version.as_deref())?;
+    etymology::import(conn, json)?;
+    difficulty::import(conn, json)?;
+    Ok(())
+}
+
+/// Replace the bundled entries (and the fallback words standing in for them) with the
+/// contents of a dictionary file, leaving entries from other sources alone. Nothing
+/// changes if the file doesn't parse.
+pub fn replace_bundled(conn: &Connection, json: &str) -> Result<usize, String> {
+    let data = serde_json::from_str::<DictionaryData>(json).map_err(|e| e.to_string())?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM dictionary WHERE source IN ('bundled', 'fallback')",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Every link comes from the bundled file, so they're rebuilt along with it
+    tx.execute("DELETE FROM etymology_links", [])
+        .map_err(|e| e.to_string())?;
+    import_bundled(&tx, &data, json).map_err(|e| e.to_string())?;
+    senses::reapply(&tx).map_err(|e| e.to_string())?;
+    tx.execute_batch("REINDEX dictionary")
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(data.words.len())
+}
+
+/// Add the entries of a downloaded dictionary pack under `source`, next to whatever is
+/// already installed. Returns how many were added; nothing changes if the file doesn't parse.
+pub fn add_pack(conn: &Connection, json: &str, source: &str) -> Result<usize, String> {
+    let data = serde_json::from_str::<DictionaryData>(json).map_err(|e| e.to_string())?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    insert_entries(&tx, &data.words, source).map_err(|e| e.to_string())?;
+    licenses::record(&tx, source, data.license.as_ref()).map_err(|e| e.to_string())?;
+    pack_updates::record_version(&tx, source, data.version.as_deref())
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(data.words.len())
+}
+
+/// Replace the entries of `source` with a new release of its file. The new entries are
+/// imported under a staging source, the tags on old entries are carried over to new
+/// entries with the same headword, and only then are the old entries removed and the
+/// staging ones renamed, all in one transaction. Returns how many entries the new release
+/// has and how many tags carried over; nothing changes if the file doesn't parse.
+pub fn swap_source(conn: &Connection, json: &str, source: &str) -> Result<(usize, usize), String> {
+    let data = serde_json::from_str::<DictionaryData>(json).map_err(|e| e.to_string())?;
+    let staging = format!("{}.staging", source);
+    // The fallback words only ever stand in for the bundled entries
+    let replaced = if source == "bundled" {
+        vec!["bundled", "fallback"]
+    } else {
+        vec![source]
+    };
+    let replaced = serde_json::to_string(&replaced).unwrap_or_default();
+    let sql_err = |e: rusqlite::Error| e.to_string();
+
+    let tx = conn.unchecked_transaction().map_err(sql_err)?;
+    // Left behind if a previous swap failed partway through its own transaction
+    tx.execute("DELETE FROM dictionary WHERE source = ?", params![staging])
+        .map_err(sql_err)?;
+    insert_entries(&tx, &data.words, &staging).map_err(sql_err)?;
+    let tags = tx
+        .execute(
+            "INSERT OR IGNORE INTO entry_tags (entry_id, tag)
+             SELECT n.id, t.tag FROM entry_tags t
+             JOIN dictionary o ON o.id = t.entry_id
+             JOIN dictionary n ON n.word = o.word AND n.source = ?2
+             WHERE o.source IN (SELECT value FROM json_each(?1))",
+            params![replaced, staging],
+        )
+        .map_err(sql_err)?;
+    tx.execute(
+        "DELETE FROM dictionary WHERE source IN (SELECT value FROM json_each(?))",
+        params![replaced],
+    )
+    .map_err(sql_err)?;
+    tx.execute(
+        "UPDATE dictionary SET source = ? WHERE source = ?",
+        params![source, staging],
+    )
+    .map_err(sql_err)?;
+
+    licenses::record(&tx, source, data.license.as_ref()).map_err(sql_err)?;
+    pack_updates::record_version(&tx, source, data.version.as_deref()).map_err(sql_err)?;
+    if source == "bundled" {
+        // As in `replace_bundled`: the links and levels come with the bundled file
+        tx.execute("DELETE FROM etymology_links", [])
+            .map_err(sql_err)?;
+        etymology::import(&tx, json).map_err(sql_err)?;
+        difficulty::import(&tx, json).map_err(sql_err)?;
+    }
+    senses::reapply(&tx).map_err(sql_err)?;
+    tx.execute_batch("REINDEX dictionary").map_err(sql_err)?;
+    tx.commit().map_err(sql_err)?;
+
+    Ok((data.words.len(), tags))
+}
+
+fn insert_entries(conn: &Connection, entries: &[DictionaryEntry], source: &str) -> Result<()> {
     for entry in entries {
+        let labels = (!entry.labels.is_empty())
+            .then(|| serde_json::to_string(&entry.labels).unwrap_or_default());
         conn.execute(
-            "INSERT INTO dictionary (word, definition) VALUES (?, ?)",
-            params![entry.word.to_lowercase(), entry.definition],
+            "INSERT INTO dictionary (word, definition, pos, source, language, labels, frequency)
+             VALUES (?, ?, ?, ?, COALESCE(?, 'en'), ?, ?)",
+            params![
+                entry.word.to_lowercase(),
+                entry.definition,
+                entry.pos,
+                source,
+                entry.language,
+                labels,
+                entry.frequency
+            ],
         )?;
     }
     Ok(())
 }
 
 fn get_fallback_entries() -> Vec<DictionaryEntry> {
-    vec![
-        DictionaryEntry {
-            word: "algorithm".to_string(),
-            definition: "A step-by-step procedure for solving a problem.".to_string(),
-        },
-        DictionaryEntry {
-            word: "api".to_string(),
-            definition: "Application Programming Interface; protocols for building software."
-                .to_string(),
-        },
-        DictionaryEntry {
-            word: "array".to_string(),
-            definition: "A data structure containing a collection of elements.".to_string(),
-        },
-        DictionaryEntry {
-            word: "bank".to_string(),
-            definition: "An institution for handling money; also, the land beside water."
-                .to_string(),
-        },
-        DictionaryEntry {
-            word: "boolean".to_string(),
-            definition: "A data type with only two values: true or false.".to_string(),
-        },
-        DictionaryEntry {
-            word: "buffer".to_string(),
-            definition: "Temporary storage for data being transferred.".to_string(),
-        },
-        DictionaryEntry {
-            word: "cache".to_string(),
-            definition: "Storage for faster future data access.".to_string(),
-        },
-        DictionaryEntry {
-            word: "class".to_string(),
-            definition: "A blueprint for creating objects in OOP.".to_string(),
-        },
-        DictionaryEntry {
-            word: "compiler".to_string(),
-            definition: "A program that translates source code into machine code.".to_string(),
-        },
-        DictionaryEntry {
-            word: "database".to_string(),
-            definition: "An organized collection of structured data.".to_string(),
-        },
-        DictionaryEntry {
-            word: "debug".to_string(),
-            definition: "To find and fix errors in software.".to_string(),
-        },
-        DictionaryEntry {
-            word: "function".to_string(),
-            definition: "A reusable block of code that performs a task.".to_string(),
-        },
-        DictionaryEntry {
-            word: "interpreter".to_string(),
-            definition: "A program that executes instructions directly.".to_string(),
-        },
-        DictionaryEntry {
-            word: "loop".to_string(),
-            definition: "A construct that repeats a block of code.".to_string(),
-        },
-        DictionaryEntry {
-            word: "memory".to_string(),
-            definition: "Storage for data and instructions.".to_string(),
-        },
-        DictionaryEntry {
-            word: "object".to_string(),
-            definition: "An instance of a class with data and methods.".to_string(),
-        },
-        DictionaryEntry {
-            word: "pointer".to_string(),
-            definition: "A variable storing a memory address.".to_string(),
-        },
-        DictionaryEntry {
-            word: "recursion".to_string(),
-            definition: "A technique where a function calls itself.".to_string(),
-        },
-        DictionaryEntry {
-            word: "string".to_string(),
-            definition: "A sequence of characters representing text.".to_string(),
-        },
-        DictionaryEntry {
-            word: "variable".to_string(),
-            definition: "A named storage location for data.".to_string(),
-        },
+    [
+        (
+            "algorithm",
+            "A step-by-step procedure for solving a problem.",
+        ),
+        (
+            "api",
+            "Application Programming Interface; protocols for building software.",
+        ),
+        (
+            "array",
+            "A data structure containing a collection of elements.",
+        ),
+        (
+            "bank",
+            "An institution for handling money; also, the land beside water.",
+        ),
+        (
+            "boolean",
+            "A data type with only two values: true or false.",
+        ),
+        ("buffer", "Temporary storage for data being transferred."),
+        ("cache", "Storage for faster future data access."),
+        ("class", "A blueprint for creating objects in OOP."),
+        (
+            "compiler",
+            "A program that translates source code into machine code.",
+        ),
+        ("database", "An organized collection of structured data."),
+        ("debug", "To find and fix errors in software."),
+        ("function", "A reusable block of code that performs a task."),
+        (
+            "interpreter",
+            "A program that executes instructions directly.",
+        ),
+        ("loop", "A construct that repeats a block of code."),
+        ("memory", "Storage for data and instructions."),
+        ("object", "An instance of a class with data and methods."),
+        ("pointer", "A variable storing a memory address."),
+        ("recursion", "A technique where a function calls itself."),
+        ("string", "A sequence of characters representing text."),
+        ("variable", "A named storage location for data."),
     ]
+    .into_iter()
+    .map(|(word, definition)| DictionaryEntry {
+        word: word.to_string(),
+        definition: definition.to_string(),
+        ..Default::default()
+    })
+    .collect()
+}
+
+/// Normalize a search term the way the dictionary stores words
+pub fn normalize(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+/// Strip common English inflections so "debugging", "debugged" and "debugs" all reduce to
+/// "debug". Shared by dictionary lookups and library matching so both agree on word forms.
+pub fn stem(word: &str) -> String {
+    let word = word.to_lowercase();
+    let long_enough = |base: &str| base.chars().count() >= 3;
+
+    let mut base = if let Some(b) = word.strip_suffix("ies").filter(|b| long_enough(b)) {
+        format!("{}y", b)
+    } else if let Some(b) = word
+        .strip_suffix("ing")
+        .or_else(|| word.strip_suffix("ed"))
+        .filter(|b| long_enough(b))
+    {
+        // "running" -> "runn" -> "run"
+        let chars: Vec<char> = b.chars().collect();
+        match chars[..] {
+            [.., x, y] if x == y && !matches!(x, 'l' | 's' | 'z' | 'a' | 'e' | 'i' | 'o' | 'u') => {
+                chars[..chars.len() - 1].iter().collect()
+            }
+            _ => b.to_string(),
+        }
+    } else if let Some(b) = word.strip_suffix("es").filter(|b| {
+        long_enough(b)
+            && ["s", "x", "z", "ch", "sh"]
+                .iter()
+                .any(|end| b.ends_with(end))
+    }) {
+        b.to_string()
+    } else if let Some(b) = word
+        .strip_suffix('s')
+        .filter(|b| long_enough(b) && !b.ends_with('s') && !b.ends_with('u') && !b.ends_with('i'))
+    {
+        // "readings" -> "reading" -> "read"
+        return stem(b);
+    } else {
+        word.clone()
+    };
+
+    // "make" and "making" both end up as "mak"
+    if base.chars().count() > 3 && base.ends_with('e') {
+        base.pop();
+    }
+    base
+}
+
+/// A definition found by a lookup, with the source it came from
+pub struct Sense {
+    pub definition: String,
+    pub source: String,
+}
+
+fn definitions(senses: Vec<Sense>) -> Vec<String> {
+    senses.into_iter().map(|sense| sense.definition).collect()
+}
+
+fn query_senses(conn: &Connection, sql: &str, term: &str) -> Result<Vec<Sense>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![term], |row| {
+        Ok(Sense {
+            definition: row.get(0)?,
+            source: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn exact_senses(conn: &Connection, term: &str) -> Result<Vec<Sense>> {
+    let sql = format!(
+        "SELECT definition, source FROM dictionary WHERE word = ? COLLATE NOCASE ORDER BY {}",
+        senses::SENSE_ORDER
+    );
+    query_senses(conn, &sql, term)
+}
+
+/// Definitions whose headword matches `term` exactly (case-insensitive)
+pub fn lookup_exact(conn: &Connection, term: &str) -> Result<Vec<String>> {
+    exact_senses(conn, term).map(definitions)
+}
+
+/// Up to three entries whose word starts with the term
+fn prefix_senses(conn: &Connection, term: &str) -> Result<Vec<Sense>> {
+    let sql = format!(
+        "SELECT definition, source FROM dictionary WHERE word LIKE ? COLLATE NOCASE
+         ORDER BY word COLLATE NOCASE, {} LIMIT 3",
+        senses::SENSE_ORDER
+    );
+    query_senses(conn, &sql, &format!("{}%", term))
+}
+
+/// Definitions of the base form of an inflected word, e.g. "debug" for "debugging"
+fn inflected_senses(conn: &Connection, term: &str) -> Result<Vec<Sense>> {
+    let base = stem(term);
+    for candidate in [base.clone(), format!("{}e", base)] {
+        if candidate == term {
+            continue;
+        }
+        let results = exact_senses(conn, &candidate)?;
+        if !results.is_empty() {
+            return Ok(results);
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// What to show when there's no exact match: the base form, else a few prefix matches
+fn fallback_senses(conn: &Connection, term: &str) -> Result<Vec<Sense>> {
+    let results = inflected_senses(conn, term)?;
+    if results.is_empty() {
+        prefix_senses(conn, term)
+    } else {
+        Ok(results)
+    }
+}
+
+fn matched_senses(conn: &Connection, term: &str) -> Result<(Vec<Sense>, bool)> {
+    let results = exact_senses(conn, term)?;
+    if results.is_empty() {
+        let results = fallback_senses(conn, term)?;
+        let fallback_found = !results.is_empty();
+        Ok((results, fallback_found))
+    } else {
+        Ok((results, false))
+    }
+}
+
+/// Like `lookup`, keeping the source of each definition, e.g. to credit it
+pub fn lookup_senses(conn: &Connection, term: &str) -> Result<Vec<Sense>> {
+    matched_senses(conn, term).map(|(results, _)| results)
+}
+
+/// Exact match first, falling back to the base form and then a few prefix matches
+pub fn lookup(conn: &Connection, term: &str) -> Result<Vec<String>> {
+    lookup_senses(conn, term).map(definitions)
+}
+
+/// Like `lookup`, also saying whether the results came from the fallback
+pub fn lookup_matched(conn: &Connection, term: &str) -> Result<(Vec<String>, bool)> {
+    matched_senses(conn, term)
+        .map(|(results, fallback_found)| (definitions(results), fallback_found))
+}
+
+/// Result order for dictionary searches
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Headword order, each word's senses in their display order
+    #[default]
+    Relevance,
+    Alphabetical,
+    /// Shortest headwords first
+    Length,
+    /// Most common first; entries without a frequency go last
+    Frequency,
+}
+
+impl SortOrder {
+    fn order_clause(self) -> &'static str {
+        match self {
+            SortOrder::Relevance => {
+                "d.word COLLATE NOCASE, d.sense_order IS NULL, d.sense_order, d.id"
+            }
+            SortOrder::Alphabetical => "d.word COLLATE NOCASE, d.definition",
+            SortOrder::Length => {
+                "length(d.word), d.word COLLATE NOCASE, d.sense_order IS NULL, d.sense_order, d.id"
+            }
+            SortOrder::Frequency => {
+                "d.frequency IS NULL, d.frequency DESC, d.sense_order IS NULL, d.sense_order, d.id"
+            }
+        }
+    }
+}
+
+/// Filters and ordering for `search_dictionary`. Every field is optional and the defaults
+/// give the unfiltered search.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub pos: Option<String>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub source: Option<String>,
+    pub language: Option<String>,
+    /// Leave out entries carrying any of these labels ("archaic", "offensive")
+    pub exclude_labels: Vec<String>,
+    pub sort: SortOrder,
+}
+
+impl SearchOptions {
+    /// The options as actually applied: blank strings dropped, labels trimmed and deduplicated,
+    /// and a reversed length range swapped
+    pub fn effective(mut self) -> Self {
+        let clean = |value: Option<String>| {
+            value
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        self.pos = clean(self.pos);
+        self.source = clean(self.source);
+        self.language = clean(self.language);
+
+        let mut labels: Vec<String> = Vec::new();
+        for label in self.exclude_labels.iter().map(|l| l.trim().to_lowercase()) {
+            if !label.is_empty() && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        self.exclude_labels = labels;
+
+        if let (Some(min), Some(max)) = (self.min_length, self.max_length) {
+            if min > max {
+                self.min_length = Some(max);
+                self.max_length = Some(min);
+            }
+        }
+        self
+    }
+
+    pub fn apply(&self, mut query: DictionaryQuery) -> DictionaryQuery {
+        if let Some(pos) = &self.pos {
+            query = query.filter("d.pos = ? COLLATE NOCASE", pos.clone());
+        }
+        if let Some(min) = self.min_length {
+            query = query.filter("length(d.word) >= ?", min as i64);
+        }
+        if let Some(max) = self.max_length {
+            query = query.filter("length(d.word) <= ?", max as i64);
+        }
+        if let Some(source) = &self.source {
+            query = query.filter("d.source = ?", source.clone());
+        }
+        if let Some(language) = &self.language {
+            query = query.filter("d.language = ? COLLATE NOCASE", language.clone());
+        }
+        for label in &self.exclude_labels {
+            query = query.filter(
+                "NOT EXISTS (SELECT 1 FROM json_each(d.labels) WHERE value = ? COLLATE NOCASE)",
+                label.clone(),
+            );
+        }
+        query.order_by(self.sort.order_clause())
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchEntry {
+    pub word: String,
+    pub definition: String,
+    pub pos: Option<String>,
+    pub difficulty: Option<String>,
+    pub source: String,
+    /// `definition` was shortened to the snippet length; `get_entry_details` has it whole
+    pub truncated: bool,
+}
+
+/// Why a search came back the way it did
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchStatus {
+    Ok,
+    /// No dictionary has been installed yet; the onboarding choice is still open or was
+    /// put off
+    EmptyDictionary,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    status: SearchStatus,
+    results: Vec<SearchEntry>,
+    /// The filters that were applied, for showing active filters
+    options: SearchOptions,
+}
+
+/// Dictionary query assembled from fixed SQL fragments. Values only ever reach SQLite as
+/// bound parameters, so filters combine freely without building SQL from user input.
+pub struct DictionaryQuery {
+    conditions: Vec<&'static str>,
+    values: Vec<Value>,
+    order: &'static str,
+    limit: Option<i64>,
+}
+
+impl DictionaryQuery {
+    /// Start from the condition that selects candidate headwords, e.g. `d.word = ?`
+    pub fn matching(condition: &'static str, value: impl Into<Value>) -> Self {
+        DictionaryQuery {
+            conditions: vec![condition],
+            values: vec![value.into()],
+            order: SortOrder::Relevance.order_clause(),
+            limit: None,
+        }
+    }
+
+    /// Add a condition with a single `?` placeholder
+    pub fn filter(mut self, condition: &'static str, value: impl Into<Value>) -> Self {
+        self.conditions.push(condition);
+        self.values.push(value.into());
+        self
+    }
+
+    pub fn order_by(mut self, order: &'static str) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn select(&self, columns: &str) -> String {
+        format!(
+            "SELECT {} FROM dictionary d WHERE {} ORDER BY {}{}",
+            columns,
+            self.conditions.join(" AND "),
+            self.order,
+            if self.limit.is_some() { " LIMIT ?" } else { "" }
+        )
+    }
+
+    pub fn sql(&self) -> String {
+        self.select("d.word, d.definition, d.pos, d.difficulty, d.source")
+    }
+
+    fn bound_values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.values
+            .iter()
+            .cloned()
+            .chain(self.limit.map(Value::from))
+    }
+
+    /// Ids of the matching entries rather than the entries themselves
+    pub fn fetch_ids(&self, conn: &Connection) -> Result<Vec<i64>> {
+        let mut stmt = conn.prepare(&self.select("d.id"))?;
+        let rows = stmt.query_map(params_from_iter(self.bound_values()), |row| row.get(0))?;
+        rows.collect()
+    }
+
+    pub fn fetch(&self, conn: &Connection) -> Result<Vec<SearchEntry>> {
+        let mut stmt = conn.prepare(&self.sql())?;
+        let rows = stmt.query_map(params_from_iter(self.bound_values()), |row| {
+            Ok(SearchEntry {
+                word: row.get(0)?,
+                definition: row.get(1)?,
+                pos: row.get(2)?,
+                difficulty: row
+                    .get::<_, Option<i64>>(3)?
+                    .and_then(difficulty::level_name),
+                source: row.get(4)?,
+                truncated: false,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+/// Escape LIKE wildcards so a term only ever matches literally
+pub fn like_prefix(term: &str) -> String {
+    let mut pattern = String::with_capacity(term.len() + 1);
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// The same stages as `lookup`, with the options applied to each. The flag is true when
+/// the results are exact matches.
+pub fn search(
+    conn: &Connection,
+    term: &str,
+    options: &SearchOptions,
+) -> Result<(Vec<SearchEntry>, bool)> {
+    let exact = options
+        .apply(DictionaryQuery::matching("d.word = ?", term.to_string()))
+        .fetch(conn)?;
+    if !exact.is_empty() {
+        return Ok((exact, true));
+    }
+
+    let base = stem(term);
+    for candidate in [base.clone(), format!("{}e", base)] {
+        if candidate == term {
+            continue;
+        }
+        let inflected = options
+            .apply(DictionaryQuery::matching("d.word = ?", candidate))
+            .fetch(conn)?;
+        if !inflected.is_empty() {
+            return Ok((inflected, false));
+        }
+    }
+
+    let prefixed = options
+        .apply(DictionaryQuery::matching(
+            "d.word LIKE ? ESCAPE '\\'",
+            like_prefix(term),
+        ))
+        .limit(3)
+        .fetch(conn)?;
+    Ok((prefixed, false))
 }
 
 #[tauri::command]
-pub fn search_dictionary(word: &str, state: tauri::State<DbState>) -> Result<Vec<String>, String> {
+pub fn search_dictionary<R: Runtime>(
+    word: &str,
+    options: Option<SearchOptions>,
+    window: tauri::Window<R>,
+    state: tauri::State<DbState>,
+    session: tauri::State<SessionLookups>,
+    counters: tauri::State<SourceCounters>,
+) -> Result<SearchResponse, QueryError> {
+    search_from(window.label(), word, options, &state, &session, &counters)
+}
+
+/// `search_dictionary` on behalf of the window (or other caller) with the given label,
+/// whose session lookups an exact match is added to
+pub fn search_from(
+    label: &str,
+    word: &str,
+    options: Option<SearchOptions>,
+    state: &DbState,
+    session: &SessionLookups,
+    counters: &SourceCounters,
+) -> Result<SearchResponse, QueryError> {
     let conn = state.0.lock().unwrap();
-    let search_term = word.trim().to_lowercase();
+    let search_term = normalize(word);
+    let options = options.unwrap_or_default().effective();
 
-    // First try exact match
-    let mut stmt = conn
-        .prepare("SELECT definition FROM dictionary WHERE word = ? COLLATE NOCASE")
-        .map_err(|e| e.to_string())?;
+    let (results, exact) = query_budget::run(&conn, |conn| search(conn, &search_term, &options))?;
+    let status = if results.is_empty() && !onboarding::has_entries(&conn)? {
+        SearchStatus::EmptyDictionary
+    } else {
+        SearchStatus::Ok
+    };
+    if let (true, Some(first)) = (exact, results.first()) {
+        session.push(label, &search_term, &first.definition);
+    }
 
-    let rows = stmt
-        .query_map(params![&search_term], |row| row.get::<_, String>(0))
-        .map_err(|e| e.to_string())?;
+    // Held in memory until unlocked rather than saved into the stand-in
+    if counters.record(&results) && !state.is_locked() {
+        if let Err(e) = counters.flush(&conn) {
+            eprintln!("Failed to save source statistics: {}", e);
+        }
+    }
 
-    let mut results: Vec<String> = Vec::new();
-    for row in rows {
-        results.push(row.map_err(|e| e.to_string())?);
+    // Usage records belong to the locked database, not the dictionary stand-in
+    if !state.is_locked() {
+        // A miss goes into the history for the missed-words list. Found words are recorded
+        // once they're actually read, by `define_selection`.
+        if results.is_empty() && !search_term.is_empty() && status == SearchStatus::Ok {
+            if let Err(e) = history::record_lookup(&conn, &search_term, false, false, None, None) {
+                eprintln!("Failed to record lookup: {}", e);
+            }
+        }
+        analytics::record_event(
+            &conn,
+            "search",
+            serde_json::json!({ "found": !results.is_empty() }),
+        );
     }
 
-    // If no exact match, try prefix match
-    if results.is_empty() {
-        let mut stmt = conn
-            .prepare("SELECT definition FROM dictionary WHERE word LIKE ? COLLATE NOCASE LIMIT 3")
-            .map_err(|e| e.to_string())?;
+    let max_chars = settings::get_f64(
+        &conn,
+        snippet::SNIPPET_LENGTH_SETTING,
+        snippet::DEFAULT_SNIPPET_LENGTH,
+    )
+    .max(0.0) as usize;
+    let results = results
+        .into_iter()
+        .map(|mut entry| {
+            let snippet = snippet::snippet(&entry.definition, max_chars);
+            entry.definition = snippet.text;
+            entry.truncated = snippet.truncated;
+            entry
+        })
+        .collect();
+
+    Ok(SearchResponse {
+        status,
+        results,
+        options,
+    })
+}
 
-        let pattern = format!("{}%", search_term);
-        let rows = stmt
-            .query_map(params![&pattern], |row| row.get::<_, String>(0))
-            .map_err(|e| e.to_string())?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for row in rows {
-            results.push(row.map_err(|e| e.to_string())?);
+    /// (word, definition, pos, source, labels, frequency)
+    type Row = (
+        &'static str,
+        &'static str,
+        &'static str,
+        &'static str,
+        &'static str,
+        Option<i64>,
+    );
+
+    const ENTRIES: &[Row] = &[
+        ("run", "To move quickly.", "verb", "core", "[]", Some(900)),
+        (
+            "run",
+            "A score in cricket.",
+            "noun",
+            "sport",
+            "[]",
+            Some(200),
+        ),
+        (
+            "run",
+            "A series of performances.",
+            "noun",
+            "core",
+            "[\"dated\"]",
+            None,
+        ),
+        ("runner", "One who runs.", "noun", "core", "[]", Some(300)),
+        (
+            "rung",
+            "A step of a ladder.",
+            "noun",
+            "core",
+            "[\"Archaic\"]",
+            Some(50),
+        ),
+        (
+            "runt",
+            "The smallest of a litter.",
+            "noun",
+            "core",
+            "[\"offensive\"]",
+            Some(80),
+        ),
+        ("ru_n", "A literal underscore.", "noun", "core", "[]", None),
+    ];
+
+    fn dictionary() -> Connection {
+        let conn = init_db(None).unwrap();
+        for (word, definition, pos, source, labels, frequency) in ENTRIES {
+            conn.execute(
+                "INSERT INTO dictionary (word, definition, pos, source, labels, frequency)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                params![word, definition, pos, source, labels, frequency],
+            )
+            .unwrap();
         }
+        conn
+    }
+
+    #[test]
+    fn locked_state_keeps_user_data_out() {
+        let state = DbState::new(init_db(None).unwrap(), true);
+        assert_eq!(state.user_data().err().as_deref(), Some(LOCKED_ERROR));
+        // The dictionary stand-in still answers searches
+        assert!(state.0.lock().is_ok());
+
+        let state = DbState::new(init_db(None).unwrap(), false);
+        assert!(state.user_data().is_ok());
     }
 
-    Ok(results)
+    fn found(conn: &Connection, term: &str, options: SearchOptions) -> (Vec<String>, bool) {
+        let (results, exact) = search(conn, term, &options.effective()).unwrap();
+        let shown = results
+            .into_iter()
+            .map(|e| format!("{}: {}", e.word, e.definition))
+            .collect();
+        (shown, exact)
+    }
+
+    #[test]
+    fn filters_combine() {
+        let conn = dictionary();
+        let cases: &[(&str, SearchOptions, &[&str], bool)] = &[
+            (
+                "run",
+                SearchOptions::default(),
+                &[
+                    "run: To move quickly.",
+                    "run: A score in cricket.",
+                    "run: A series of performances.",
+                ],
+                true,
+            ),
+            (
+                "run",
+                SearchOptions {
+                    pos: Some(" NOUN ".into()),
+                    source: Some("core".into()),
+                    ..Default::default()
+                },
+                &["run: A series of performances."],
+                true,
+            ),
+            (
+                "run",
+                SearchOptions {
+                    pos: Some("noun".into()),
+                    exclude_labels: vec!["Dated".into(), "dated".into(), " ".into()],
+                    ..Default::default()
+                },
+                &["run: A score in cricket."],
+                true,
+            ),
+            (
+                "running",
+                SearchOptions {
+                    source: Some("sport".into()),
+                    ..Default::default()
+                },
+                &["run: A score in cricket."],
+                false,
+            ),
+            (
+                "run",
+                SearchOptions {
+                    sort: SortOrder::Frequency,
+                    ..Default::default()
+                },
+                &[
+                    "run: To move quickly.",
+                    "run: A score in cricket.",
+                    "run: A series of performances.",
+                ],
+                true,
+            ),
+            (
+                "ru",
+                SearchOptions {
+                    min_length: Some(4),
+                    max_length: Some(4),
+                    exclude_labels: vec!["archaic".into()],
+                    ..Default::default()
+                },
+                &[
+                    "ru_n: A literal underscore.",
+                    "runt: The smallest of a litter.",
+                ],
+                false,
+            ),
+            (
+                "ru",
+                SearchOptions {
+                    min_length: Some(6),
+                    max_length: Some(4),
+                    sort: SortOrder::Length,
+                    ..Default::default()
+                },
+                &[
+                    "ru_n: A literal underscore.",
+                    "rung: A step of a ladder.",
+                    "runt: The smallest of a litter.",
+                ],
+                false,
+            ),
+            (
+                "ru_",
+                SearchOptions::default(),
+                &["ru_n: A literal underscore."],
+                false,
+            ),
+            (
+                "run",
+                SearchOptions {
+                    language: Some("fr".into()),
+                    ..Default::default()
+                },
+                &[],
+                false,
+            ),
+        ];
+
+        for (term, options, expected, exact) in cases {
+            let (results, was_exact) = found(&conn, term, options.clone());
+            assert_eq!(&results, expected, "searching {:?}", term);
+            assert_eq!(was_exact, *exact, "exactness of {:?}", term);
+        }
+    }
+
+    #[test]
+    fn effective_options_are_cleaned_up() {
+        let options = SearchOptions {
+            pos: Some("  ".into()),
+            source: Some(" core ".into()),
+            min_length: Some(9),
+            max_length: Some(2),
+            exclude_labels: vec![" Slang".into(), "slang".into(), "".into()],
+            ..Default::default()
+        }
+        .effective();
+        assert_eq!(options.pos, None);
+        assert_eq!(options.source.as_deref(), Some("core"));
+        assert_eq!((options.min_length, options.max_length), (Some(2), Some(9)));
+        assert_eq!(options.exclude_labels, ["slang"]);
+    }
+
+    #[test]
+    fn every_combination_looks_headwords_up_by_index() {
+        let conn = dictionary();
+        let all = SearchOptions {
+            pos: Some("noun".into()),
+            min_length: Some(2),
+            max_length: Some(8),
+            source: Some("core".into()),
+            language: Some("en".into()),
+            exclude_labels: vec!["archaic".into()],
+            sort: SortOrder::Relevance,
+        };
+        let variants = [
+            SearchOptions::default(),
+            all.clone(),
+            SearchOptions {
+                sort: SortOrder::Frequency,
+                ..all.clone()
+            },
+            SearchOptions {
+                sort: SortOrder::Length,
+                ..SearchOptions::default()
+            },
+        ];
+
+        for options in variants {
+            let exact = options.apply(DictionaryQuery::matching("d.word = ?", "run".to_string()));
+            let prefix = options
+                .apply(DictionaryQuery::matching(
+                    "d.word LIKE ? ESCAPE '\\'",
+                    like_prefix("ru"),
+                ))
+                .limit(3);
+            for query in [exact, prefix] {
+                let mut stmt = conn
+                    .prepare(&format!("EXPLAIN QUERY PLAN {}", query.sql()))
+                    .unwrap();
+                let plan: Vec<String> = stmt
+                    .query_map(params_from_iter(query.bound_values()), |row| row.get(3))
+                    .unwrap()
+                    .collect::<Result<_>>()
+                    .unwrap();
+                assert!(
+                    plan.iter()
+                        .any(|step| step.contains("USING INDEX idx_word")),
+                    "{} planned as {:?}",
+                    query.sql(),
+                    plan
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,89 @@
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+
+use crate::db::{self, DbState};
+
+/// Saves closer together than this are handled as one change
+const DEBOUNCE: Duration = Duration::from_millis(400);
+
+#[derive(Serialize, Clone)]
+struct Reloaded {
+    entries: usize,
+}
+
+#[derive(Serialize, Clone)]
+struct ReloadFailed {
+    message: String,
+}
+
+fn reload(app: &tauri::AppHandle, path: &std::path::Path) {
+    let result = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            let state = app.state::<DbState>();
+            let conn = state.0.lock().unwrap();
+            db::replace_bundled(&conn, &json)
+        });
+
+    match result {
+        Ok(entries) => {
+            println!(
+                "Reloaded {} dictionary entries from {}",
+                entries,
+                path.display()
+            );
+            let _ = app.emit("dictionary-reloaded", Reloaded { entries });
+        }
+        Err(message) => {
+            eprintln!("Keeping previous dictionary data: {}", message);
+            let _ = app.emit("dictionary-reload-failed", ReloadFailed { message });
+        }
+    }
+}
+
+/// Reload the bundled dictionary whenever resources/dictionary.json is saved. Debug builds
+/// only, so edits show up without wiping app data.
+pub fn watch(app: &tauri::AppHandle) {
+    let Some(path) = db::bundled_dictionary_path(app) else {
+        return;
+    };
+    let Some(dir) = path.parent().map(|p| p.to_path_buf()) else {
+        return;
+    };
+    let app = app.clone();
+
+    std::thread::spawn(move || {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("Dictionary hot reload unavailable: {}", e);
+                return;
+            }
+        };
+        // Watch the directory: editors often save by replacing the file
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            eprintln!("Dictionary hot reload unavailable: {}", e);
+            return;
+        }
+
+        let touches_file = |event: &notify::Result<notify::Event>| {
+            event
+                .as_ref()
+                .is_ok_and(|e| !e.kind.is_access() && e.paths.iter().any(|p| p == &path))
+        };
+
+        while let Ok(event) = rx.recv() {
+            if !touches_file(&event) {
+                continue;
+            }
+            // Wait for the burst of events from one save to settle
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+            reload(&app, &path);
+        }
+    });
+}
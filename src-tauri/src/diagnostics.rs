@@ -0,0 +1,53 @@
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use tauri::Runtime;
+
+use crate::backup;
+use crate::book_search::{self, BookIndexStats};
+use crate::db::DbState;
+use crate::memory::{self, MemoryModeName};
+use crate::permissions::{self, EntryPolicy};
+
+#[derive(Serialize)]
+pub struct Diagnostics {
+    database_bytes: i64,
+    dictionary_entries: i64,
+    books: i64,
+    book_search_index: BookIndexStats,
+    last_backup_at: Option<i64>,
+    memory_mode: MemoryModeName,
+    page_cache_kib: i64,
+    /// Tiers of commands each entry point outside the frontend may run
+    permissions: Vec<EntryPolicy>,
+}
+
+fn collect(conn: &Connection, memory_mode: MemoryModeName) -> Result<Diagnostics> {
+    let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0));
+    Ok(Diagnostics {
+        database_bytes: count(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        )?,
+        dictionary_entries: count("SELECT COUNT(*) FROM dictionary")?,
+        books: count("SELECT COUNT(*) FROM books")?,
+        book_search_index: book_search::index_stats(conn)?,
+        last_backup_at: backup::last_backup_at(conn)?,
+        memory_mode,
+        // Negative cache_size is in KiB, positive in pages
+        page_cache_kib: count(
+            "SELECT CASE WHEN cache_size < 0 THEN -cache_size
+                         ELSE cache_size * page_size / 1024 END
+             FROM pragma_cache_size(), pragma_page_size()",
+        )?,
+        permissions: permissions::effective(conn),
+    })
+}
+
+/// Storage figures for the settings screen
+#[tauri::command]
+pub fn get_diagnostics<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: tauri::State<DbState>,
+) -> Result<Diagnostics, String> {
+    let conn = state.0.lock().unwrap();
+    collect(&conn, memory::mode_name(&app)).map_err(|e| e.to_string())
+}
@@ -0,0 +1,291 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::backup;
+use crate::db::{self, DbState};
+use crate::tokens::Tokenizer;
+
+/// CEFR levels, stored in the `difficulty` column as their rank (A1 = 1 .. C2 = 6)
+const LEVELS: [&str; 6] = ["A1", "A2", "B1", "B2", "C1", "C2"];
+
+const DEFAULT_UNKNOWN_LIMIT: i64 = 100;
+const DEFAULT_QUIZ_SIZE: i64 = 10;
+const QUIZ_CHOICES: i64 = 4;
+
+#[derive(Serialize, Default)]
+pub struct DifficultyImport {
+    updated: usize,
+    /// Words in the file that aren't dictionary headwords
+    unknown_words: usize,
+    /// Lines without a recognisable level
+    invalid_lines: usize,
+}
+
+#[derive(Serialize)]
+pub struct UnknownWord {
+    word: String,
+    lookups: i64,
+    last_looked_up_at: i64,
+    difficulty: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct QuizQuestion {
+    word: String,
+    difficulty: Option<String>,
+    choices: Vec<String>,
+    /// Index of the correct definition in `choices`
+    answer: usize,
+}
+
+#[derive(Deserialize)]
+struct LevelRecord {
+    word: String,
+    #[serde(default, alias = "level")]
+    difficulty: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct LevelData {
+    words: Vec<LevelRecord>,
+}
+
+/// Parse a CEFR level ("B2", "c1") or a rank on the same scale ("4"; a 1–5 scale tops out at C1)
+pub fn parse_level(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Some(rank) = LEVELS.iter().position(|l| l.eq_ignore_ascii_case(value)) {
+        return Some(rank as i64 + 1);
+    }
+    value
+        .parse::<i64>()
+        .ok()
+        .filter(|rank| (1..=LEVELS.len() as i64).contains(rank))
+}
+
+pub fn level_name(rank: i64) -> Option<String> {
+    usize::try_from(rank - 1)
+        .ok()
+        .and_then(|i| LEVELS.get(i))
+        .map(|l| l.to_string())
+}
+
+/// Add the difficulty column and the index the level filters rely on
+pub fn create_table(conn: &Connection) -> Result<()> {
+    db::add_column_if_missing(conn, "dictionary", "difficulty", "INTEGER")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_dictionary_difficulty
+         ON dictionary(difficulty, word COLLATE NOCASE)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn set_level(conn: &Connection, word: &str, rank: i64) -> Result<usize> {
+    conn.execute(
+        "UPDATE dictionary SET difficulty = ? WHERE word = ? COLLATE NOCASE",
+        params![rank, word.trim()],
+    )
+}
+
+/// Levels given in a dictionary file as `difficulty` or `level`, string or number
+pub fn import(conn: &Connection, json: &str) -> Result<usize> {
+    let Ok(data) = serde_json::from_str::<LevelData>(json) else {
+        return Ok(0);
+    };
+
+    let mut updated = 0;
+    for record in &data.words {
+        let rank = match &record.difficulty {
+            Some(serde_json::Value::String(level)) => parse_level(level),
+            Some(serde_json::Value::Number(n)) => parse_level(&n.to_string()),
+            _ => None,
+        };
+        if let Some(rank) = rank {
+            updated += set_level(conn, &record.word, rank)?;
+        }
+    }
+    Ok(updated)
+}
+
+pub fn difficulty_of(conn: &Connection, word: &str) -> Result<Option<String>> {
+    let rank: Option<i64> = conn
+        .query_row(
+            "SELECT difficulty FROM dictionary
+             WHERE word = ? COLLATE NOCASE AND difficulty IS NOT NULL
+             LIMIT 1",
+            params![word],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(rank.and_then(level_name))
+}
+
+fn parse_filter(level: Option<&str>) -> Result<Option<i64>, String> {
+    level
+        .map(|l| parse_level(l).ok_or_else(|| format!("Unknown difficulty level: {}", l)))
+        .transpose()
+}
+
+/// Import levels from a "word,level" CSV. A header line and blank lines are skipped.
+#[tauri::command]
+pub fn set_difficulty_data(
+    path: &str,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<DifficultyImport, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let conn = state.0.lock().unwrap();
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    let mut report = DifficultyImport::default();
+    for (n, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let unquote = |field: &str| field.trim().trim_matches('"').to_string();
+        let Some((word, level)) = line.split_once(',').map(|(w, l)| (unquote(w), unquote(l)))
+        else {
+            report.invalid_lines += 1;
+            continue;
+        };
+        match parse_level(&level) {
+            Some(rank) => {
+                if set_level(&tx, &word, rank).map_err(|e| e.to_string())? > 0 {
+                    report.updated += 1;
+                } else {
+                    report.unknown_words += 1;
+                }
+            }
+            None if n == 0 => {}
+            None => report.invalid_lines += 1,
+        }
+    }
+
+    tx.commit().map_err(|e| e.to_string())?;
+    backup::schedule(&app);
+    Ok(report)
+}
+
+/// Words the active profile has looked up, most looked-up first, optionally only those at or
+/// above a level. Stopwords and words too short to count are left out, as in book analysis.
+#[tauri::command]
+pub fn get_unknown_words(
+    min_level: Option<&str>,
+    limit: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<UnknownWord>, String> {
+    let min_rank = parse_filter(min_level)?;
+    let conn = state.user_data()?;
+    let tokenizer = Tokenizer::for_language(&conn, None).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT h.word, COUNT(*), MAX(h.looked_up_at),
+                    (SELECT MAX(d.difficulty) FROM dictionary d
+                     WHERE d.word = h.word COLLATE NOCASE) AS level
+             FROM lookup_history h
+             WHERE h.profile_id = (SELECT id FROM temp.current_profile)
+               AND h.found = 1
+               AND lower(h.word) NOT IN (SELECT value FROM json_each(?3))
+               AND length(h.word) >= ?4
+               AND (?1 IS NULL OR EXISTS (
+                   SELECT 1 FROM dictionary d
+                   WHERE d.difficulty >= ?1 AND d.word = h.word COLLATE NOCASE))
+             GROUP BY h.word COLLATE NOCASE
+             ORDER BY COUNT(*) DESC, MAX(h.looked_up_at) DESC
+             LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map(
+            params![
+                min_rank,
+                limit.unwrap_or(DEFAULT_UNKNOWN_LIMIT),
+                tokenizer.stopwords_json(),
+                tokenizer.min_length() as i64
+            ],
+            |row| {
+                Ok(UnknownWord {
+                    word: row.get(0)?,
+                    lookups: row.get(1)?,
+                    last_looked_up_at: row.get(2)?,
+                    difficulty: row.get::<_, Option<i64>>(3)?.and_then(level_name),
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut words = Vec::new();
+    for row in rows {
+        words.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(words)
+}
+
+/// Multiple-choice definition questions, optionally only for words at one level
+#[tauri::command]
+pub fn generate_quiz(
+    level: Option<&str>,
+    count: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<QuizQuestion>, String> {
+    let rank = parse_filter(level)?;
+    let conn = state.0.lock().unwrap();
+
+    // Separate statements so the level filter can use idx_dictionary_difficulty
+    let filter = if rank.is_some() {
+        "WHERE difficulty = ?1"
+    } else {
+        "WHERE ?1 IS NULL"
+    };
+    let mut words = conn
+        .prepare(&format!(
+            "SELECT word, definition, difficulty, random() & 65535 FROM dictionary
+             {}
+             ORDER BY random()
+             LIMIT ?2",
+            filter
+        ))
+        .map_err(|e| e.to_string())?;
+    let picked = words
+        .query_map(params![rank, count.unwrap_or(DEFAULT_QUIZ_SIZE)], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut distractors = conn
+        .prepare(
+            "SELECT definition FROM dictionary
+             WHERE word <> ?1 COLLATE NOCASE
+             ORDER BY random()
+             LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut questions = Vec::new();
+    for (word, definition, difficulty, seed) in picked {
+        let mut choices = distractors
+            .query_map(params![word, QUIZ_CHOICES - 1], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<String>>>()
+            .map_err(|e| e.to_string())?;
+        let answer = seed as usize % (choices.len() + 1);
+        choices.insert(answer, definition);
+        questions.push(QuizQuestion {
+            word,
+            difficulty: difficulty.and_then(level_name),
+            choices,
+            answer,
+        });
+    }
+    Ok(questions)
+}
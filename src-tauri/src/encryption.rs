@@ -0,0 +1,311 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tauri::Runtime;
+
+use crate::db::{self, DbState};
+use crate::onboarding;
+
+/// First bytes of every unencrypted SQLite file; SQLCipher files start with random salt
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+// Only `Unsupported` is built without the feature
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub enum EncryptionError {
+    /// Built without the `encryption` feature
+    #[cfg_attr(feature = "encryption", allow(dead_code))]
+    Unsupported,
+    WrongPassphrase,
+    EmptyPassphrase,
+    AlreadyEncrypted,
+    NotEncrypted,
+    AlreadyUnlocked,
+    Locked,
+    /// No database file, e.g. the app data directory is unavailable
+    NoDatabase,
+    Database(String),
+}
+
+impl From<rusqlite::Error> for EncryptionError {
+    fn from(e: rusqlite::Error) -> Self {
+        EncryptionError::Database(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for EncryptionError {
+    fn from(e: std::io::Error) -> Self {
+        EncryptionError::Database(e.to_string())
+    }
+}
+
+#[derive(Serialize)]
+pub struct EncryptionStatus {
+    available: bool,
+    encrypted: bool,
+    locked: bool,
+}
+
+/// Whether the file at `path` is an encrypted database. Missing and empty files aren't.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    match File::open(path).and_then(|mut file| file.read_exact(&mut header)) {
+        Ok(()) => &header != SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+pub fn status<R: Runtime>(app: &tauri::AppHandle<R>, state: &DbState) -> EncryptionStatus {
+    EncryptionStatus {
+        available: cfg!(feature = "encryption"),
+        encrypted: db::database_path(app).is_some_and(|path| is_encrypted(&path)),
+        locked: state.is_locked(),
+    }
+}
+
+/// Open the database at startup. An encrypted file stays closed until `unlock`, and the app
+/// runs on an in-memory database holding only the bundled dictionary, so lookups keep
+/// working while nothing personal is readable. The returned flag says whether the
+/// connection is that locked stand-in. Otherwise a `read_only` run opens the file without
+/// write access.
+pub fn open_database(
+    app: &tauri::AppHandle,
+    read_only: bool,
+) -> rusqlite::Result<(Connection, bool)> {
+    if cfg!(feature = "encryption") && db::database_path(app).is_some_and(|p| is_encrypted(&p)) {
+        let stand_in = db::prepare(Connection::open_in_memory()?)?;
+        onboarding::populate_bundled(&stand_in, app)?;
+        return Ok((stand_in, true));
+    }
+    if read_only {
+        return Ok((crate::read_only::open(app)?, false));
+    }
+    Ok((db::init_db(Some(app))?, false))
+}
+
+#[cfg(feature = "encryption")]
+mod cipher {
+    use rusqlite::{params, Connection, ErrorCode};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::Ordering;
+    use tauri::{Emitter, Manager, Runtime};
+
+    use super::{is_encrypted, EncryptionError};
+    use crate::db::{self, DbState};
+    use crate::profiles::{self, CurrentProfile};
+    use crate::read_only::{self, ReadOnly};
+    use crate::session_lookups::SessionLookups;
+
+    fn require_passphrase(passphrase: &str) -> Result<(), EncryptionError> {
+        if passphrase.is_empty() {
+            return Err(EncryptionError::EmptyPassphrase);
+        }
+        Ok(())
+    }
+
+    fn encrypted_path<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<PathBuf, EncryptionError> {
+        let path = db::database_path(app).ok_or(EncryptionError::NoDatabase)?;
+        if !is_encrypted(&path) {
+            return Err(EncryptionError::NotEncrypted);
+        }
+        Ok(path)
+    }
+
+    /// Open an encrypted file, telling a wrong passphrase apart from other failures.
+    /// SQLCipher only checks the key on the first read.
+    fn open_encrypted(
+        path: &Path,
+        passphrase: &str,
+        read_only: bool,
+    ) -> Result<Connection, EncryptionError> {
+        let conn = if read_only {
+            read_only::open_file(path)?
+        } else {
+            Connection::open(path)?
+        };
+        conn.pragma_update(None, "key", passphrase)?;
+        match conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+            row.get::<_, i64>(0)
+        }) {
+            Ok(_) => Ok(conn),
+            Err(e) if e.sqlite_error_code() == Some(ErrorCode::NotADatabase) => {
+                Err(EncryptionError::WrongPassphrase)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Put the unlocked database in place of the current connection and point the
+    /// per-run state at it. A read-only one isn't migrated.
+    fn install<R: Runtime>(
+        app: &tauri::AppHandle<R>,
+        state: &DbState,
+        slot: &mut Connection,
+        conn: Connection,
+        read_only: bool,
+    ) -> Result<(), EncryptionError> {
+        let conn = if read_only {
+            read_only::prepare(conn)?
+        } else {
+            db::prepare(conn)?
+        };
+        let profile = profiles::current_id(&conn)?;
+        *slot = conn;
+
+        state.1.store(false, Ordering::SeqCst);
+        app.state::<CurrentProfile>()
+            .0
+            .store(profile, Ordering::SeqCst);
+        app.state::<SessionLookups>().0.lock().unwrap().clear();
+        Ok(())
+    }
+
+    pub fn unlock<R: Runtime>(
+        passphrase: &str,
+        app: &tauri::AppHandle<R>,
+        state: &DbState,
+    ) -> Result<(), EncryptionError> {
+        if !state.is_locked() {
+            return Err(EncryptionError::AlreadyUnlocked);
+        }
+        let path = encrypted_path(app)?;
+        let read_only = app.state::<ReadOnly>().0.is_some();
+        let unlocked = open_encrypted(&path, passphrase, read_only)?;
+
+        let mut conn = state.0.lock().unwrap();
+        install(app, state, &mut conn, unlocked, read_only)?;
+        let _ = app.emit("database-unlocked", ());
+        Ok(())
+    }
+
+    /// Copy the plaintext database into an encrypted file beside it, check the copy opens,
+    /// then replace the original with it. The original is untouched until that last step.
+    pub fn enable(
+        passphrase: &str,
+        app: &tauri::AppHandle,
+        state: &DbState,
+    ) -> Result<(), EncryptionError> {
+        require_passphrase(passphrase)?;
+        if state.is_locked() {
+            return Err(EncryptionError::Locked);
+        }
+        let path = db::database_path(app).ok_or(EncryptionError::NoDatabase)?;
+        if is_encrypted(&path) {
+            return Err(EncryptionError::AlreadyEncrypted);
+        }
+
+        let staging = path.with_extension("db.encrypting");
+        let _ = fs::remove_file(&staging);
+
+        let mut conn = state.0.lock().unwrap();
+        conn.execute(
+            "ATTACH DATABASE ? AS encrypted KEY ?",
+            params![staging.to_string_lossy(), passphrase],
+        )?;
+        let exported = conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()));
+        conn.execute("DETACH DATABASE encrypted", [])?;
+        if let Err(e) = exported
+            .map_err(EncryptionError::from)
+            .and_then(|_| open_encrypted(&staging, passphrase, false).map(drop))
+        {
+            let _ = fs::remove_file(&staging);
+            return Err(e);
+        }
+
+        // Close the plaintext file before replacing it
+        *conn = Connection::open_in_memory()?;
+        if let Err(e) = fs::rename(&staging, &path) {
+            *conn = db::init_db(Some(app))?;
+            let _ = fs::remove_file(&staging);
+            return Err(e.into());
+        }
+
+        let encrypted = open_encrypted(&path, passphrase, false)?;
+        install(app, state, &mut conn, encrypted, false)
+    }
+
+    pub fn change_passphrase(
+        old: &str,
+        new: &str,
+        app: &tauri::AppHandle,
+        state: &DbState,
+    ) -> Result<(), EncryptionError> {
+        require_passphrase(new)?;
+        if state.is_locked() {
+            return Err(EncryptionError::Locked);
+        }
+        let path = encrypted_path(app)?;
+        drop(open_encrypted(&path, old, false)?);
+
+        let conn = state.0.lock().unwrap();
+        conn.pragma_update(None, "rekey", new)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+mod cipher {
+    use tauri::Runtime;
+
+    use super::EncryptionError;
+    use crate::db::DbState;
+
+    pub fn unlock<R: Runtime>(
+        _: &str,
+        _: &tauri::AppHandle<R>,
+        _: &DbState,
+    ) -> Result<(), EncryptionError> {
+        Err(EncryptionError::Unsupported)
+    }
+
+    pub fn enable(_: &str, _: &tauri::AppHandle, _: &DbState) -> Result<(), EncryptionError> {
+        Err(EncryptionError::Unsupported)
+    }
+
+    pub fn change_passphrase(
+        _: &str,
+        _: &str,
+        _: &tauri::AppHandle,
+        _: &DbState,
+    ) -> Result<(), EncryptionError> {
+        Err(EncryptionError::Unsupported)
+    }
+}
+
+/// Encrypt the database with a passphrase. It has to be unlocked on every later start.
+#[tauri::command]
+pub fn enable_encryption(
+    passphrase: &str,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<EncryptionStatus, EncryptionError> {
+    cipher::enable(passphrase, &app, &state)?;
+    Ok(status(&app, &state))
+}
+
+/// Open the encrypted database. A wrong passphrase is reported as `wrong_passphrase`.
+/// In a read-only run it's opened read-only, as an unencrypted one would be.
+#[tauri::command]
+pub fn unlock<R: Runtime>(
+    passphrase: &str,
+    app: tauri::AppHandle<R>,
+    state: tauri::State<DbState>,
+) -> Result<EncryptionStatus, EncryptionError> {
+    cipher::unlock(passphrase, &app, &state)?;
+    Ok(status(&app, &state))
+}
+
+#[tauri::command]
+pub fn change_passphrase(
+    old: &str,
+    new: &str,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<EncryptionStatus, EncryptionError> {
+    cipher::change_passphrase(old, new, &app, &state)?;
+    Ok(status(&app, &state))
+}
@@ -0,0 +1,782 @@
+use percent_encoding::percent_decode_str;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek};
+use zip::ZipArchive;
+
+use crate::books::{self, Book};
+use crate::db::DbState;
+use crate::read_only;
+
+const MB: u64 = 1024 * 1024;
+
+/// Largest single resource (chapter, image, stylesheet) handed to the frontend
+const MAX_RESOURCE_BYTES: u64 = 20 * MB;
+
+/// Package documents (container, OPF, NCX, nav) are small; anything bigger is suspect
+const MAX_PACKAGE_BYTES: u64 = 4 * MB;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SpineItem {
+    pub href: String,
+    pub media_type: String,
+    pub linear: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TocEntry {
+    pub label: String,
+    /// Archive path of the target document, with its fragment if any
+    pub href: String,
+    pub depth: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EpubStructure {
+    pub title: Option<String>,
+    pub spine: Vec<SpineItem>,
+    pub toc: Vec<TocEntry>,
+}
+
+struct ManifestItem {
+    href: String,
+    media_type: String,
+    properties: String,
+}
+
+fn attr(tag: &BytesStart, name: &[u8]) -> Option<String> {
+    tag.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// Directory part of an archive path, without a trailing slash
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+/// Resolve `href` against the archive directory `base`, rejecting anything that climbs out
+/// of the archive. Fragments and queries are dropped.
+pub fn resolve_path(base: &str, href: &str) -> Option<String> {
+    if href.contains('\\') {
+        return None;
+    }
+    let path = href.split(['#', '?']).next()?;
+    let decoded = percent_decode_str(path).decode_utf8().ok()?;
+
+    let mut parts: Vec<&str> = if decoded.starts_with('/') {
+        Vec::new()
+    } else {
+        base.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in decoded.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("/"))
+    }
+}
+
+/// Like `resolve_path`, but keeps the fragment so TOC entries can point inside a document
+fn resolve_href(base: &str, href: &str) -> Option<String> {
+    let path = resolve_path(base, href)?;
+    Some(match href.split_once('#') {
+        Some((_, fragment)) if !fragment.is_empty() => format!("{}#{}", path, fragment),
+        _ => path,
+    })
+}
+
+fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    cap: u64,
+) -> Result<Vec<u8>, String> {
+    let file = archive
+        .by_name(name)
+        .map_err(|_| format!("{} is missing from the EPUB", name))?;
+    if file.size() > cap {
+        return Err(format!("{} is larger than the {} MB limit", name, cap / MB));
+    }
+
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.take(cap)
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+fn read_xml<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String, String> {
+    let data = read_entry(archive, name, MAX_PACKAGE_BYTES)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn xml_error(name: &str, e: quick_xml::Error) -> String {
+    format!("Invalid XML in {}: {}", name, e)
+}
+
+/// Path of the OPF package document, from META-INF/container.xml
+fn find_package(container: &str) -> Result<String, String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(tag)) | Ok(Event::Empty(tag))
+                if tag.local_name().as_ref() == b"rootfile" =>
+            {
+                if let Some(path) = attr(&tag, b"full-path") {
+                    return Ok(path);
+                }
+            }
+            Ok(Event::Eof) => return Err("EPUB container lists no package document".to_string()),
+            Err(e) => return Err(xml_error("container.xml", e)),
+            _ => {}
+        }
+    }
+}
+
+struct Package {
+    title: Option<String>,
+    manifest: HashMap<String, ManifestItem>,
+    spine: Vec<(String, bool)>,
+    ncx_id: Option<String>,
+}
+
+fn parse_package(opf: &str) -> Result<Package, String> {
+    let mut reader = Reader::from_str(opf);
+    reader.trim_text(true);
+
+    let mut package = Package {
+        title: None,
+        manifest: HashMap::new(),
+        spine: Vec::new(),
+        ncx_id: None,
+    };
+    let mut in_title = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(tag)) | Ok(Event::Empty(tag)) => match tag.local_name().as_ref() {
+                b"title" if package.title.is_none() => in_title = true,
+                b"item" => {
+                    if let (Some(id), Some(href)) = (attr(&tag, b"id"), attr(&tag, b"href")) {
+                        package.manifest.insert(
+                            id,
+                            ManifestItem {
+                                href,
+                                media_type: attr(&tag, b"media-type").unwrap_or_default(),
+                                properties: attr(&tag, b"properties").unwrap_or_default(),
+                            },
+                        );
+                    }
+                }
+                b"spine" => package.ncx_id = attr(&tag, b"toc"),
+                b"itemref" => {
+                    if let Some(idref) = attr(&tag, b"idref") {
+                        let linear = attr(&tag, b"linear").as_deref() != Some("no");
+                        package.spine.push((idref, linear));
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Text(text)) if in_title => {
+                package.title = text.unescape().ok().map(|t| t.trim().to_string());
+                in_title = false;
+            }
+            Ok(Event::End(tag)) if tag.local_name().as_ref() == b"title" => in_title = false,
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(xml_error("the package document", e)),
+            _ => {}
+        }
+    }
+
+    Ok(package)
+}
+
+/// EPUB2 table of contents from an NCX document
+fn parse_ncx(ncx: &str, base: &str) -> Result<Vec<TocEntry>, String> {
+    let mut reader = Reader::from_str(ncx);
+    reader.trim_text(true);
+
+    let mut toc: Vec<TocEntry> = Vec::new();
+    // Indexes into `toc` of the navPoints we are currently inside
+    let mut open: Vec<usize> = Vec::new();
+    let mut in_label = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(tag)) if tag.local_name().as_ref() == b"navPoint" => {
+                open.push(toc.len());
+                toc.push(TocEntry {
+                    label: String::new(),
+                    href: String::new(),
+                    depth: open.len() - 1,
+                });
+            }
+            Ok(Event::Start(tag)) if tag.local_name().as_ref() == b"navLabel" => in_label = true,
+            Ok(Event::End(tag)) if tag.local_name().as_ref() == b"navLabel" => in_label = false,
+            Ok(Event::Text(text)) if in_label => {
+                if let (Some(&i), Ok(label)) = (open.last(), text.unescape()) {
+                    toc[i].label.push_str(label.trim());
+                }
+            }
+            Ok(Event::Start(tag)) | Ok(Event::Empty(tag))
+                if tag.local_name().as_ref() == b"content" =>
+            {
+                if let (Some(&i), Some(src)) = (open.last(), attr(&tag, b"src")) {
+                    toc[i].href = resolve_href(base, &src).unwrap_or_default();
+                }
+            }
+            Ok(Event::End(tag)) if tag.local_name().as_ref() == b"navPoint" => {
+                open.pop();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(xml_error("the NCX table of contents", e)),
+            _ => {}
+        }
+    }
+
+    toc.retain(|entry| !entry.href.is_empty());
+    Ok(toc)
+}
+
+/// EPUB3 table of contents from the `<nav epub:type="toc">` of a navigation document
+fn parse_nav(nav: &str, base: &str) -> Result<Vec<TocEntry>, String> {
+    let mut reader = Reader::from_str(nav);
+
+    let mut toc = Vec::new();
+    let mut nav_depth = 0usize;
+    let mut in_toc = false;
+    let mut list_depth = 0usize;
+    // Element that opened the entry being read, its label text so far, and its link
+    let mut current: Option<(Vec<u8>, String, Option<String>)> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(tag)) => match tag.local_name().as_ref() {
+                b"nav" => {
+                    nav_depth += 1;
+                    if !in_toc && toc.is_empty() {
+                        in_toc = attr(&tag, b"type").is_some_and(|t| t.contains("toc"));
+                    }
+                }
+                b"ol" if in_toc => list_depth += 1,
+                name @ (b"a" | b"span") if in_toc && current.is_none() => {
+                    current = Some((name.to_vec(), String::new(), attr(&tag, b"href")));
+                }
+                _ => {}
+            },
+            Ok(Event::Text(text)) => {
+                if let (Some((_, label, _)), Ok(t)) = (current.as_mut(), text.unescape()) {
+                    label.push_str(&t);
+                }
+            }
+            Ok(Event::End(tag)) => match tag.local_name().as_ref() {
+                b"nav" => {
+                    nav_depth = nav_depth.saturating_sub(1);
+                    if nav_depth == 0 {
+                        in_toc = false;
+                    }
+                }
+                b"ol" if in_toc => list_depth = list_depth.saturating_sub(1),
+                name if current
+                    .as_ref()
+                    .is_some_and(|(opener, _, _)| opener == name) =>
+                {
+                    if let Some((_, label, Some(href))) = current.take() {
+                        // Headings without a link (<span>) group entries but aren't targets
+                        if let Some(href) = resolve_href(base, &href) {
+                            toc.push(TocEntry {
+                                label: label.split_whitespace().collect::<Vec<_>>().join(" "),
+                                href,
+                                depth: list_depth.saturating_sub(1),
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(xml_error("the navigation document", e)),
+            _ => {}
+        }
+    }
+
+    Ok(toc)
+}
+
+fn parse_structure<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<EpubStructure, String> {
+    let container = read_xml(archive, "META-INF/container.xml")?;
+    let opf_path = find_package(&container)?;
+    let opf_dir = parent_dir(&opf_path).to_string();
+    let package = parse_package(&read_xml(archive, &opf_path)?)?;
+
+    let spine = package
+        .spine
+        .iter()
+        .filter_map(|(idref, linear)| {
+            let item = package.manifest.get(idref)?;
+            Some(SpineItem {
+                href: resolve_path(&opf_dir, &item.href)?,
+                media_type: item.media_type.clone(),
+                linear: *linear,
+            })
+        })
+        .collect();
+
+    // Prefer the EPUB3 nav document, falling back to the EPUB2 NCX
+    let nav_path = package
+        .manifest
+        .values()
+        .find(|item| item.properties.split_whitespace().any(|p| p == "nav"))
+        .and_then(|item| resolve_path(&opf_dir, &item.href));
+    let ncx_path = package
+        .ncx_id
+        .as_ref()
+        .and_then(|id| package.manifest.get(id))
+        .or_else(|| {
+            package
+                .manifest
+                .values()
+                .find(|item| item.media_type == "application/x-dtbncx+xml")
+        })
+        .and_then(|item| resolve_path(&opf_dir, &item.href));
+
+    let mut toc = Vec::new();
+    if let Some(path) = nav_path {
+        toc = parse_nav(&read_xml(archive, &path)?, parent_dir(&path))?;
+    }
+    if toc.is_empty() {
+        if let Some(path) = ncx_path {
+            toc = parse_ncx(&read_xml(archive, &path)?, parent_dir(&path))?;
+        }
+    }
+
+    Ok(EpubStructure {
+        title: package.title,
+        spine,
+        toc,
+    })
+}
+
+/// Elements whose boundaries end a paragraph of extracted text
+const BLOCK_TAGS: [&str; 16] = [
+    "p",
+    "div",
+    "br",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "blockquote",
+    "tr",
+    "section",
+    "pre",
+    "dt",
+    "dd",
+];
+
+/// Decode the handful of entities that show up in book content
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let after = &rest[amp..];
+        let decoded = after.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &after[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn flush_paragraph(paragraphs: &mut Vec<String>, current: &mut String) {
+    let text = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        paragraphs.push(text);
+    }
+    current.clear();
+}
+
+/// Plain-text paragraphs of an XHTML content document, split at block-level elements
+pub fn html_paragraphs(html: &str) -> Vec<String> {
+    let body = html.find("<body").map(|i| &html[i..]).unwrap_or(html);
+
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut skipping: Option<&str> = None;
+    let mut rest = body;
+
+    while let Some(lt) = rest.find('<') {
+        if skipping.is_none() {
+            current.push_str(&decode_entities(&rest[..lt]));
+        }
+        let after = &rest[lt..];
+        let Some(gt) = after.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &after[1..gt];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        match skipping {
+            Some(until) if closing && name == until => skipping = None,
+            Some(_) => {}
+            None if !closing && !tag.ends_with('/') && (name == "script" || name == "style") => {
+                skipping = Some(if name == "script" { "script" } else { "style" });
+            }
+            None if BLOCK_TAGS.contains(&name.as_str()) => {
+                flush_paragraph(&mut paragraphs, &mut current);
+            }
+            None => {}
+        }
+
+        rest = &after[gt + 1..];
+    }
+
+    if skipping.is_none() {
+        current.push_str(&decode_entities(rest));
+    }
+    flush_paragraph(&mut paragraphs, &mut current);
+    paragraphs
+}
+
+/// Create the parsed-structure cache, keyed by content hash
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS epub_structure (
+            content_hash TEXT PRIMARY KEY,
+            structure TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn ensure_epub(book: &Book) -> Result<(), String> {
+    if book.format == "epub" {
+        Ok(())
+    } else {
+        Err(format!("{} is not an EPUB book", book.title))
+    }
+}
+
+/// The EPUB file and its content hash. Kept apart from `structure_for` so the file can be
+/// read and hashed without holding the connection.
+pub fn read_epub(book: &Book) -> Result<(Vec<u8>, String), String> {
+    ensure_epub(book)?;
+    let bytes = fs::read(&book.path).map_err(|e| e.to_string())?;
+    let hash = books::content_hash(&bytes);
+    Ok((bytes, hash))
+}
+
+/// Parsed structure of an EPUB file, from the cache when one with the same hash was
+/// parsed before
+pub fn structure_for(
+    conn: &Connection,
+    bytes: Vec<u8>,
+    hash: &str,
+) -> Result<EpubStructure, String> {
+    let cached: Option<String> = conn
+        .query_row(
+            "SELECT structure FROM epub_structure WHERE content_hash = ?",
+            params![hash],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(structure) = cached.and_then(|json| serde_json::from_str(&json).ok()) {
+        return Ok(structure);
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let structure = parse_structure(&mut archive)?;
+    if read_only::is_read_only(conn) {
+        return Ok(structure);
+    }
+
+    let json = serde_json::to_string(&structure).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO epub_structure (content_hash, structure, created_at)
+         VALUES (?, ?, unixepoch())",
+        params![hash, json],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(structure)
+}
+
+/// Read one document or image out of an EPUB without extracting the archive
+pub fn read_resource(book: &Book, href: &str) -> Result<Vec<u8>, String> {
+    ensure_epub(book)?;
+    let path = resolve_path("", href).ok_or_else(|| format!("Invalid resource path {}", href))?;
+    let file = File::open(&book.path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    read_entry(&mut archive, &path, MAX_RESOURCE_BYTES)
+}
+
+#[tauri::command]
+pub fn get_epub_structure(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<EpubStructure, String> {
+    let book = {
+        let conn = state.0.lock().unwrap();
+        books::load_book(&conn, book_id)?
+    };
+    let (bytes, hash) = read_epub(&book)?;
+    let conn = state.0.lock().unwrap();
+    structure_for(&conn, bytes, &hash)
+}
+
+/// Raw bytes of a resource, returned as an ArrayBuffer to the frontend
+#[tauri::command]
+pub fn get_epub_resource(
+    book_id: i64,
+    href: String,
+    state: tauri::State<DbState>,
+) -> Result<tauri::ipc::Response, String> {
+    let book = {
+        let conn = state.0.lock().unwrap();
+        books::load_book(&conn, book_id)?
+    };
+    read_resource(&book, &href).map(tauri::ipc::Response::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    const CONTAINER: &str = include_str!("../tests/fixtures/epub/container.xml");
+    const CHAPTER: &str = include_str!("../tests/fixtures/epub/chapter.xhtml");
+
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn epub2() -> Vec<u8> {
+        archive(&[
+            ("META-INF/container.xml", CONTAINER),
+            (
+                "OEBPS/content.opf",
+                include_str!("../tests/fixtures/epub/epub2/content.opf"),
+            ),
+            (
+                "OEBPS/toc.ncx",
+                include_str!("../tests/fixtures/epub/epub2/toc.ncx"),
+            ),
+            ("OEBPS/cover.xhtml", CHAPTER),
+            ("OEBPS/text/chapter 1.xhtml", CHAPTER),
+            ("OEBPS/text/chapter2.xhtml", CHAPTER),
+        ])
+    }
+
+    fn epub3() -> Vec<u8> {
+        archive(&[
+            ("META-INF/container.xml", CONTAINER),
+            (
+                "OEBPS/content.opf",
+                include_str!("../tests/fixtures/epub/epub3/content.opf"),
+            ),
+            (
+                "OEBPS/nav.xhtml",
+                include_str!("../tests/fixtures/epub/epub3/nav.xhtml"),
+            ),
+            (
+                "OEBPS/toc.ncx",
+                include_str!("../tests/fixtures/epub/epub3/toc.ncx"),
+            ),
+            ("OEBPS/xhtml/part1.xhtml", CHAPTER),
+            ("OEBPS/xhtml/part2.xhtml", CHAPTER),
+        ])
+    }
+
+    fn parse(bytes: Vec<u8>) -> EpubStructure {
+        parse_structure(&mut ZipArchive::new(Cursor::new(bytes)).unwrap()).unwrap()
+    }
+
+    fn toc(structure: &EpubStructure) -> Vec<(&str, &str, usize)> {
+        structure
+            .toc
+            .iter()
+            .map(|e| (e.label.as_str(), e.href.as_str(), e.depth))
+            .collect()
+    }
+
+    #[test]
+    fn epub2_reads_spine_and_ncx() {
+        let structure = parse(epub2());
+        assert_eq!(structure.title.as_deref(), Some("The Time Machine"));
+
+        let spine: Vec<_> = structure
+            .spine
+            .iter()
+            .map(|s| (s.href.as_str(), s.linear))
+            .collect();
+        assert_eq!(
+            spine,
+            [
+                ("OEBPS/cover.xhtml", false),
+                ("OEBPS/text/chapter 1.xhtml", true),
+                ("OEBPS/text/chapter2.xhtml", true),
+            ]
+        );
+        assert_eq!(
+            toc(&structure),
+            [
+                ("I. Introduction", "OEBPS/text/chapter 1.xhtml", 0),
+                (
+                    "The Time Traveller & his guests",
+                    "OEBPS/text/chapter 1.xhtml#guests",
+                    1
+                ),
+                ("II. The Machine", "OEBPS/text/chapter2.xhtml", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn epub3_prefers_the_nav_document() {
+        let structure = parse(epub3());
+        assert_eq!(structure.title.as_deref(), Some("Flatland"));
+        assert_eq!(structure.spine.len(), 2);
+        // The landmarks nav and the stale NCX are ignored, and the unlinked part heading
+        // only shapes the depth of the entries under it
+        assert_eq!(
+            toc(&structure),
+            [
+                ("Of the Nature of Flatland", "OEBPS/xhtml/part1.xhtml#s1", 1),
+                ("Of the Climate and Houses", "OEBPS/xhtml/part1.xhtml#s2", 1),
+                ("Part II: Other Worlds", "OEBPS/xhtml/part2.xhtml", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn epub3_without_toc_entries_falls_back_to_the_ncx() {
+        let empty_nav = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body>
+            <nav epub:type="toc"><ol></ol></nav></body></html>"#;
+        let structure = parse(archive(&[
+            ("META-INF/container.xml", CONTAINER),
+            (
+                "OEBPS/content.opf",
+                include_str!("../tests/fixtures/epub/epub3/content.opf"),
+            ),
+            ("OEBPS/nav.xhtml", empty_nav),
+            (
+                "OEBPS/toc.ncx",
+                include_str!("../tests/fixtures/epub/epub3/toc.ncx"),
+            ),
+        ]));
+        assert_eq!(
+            toc(&structure),
+            [("Stale NCX entry", "OEBPS/xhtml/part1.xhtml", 0)]
+        );
+    }
+
+    #[test]
+    fn missing_package_document_is_an_error() {
+        let bytes = archive(&[("META-INF/container.xml", CONTAINER)]);
+        let error = parse_structure(&mut ZipArchive::new(Cursor::new(bytes)).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(error, "OEBPS/content.opf is missing from the EPUB");
+    }
+
+    #[test]
+    fn paths_cannot_leave_the_archive() {
+        assert_eq!(
+            resolve_path("OEBPS/text", "../images/a%20b.png").as_deref(),
+            Some("OEBPS/images/a b.png")
+        );
+        assert_eq!(
+            resolve_path("OEBPS", "/cover.xhtml#top").as_deref(),
+            Some("cover.xhtml")
+        );
+        assert_eq!(resolve_path("OEBPS", "../../etc/passwd"), None);
+        assert_eq!(resolve_path("OEBPS", "..\\secret"), None);
+    }
+
+    #[test]
+    fn paragraphs_skip_scripts_and_split_at_blocks() {
+        assert_eq!(
+            html_paragraphs(CHAPTER),
+            [
+                "I.",
+                "The Time Traveller (for so it will be convenient to speak of him) was \
+                 expounding a recondite matter to us.",
+                "His grey eyes shone & twinkled,",
+                "and his usually pale face was flushed.",
+            ]
+        );
+    }
+
+    #[test]
+    fn structure_is_cached_by_hash() {
+        let conn = crate::db::init_db(None).unwrap();
+        let bytes = epub3();
+        let hash = books::content_hash(&bytes);
+        let parsed = structure_for(&conn, bytes, &hash).unwrap();
+        // A second call with the same hash is answered without parsing the bytes
+        let cached = structure_for(&conn, b"not a zip".to_vec(), &hash).unwrap();
+        assert_eq!(cached.title, parsed.title);
+        assert_eq!(toc(&cached), toc(&parsed));
+    }
+}
@@ -0,0 +1,402 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::db::{self, DbState};
+
+const DEFAULT_DEPTH: usize = 2;
+const MAX_DEPTH: usize = 5;
+/// A chain stops growing past this many nodes, whatever the depth
+const MAX_NODES: usize = 50;
+/// Quoted text longer than this is a citation, not a referenced form
+const MAX_FORM_CHARS: usize = 40;
+const MAX_FORM_WORDS: usize = 3;
+
+const DICTIONARY_LANGUAGE: &str = "English";
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    Inherited,
+    Borrowed,
+    DerivedFrom,
+    Cognate,
+    Related,
+}
+
+impl Relation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Relation::Inherited => "inherited",
+            Relation::Borrowed => "borrowed",
+            Relation::DerivedFrom => "derived_from",
+            Relation::Cognate => "cognate",
+            Relation::Related => "related",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "inherited" => Relation::Inherited,
+            "borrowed" => Relation::Borrowed,
+            "derived_from" => Relation::DerivedFrom,
+            "cognate" => Relation::Cognate,
+            _ => Relation::Related,
+        }
+    }
+}
+
+pub struct EtymologyLink {
+    pub target: String,
+    /// `None` when the referenced form is in the dictionary's own language
+    pub language: Option<String>,
+    pub relation: Relation,
+    pub gloss: Option<String>,
+}
+
+/// wiktextract etymology template, e.g. `{"name": "der", "args": {"2": "la", "3": "algorismus"}}`
+#[derive(Deserialize)]
+struct Template {
+    name: String,
+    #[serde(default)]
+    args: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct EtymologyRecord {
+    word: String,
+    #[serde(default)]
+    etymology_text: Option<String>,
+    #[serde(default)]
+    etymology_templates: Vec<Template>,
+}
+
+#[derive(Deserialize)]
+struct EtymologyData {
+    words: Vec<EtymologyRecord>,
+}
+
+#[derive(Serialize)]
+pub struct EtymologyNode {
+    word: String,
+    language: String,
+    gloss: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EtymologyEdge {
+    from: String,
+    to: String,
+    relation: Relation,
+}
+
+#[derive(Serialize)]
+pub struct EtymologyChain {
+    nodes: Vec<EtymologyNode>,
+    edges: Vec<EtymologyEdge>,
+}
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS etymology_links (
+            word TEXT NOT NULL COLLATE NOCASE,
+            target TEXT NOT NULL COLLATE NOCASE,
+            target_language TEXT,
+            relation TEXT NOT NULL,
+            gloss TEXT,
+            PRIMARY KEY (word, target, relation)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn language_name(code: &str) -> String {
+    let name = match code {
+        "en" => DICTIONARY_LANGUAGE,
+        "enm" => "Middle English",
+        "ang" => "Old English",
+        "la" => "Latin",
+        "la-med" => "Medieval Latin",
+        "grc" => "Ancient Greek",
+        "fr" => "French",
+        "fro" => "Old French",
+        "frm" => "Middle French",
+        "de" => "German",
+        "non" => "Old Norse",
+        "it" => "Italian",
+        "es" => "Spanish",
+        "ar" => "Arabic",
+        "fa" => "Persian",
+        "gem-pro" => "Proto-Germanic",
+        "ine-pro" => "Proto-Indo-European",
+        other => other,
+    };
+    name.to_string()
+}
+
+/// Links from wiktextract templates; templates that don't reference a word are skipped
+fn links_from_templates(templates: &[Template]) -> Vec<EtymologyLink> {
+    templates
+        .iter()
+        .filter_map(|template| {
+            let (relation, lang_arg, word_arg, gloss_arg) = match template.name.as_str() {
+                "inh" | "inh+" => (Relation::Inherited, "2", "3", "5"),
+                "bor" | "bor+" | "lbor" => (Relation::Borrowed, "2", "3", "5"),
+                "der" | "der+" => (Relation::DerivedFrom, "2", "3", "5"),
+                "cog" => (Relation::Cognate, "1", "2", "4"),
+                "m" | "l" | "mention" => (Relation::Related, "1", "2", "4"),
+                _ => return None,
+            };
+            let target = template.args.get(word_arg)?.trim();
+            if target.is_empty() || target == "-" {
+                return None;
+            }
+            let language = template
+                .args
+                .get(lang_arg)
+                .map(|code| language_name(code))
+                .filter(|name| name != DICTIONARY_LANGUAGE);
+            let gloss = ["t", "gloss", gloss_arg]
+                .iter()
+                .find_map(|key| template.args.get(*key))
+                .filter(|g| !g.is_empty())
+                .cloned();
+            Some(EtymologyLink {
+                target: target.to_string(),
+                language,
+                relation,
+                gloss,
+            })
+        })
+        .collect()
+}
+
+fn closing_quote(open: char) -> Option<char> {
+    match open {
+        '\'' => Some('\''),
+        '"' => Some('"'),
+        '‘' => Some('’'),
+        '“' => Some('”'),
+        _ => None,
+    }
+}
+
+fn is_form(text: &str) -> bool {
+    !text.is_empty()
+        && text.chars().count() <= MAX_FORM_CHARS
+        && text.split_whitespace().count() <= MAX_FORM_WORDS
+        && text
+            .chars()
+            .all(|c| c.is_alphabetic() || matches!(c, '-' | ' ' | '*'))
+}
+
+/// Relation and language implied by the text leading up to a quoted form,
+/// e.g. "from Old French" or "; see also"
+fn describe(lead: &str) -> (Relation, Option<String>) {
+    let lower = lead.to_lowercase();
+    let last = |needle: &str| lower.rfind(needle);
+    let see_also = [last("see also"), last("compare"), last("cf.")]
+        .into_iter()
+        .flatten()
+        .max();
+    let cognate = last("cognate");
+    let from = last("from");
+
+    let relation = match [see_also, cognate, from].into_iter().flatten().max() {
+        None => Relation::Related,
+        Some(pos) if Some(pos) == from => Relation::DerivedFrom,
+        Some(pos) if Some(pos) == cognate => Relation::Cognate,
+        Some(_) => Relation::Related,
+    };
+
+    let mut language: Vec<&str> = lead
+        .split_whitespace()
+        .rev()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphabetic() && c != '-'))
+        .take_while(|w| w.chars().next().is_some_and(char::is_uppercase))
+        .collect();
+    language.reverse();
+    let language = Some(language.join(" ")).filter(|l| !l.is_empty() && l != DICTIONARY_LANGUAGE);
+
+    (relation, language)
+}
+
+/// Find quoted forms in free-text etymology: "from Latin 'algorismus'; see also 'algorism'"
+pub fn parse_etymology_text(text: &str) -> Vec<EtymologyLink> {
+    let mut links = Vec::new();
+    let mut lead_start = 0;
+    let mut chars = text.char_indices().peekable();
+    let mut previous: Option<char> = None;
+
+    while let Some((start, c)) = chars.next() {
+        let opens =
+            closing_quote(c).filter(|_| previous.is_none_or(|p| p.is_whitespace() || p == '('));
+        previous = Some(c);
+        let Some(close) = opens else {
+            continue;
+        };
+
+        let inner_start = start + c.len_utf8();
+        let Some(len) = text[inner_start..].find(close) else {
+            continue;
+        };
+        let form = text[inner_start..inner_start + len].trim();
+        if !is_form(form) {
+            continue;
+        }
+
+        let (relation, language) = describe(&text[lead_start..start]);
+        links.push(EtymologyLink {
+            target: form.trim_start_matches('*').to_string(),
+            language,
+            relation,
+            gloss: None,
+        });
+
+        lead_start = inner_start + len + close.len_utf8();
+        while chars.peek().is_some_and(|&(i, _)| i < lead_start) {
+            previous = chars.next().map(|(_, c)| c);
+        }
+    }
+    links
+}
+
+fn insert_links(conn: &Connection, word: &str, links: &[EtymologyLink]) -> Result<()> {
+    for link in links {
+        if link.target.eq_ignore_ascii_case(word) && link.language.is_none() {
+            continue;
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO etymology_links (word, target, target_language, relation, gloss)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                word.to_lowercase(),
+                link.target,
+                link.language,
+                link.relation.as_str(),
+                link.gloss
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Store etymology links for entries of a dictionary file, preferring structured templates
+/// and falling back to quoted forms in the etymology text. Files without etymology data
+/// add nothing.
+pub fn import(conn: &Connection, json: &str) -> Result<usize> {
+    let Ok(data) = serde_json::from_str::<EtymologyData>(json) else {
+        return Ok(0);
+    };
+
+    let mut imported = 0;
+    for record in &data.words {
+        let links = if record.etymology_templates.is_empty() {
+            record
+                .etymology_text
+                .as_deref()
+                .map(parse_etymology_text)
+                .unwrap_or_default()
+        } else {
+            links_from_templates(&record.etymology_templates)
+        };
+        if !links.is_empty() {
+            insert_links(conn, &record.word, &links)?;
+            imported += links.len();
+        }
+    }
+    Ok(imported)
+}
+
+fn links_of(conn: &Connection, word: &str) -> Result<Vec<EtymologyLink>> {
+    let mut stmt = conn.prepare(
+        "SELECT target, target_language, relation, gloss FROM etymology_links
+         WHERE word = ? ORDER BY rowid",
+    )?;
+    let rows = stmt.query_map(params![word], |row| {
+        Ok(EtymologyLink {
+            target: row.get(0)?,
+            language: row.get(1)?,
+            relation: Relation::parse(&row.get::<_, String>(2)?),
+            gloss: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn first_definition(conn: &Connection, word: &str) -> Result<Option<String>> {
+    Ok(db::lookup_exact(conn, word)?.into_iter().next())
+}
+
+/// Walk etymology links breadth-first from `word`, up to `depth` hops. Only forms in the
+/// dictionary's own language are followed further; every word is visited once.
+pub fn chain_for(conn: &Connection, word: &str, depth: usize) -> Result<EtymologyChain> {
+    let word = db::normalize(word);
+    let depth = depth.min(MAX_DEPTH);
+
+    let mut chain = EtymologyChain {
+        nodes: vec![EtymologyNode {
+            word: word.clone(),
+            language: DICTIONARY_LANGUAGE.to_string(),
+            gloss: first_definition(conn, &word)?,
+        }],
+        edges: Vec::new(),
+    };
+    let mut node_keys: HashSet<(String, String)> =
+        HashSet::from([(word.clone(), DICTIONARY_LANGUAGE.to_string())]);
+    let mut visited = HashSet::from([word.clone()]);
+    let mut queue = VecDeque::from([(word, 0)]);
+
+    while let Some((current, level)) = queue.pop_front() {
+        if level >= depth {
+            continue;
+        }
+        for link in links_of(conn, &current)? {
+            let target = link.target.to_lowercase();
+            let native = link.language.is_none();
+            let language = link
+                .language
+                .clone()
+                .unwrap_or_else(|| DICTIONARY_LANGUAGE.to_string());
+
+            if !node_keys.contains(&(target.clone(), language.clone())) {
+                if chain.nodes.len() >= MAX_NODES {
+                    return Ok(chain);
+                }
+                let gloss = match link.gloss {
+                    Some(gloss) => Some(gloss),
+                    None if native => first_definition(conn, &target)?,
+                    None => None,
+                };
+                node_keys.insert((target.clone(), language.clone()));
+                chain.nodes.push(EtymologyNode {
+                    word: target.clone(),
+                    language,
+                    gloss,
+                });
+            }
+            chain.edges.push(EtymologyEdge {
+                from: current.clone(),
+                to: target.clone(),
+                relation: link.relation,
+            });
+
+            if native && visited.insert(target.clone()) {
+                queue.push_back((target, level + 1));
+            }
+        }
+    }
+
+    Ok(chain)
+}
+
+#[tauri::command]
+pub fn get_etymology_chain(
+    word: &str,
+    depth: Option<usize>,
+    state: tauri::State<DbState>,
+) -> Result<EtymologyChain, String> {
+    let conn = state.0.lock().unwrap();
+    chain_for(&conn, word, depth.unwrap_or(DEFAULT_DEPTH)).map_err(|e| e.to_string())
+}
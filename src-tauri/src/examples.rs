@@ -0,0 +1,249 @@
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+use tauri::{Manager, Runtime};
+
+use crate::book_search;
+use crate::books::{self, Book, BOOK_COLUMNS, BOOK_SOURCE};
+use crate::db::{self, DbState};
+use crate::difficulty;
+use crate::hyphenation::{self, Hyphenation};
+use crate::licenses;
+use crate::selection::split_sentences;
+use crate::{settings, text_book};
+
+/// Opt-in: scanning the library adds noticeable time to every entry lookup
+pub const EXAMPLES_SETTING: &str = "library_examples_enabled";
+
+const DEFAULT_LIMIT: usize = 10;
+/// Examples shown alongside an entry
+const ENTRY_SAMPLE: usize = 3;
+/// Longer sentences are skipped rather than cut mid-highlight
+const MAX_SENTENCE_CHARS: usize = 300;
+/// Indexed chunks fetched per wanted example, since not every chunk yields a clean sentence
+const CHUNKS_PER_EXAMPLE: usize = 3;
+
+#[derive(Serialize)]
+pub struct LibraryExample {
+    book_id: i64,
+    title: String,
+    locator: String,
+    /// The sentence with every form of the word wrapped in `<mark>`
+    sentence: String,
+}
+
+#[derive(Serialize)]
+pub struct EntryDetails {
+    word: String,
+    definitions: Vec<String>,
+    /// CEFR level, when the dictionary has one for the word
+    difficulty: Option<String>,
+    /// Syllables of the headword, `None` when no patterns are available
+    hyphenation: Option<Hyphenation>,
+    /// `None` when library examples are turned off in settings
+    examples: Option<Vec<LibraryExample>>,
+    /// Attribution required by the licenses of the sources the definitions came from
+    attributions: Vec<String>,
+}
+
+/// Wrap each word of the sentence that stems to `target` in `<mark>`, or `None` if none does
+fn highlight(sentence: &str, target: &str) -> Option<String> {
+    let mut out = String::with_capacity(sentence.len() + 13);
+    let mut found = false;
+    let mut word_start: Option<usize> = None;
+
+    let mut flush = |out: &mut String, word: &str| {
+        if db::stem(word.trim_end_matches("'s")) == target {
+            found = true;
+            out.push_str("<mark>");
+            out.push_str(word);
+            out.push_str("</mark>");
+        } else {
+            out.push_str(word);
+        }
+    };
+
+    for (i, c) in sentence.char_indices() {
+        if c.is_alphanumeric() || (c == '\'' && word_start.is_some()) {
+            word_start.get_or_insert(i);
+        } else {
+            if let Some(start) = word_start.take() {
+                flush(&mut out, &sentence[start..i]);
+            }
+            out.push(c);
+        }
+    }
+    if let Some(start) = word_start {
+        flush(&mut out, &sentence[start..]);
+    }
+
+    found.then_some(out)
+}
+
+/// First usable sentence of the text that uses the word
+pub fn example_sentence(text: &str, target: &str) -> Option<String> {
+    text.lines()
+        .flat_map(split_sentences)
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|s| s.chars().count() <= MAX_SENTENCE_CHARS)
+        .find_map(|s| highlight(&s, target))
+}
+
+/// Examples from the search index, one per matching chunk
+fn indexed_examples(conn: &Connection, word: &str, limit: usize) -> Result<Vec<LibraryExample>> {
+    let Some(query) = book_search::fts_query(word) else {
+        return Ok(Vec::new());
+    };
+    let target = db::stem(word);
+
+    let mut stmt = conn.prepare(
+        "SELECT book_search.book_id, b.title, book_search.locator, book_search.text
+         FROM book_search
+         JOIN books b ON b.id = book_search.book_id
+         WHERE book_search MATCH ?
+         ORDER BY bm25(book_search)
+         LIMIT ?",
+    )?;
+    let rows = stmt.query_map(params![query, (limit * CHUNKS_PER_EXAMPLE) as i64], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+        ))
+    })?;
+
+    let mut examples = Vec::new();
+    for row in rows {
+        let (book_id, title, locator, text) = row?;
+        if let Some(sentence) = example_sentence(&text, &target) {
+            examples.push(LibraryExample {
+                book_id,
+                title,
+                locator,
+                sentence,
+            });
+            if examples.len() == limit {
+                break;
+            }
+        }
+    }
+    Ok(examples)
+}
+
+/// Plain-text books that haven't been indexed yet and have to be scanned directly
+fn unindexed_text_books(conn: &Connection) -> Result<Vec<Book>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM {}
+         WHERE b.format IN ('txt', 'md')
+           AND b.id NOT IN (SELECT book_id FROM book_search_state)",
+        BOOK_COLUMNS, BOOK_SOURCE
+    ))?;
+    let rows = stmt.query_map([], books::book_from_row)?;
+    rows.collect()
+}
+
+fn scanned_examples(book: &Book, target: &str, limit: usize) -> Vec<LibraryExample> {
+    let Ok((text, _)) = text_book::read_text(book) else {
+        return Vec::new();
+    };
+
+    book_search::text_paragraphs(&text)
+        .into_iter()
+        .filter_map(|(offset, paragraph)| {
+            example_sentence(&paragraph, target).map(|sentence| LibraryExample {
+                book_id: book.id,
+                title: book.title.clone(),
+                locator: format!("txt:{}", offset),
+                sentence,
+            })
+        })
+        .take(limit)
+        .collect()
+}
+
+/// Sentences from the user's books using the word in any inflected form.
+///
+/// Blocking: the database lock is only held for the index query, not the file scans.
+pub fn library_examples<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    word: &str,
+    limit: usize,
+) -> Result<Vec<LibraryExample>, String> {
+    let word = db::normalize(word);
+    let target = db::stem(&word);
+
+    let (mut examples, unindexed) = {
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        (
+            indexed_examples(&conn, &word, limit).map_err(|e| e.to_string())?,
+            unindexed_text_books(&conn).map_err(|e| e.to_string())?,
+        )
+    };
+
+    for book in &unindexed {
+        if examples.len() >= limit {
+            break;
+        }
+        examples.extend(scanned_examples(book, &target, limit - examples.len()));
+    }
+    Ok(examples)
+}
+
+#[tauri::command]
+pub async fn find_word_in_library<R: Runtime>(
+    word: String,
+    limit: Option<usize>,
+    app: tauri::AppHandle<R>,
+) -> Result<Vec<LibraryExample>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        library_examples(&app, &word, limit.unwrap_or(DEFAULT_LIMIT))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Definitions and syllables for a word, plus a few library examples when they're enabled
+#[tauri::command]
+pub async fn get_entry_details<R: Runtime>(
+    word: String,
+    app: tauri::AppHandle<R>,
+) -> Result<EntryDetails, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let word = db::normalize(&word);
+        let (definitions, difficulty, enabled, attributions) = {
+            let state = app.state::<DbState>();
+            let conn = state.0.lock().unwrap();
+            let senses = db::lookup_senses(&conn, &word).map_err(|e| e.to_string())?;
+            let sources: Vec<String> = senses.iter().map(|sense| sense.source.clone()).collect();
+            let attributions =
+                licenses::attributions_for(&conn, &sources).map_err(|e| e.to_string())?;
+            let definitions: Vec<String> =
+                senses.into_iter().map(|sense| sense.definition).collect();
+            (
+                definitions,
+                difficulty::difficulty_of(&conn, &word).map_err(|e| e.to_string())?,
+                settings::get_bool(&conn, EXAMPLES_SETTING, false),
+                attributions,
+            )
+        };
+
+        let hyphenation = hyphenation::hyphenate(&app, &word, "en")?;
+        let examples = if enabled {
+            Some(library_examples(&app, &word, ENTRY_SAMPLE)?)
+        } else {
+            None
+        };
+
+        Ok(EntryDetails {
+            word,
+            definitions,
+            difficulty,
+            hyphenation,
+            examples,
+            attributions,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,208 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::books;
+use crate::db::DbState;
+
+/// Bumped whenever `CATALOG_COLUMNS` changes, so re-imports can tell layouts apart
+pub const CATALOG_VERSION: u32 = 1;
+
+/// Catalog columns, in file order. Append-only within a version.
+pub const CATALOG_COLUMNS: [&str; 7] = [
+    "title", "author", "language", "tags", "added", "progress", "finished",
+];
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CatalogFormat {
+    Csv,
+    Opml,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CatalogFilter {
+    tag: Option<String>,
+    finished_only: bool,
+}
+
+#[derive(Serialize)]
+pub struct CatalogExportReport {
+    path: String,
+    version: u32,
+    columns: Vec<&'static str>,
+    books: usize,
+}
+
+struct CatalogRow {
+    title: String,
+    author: Option<String>,
+    language: Option<String>,
+    tags: Vec<String>,
+    added: String,
+    progress: f64,
+    finished: Option<String>,
+}
+
+/// Quote a CSV field per RFC 4180 when it contains a delimiter, quote or line break
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Append one CRLF-terminated CSV record
+pub fn write_csv_row<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    let row: Vec<String> = fields.iter().map(|f| csv_field(f.as_ref())).collect();
+    out.push_str(&row.join(","));
+    out.push_str("\r\n");
+}
+
+/// Escape text for use in XML attributes and character data
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn catalog_rows(conn: &Connection, filter: &CatalogFilter) -> Result<Vec<CatalogRow>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT b.title, b.author, b.language,
+                (SELECT group_concat(t.tag, char(31)) FROM book_tags t WHERE t.book_id = b.id),
+                date(b.added_at, 'unixepoch', 'localtime'),
+                COALESCE(p.progress, 0.0),
+                date(p.finished_at, 'unixepoch', 'localtime')
+         FROM {}
+         WHERE (?1 IS NULL OR EXISTS(
+                    SELECT 1 FROM book_tags t WHERE t.book_id = b.id AND t.tag = ?1))
+           AND (?2 = 0 OR p.finished_at IS NOT NULL)
+         ORDER BY b.title COLLATE NOCASE",
+        books::BOOK_SOURCE
+    ))?;
+
+    let rows = stmt.query_map(params![filter.tag, filter.finished_only], |row| {
+        let tags: Option<String> = row.get(3)?;
+        let mut tags: Vec<String> = tags
+            .map(|t| t.split('\u{1f}').map(str::to_string).collect())
+            .unwrap_or_default();
+        tags.sort_by_key(|t| t.to_lowercase());
+
+        Ok(CatalogRow {
+            title: row.get(0)?,
+            author: row.get(1)?,
+            language: row.get(2)?,
+            tags,
+            added: row.get(4)?,
+            progress: row.get(5)?,
+            finished: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn render_csv(rows: &[CatalogRow]) -> String {
+    let mut out = String::new();
+    write_csv_row(&mut out, &CATALOG_COLUMNS);
+    for row in rows {
+        write_csv_row(
+            &mut out,
+            &[
+                row.title.clone(),
+                row.author.clone().unwrap_or_default(),
+                row.language.clone().unwrap_or_default(),
+                row.tags.join(";"),
+                row.added.clone(),
+                format!("{:.1}", row.progress),
+                row.finished.clone().unwrap_or_default(),
+            ],
+        );
+    }
+    out
+}
+
+fn opml_outline(row: &CatalogRow) -> String {
+    let mut attrs = format!("text=\"{}\" type=\"book\"", xml_escape(&row.title));
+    if let Some(author) = &row.author {
+        attrs.push_str(&format!(" author=\"{}\"", xml_escape(author)));
+    }
+    if let Some(language) = &row.language {
+        attrs.push_str(&format!(" language=\"{}\"", xml_escape(language)));
+    }
+    attrs.push_str(&format!(
+        " added=\"{}\" progress=\"{:.1}\"",
+        row.added, row.progress
+    ));
+    if let Some(finished) = &row.finished {
+        attrs.push_str(&format!(" finished=\"{}\"", finished));
+    }
+    format!("      <outline {}/>\n", attrs)
+}
+
+/// Books nested under one outline per tag; untagged books get their own group
+fn render_opml(rows: &[CatalogRow]) -> String {
+    let mut groups: BTreeMap<String, Vec<&CatalogRow>> = BTreeMap::new();
+    let mut untagged = Vec::new();
+    for row in rows {
+        if row.tags.is_empty() {
+            untagged.push(row);
+        }
+        for tag in &row.tags {
+            groups.entry(tag.clone()).or_default().push(row);
+        }
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<opml version=\"2.0\">\n");
+    out.push_str(&format!(
+        "  <head>\n    <title>open_read library (catalog v{})</title>\n  </head>\n",
+        CATALOG_VERSION
+    ));
+    out.push_str("  <body>\n");
+
+    let untagged_group = (!untagged.is_empty()).then(|| ("Untagged".to_string(), untagged));
+    for (tag, books) in groups.into_iter().chain(untagged_group) {
+        out.push_str(&format!("    <outline text=\"{}\">\n", xml_escape(&tag)));
+        for row in books {
+            out.push_str(&opml_outline(row));
+        }
+        out.push_str("    </outline>\n");
+    }
+
+    out.push_str("  </body>\n</opml>\n");
+    out
+}
+
+/// Write the book catalog (no annotations) to `path` as CSV or OPML
+#[tauri::command]
+pub fn export_library(
+    path: String,
+    format: CatalogFormat,
+    filter: Option<CatalogFilter>,
+    state: tauri::State<DbState>,
+) -> Result<CatalogExportReport, String> {
+    let filter = filter.unwrap_or_default();
+    let rows = {
+        let conn = state.user_data()?;
+        catalog_rows(&conn, &filter).map_err(|e| e.to_string())?
+    };
+
+    let contents = match format {
+        CatalogFormat::Csv => render_csv(&rows),
+        CatalogFormat::Opml => render_opml(&rows),
+    };
+    fs::write(&path, contents).map_err(|e| e.to_string())?;
+
+    Ok(CatalogExportReport {
+        path,
+        version: CATALOG_VERSION,
+        columns: CATALOG_COLUMNS.to_vec(),
+        books: rows.len(),
+    })
+}
@@ -0,0 +1,571 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::book_search;
+use crate::book_words;
+use crate::books;
+use crate::db::{self, DbState};
+use crate::difficulty;
+use crate::examples;
+use crate::export::{write_csv_row, xml_escape};
+use crate::licenses;
+use crate::tokens::Tokenizer;
+
+const DEFAULT_MAX_WORDS: usize = 50;
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum GlossaryOrder {
+    /// Where each word first appears in the book; words added by hand go last
+    #[default]
+    Appearance,
+    Alphabetical,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum GlossaryFormat {
+    Csv,
+    Html,
+}
+
+#[derive(Deserialize)]
+pub struct GlossaryExport {
+    path: String,
+    format: GlossaryFormat,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct GlossaryOptions {
+    max_words: Option<usize>,
+    order: GlossaryOrder,
+    /// Only words at or above this CEFR level; without it, words with no level qualify too
+    min_level: Option<String>,
+    /// Only words used at least this often in the book
+    min_count: Option<i64>,
+    /// Add the first sentence of the book that uses each word
+    include_context: bool,
+    /// Also write the glossary to a file
+    export: Option<GlossaryExport>,
+}
+
+#[derive(Serialize)]
+pub struct GlossaryEntry {
+    word: String,
+    definition: Option<String>,
+    /// Sentence from the book with the word wrapped in `<mark>`
+    context: Option<String>,
+    /// Made by `generate_book_glossary` rather than added by hand
+    generated: bool,
+}
+
+#[derive(Serialize)]
+pub struct Glossary {
+    book_id: i64,
+    entries: Vec<GlossaryEntry>,
+    /// Entries this generation added
+    generated: usize,
+    export_path: Option<String>,
+}
+
+/// A profile's glossary for each book: generated entries, replaced on each generation, and
+/// entries the user added, which generation leaves alone
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_glossary (
+            profile_id INTEGER NOT NULL,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            word TEXT NOT NULL COLLATE NOCASE,
+            definition TEXT,
+            context TEXT,
+            position INTEGER,
+            generated INTEGER NOT NULL,
+            added_at INTEGER NOT NULL,
+            PRIMARY KEY (profile_id, book_id, word)
+        )",
+        [],
+    )?;
+    // Where the definition came from, to credit it in exports
+    db::add_column_if_missing(conn, "book_glossary", "source", "TEXT")?;
+    Ok(())
+}
+
+/// The book's words worth a glossary entry: dictionary headwords the active profile hasn't
+/// marked known and hasn't added by hand, hardest and generally rarest first. Returns each
+/// word with the position of its first occurrence.
+fn candidates(
+    conn: &Connection,
+    book_id: i64,
+    counts: &book_words::WordCounts,
+    options: &GlossaryOptions,
+) -> Result<Vec<(String, i64)>, String> {
+    let min_rank = options
+        .min_level
+        .as_deref()
+        .map(|level| {
+            difficulty::parse_level(level)
+                .ok_or_else(|| format!("Unknown difficulty level: {}", level))
+        })
+        .transpose()?;
+    let words = serde_json::to_string(&counts.words).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT j.word, j.first, MAX(d.difficulty) AS level, MAX(d.frequency) AS frequency
+             FROM (SELECT json_extract(value, '$.word') AS word,
+                          json_extract(value, '$.count') AS count,
+                          json_extract(value, '$.first') AS first
+                   FROM json_each(?1)) j
+             JOIN dictionary d ON d.word = j.word
+             WHERE j.count >= ?3
+               AND NOT EXISTS (SELECT 1 FROM known_words k
+                               WHERE k.profile_id = (SELECT id FROM temp.current_profile)
+                                 AND k.word = j.word)
+               AND NOT EXISTS (SELECT 1 FROM book_glossary g
+                               WHERE g.profile_id = (SELECT id FROM temp.current_profile)
+                                 AND g.book_id = ?2 AND g.word = j.word AND g.generated = 0)
+             GROUP BY j.word
+             HAVING ?4 IS NULL OR level >= ?4
+             ORDER BY level IS NULL, level DESC, frequency IS NULL, frequency, j.count DESC
+             LIMIT ?5",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                words,
+                book_id,
+                options.min_count.unwrap_or(1),
+                min_rank,
+                options.max_words.unwrap_or(DEFAULT_MAX_WORDS) as i64
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_>>().map_err(|e| e.to_string())
+}
+
+/// First sentence in the book's indexed text that uses the word
+fn context_sentence(conn: &Connection, book_id: i64, word: &str) -> Result<Option<String>> {
+    let Some(query) = book_search::fts_query(word) else {
+        return Ok(None);
+    };
+    let mut stmt = conn.prepare(
+        "SELECT text FROM book_search WHERE book_search MATCH ? AND book_id = ? ORDER BY rowid",
+    )?;
+    let chunks = stmt.query_map(params![query, book_id], |row| row.get::<_, String>(0))?;
+    let target = db::stem(word);
+    for chunk in chunks {
+        if let Some(sentence) = examples::example_sentence(&chunk?, &target) {
+            return Ok(Some(sentence));
+        }
+    }
+    Ok(None)
+}
+
+fn entries(conn: &Connection, book_id: i64, order: GlossaryOrder) -> Result<Vec<GlossaryEntry>> {
+    let order_by = match order {
+        GlossaryOrder::Appearance => "position IS NULL, position, word",
+        GlossaryOrder::Alphabetical => "word",
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT word, definition, context, generated FROM book_glossary
+         WHERE profile_id = (SELECT id FROM temp.current_profile) AND book_id = ?
+         ORDER BY {}",
+        order_by
+    ))?;
+    let rows = stmt.query_map(params![book_id], |row| {
+        Ok(GlossaryEntry {
+            word: row.get(0)?,
+            definition: row.get(1)?,
+            context: row.get(2)?,
+            generated: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Attribution texts of the sources the glossary's definitions came from
+fn attributions(conn: &Connection, book_id: i64) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT source FROM book_glossary
+         WHERE profile_id = (SELECT id FROM temp.current_profile) AND book_id = ?
+           AND source IS NOT NULL",
+    )?;
+    let sources = stmt
+        .query_map(params![book_id], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    licenses::attributions_for(conn, &sources)
+}
+
+/// The attributions go first, each on a comment row
+fn render_csv(entries: &[GlossaryEntry], attributions: &[String]) -> String {
+    let mut out = String::new();
+    for attribution in attributions {
+        write_csv_row(&mut out, &[format!("# {}", attribution)]);
+    }
+    write_csv_row(&mut out, &["word", "definition", "context"]);
+    for entry in entries {
+        let context = entry
+            .context
+            .as_deref()
+            .map(|c| c.replace("<mark>", "").replace("</mark>", ""));
+        write_csv_row(
+            &mut out,
+            &[
+                entry.word.as_str(),
+                entry.definition.as_deref().unwrap_or_default(),
+                context.as_deref().unwrap_or_default(),
+            ],
+        );
+    }
+    out
+}
+
+fn render_html(title: &str, entries: &[GlossaryEntry], attributions: &[String]) -> String {
+    let title = xml_escape(title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Glossary: {0}</title>\n\
+         </head>\n<body>\n<header>\n<h1>Glossary: {0}</h1>\n",
+        title
+    );
+    for attribution in attributions {
+        out.push_str(&format!("<p>{}</p>\n", xml_escape(attribution)));
+    }
+    out.push_str("</header>\n<dl>\n");
+    for entry in entries {
+        out.push_str(&format!("<dt>{}</dt>\n", xml_escape(&entry.word)));
+        if let Some(definition) = &entry.definition {
+            out.push_str(&format!("<dd>{}</dd>\n", xml_escape(definition)));
+        }
+        if let Some(context) = &entry.context {
+            // The sentence is escaped whole, then its own highlighting restored
+            let context = xml_escape(context)
+                .replace("&lt;mark&gt;", "<mark>")
+                .replace("&lt;/mark&gt;", "</mark>");
+            out.push_str(&format!("<dd><blockquote>{}</blockquote></dd>\n", context));
+        }
+    }
+    out.push_str("</dl>\n</body>\n</html>\n");
+    out
+}
+
+/// Build a glossary of a book's hard words from its vocabulary analysis: words the
+/// dictionary defines that the active profile doesn't know, limited and ordered by
+/// `options`. Replaces the previously generated entries; words added by hand stay. An
+/// export credits the sources of its definitions in its header.
+#[tauri::command]
+pub fn generate_book_glossary(
+    book_id: i64,
+    options: Option<GlossaryOptions>,
+    state: tauri::State<DbState>,
+) -> Result<Glossary, String> {
+    let options = options.unwrap_or_default();
+    let conn = state.user_data()?;
+    let book = books::load_book(&conn, book_id)?;
+    let tokenizer =
+        Tokenizer::for_language(&conn, book.language.as_deref()).map_err(|e| e.to_string())?;
+    let counts = book_words::counts_for(&conn, book_id, &tokenizer)?;
+    let words = candidates(&conn, book_id, &counts, &options)?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM book_glossary
+         WHERE profile_id = (SELECT id FROM temp.current_profile) AND book_id = ? AND generated = 1",
+        params![book_id],
+    )
+    .map_err(|e| e.to_string())?;
+    for (word, position) in &words {
+        // Candidates are headwords, so this is their first exact sense
+        let sense = db::lookup_senses(&tx, word)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .next();
+        let context = if options.include_context {
+            context_sentence(&tx, book_id, word).map_err(|e| e.to_string())?
+        } else {
+            None
+        };
+        tx.execute(
+            "INSERT OR IGNORE INTO book_glossary
+                (profile_id, book_id, word, definition, source, context, position, generated,
+                 added_at)
+             VALUES ((SELECT id FROM temp.current_profile), ?, ?, ?, ?, ?, ?, 1, unixepoch())",
+            params![
+                book_id,
+                word,
+                sense.as_ref().map(|sense| &sense.definition),
+                sense.as_ref().map(|sense| &sense.source),
+                context,
+                position
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let entries = entries(&conn, book_id, options.order).map_err(|e| e.to_string())?;
+    let export_path = match options.export {
+        Some(export) => {
+            let attributions = attributions(&conn, book_id).map_err(|e| e.to_string())?;
+            let contents = match export.format {
+                GlossaryFormat::Csv => render_csv(&entries, &attributions),
+                GlossaryFormat::Html => render_html(&book.title, &entries, &attributions),
+            };
+            fs::write(&export.path, contents).map_err(|e| e.to_string())?;
+            Some(export.path)
+        }
+        None => None,
+    };
+
+    Ok(Glossary {
+        book_id,
+        entries,
+        generated: words.len(),
+        export_path,
+    })
+}
+
+#[tauri::command]
+pub fn get_book_glossary(
+    book_id: i64,
+    order: Option<GlossaryOrder>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<GlossaryEntry>, String> {
+    let conn = state.user_data()?;
+    entries(&conn, book_id, order.unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+/// Add a word to a book's glossary by hand, with its first definition. Turns a generated
+/// entry for the word into a kept one.
+#[tauri::command]
+pub fn add_glossary_word(
+    book_id: i64,
+    word: &str,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let word = db::normalize(word);
+    if word.is_empty() {
+        return Err("The word can't be empty".to_string());
+    }
+    let conn = state.user_data()?;
+    books::load_book(&conn, book_id)?;
+    let sense = db::lookup_senses(&conn, &word)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next();
+    conn.execute(
+        "INSERT INTO book_glossary
+            (profile_id, book_id, word, definition, source, generated, added_at)
+         VALUES ((SELECT id FROM temp.current_profile), ?, ?, ?, ?, 0, unixepoch())
+         ON CONFLICT(profile_id, book_id, word) DO UPDATE SET generated = 0",
+        params![
+            book_id,
+            word,
+            sense.as_ref().map(|sense| &sense.definition),
+            sense.as_ref().map(|sense| &sense.source)
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_glossary_word(
+    book_id: i64,
+    word: &str,
+    state: tauri::State<DbState>,
+) -> Result<bool, String> {
+    let conn = state.user_data()?;
+    let removed = conn
+        .execute(
+            "DELETE FROM book_glossary
+             WHERE profile_id = (SELECT id FROM temp.current_profile) AND book_id = ? AND word = ?",
+            params![book_id, db::normalize(word)],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(removed > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::licenses::License;
+    use tauri::test::MockRuntime;
+    use tauri::Manager;
+
+    /// A book indexed for search, whose dictionary words are, in order of appearance,
+    /// harpoon (B1), leviathan (C1), ishmael (no level) and cetacean (C2)
+    fn library() -> tauri::App<MockRuntime> {
+        let conn = db::init_db(None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO books (id, path, title, format, added_at)
+             VALUES (1, '/books/moby-dick.txt', 'Moby-Dick', 'txt', 0);
+             INSERT INTO book_search (text, book_id, locator) VALUES
+                 ('The harpoon struck the leviathan.', 1, 'chapter-1'),
+                 ('Ishmael watched the cetacean, and the harpoon again.', 1, 'chapter-2');
+             INSERT INTO book_search_state (book_id, content_hash, chunks, indexed_at)
+             VALUES (1, 'hash', 2, 0);
+             INSERT INTO dictionary (word, definition, difficulty, source) VALUES
+                 ('harpoon', 'A barbed spear.', 3, 'wiktionary'),
+                 ('leviathan', 'A sea monster.', 5, 'wordnet'),
+                 ('ishmael', 'An outcast.', NULL, 'wordnet'),
+                 ('cetacean', 'A whale or dolphin.', 6, 'bundled'),
+                 ('whale', 'A large sea mammal.', 2, 'wiktionary');",
+        )
+        .unwrap();
+        for (source, attribution) in [
+            ("wiktionary", Some("Contains data from Wiktionary")),
+            ("wordnet", Some("WordNet 3.0")),
+            ("bundled", None),
+        ] {
+            let license = License {
+                name: "Some license".to_string(),
+                url: None,
+                attribution: attribution.map(str::to_string),
+            };
+            licenses::record(&conn, source, Some(&license)).unwrap();
+        }
+
+        let app = tauri::test::mock_app();
+        app.manage(DbState::new(conn, false));
+        app
+    }
+
+    fn options(value: serde_json::Value) -> Option<GlossaryOptions> {
+        Some(serde_json::from_value(value).unwrap())
+    }
+
+    fn words(entries: &[GlossaryEntry]) -> Vec<(&str, bool)> {
+        entries
+            .iter()
+            .map(|entry| (entry.word.as_str(), entry.generated))
+            .collect()
+    }
+
+    #[test]
+    fn regenerating_keeps_words_added_by_hand() {
+        let app = library();
+        let first = generate_book_glossary(1, None, app.state()).unwrap();
+        assert_eq!(first.generated, 4);
+
+        // One generated entry kept by hand, and one word the book doesn't use
+        add_glossary_word(1, "Harpoon", app.state()).unwrap();
+        add_glossary_word(1, "whale", app.state()).unwrap();
+
+        let second = generate_book_glossary(
+            1,
+            options(serde_json::json!({ "max_words": 2 })),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(second.generated, 2);
+        assert_eq!(
+            words(&second.entries),
+            [
+                ("harpoon", false),
+                ("leviathan", true),
+                ("cetacean", true),
+                // Added by hand without a place in the book, so last
+                ("whale", false),
+            ]
+        );
+        assert_eq!(
+            second.entries[3].definition.as_deref(),
+            Some("A large sea mammal.")
+        );
+
+        assert!(remove_glossary_word(1, "harpoon", app.state()).unwrap());
+        let third = generate_book_glossary(1, None, app.state()).unwrap();
+        assert_eq!(third.generated, 4);
+        assert_eq!(third.entries.len(), 5);
+    }
+
+    #[test]
+    fn orders_by_appearance_or_alphabetically() {
+        let app = library();
+        let glossary = generate_book_glossary(1, None, app.state()).unwrap();
+        let appearance: Vec<_> = words(&glossary.entries).into_iter().map(|w| w.0).collect();
+        assert_eq!(appearance, ["harpoon", "leviathan", "ishmael", "cetacean"]);
+
+        let sorted = get_book_glossary(1, Some(GlossaryOrder::Alphabetical), app.state()).unwrap();
+        let alphabetical: Vec<_> = words(&sorted).into_iter().map(|w| w.0).collect();
+        assert_eq!(
+            alphabetical,
+            ["cetacean", "harpoon", "ishmael", "leviathan"]
+        );
+    }
+
+    #[test]
+    fn min_level_filters_words() {
+        let cases: [(Option<&str>, &[&str]); 4] = [
+            // Without a minimum, words with no level qualify too
+            (None, &["harpoon", "leviathan", "ishmael", "cetacean"]),
+            (Some("B1"), &["harpoon", "leviathan", "cetacean"]),
+            (Some("c1"), &["leviathan", "cetacean"]),
+            (Some("6"), &["cetacean"]),
+        ];
+        for (min_level, expected) in cases {
+            let app = library();
+            let glossary = generate_book_glossary(
+                1,
+                options(serde_json::json!({ "min_level": min_level })),
+                app.state(),
+            )
+            .unwrap();
+            let got: Vec<_> = words(&glossary.entries).into_iter().map(|w| w.0).collect();
+            assert_eq!(got, expected, "{:?}", min_level);
+        }
+
+        let app = library();
+        let result = generate_book_glossary(
+            1,
+            options(serde_json::json!({ "min_level": "Z9" })),
+            app.state(),
+        );
+        assert_eq!(
+            result.err().as_deref(),
+            Some("Unknown difficulty level: Z9")
+        );
+    }
+
+    #[test]
+    fn exports_credit_the_sources_they_use() {
+        let dir = std::env::temp_dir().join(format!("open-read-glossary-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let app = library();
+
+        let csv = dir.join("glossary.csv");
+        generate_book_glossary(
+            1,
+            options(serde_json::json!({
+                "min_level": "C1",
+                "export": { "path": csv, "format": "csv" },
+            })),
+            app.state(),
+        )
+        .unwrap();
+        let contents = fs::read_to_string(&csv).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        // Only WordNet has attribution text among leviathan's and cetacean's sources
+        assert_eq!(lines[..2], ["# WordNet 3.0", "word,definition,context"]);
+        assert!(!contents.contains("Wiktionary"));
+
+        let html = dir.join("glossary.html");
+        generate_book_glossary(
+            1,
+            options(serde_json::json!({ "export": { "path": html, "format": "html" } })),
+            app.state(),
+        )
+        .unwrap();
+        let contents = fs::read_to_string(&html).unwrap();
+        let header =
+            &contents[contents.find("<header>").unwrap()..contents.find("</header>").unwrap()];
+        assert!(header.contains("<p>Contains data from Wiktionary</p>"));
+        assert!(header.contains("<p>WordNet 3.0</p>"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
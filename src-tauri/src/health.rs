@@ -0,0 +1,129 @@
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use std::sync::{Mutex, TryLockError};
+use tauri::{Manager, Runtime};
+
+use crate::backup::BackupJob;
+use crate::book_search::BookIndexJobs;
+use crate::db::DbState;
+use crate::indexes::StaleIndexes;
+use crate::read_only::{ReadOnly, Reason};
+use crate::{migrations, repair};
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    /// The package shipped without resources/dictionary.json and it hasn't been repaired
+    BundledResourceMissing,
+    /// Only the built-in fallback words are available
+    FallbackDictionary,
+    /// An encrypted database is waiting for its passphrase
+    Locked,
+    /// A derived index looked out of date at startup; `index_status` says which
+    StaleIndexes,
+}
+
+#[derive(Serialize, Clone, Copy, Default)]
+struct Counts {
+    schema_version: Option<i64>,
+    dictionary_entries: i64,
+    books: i64,
+    resource_missing: bool,
+    fallback_only: bool,
+}
+
+/// Counts from the last health check, with the connection's `total_changes()` at the time.
+/// They're reused until something has been written since.
+#[derive(Default)]
+pub struct HealthCache(Mutex<Option<(i64, Counts)>>);
+
+#[derive(Serialize)]
+pub struct Health {
+    database_open: bool,
+    /// The connection is held by a long-running call (e.g. an import); counts may be stale
+    database_busy: bool,
+    /// Why the database is open without write access, if it is; writing commands fail
+    /// with `read_only_mode`
+    read_only: Option<Reason>,
+    schema_version: Option<i64>,
+    dictionary_entries: i64,
+    books: i64,
+    index_builds_running: usize,
+    backup_running: bool,
+    problems: Vec<Problem>,
+}
+
+fn count_all<R: Runtime>(app: &tauri::AppHandle<R>, conn: &Connection) -> Result<Counts> {
+    let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0));
+    let dictionary_entries = count("SELECT COUNT(*) FROM dictionary")?;
+    let bundled = repair::bundled_status(app, conn)?;
+
+    Ok(Counts {
+        schema_version: migrations::get_meta(conn, "schema_version")?.and_then(|v| v.parse().ok()),
+        dictionary_entries,
+        books: count("SELECT COUNT(*) FROM books")?,
+        resource_missing: bundled.is_missing(),
+        fallback_only: dictionary_entries > 0 && bundled.fallback_entries() == dictionary_entries,
+    })
+}
+
+/// Counts for the health check, recounted only when the connection has written since
+fn counts<R: Runtime>(app: &tauri::AppHandle<R>, conn: &Connection) -> Result<Counts> {
+    let changes: i64 = conn.query_row("SELECT total_changes()", [], |row| row.get(0))?;
+    let cache = app.state::<HealthCache>();
+    let mut cached = cache.0.lock().unwrap();
+    if let Some((at, counts)) = *cached {
+        if at == changes {
+            return Ok(counts);
+        }
+    }
+
+    let counts = count_all(app, conn)?;
+    *cached = Some((changes, counts));
+    Ok(counts)
+}
+
+/// One cheap call for the frontend (and anything else probing readiness): whether the
+/// database is usable, what it holds, what's running and what needs attention. Never
+/// waits for the connection; when it's busy, the last counts are returned.
+#[tauri::command]
+pub fn health_check<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: tauri::State<DbState>,
+) -> Result<Health, String> {
+    let locked = state.is_locked();
+    let (counts, busy) = match state.0.try_lock() {
+        Ok(conn) => (counts(&app, &conn).map_err(|e| e.to_string())?, false),
+        Err(TryLockError::WouldBlock) => {
+            let cached = app.state::<HealthCache>().0.lock().unwrap().map(|(_, c)| c);
+            (cached.unwrap_or_default(), true)
+        }
+        Err(TryLockError::Poisoned(e)) => return Err(e.to_string()),
+    };
+
+    let mut problems = Vec::new();
+    if locked {
+        problems.push(Problem::Locked);
+    }
+    if counts.resource_missing {
+        problems.push(Problem::BundledResourceMissing);
+    }
+    if counts.fallback_only {
+        problems.push(Problem::FallbackDictionary);
+    }
+    if !app.state::<StaleIndexes>().0.lock().unwrap().is_empty() {
+        problems.push(Problem::StaleIndexes);
+    }
+
+    Ok(Health {
+        database_open: !locked,
+        database_busy: busy,
+        read_only: app.state::<ReadOnly>().0,
+        schema_version: counts.schema_version,
+        dictionary_entries: counts.dictionary_entries,
+        books: counts.books,
+        index_builds_running: app.state::<BookIndexJobs>().0.lock().unwrap().len(),
+        backup_running: app.state::<BackupJob>().is_running(),
+        problems,
+    })
+}
@@ -0,0 +1,68 @@
+use rusqlite::{params, Connection, Result};
+
+use crate::{db, read_only};
+
+/// Create the lookup history table
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lookup_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            word TEXT NOT NULL COLLATE NOCASE,
+            found INTEGER NOT NULL,
+            book_id INTEGER,
+            context TEXT,
+            looked_up_at INTEGER NOT NULL,
+            profile_id INTEGER NOT NULL DEFAULT 1
+        )",
+        [],
+    )?;
+
+    // Databases from before profiles; their history belongs to the Default profile
+    db::add_column_if_missing(
+        conn,
+        "lookup_history",
+        "profile_id",
+        "INTEGER NOT NULL DEFAULT 1",
+    )?;
+
+    // Whether an exact miss was answered by the fallback (base form or prefix match);
+    // NULL for lookups recorded before it was tracked
+    db::add_column_if_missing(conn, "lookup_history", "fallback_found", "INTEGER")?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_history_word ON lookup_history(word COLLATE NOCASE)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_history_profile
+         ON lookup_history(profile_id, word COLLATE NOCASE)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Record a single lookup for the active profile, optionally tied to the book and sentence
+/// it came from. `fallback_found` is set when the results came from the fallback rather
+/// than an exact match.
+pub fn record_lookup(
+    conn: &Connection,
+    word: &str,
+    found: bool,
+    fallback_found: bool,
+    book_id: Option<i64>,
+    context: Option<&str>,
+) -> Result<()> {
+    // Guests of a read-only install leave no history
+    if read_only::is_read_only(conn) {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO lookup_history
+            (word, found, fallback_found, book_id, context, looked_up_at, profile_id)
+         VALUES (?, ?, ?, ?, ?, unixepoch(), (SELECT id FROM temp.current_profile))",
+        params![word, found, fallback_found, book_id, context],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::{Manager, Runtime};
+
+use crate::db::{self, DbState};
+use crate::memory::MemoryMode;
+use crate::read_only;
+
+/// Separator used when showing syllables, as in "al·go·rithm"
+const SYLLABLE_SEPARATOR: &str = "·";
+
+/// Language of the bundled dictionary; only its hyphenations are cached on the entry
+const DICTIONARY_LANGUAGE: &str = "en";
+
+struct Language {
+    /// Primary language subtag requests are matched on
+    code: &'static str,
+    /// Name of the hyph-utf8 files in resources, `hyph-<file>.pat.txt` and `hyph-<file>.hyp.txt`
+    file: &'static str,
+    /// Contents of those files, compiled in so hyphenation never depends on what got bundled
+    patterns: &'static str,
+    exceptions: &'static str,
+    left_min: usize,
+    right_min: usize,
+}
+
+const LANGUAGES: &[Language] = &[Language {
+    code: "en",
+    file: "en-us",
+    patterns: include_str!("../resources/hyph-en-us.pat.txt"),
+    exceptions: include_str!("../resources/hyph-en-us.hyp.txt"),
+    left_min: 2,
+    right_min: 3,
+}];
+
+/// Knuth–Liang patterns for one language
+pub struct Patterns {
+    patterns: HashMap<String, Vec<u8>>,
+    longest: usize,
+    /// Words whose breaks are listed explicitly, as positions into the word
+    exceptions: HashMap<String, Vec<usize>>,
+    left_min: usize,
+    right_min: usize,
+}
+
+#[derive(Serialize)]
+pub struct Hyphenation {
+    word: String,
+    syllables: Vec<String>,
+    display: String,
+}
+
+/// Parsed pattern sets by language file
+#[derive(Default)]
+pub struct Hyphenators(pub Mutex<HashMap<&'static str, Arc<Patterns>>>);
+
+impl Patterns {
+    /// Parse patterns in TeX notation ("hy3ph", ".ach4") and exceptions ("as-so-ciate"),
+    /// both whitespace separated
+    pub fn parse(patterns: &str, exceptions: &str, left_min: usize, right_min: usize) -> Self {
+        let mut parsed = HashMap::new();
+        let mut longest = 0;
+        for pattern in patterns.split_whitespace() {
+            let mut letters = String::new();
+            let mut values = vec![0u8];
+            for c in pattern.chars() {
+                match c.to_digit(10) {
+                    Some(d) => *values.last_mut().unwrap() = d as u8,
+                    None => {
+                        letters.push(c);
+                        values.push(0);
+                    }
+                }
+            }
+            longest = longest.max(values.len() - 1);
+            parsed.insert(letters, values);
+        }
+
+        let exceptions = exceptions
+            .split_whitespace()
+            .map(|exception| {
+                let mut word = String::new();
+                let mut breaks = Vec::new();
+                for c in exception.chars() {
+                    if c == '-' {
+                        breaks.push(word.chars().count());
+                    } else {
+                        word.push(c);
+                    }
+                }
+                (word.to_lowercase(), breaks)
+            })
+            .collect();
+
+        Patterns {
+            patterns: parsed,
+            longest,
+            exceptions,
+            left_min,
+            right_min,
+        }
+    }
+
+    /// Character positions where the word may be broken
+    pub fn break_points(&self, word: &str) -> Vec<usize> {
+        let lower = word.to_lowercase();
+        if let Some(breaks) = self.exceptions.get(&lower) {
+            return breaks.clone();
+        }
+
+        let letters: Vec<char> = lower.chars().collect();
+        if !letters.iter().all(|c| c.is_alphabetic())
+            || letters.len() < self.left_min + self.right_min
+        {
+            return Vec::new();
+        }
+
+        let mut padded = Vec::with_capacity(letters.len() + 2);
+        padded.push('.');
+        padded.extend(&letters);
+        padded.push('.');
+
+        // points[i] is the value for the gap before padded[i]
+        let mut points = vec![0u8; padded.len() + 1];
+        for start in 0..padded.len() {
+            for end in start + 1..=padded.len().min(start + self.longest) {
+                let key: String = padded[start..end].iter().collect();
+                if let Some(values) = self.patterns.get(&key) {
+                    for (k, &value) in values.iter().enumerate() {
+                        points[start + k] = points[start + k].max(value);
+                    }
+                }
+            }
+        }
+
+        (self.left_min..=letters.len() - self.right_min)
+            .filter(|&i| points[i + 1] % 2 == 1)
+            .collect()
+    }
+
+    /// The word split at its break points
+    pub fn syllables(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut syllables = Vec::new();
+        let mut start = 0;
+        for point in self.break_points(word) {
+            if point > start && point < chars.len() {
+                syllables.push(chars[start..point].iter().collect());
+                start = point;
+            }
+        }
+        syllables.push(chars[start..].iter().collect());
+        syllables
+    }
+}
+
+fn language_for(lang: &str) -> Option<&'static Language> {
+    let primary = lang.split(['-', '_']).next()?.to_lowercase();
+    LANGUAGES.iter().find(|l| l.code == primary)
+}
+
+/// Patterns for a language, parsing them on first use. `None` for unsupported languages,
+/// so callers never show guessed breaks.
+pub fn patterns_for<R: Runtime>(app: &tauri::AppHandle<R>, lang: &str) -> Option<Arc<Patterns>> {
+    let language = language_for(lang)?;
+    let hyphenators = app.state::<Hyphenators>();
+    let mut loaded = hyphenators.0.lock().unwrap();
+    if app.state::<MemoryMode>().is_low() && !loaded.contains_key(language.file) {
+        // Keep only one language's patterns in memory at a time
+        loaded.clear();
+    }
+    let patterns = loaded.entry(language.file).or_insert_with(|| {
+        Arc::new(Patterns::parse(
+            language.patterns,
+            language.exceptions,
+            language.left_min,
+            language.right_min,
+        ))
+    });
+    Some(patterns.clone())
+}
+
+/// Add the cached hyphenation column to dictionary entries
+pub fn create_table(conn: &Connection) -> Result<()> {
+    db::add_column_if_missing(conn, "dictionary", "hyphenation", "TEXT")
+}
+
+fn cached(conn: &Connection, word: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT hyphenation FROM dictionary
+         WHERE word = ? COLLATE NOCASE AND hyphenation IS NOT NULL
+         LIMIT 1",
+        params![word],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn from_display(word: &str, display: String) -> Hyphenation {
+    Hyphenation {
+        word: word.to_string(),
+        syllables: display
+            .split(SYLLABLE_SEPARATOR)
+            .map(str::to_string)
+            .collect(),
+        display,
+    }
+}
+
+/// Syllables of a word in the given language, cached on the dictionary entry when there is one
+pub fn hyphenate<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    word: &str,
+    lang: &str,
+) -> Result<Option<Hyphenation>, String> {
+    let word = db::normalize(word);
+    let cacheable = language_for(lang).is_some_and(|l| l.code == DICTIONARY_LANGUAGE);
+    let state = app.state::<DbState>();
+
+    if cacheable {
+        let conn = state.0.lock().unwrap();
+        if let Some(display) = cached(&conn, &word).map_err(|e| e.to_string())? {
+            return Ok(Some(from_display(&word, display)));
+        }
+    }
+
+    let Some(patterns) = patterns_for(app, lang) else {
+        return Ok(None);
+    };
+    let display = patterns.syllables(&word).join(SYLLABLE_SEPARATOR);
+
+    if cacheable {
+        let conn = state.0.lock().unwrap();
+        // A read-only database hyphenates the word again next time
+        if !read_only::is_read_only(&conn) {
+            conn.execute(
+                "UPDATE dictionary SET hyphenation = ? WHERE word = ? COLLATE NOCASE",
+                params![display, word],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(Some(from_display(&word, display)))
+}
+
+/// Syllables and hyphenation points, or `None` if the language isn't supported
+#[tauri::command]
+pub fn get_hyphenation<R: Runtime>(
+    word: &str,
+    lang: Option<&str>,
+    app: tauri::AppHandle<R>,
+) -> Result<Option<Hyphenation>, String> {
+    hyphenate(&app, word, lang.unwrap_or(DICTIONARY_LANGUAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english() -> Patterns {
+        let language = language_for("en-US").unwrap();
+        Patterns::parse(
+            language.patterns,
+            language.exceptions,
+            language.left_min,
+            language.right_min,
+        )
+    }
+
+    #[test]
+    fn known_words_break_like_tex() {
+        let patterns = english();
+        for expected in [
+            "hy-phen-ation",
+            "al-go-rithm",
+            "dic-tio-nary",
+            "en-cy-clo-pe-dia",
+            "math-e-mat-ics",
+            "pro-gram-ming",
+            "com-puter",
+            "su-per-cal-ifrag-ilis-tic-ex-pi-ali-do-cious",
+        ] {
+            let word = expected.replace('-', "");
+            assert_eq!(patterns.syllables(&word).join("-"), expected);
+        }
+    }
+
+    #[test]
+    fn exceptions_override_patterns() {
+        let patterns = english();
+        assert_eq!(patterns.syllables("table").join("-"), "ta-ble");
+        assert_eq!(patterns.syllables("Associate").join("-"), "As-so-ciate");
+        assert_eq!(patterns.syllables("project"), ["project"]);
+    }
+
+    #[test]
+    fn short_and_non_alphabetic_words_are_not_broken() {
+        let patterns = english();
+        assert_eq!(patterns.syllables("cat"), ["cat"]);
+        assert_eq!(patterns.syllables("e-mail"), ["e-mail"]);
+        assert_eq!(patterns.syllables("mp3player"), ["mp3player"]);
+    }
+
+    #[test]
+    fn breaks_respect_the_minimum_fragment_lengths() {
+        // Liang's example pattern allows a break after the first letter
+        let patterns = Patterns::parse("1b1", "", 2, 3);
+        assert!(patterns
+            .break_points("abbbbb")
+            .iter()
+            .all(|&p| (2..=3).contains(&p)));
+        assert_eq!(patterns.syllables("abbbbb"), ["ab", "b", "bbb"]);
+    }
+
+    #[test]
+    fn languages_match_on_the_primary_subtag() {
+        assert!(language_for("en").is_some());
+        assert!(language_for("EN_gb").is_some());
+        assert!(language_for("fr-FR").is_none());
+    }
+}
@@ -0,0 +1,323 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{Emitter, Manager, Runtime};
+
+use crate::books::{self, Book};
+use crate::db::DbState;
+use crate::permissions::{self, EntryPoint, PolicyError, Tier};
+use crate::selection::{self, SelectionError, SelectionResult};
+
+/// `openread://define?word=...` and `openread://open?path=...`
+const DEEP_LINK_SCHEME: &str = "openread";
+
+const BOOK_EXTENSIONS: [&str; 4] = ["epub", "pdf", "txt", "md"];
+const DICTIONARY_EXTENSIONS: [&str; 1] = ["json"];
+
+/// Something handed to the app from outside: shared text, or a file opened with it
+#[derive(Debug)]
+pub enum Incoming {
+    Text(String),
+    Book(PathBuf),
+    Dictionary(PathBuf),
+}
+
+/// What an incoming item turned into, sent to the frontend as "incoming-opened"
+#[derive(Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Opened {
+    Definition {
+        result: SelectionResult,
+    },
+    DefinitionFailed {
+        error: SelectionError,
+    },
+    Book {
+        book: Book,
+    },
+    /// Dictionary files aren't imported unasked; the frontend confirms first
+    DictionaryFile {
+        path: String,
+    },
+    Failed {
+        message: String,
+    },
+    /// The permission policy doesn't allow this kind of item from outside the app
+    Denied {
+        error: PolicyError,
+    },
+}
+
+/// Items that arrived before the frontend was listening, e.g. the share or file that
+/// launched the app. `None` once `take_incoming` has been called; items are emitted then.
+pub struct IncomingQueue(Mutex<Option<Vec<Opened>>>);
+
+impl Default for IncomingQueue {
+    fn default() -> Self {
+        IncomingQueue(Mutex::new(Some(Vec::new())))
+    }
+}
+
+/// The file-association rule: books and dictionary files by extension, anything else is
+/// ignored
+pub fn classify_file(path: &Path) -> Option<Incoming> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    if BOOK_EXTENSIONS.contains(&extension.as_str()) {
+        Some(Incoming::Book(path.to_path_buf()))
+    } else if DICTIONARY_EXTENSIONS.contains(&extension.as_str()) {
+        Some(Incoming::Dictionary(path.to_path_buf()))
+    } else {
+        None
+    }
+}
+
+fn parse_url(url: &url::Url) -> Option<Incoming> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    match url.scheme() {
+        "file" => classify_file(&url.to_file_path().ok()?),
+        DEEP_LINK_SCHEME => match url.host_str()? {
+            "define" => param("word")
+                .or_else(|| param("text"))
+                .filter(|text| !text.trim().is_empty())
+                .map(Incoming::Text),
+            "open" => classify_file(Path::new(&param("path")?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Read one payload from the OS: a `file://` URL, an `openread://` link, a file path, or
+/// plain text to define. Links to anything else (e.g. a shared web page) are ignored.
+pub fn parse_payload(payload: &str) -> Option<Incoming> {
+    let payload = payload.trim();
+    if payload.is_empty() {
+        return None;
+    }
+
+    let path = Path::new(payload);
+    if path.is_absolute() {
+        return classify_file(path);
+    }
+
+    // Only a single token can be a link; "note: this" is text
+    if !payload.contains(char::is_whitespace) {
+        if let Ok(url) = url::Url::parse(payload) {
+            return parse_url(&url);
+        }
+    }
+
+    Some(Incoming::Text(payload.to_string()))
+}
+
+impl Incoming {
+    /// Defining text only leaves history behind; opening a book adds it to the library
+    fn tier(&self) -> Tier {
+        match self {
+            Incoming::Text(_) | Incoming::Dictionary(_) => Tier::ReadOnly,
+            Incoming::Book(_) => Tier::UserData,
+        }
+    }
+}
+
+fn resolve<R: Runtime>(app: &tauri::AppHandle<R>, incoming: Incoming) -> Opened {
+    let state = app.state::<DbState>();
+    let allowed = {
+        let conn = state.0.lock().unwrap();
+        permissions::check(&conn, EntryPoint::Incoming, incoming.tier())
+    };
+    if let Err(error) = allowed {
+        return Opened::Denied { error };
+    }
+    match incoming {
+        Incoming::Text(text) => {
+            let defined = state
+                .user_data()
+                .map_err(SelectionError::from)
+                .and_then(|conn| selection::define(&conn, &text, "", None));
+            match defined {
+                Ok(result) => Opened::Definition { result },
+                Err(error) => Opened::DefinitionFailed { error },
+            }
+        }
+        Incoming::Book(path) => {
+            let book = state.user_data().and_then(|conn| {
+                books::insert_book(&conn, &path.to_string_lossy(), None, None, None)
+                    .map_err(|e| e.to_string())
+            });
+            match book {
+                Ok(book) => Opened::Book { book },
+                Err(message) => Opened::Failed { message },
+            }
+        }
+        Incoming::Dictionary(path) => Opened::DictionaryFile {
+            path: path.to_string_lossy().into_owned(),
+        },
+    }
+}
+
+/// Handle a payload from any platform's glue: define shared text, add a shared book to the
+/// library, and bring the app to the front
+pub fn open<R: Runtime>(app: &tauri::AppHandle<R>, payload: &str) {
+    let Some(incoming) = parse_payload(payload) else {
+        return;
+    };
+    let opened = resolve(app, incoming);
+
+    let queue = app.state::<IncomingQueue>();
+    match queue.0.lock().unwrap().as_mut() {
+        Some(pending) => pending.push(opened),
+        None => {
+            let _ = app.emit("incoming-opened", opened);
+        }
+    }
+
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Desktop glue: a file association launches the app with the file path as an argument
+#[cfg(desktop)]
+pub fn open_launch_args(app: &tauri::AppHandle) {
+    for arg in std::env::args().skip(1).filter(|arg| !arg.starts_with('-')) {
+        open(app, &arg);
+    }
+}
+
+/// Apple glue: files and links opened with the app, including from the iOS share sheet
+pub fn handle_run_event(app: &tauri::AppHandle, event: &tauri::RunEvent) {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let tauri::RunEvent::Opened { urls } = event {
+        for url in urls {
+            open(app, url.as_str());
+        }
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let _ = (app, event);
+}
+
+/// Android glue: the activity receiving ACTION_SEND / ACTION_VIEW forwards the shared text
+/// or file path here
+#[tauri::command]
+pub fn open_shared<R: Runtime>(payloads: Vec<String>, app: tauri::AppHandle<R>) {
+    for payload in &payloads {
+        open(&app, payload);
+    }
+}
+
+/// Items that arrived before the frontend started listening; from now on they're emitted
+#[tauri::command]
+pub fn take_incoming(queue: tauri::State<IncomingQueue>) -> Vec<Opened> {
+    queue.0.lock().unwrap().take().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(payload: &str) -> Option<String> {
+        parse_payload(payload).map(|incoming| match incoming {
+            Incoming::Text(text) => format!("text {}", text),
+            Incoming::Book(path) => format!("book {}", path.display()),
+            Incoming::Dictionary(path) => format!("dictionary {}", path.display()),
+        })
+    }
+
+    #[test]
+    fn payloads_are_classified() {
+        let cases = [
+            ("serendipity", Some("text serendipity")),
+            ("  a phrase to define \n", Some("text a phrase to define")),
+            (
+                "note: see https://example.com",
+                Some("text note: see https://example.com"),
+            ),
+            ("", None),
+            ("   ", None),
+            (
+                "/home/reader/Books/Emma.EPUB",
+                Some("book /home/reader/Books/Emma.EPUB"),
+            ),
+            (
+                "/home/reader/words.json",
+                Some("dictionary /home/reader/words.json"),
+            ),
+            ("/home/reader/notes.docx", None),
+            ("/home/reader/no-extension", None),
+            ("file:///tmp/My%20Book.pdf", Some("book /tmp/My Book.pdf")),
+            ("file:///tmp/archive.zip", None),
+            (
+                "openread://define?word=serendipity",
+                Some("text serendipity"),
+            ),
+            ("openread://define?word=caf%C3%A9", Some("text café")),
+            ("openread://define?text=two+words", Some("text two words")),
+            ("openread://define?word=%20", None),
+            ("openread://define", None),
+            (
+                "openread://open?path=/tmp/notes.md",
+                Some("book /tmp/notes.md"),
+            ),
+            (
+                "openread://open?path=/tmp/pack.json",
+                Some("dictionary /tmp/pack.json"),
+            ),
+            ("openread://open", None),
+            ("openread://delete?path=/tmp/notes.md", None),
+            ("https://example.com/article", None),
+            ("mailto:someone@example.com", None),
+        ];
+        for (payload, expected) in cases {
+            assert_eq!(
+                parsed(payload).as_deref(),
+                expected,
+                "payload {:?}",
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn only_books_need_user_data_access() {
+        let tier = |payload| parse_payload(payload).unwrap().tier();
+        assert_eq!(tier("serendipity"), Tier::ReadOnly);
+        assert_eq!(tier("/tmp/pack.json"), Tier::ReadOnly);
+        assert_eq!(tier("/tmp/book.epub"), Tier::UserData);
+    }
+
+    #[test]
+    fn a_denied_book_isnt_added() {
+        let app = tauri::test::mock_app();
+        let conn = crate::db::init_db(None).unwrap();
+        crate::settings::set(&conn, "incoming_allow_user_data", "false").unwrap();
+        app.manage(DbState::new(conn, false));
+
+        let opened = resolve(
+            app.handle(),
+            parse_payload("/home/reader/Books/Emma.epub").unwrap(),
+        );
+        assert!(matches!(
+            opened,
+            Opened::Denied {
+                error: PolicyError::PermissionDenied {
+                    entry_point: EntryPoint::Incoming,
+                    tier: Tier::UserData,
+                    setting: "incoming_allow_user_data",
+                }
+            }
+        ));
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        let books: i64 = conn
+            .query_row("SELECT COUNT(*) FROM books", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(books, 0);
+    }
+}
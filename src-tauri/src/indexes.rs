@@ -0,0 +1,282 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+
+use crate::book_search::{self, IndexStatus};
+use crate::books;
+use crate::db::DbState;
+use crate::migrations;
+
+/// Structures derived from other tables, which searches quietly get worse without
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexKind {
+    /// The full-text index of book contents, built per book
+    BookSearch,
+    /// The headword index dictionary lookups go through
+    DictionaryWords,
+}
+
+const ALL_KINDS: [IndexKind; 2] = [IndexKind::BookSearch, IndexKind::DictionaryWords];
+
+impl IndexKind {
+    fn meta_key(self) -> &'static str {
+        match self {
+            IndexKind::BookSearch => "index_built.book_search",
+            IndexKind::DictionaryWords => "index_built.dictionary_words",
+        }
+    }
+}
+
+/// What a full rebuild saw, kept in `meta`
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct Build {
+    built_at: i64,
+    source_rows: i64,
+}
+
+#[derive(Serialize)]
+pub struct IndexReport {
+    kind: IndexKind,
+    exists: bool,
+    /// Rows covered by the index: indexed books, or indexed headword entries
+    rows: i64,
+    /// Rows the index is built from: books, or dictionary entries
+    source_rows: i64,
+    /// The last full rebuild, if there's been one
+    built_at: Option<i64>,
+    /// Missing rows, or the source changed size since the last full rebuild
+    stale: bool,
+}
+
+/// Kinds found stale when the app started, reported by `health_check` until rebuilt
+#[derive(Default)]
+pub struct StaleIndexes(pub Mutex<BTreeSet<IndexKind>>);
+
+/// Cancellation flag of the rebuild in flight; only one runs at a time
+#[derive(Default)]
+pub struct IndexRebuild(Mutex<Option<Arc<AtomicBool>>>);
+
+#[derive(Serialize, Clone)]
+struct RebuildProgress {
+    kind: IndexKind,
+    status: IndexStatus,
+    done: usize,
+    total: usize,
+    error: Option<String>,
+}
+
+fn emit_progress(app: &tauri::AppHandle, progress: RebuildProgress) {
+    let _ = app.emit("index-rebuild-progress", progress);
+}
+
+fn last_build(conn: &Connection, kind: IndexKind) -> Result<Option<Build>> {
+    Ok(migrations::get_meta(conn, kind.meta_key())?
+        .and_then(|json| serde_json::from_str(&json).ok()))
+}
+
+fn record_build(conn: &Connection, kind: IndexKind, source_rows: i64) -> Result<()> {
+    let build = Build {
+        built_at: conn.query_row("SELECT unixepoch()", [], |row| row.get(0))?,
+        source_rows,
+    };
+    migrations::set_meta(
+        conn,
+        kind.meta_key(),
+        &serde_json::to_string(&build).unwrap_or_default(),
+    )
+}
+
+fn source_rows(conn: &Connection, kind: IndexKind) -> Result<i64> {
+    let sql = match kind {
+        IndexKind::BookSearch => "SELECT COUNT(*) FROM books",
+        IndexKind::DictionaryWords => "SELECT COUNT(*) FROM dictionary",
+    };
+    conn.query_row(sql, [], |row| row.get(0))
+}
+
+pub fn report(conn: &Connection, kind: IndexKind) -> Result<IndexReport> {
+    let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0));
+    let source_rows = source_rows(conn, kind)?;
+    let (exists, rows) = match kind {
+        IndexKind::BookSearch => (
+            count("SELECT COUNT(*) FROM sqlite_master WHERE name = 'book_search'")? > 0,
+            count("SELECT COUNT(*) FROM book_search_state")?,
+        ),
+        IndexKind::DictionaryWords => {
+            let exists = count("SELECT COUNT(*) FROM sqlite_master WHERE name = 'idx_word'")? > 0;
+            (exists, if exists { source_rows } else { 0 })
+        }
+    };
+    let build = last_build(conn, kind)?;
+
+    Ok(IndexReport {
+        kind,
+        exists,
+        rows,
+        source_rows,
+        built_at: build.map(|b| b.built_at),
+        stale: !exists || rows < source_rows || build.is_some_and(|b| b.source_rows != source_rows),
+    })
+}
+
+/// Note which indexes look stale at startup, without rebuilding anything
+pub fn check_stale(app: &tauri::AppHandle) {
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+    let stale: BTreeSet<IndexKind> = ALL_KINDS
+        .into_iter()
+        .filter(|&kind| report(&conn, kind).is_ok_and(|r| r.stale))
+        .collect();
+    *app.state::<StaleIndexes>().0.lock().unwrap() = stale;
+}
+
+fn book_ids(conn: &Connection) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT id FROM books ORDER BY id")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Re-index every book from scratch, including ones whose content hasn't changed.
+/// Returns false if cancelled; books not reached yet keep their current index.
+fn rebuild_book_search(app: &tauri::AppHandle, cancel: &AtomicBool) -> Result<bool, String> {
+    let state = app.state::<DbState>();
+    let book_ids = book_ids(&state.0.lock().unwrap()).map_err(|e| e.to_string())?;
+
+    let total = book_ids.len();
+    for (done, book_id) in book_ids.into_iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        emit_progress(
+            app,
+            RebuildProgress {
+                kind: IndexKind::BookSearch,
+                status: IndexStatus::Running,
+                done,
+                total,
+                error: None,
+            },
+        );
+
+        let book = {
+            let conn = state.0.lock().unwrap();
+            // Forget the content hash so an unchanged book is indexed again
+            conn.execute(
+                "DELETE FROM book_search_state WHERE book_id = ?",
+                params![book_id],
+            )
+            .map_err(|e| e.to_string())?;
+            books::get_book(&conn, book_id).map_err(|e| e.to_string())?
+        };
+        // Removed since the list was taken
+        let Some(book) = book else {
+            continue;
+        };
+        if matches!(
+            book_search::run_build(app, &book, cancel)?,
+            IndexStatus::Cancelled
+        ) {
+            return Ok(false);
+        }
+    }
+
+    let conn = state.0.lock().unwrap();
+    let books = source_rows(&conn, IndexKind::BookSearch).map_err(|e| e.to_string())?;
+    record_build(&conn, IndexKind::BookSearch, books).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+fn rebuild_dictionary_words(app: &tauri::AppHandle) -> Result<(), String> {
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_word ON dictionary(word COLLATE NOCASE);
+         REINDEX idx_word;",
+    )
+    .map_err(|e| e.to_string())?;
+    let entries = source_rows(&conn, IndexKind::DictionaryWords).map_err(|e| e.to_string())?;
+    record_build(&conn, IndexKind::DictionaryWords, entries).map_err(|e| e.to_string())
+}
+
+fn rebuild(app: &tauri::AppHandle, kind: IndexKind, cancel: &AtomicBool) -> Result<bool, String> {
+    match kind {
+        IndexKind::BookSearch => rebuild_book_search(app, cancel),
+        IndexKind::DictionaryWords => rebuild_dictionary_words(app).map(|_| true),
+    }
+}
+
+/// Status of every derived structure
+#[tauri::command]
+pub fn index_status(state: tauri::State<DbState>) -> Result<Vec<IndexReport>, String> {
+    let conn = state.0.lock().unwrap();
+    ALL_KINDS
+        .into_iter()
+        .map(|kind| report(&conn, kind).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Rebuild the given structures (all of them when empty) one after another in the
+/// background, reporting "index-rebuild-progress" events. Runs in low-memory mode too,
+/// since it's asked for. Fails if a rebuild is already running.
+#[tauri::command]
+pub fn rebuild_indexes(
+    which: Vec<IndexKind>,
+    app: tauri::AppHandle,
+    job: tauri::State<IndexRebuild>,
+) -> Result<(), String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = job.0.lock().unwrap();
+        if running.is_some() {
+            return Err("An index rebuild is already running".to_string());
+        }
+        *running = Some(cancel.clone());
+    }
+
+    let kinds: BTreeSet<IndexKind> = if which.is_empty() {
+        ALL_KINDS.into_iter().collect()
+    } else {
+        which.into_iter().collect()
+    };
+
+    std::thread::spawn(move || {
+        for kind in kinds {
+            let (status, error) = if cancel.load(Ordering::Relaxed) {
+                (IndexStatus::Cancelled, None)
+            } else {
+                match rebuild(&app, kind, &cancel) {
+                    Ok(true) => {
+                        app.state::<StaleIndexes>().0.lock().unwrap().remove(&kind);
+                        (IndexStatus::Completed, None)
+                    }
+                    Ok(false) => (IndexStatus::Cancelled, None),
+                    Err(e) => (IndexStatus::Failed, Some(e)),
+                }
+            };
+            emit_progress(
+                &app,
+                RebuildProgress {
+                    kind,
+                    status,
+                    done: 0,
+                    total: 0,
+                    error,
+                },
+            );
+        }
+        *app.state::<IndexRebuild>().0.lock().unwrap() = None;
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cancel_index_rebuild(job: tauri::State<IndexRebuild>) {
+    if let Some(flag) = job.0.lock().unwrap().as_ref() {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
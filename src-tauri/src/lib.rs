@@ -1,21 +1,292 @@
+mod analytics;
+mod automation;
+mod backup;
+mod book_search;
+mod book_words;
+mod books;
+mod bulk_edit;
+mod coverage;
 mod db;
+#[cfg(debug_assertions)]
+mod dev_reload;
+mod diagnostics;
+mod difficulty;
+mod encryption;
+mod epub;
+mod etymology;
+mod examples;
+mod export;
+mod glossary;
+mod health;
+mod history;
+mod hyphenation;
+mod incoming;
+mod indexes;
+mod licenses;
+mod locators;
+mod memory;
+mod migrations;
+mod missed;
+mod onboarding;
+mod opds;
+mod pack_updates;
+mod pdf_text;
+mod permissions;
+mod profiles;
+mod query_budget;
+mod queue;
+mod read_only;
+mod repair;
+mod review;
+mod selection;
+mod senses;
+mod session_lookups;
+mod sessions;
+mod settings;
+mod shutdown;
+mod snippet;
+mod source_reports;
+mod source_stats;
+mod summary;
+mod text_book;
+mod tokens;
+mod tts;
+mod vocabulary;
+mod window_context;
+mod word_family;
+mod word_of_day;
 
-use db::{init_db, search_dictionary, DbState};
+use analytics::{clear_analytics, get_analytics_summary};
+use backup::BackupJob;
+use book_search::{
+    build_book_search_index, cancel_book_index, search_book_in_order, search_books, BookIndexJobs,
+};
+use book_words::get_book_vocabulary;
+use books::{
+    add_book, get_book_tags, list_books, remove_book, restart_book, save_book_position,
+    set_book_tags,
+};
+use bulk_edit::{bulk_update_entries, get_entry_tags, select_entry_ids};
+use coverage::check_words;
+use db::{search_dictionary, DbState};
+use diagnostics::get_diagnostics;
+use difficulty::{generate_quiz, get_unknown_words, set_difficulty_data};
+use encryption::{change_passphrase, enable_encryption, unlock};
+use epub::{get_epub_resource, get_epub_structure};
+use etymology::get_etymology_chain;
+use examples::{find_word_in_library, get_entry_details};
+use export::export_library;
+use glossary::{
+    add_glossary_word, generate_book_glossary, get_book_glossary, remove_glossary_word,
+};
+use health::{health_check, HealthCache};
+use hyphenation::{get_hyphenation, Hyphenators};
+use incoming::{open_shared, take_incoming, IncomingQueue};
+use indexes::{cancel_index_rebuild, index_status, rebuild_indexes, IndexRebuild, StaleIndexes};
+use licenses::{get_attributions, set_source_license};
+use memory::{build_indexes_now, DeferredIndexes, MemoryMode};
+use migrations::get_db_status;
+use missed::{add_missing_word, get_missed_words};
+use onboarding::populate_initial_dictionary;
+use opds::{browse_opds, download_opds_entry};
+use pack_updates::{check_pack_updates, update_pack};
+use pdf_text::extract_pdf_text;
+use profiles::{create_profile, delete_profile, list_profiles, switch_profile, CurrentProfile};
+use queue::{dequeue_book, get_reading_queue, queue_book, reorder_queue};
+use read_only::ReadOnly;
+use repair::repair_dictionary;
+use review::{
+    add_review_card, end_review_session, get_review_sessions, start_review_session, submit_review,
+};
+use selection::define_selection;
+use senses::{get_senses, promote_sense, reorder_senses};
+use session_lookups::{clear_session_lookups, get_session_lookups, SessionLookups};
+use sessions::{end_reading_session, get_book_progress, start_reading_session};
+use settings::{get_setting, set_setting};
+use source_reports::{analyze_source, get_source_report, ReportJobs};
+use source_stats::{get_source_stats, SourceCounters};
+use summary::{generate_weekly_summary, get_summary_history, store_summary};
 use tauri::Manager;
+use text_book::{get_book_content, get_text_book_outline};
+use tokens::set_custom_stopwords;
+use tts::{pause_tts, resume_tts, speak_text, stop_tts, TtsQueue};
+use vocabulary::{get_vocab_growth, set_word_known};
+use window_context::{set_window_context, WindowContexts};
+use word_family::get_word_family;
+use word_of_day::{get_next_notification_payload, mark_notification_shown};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    // Run through `read_only::intercept`, which in a read-only run answers every command
+    // missing from its `READ_COMMANDS` itself
+    let commands: fn(tauri::ipc::Invoke) -> bool = tauri::generate_handler![
+        search_dictionary,
+        get_source_stats,
+        analyze_source,
+        get_source_report,
+        get_attributions,
+        set_source_license,
+        get_senses,
+        reorder_senses,
+        promote_sense,
+        get_missed_words,
+        add_missing_word,
+        get_session_lookups,
+        set_window_context,
+        clear_session_lookups,
+        define_selection,
+        open_shared,
+        take_incoming,
+        get_entry_details,
+        bulk_update_entries,
+        select_entry_ids,
+        get_entry_tags,
+        find_word_in_library,
+        get_hyphenation,
+        get_word_family,
+        get_next_notification_payload,
+        mark_notification_shown,
+        get_etymology_chain,
+        set_difficulty_data,
+        get_unknown_words,
+        generate_quiz,
+        get_setting,
+        set_setting,
+        list_profiles,
+        create_profile,
+        switch_profile,
+        delete_profile,
+        add_book,
+        list_books,
+        remove_book,
+        save_book_position,
+        restart_book,
+        set_book_tags,
+        get_book_tags,
+        export_library,
+        browse_opds,
+        download_opds_entry,
+        get_text_book_outline,
+        get_book_content,
+        speak_text,
+        pause_tts,
+        resume_tts,
+        stop_tts,
+        get_epub_structure,
+        get_epub_resource,
+        extract_pdf_text,
+        build_book_search_index,
+        cancel_book_index,
+        build_indexes_now,
+        index_status,
+        rebuild_indexes,
+        cancel_index_rebuild,
+        search_books,
+        search_book_in_order,
+        get_book_vocabulary,
+        generate_book_glossary,
+        get_book_glossary,
+        add_glossary_word,
+        remove_glossary_word,
+        set_custom_stopwords,
+        start_reading_session,
+        end_reading_session,
+        get_book_progress,
+        add_review_card,
+        start_review_session,
+        submit_review,
+        end_review_session,
+        get_review_sessions,
+        queue_book,
+        dequeue_book,
+        reorder_queue,
+        get_reading_queue,
+        get_analytics_summary,
+        clear_analytics,
+        generate_weekly_summary,
+        store_summary,
+        get_summary_history,
+        set_word_known,
+        check_words,
+        get_vocab_growth,
+        get_diagnostics,
+        get_db_status,
+        health_check,
+        repair_dictionary,
+        check_pack_updates,
+        update_pack,
+        populate_initial_dictionary,
+        enable_encryption,
+        unlock,
+        change_passphrase
+    ];
+    let builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_dialog::init());
+    #[cfg(feature = "notifications")]
+    let builder = builder.plugin(tauri_plugin_notification::init());
+    builder
         .setup(|app| {
             // Initialize database with app handle to access bundled resources
-            let conn =
-                init_db(Some(app.handle())).expect("Failed to initialize dictionary database");
-            app.manage(DbState(std::sync::Mutex::new(conn)));
+            let read_only = read_only::detect(app.handle());
+            let (conn, locked) = encryption::open_database(app.handle(), read_only.is_some())
+                .expect("Failed to initialize dictionary database");
+            let profile = profiles::current_id(&conn).expect("No active profile");
+            let low_memory = memory::low_memory_enabled(&conn).unwrap_or(cfg!(mobile));
+            app.manage(DbState::new(conn, locked));
+            app.manage(CurrentProfile(profile.into()));
+            app.manage(BookIndexJobs::default());
+            app.manage(SessionLookups::default());
+            app.manage(WindowContexts::default());
+            app.manage(Hyphenators::default());
+            app.manage(BackupJob::default());
+            app.manage(IncomingQueue::default());
+            app.manage(MemoryMode(low_memory));
+            app.manage(DeferredIndexes::default());
+            app.manage(HealthCache::default());
+            app.manage(SourceCounters::default());
+            app.manage(ReportJobs::default());
+            app.manage(StaleIndexes::default());
+            app.manage(IndexRebuild::default());
+            app.manage(TtsQueue::default());
+            app.manage(ReadOnly(read_only));
+            repair::check_bundled(app.handle());
+            indexes::check_stale(app.handle());
+            // Each of these writes, which a read-only run can't
+            if read_only.is_none() {
+                vocabulary::snapshot_at_startup(app.handle());
+                word_of_day::note_app_opened(app.handle());
+                #[cfg(feature = "notifications")]
+                word_of_day::schedule_notification(app.handle());
+                source_stats::start_flush_timer(app.handle());
+            }
+            automation::start(app.handle());
+            #[cfg(debug_assertions)]
+            dev_reload::watch(app.handle());
+            #[cfg(desktop)]
+            incoming::open_launch_args(app.handle());
+            // After everything else so a backup never holds up startup
+            if read_only.is_none() {
+                backup::schedule(app.handle());
+            }
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![search_dictionary])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                window_context::forget(window.app_handle(), window.label());
+            }
+        })
+        .invoke_handler(move |invoke| match read_only::intercept(invoke) {
+            Some(invoke) => commands(invoke),
+            // Rejected, and answered already
+            None => true,
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            incoming::handle_run_event(app, &event);
+            if let tauri::RunEvent::Exit = event {
+                shutdown::run(app);
+            }
+        });
 }
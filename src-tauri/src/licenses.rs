@@ -0,0 +1,223 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbState;
+
+/// License of a dictionary source, from the `license` object of its file or set by hand
+#[derive(Serialize, Deserialize, Clone)]
+pub struct License {
+    /// e.g. "CC BY-SA 4.0"
+    pub name: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Text that has to be shown wherever the content is, e.g. "Contains data from Wiktionary"
+    #[serde(default)]
+    pub attribution: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SourceAttribution {
+    source: String,
+    /// `None` for sources that came without license information
+    license: Option<License>,
+}
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS source_licenses (
+            source TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            url TEXT,
+            attribution TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Store the license that came with a source's content, or forget the old one when the
+/// new content has none
+pub fn record(conn: &Connection, source: &str, license: Option<&License>) -> Result<()> {
+    match license {
+        Some(license) => conn.execute(
+            "INSERT INTO source_licenses (source, name, url, attribution) VALUES (?, ?, ?, ?)
+             ON CONFLICT(source) DO UPDATE SET
+                name = excluded.name, url = excluded.url, attribution = excluded.attribution",
+            params![source, license.name, license.url, license.attribution],
+        )?,
+        None => conn.execute(
+            "DELETE FROM source_licenses WHERE source = ?",
+            params![source],
+        )?,
+    };
+    Ok(())
+}
+
+/// Attribution texts of the given sources, e.g. those of the senses a lookup returned, one
+/// per source
+pub fn attributions_for(conn: &Connection, sources: &[String]) -> Result<Vec<String>> {
+    let sources = serde_json::to_string(sources).unwrap_or_default();
+    let mut stmt = conn.prepare(
+        "SELECT attribution FROM source_licenses
+         WHERE source IN (SELECT value FROM json_each(?)) AND attribution IS NOT NULL
+         ORDER BY source",
+    )?;
+    let rows = stmt.query_map(params![sources], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// License and attribution of every installed source, for an about or credits screen
+#[tauri::command]
+pub fn get_attributions(state: tauri::State<DbState>) -> Result<Vec<SourceAttribution>, String> {
+    let conn = state.user_data()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT d.source, l.name, l.url, l.attribution
+             FROM (SELECT DISTINCT source FROM dictionary) d
+             LEFT JOIN source_licenses l ON l.source = d.source
+             ORDER BY d.source",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            let name: Option<String> = row.get(1)?;
+            Ok(SourceAttribution {
+                source: row.get(0)?,
+                license: match name {
+                    Some(name) => Some(License {
+                        name,
+                        url: row.get(2)?,
+                        attribution: row.get(3)?,
+                    }),
+                    None => None,
+                },
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>>>().map_err(|e| e.to_string())
+}
+
+/// Set or clear a source's license by hand, for content imported without one
+#[tauri::command]
+pub fn set_source_license(
+    source: &str,
+    license: Option<License>,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+    record(&conn, source, license.as_ref()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn license(name: &str, attribution: Option<&str>) -> License {
+        License {
+            name: name.to_string(),
+            url: None,
+            attribution: attribution.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn recording_replaces_and_forgets_licenses() {
+        let conn = db::init_db(None).unwrap();
+        let stored = |conn: &Connection| -> Vec<(String, String, Option<String>)> {
+            let mut stmt = conn
+                .prepare("SELECT source, name, attribution FROM source_licenses ORDER BY source")
+                .unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap();
+            rows.collect::<Result<_>>().unwrap()
+        };
+
+        record(&conn, "wiktionary", Some(&license("CC BY-SA 3.0", None))).unwrap();
+        record(
+            &conn,
+            "wordnet",
+            Some(&license("WordNet", Some("WordNet 3.0"))),
+        )
+        .unwrap();
+        record(
+            &conn,
+            "wiktionary",
+            Some(&license(
+                "CC BY-SA 4.0",
+                Some("Contains data from Wiktionary"),
+            )),
+        )
+        .unwrap();
+        assert_eq!(
+            stored(&conn),
+            [
+                (
+                    "wiktionary".to_string(),
+                    "CC BY-SA 4.0".to_string(),
+                    Some("Contains data from Wiktionary".to_string())
+                ),
+                (
+                    "wordnet".to_string(),
+                    "WordNet".to_string(),
+                    Some("WordNet 3.0".to_string())
+                ),
+            ]
+        );
+
+        record(&conn, "wordnet", None).unwrap();
+        assert_eq!(stored(&conn).len(), 1);
+    }
+
+    #[test]
+    fn only_the_sources_a_lookup_used_are_credited() {
+        let conn = db::init_db(None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition, source) VALUES
+                 ('runs', 'Plural of run.', 'wiktionary'),
+                 ('runs', 'Plural of run.', 'wordnet'),
+                 ('whale', 'A large sea mammal.', 'bundled');",
+        )
+        .unwrap();
+        record(
+            &conn,
+            "wiktionary",
+            Some(&license("CC BY-SA 4.0", Some("Wiktionary"))),
+        )
+        .unwrap();
+        record(
+            &conn,
+            "wordnet",
+            Some(&license("WordNet", Some("WordNet 3.0"))),
+        )
+        .unwrap();
+        record(&conn, "bundled", Some(&license("Public domain", None))).unwrap();
+
+        // Only the Wiktionary sense is shown, though WordNet has the same text
+        let shown = vec!["wiktionary".to_string()];
+        assert_eq!(attributions_for(&conn, &shown).unwrap(), ["Wiktionary"]);
+
+        let sources: Vec<String> = db::lookup_senses(&conn, "runs")
+            .unwrap()
+            .into_iter()
+            .map(|sense| sense.source)
+            .collect();
+        assert_eq!(
+            attributions_for(&conn, &sources).unwrap(),
+            ["Wiktionary", "WordNet 3.0"]
+        );
+
+        // A license without attribution text, or a source without a license, adds nothing
+        let whale: Vec<String> = db::lookup_senses(&conn, "whales")
+            .unwrap()
+            .into_iter()
+            .map(|sense| sense.source)
+            .collect();
+        assert_eq!(whale, ["bundled"]);
+        assert!(attributions_for(&conn, &whale).unwrap().is_empty());
+        assert!(attributions_for(&conn, &["unknown".to_string()])
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+use crate::books::Book;
+use crate::{epub, text_book};
+
+/// A position in a book, parsed from a stored locator string
+#[derive(Debug, Clone, PartialEq)]
+pub enum Locator {
+    /// `txt:<byte offset>`
+    Text(usize),
+    /// `epub:<spine index>:<char offset>`
+    Epub { spine: usize, offset: usize },
+    /// An EPUB CFI such as `epubcfi(/6/4[chap01]!/4/2/1:10)`, as reading positions from the
+    /// reader are stored
+    Cfi(Cfi),
+    /// `pdf:<page>`
+    Pdf(usize),
+}
+
+/// The steps of a CFI with indirections and assertions dropped, plus the character offset
+/// of its last step. Document order is step order, a parent coming before its children.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cfi {
+    steps: Vec<u64>,
+    offset: Option<u64>,
+}
+
+impl Cfi {
+    fn parse(cfi: &str) -> Option<Cfi> {
+        let inner = cfi.strip_prefix("epubcfi(")?.strip_suffix(')')?;
+        let inner = strip_assertions(inner);
+        // A range (parent, start, end) begins where the parent and its start meet
+        let path = match inner.split(',').collect::<Vec<_>>()[..] {
+            [path] => path.to_string(),
+            [parent, start, _] => format!("{}{}", parent, start),
+            _ => return None,
+        };
+
+        let mut steps = Vec::new();
+        let mut offset = None;
+        for step in path.split('/').skip(1) {
+            // Offsets end the path; spatial and temporal ones (@, ~) don't affect order here,
+            // and are cut first since a spatial one has a colon of its own
+            let step = step.split(['@', '~']).next().unwrap_or(step);
+            let (step, char_offset) = match step.split_once(':') {
+                Some((step, rest)) => (step, Some(rest)),
+                None => (step, None),
+            };
+            let step = step.trim_end_matches('!');
+            steps.push(step.parse().ok()?);
+            if let Some(rest) = char_offset {
+                let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+                offset = Some(digits.parse().ok()?);
+                break;
+            }
+        }
+        (!steps.is_empty()).then_some(Cfi { steps, offset })
+    }
+
+    /// Spine index the CFI points into: the second step selects the spine item,
+    /// counting in twos from 2
+    fn spine(&self) -> Option<usize> {
+        self.steps
+            .get(1)
+            .and_then(|&step| (step / 2).checked_sub(1))
+            .map(|index| index as usize)
+    }
+}
+
+/// Remove `[...]` id and text assertions, honouring `^` escapes inside them
+fn strip_assertions(cfi: &str) -> String {
+    let mut out = String::with_capacity(cfi.len());
+    let mut depth = 0;
+    let mut escaped = false;
+    for c in cfi.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '^' if depth > 0 => escaped = true,
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+impl Locator {
+    pub fn parse(locator: &str) -> Option<Locator> {
+        if locator.starts_with("epubcfi(") {
+            return Cfi::parse(locator).map(Locator::Cfi);
+        }
+        let (kind, rest) = locator.split_once(':')?;
+        match kind {
+            "txt" => rest.parse().ok().map(Locator::Text),
+            "pdf" => rest.parse().ok().map(Locator::Pdf),
+            "epub" => {
+                let (spine, offset) = rest.split_once(':')?;
+                Some(Locator::Epub {
+                    spine: spine.parse().ok()?,
+                    offset: offset.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Spine index of an EPUB position
+    fn spine(&self) -> Option<usize> {
+        match self {
+            Locator::Epub { spine, .. } => Some(*spine),
+            Locator::Cfi(cfi) => cfi.spine(),
+            _ => None,
+        }
+    }
+}
+
+/// Document order of two positions in the same book. EPUB positions compare by spine item
+/// first; within one item, positions of different kinds can't be compared and character
+/// offsets go before CFIs. Positions of different formats keep a fixed order.
+pub fn compare(a: &Locator, b: &Locator) -> Ordering {
+    match (a, b) {
+        (Locator::Text(a), Locator::Text(b)) | (Locator::Pdf(a), Locator::Pdf(b)) => a.cmp(b),
+        (Locator::Epub { .. } | Locator::Cfi(_), Locator::Epub { .. } | Locator::Cfi(_)) => {
+            a.spine().cmp(&b.spine()).then_with(|| match (a, b) {
+                (Locator::Epub { offset: a, .. }, Locator::Epub { offset: b, .. }) => a.cmp(b),
+                (Locator::Cfi(a), Locator::Cfi(b)) => a.cmp(b),
+                (Locator::Epub { .. }, _) => Ordering::Less,
+                _ => Ordering::Greater,
+            })
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn rank(locator: &Locator) -> u8 {
+    match locator {
+        Locator::Text(_) => 0,
+        Locator::Epub { .. } | Locator::Cfi(_) => 1,
+        Locator::Pdf(_) => 2,
+    }
+}
+
+/// Items under one chapter heading, in document order
+#[derive(Serialize)]
+pub struct ChapterGroup<T> {
+    /// Index of the chapter in the book's outline or table of contents; `None` before the
+    /// first chapter, for books without chapters, and for the unplaced group
+    chapter: Option<usize>,
+    title: Option<String>,
+    /// Set on the last group, which holds items whose locator didn't parse
+    unplaced: bool,
+    items: Vec<T>,
+}
+
+/// Where a book's chapters start, as the outline or table of contents gives them
+enum Chapters {
+    /// Start offsets of detected chapters, in order
+    Text(Vec<(usize, String)>),
+    /// The chapter each spine item falls in: the last table of contents entry pointing at
+    /// it or an earlier item
+    Epub(Vec<Option<(usize, String)>>),
+    None,
+}
+
+impl Chapters {
+    fn of(conn: &Connection, book: &Book) -> Result<Chapters, String> {
+        match book.format.as_str() {
+            "txt" | "md" => {
+                let (text, hash) = text_book::read_text(book)?;
+                let outline = text_book::outline_for(conn, &text, &hash)?;
+                Ok(Chapters::Text(
+                    outline.into_iter().map(|c| (c.start, c.title)).collect(),
+                ))
+            }
+            "epub" => {
+                let (bytes, hash) = epub::read_epub(book)?;
+                let structure = epub::structure_for(conn, bytes, &hash)?;
+                let mut current = None;
+                let mut by_spine = Vec::with_capacity(structure.spine.len());
+                for item in &structure.spine {
+                    if let Some((index, entry)) = structure
+                        .toc
+                        .iter()
+                        .enumerate()
+                        .find(|(_, e)| e.href.split('#').next() == Some(item.href.as_str()))
+                    {
+                        current = Some((index, entry.label.clone()));
+                    }
+                    by_spine.push(current.clone());
+                }
+                Ok(Chapters::Epub(by_spine))
+            }
+            _ => Ok(Chapters::None),
+        }
+    }
+
+    fn find(&self, locator: &Locator) -> Option<(usize, String)> {
+        match (self, locator) {
+            (Chapters::Text(starts), Locator::Text(offset)) => {
+                let index = starts.partition_point(|(start, _)| start <= offset);
+                index.checked_sub(1).map(|i| (i, starts[i].1.clone()))
+            }
+            (Chapters::Epub(by_spine), _) => locator
+                .spine()
+                .and_then(|spine| by_spine.get(spine).cloned().flatten()),
+            _ => None,
+        }
+    }
+}
+
+/// Sort a book's items into document order and group them under its chapter headings.
+/// Items whose locator doesn't parse end up in a final group marked `unplaced`.
+pub fn group_by_chapter<T>(
+    conn: &Connection,
+    book: &Book,
+    items: Vec<T>,
+    locator_of: impl Fn(&T) -> &str,
+) -> Result<Vec<ChapterGroup<T>>, String> {
+    let chapters = Chapters::of(conn, book)?;
+    let mut placed = Vec::new();
+    let mut unplaced = Vec::new();
+    for item in items {
+        match Locator::parse(locator_of(&item)) {
+            Some(locator) => placed.push((locator, item)),
+            None => unplaced.push(item),
+        }
+    }
+    placed.sort_by(|(a, _), (b, _)| compare(a, b));
+
+    let mut groups: Vec<ChapterGroup<T>> = Vec::new();
+    for (locator, item) in placed {
+        let chapter = chapters.find(&locator);
+        let index = chapter.as_ref().map(|(i, _)| *i);
+        match groups.last_mut() {
+            Some(group) if group.chapter == index => group.items.push(item),
+            _ => groups.push(ChapterGroup {
+                chapter: index,
+                title: chapter.map(|(_, title)| title),
+                unplaced: false,
+                items: vec![item],
+            }),
+        }
+    }
+    if !unplaced.is_empty() {
+        unplaced.sort_by(|a, b| locator_of(a).cmp(locator_of(b)));
+        groups.push(ChapterGroup {
+            chapter: None,
+            title: None,
+            unplaced: true,
+            items: unplaced,
+        });
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(locator: &str) -> Locator {
+        Locator::parse(locator).unwrap_or_else(|| panic!("{} doesn't parse", locator))
+    }
+
+    #[test]
+    fn tricky_cfi_pairs_compare_in_document_order() {
+        use Ordering::*;
+        for (a, b, expected) in [
+            // Steps compare as numbers, not text
+            ("epubcfi(/6/4!/4/10)", "epubcfi(/6/4!/4/2)", Greater),
+            // A parent comes before its children
+            ("epubcfi(/6/4!/4)", "epubcfi(/6/4!/4/2/1:0)", Less),
+            ("epubcfi(/6/4!/4/2/1:5)", "epubcfi(/6/4!/4/2/1:40)", Less),
+            // An element comes before any offset into it
+            ("epubcfi(/6/4!/4/2)", "epubcfi(/6/4!/4/2:0)", Less),
+            // Assertions don't take part, escaped brackets included
+            (
+                "epubcfi(/6/4[chap01]!/4[body]/2)",
+                "epubcfi(/6/4!/4/2)",
+                Equal,
+            ),
+            (
+                "epubcfi(/6/4!/4/2/1:3[a^]b,c])",
+                "epubcfi(/6/4!/4/2/1:3)",
+                Equal,
+            ),
+            // A range sits where it starts
+            (
+                "epubcfi(/6/4!/4,/2/1:5,/2/1:9)",
+                "epubcfi(/6/4!/4/2/1:7)",
+                Less,
+            ),
+            (
+                "epubcfi(/6/4!/4,/2/1:8,/4/1:0)",
+                "epubcfi(/6/4!/4/2/1:7)",
+                Greater,
+            ),
+            // Spatial and temporal offsets don't move a position
+            ("epubcfi(/6/4!/4/2~23.5)", "epubcfi(/6/4!/4/2@50:50)", Equal),
+            // The spine item decides first, however deep the rest goes
+            ("epubcfi(/6/2!/4/99/1:500)", "epubcfi(/6/4!/4/2)", Less),
+            ("epubcfi(/6/14!/4/2)", "epubcfi(/6/4!/4/2)", Greater),
+            // Spine index 1 is the second item, `/6/4`
+            ("epub:1:999", "epubcfi(/6/4!/4/2)", Less),
+            ("epub:2:0", "epubcfi(/6/4!/4/2)", Greater),
+            ("epub:1:10", "epub:1:9", Greater),
+            ("txt:1024", "txt:12", Greater),
+            ("pdf:3", "pdf:3", Equal),
+            ("txt:99999", "epub:0:0", Less),
+            ("epubcfi(/6/2)", "pdf:1", Less),
+        ] {
+            assert_eq!(
+                compare(&parse(a), &parse(b)),
+                expected,
+                "{} against {}",
+                a,
+                b
+            );
+            assert_eq!(
+                compare(&parse(b), &parse(a)),
+                expected.reverse(),
+                "{} against {}",
+                b,
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_locators_dont_parse() {
+        for locator in [
+            "epubcfi(/6/4!/4/x)",
+            "epubcfi(/6/4!/4,/2/1:5)",
+            "epubcfi(/6/4!/4/2",
+            "epubcfi()",
+            "epubcfi(/6/4!/4/2/1:)",
+            "epub:1",
+            "epub:one:2",
+            "txt:-1",
+            "page:3",
+            "",
+        ] {
+            assert_eq!(Locator::parse(locator), None, "{}", locator);
+        }
+    }
+
+    #[test]
+    fn sorting_puts_annotations_front_to_back() {
+        let mut locators = vec![
+            "epubcfi(/6/6!/4/2/1:0)",
+            "epubcfi(/6/4!/4/10/1:3)",
+            "epubcfi(/6/4[chap01]!/4/2/1:12)",
+            "epub:1:0",
+            "epubcfi(/6/4!/4/2/1:4)",
+            "epubcfi(/6/2!/4/2)",
+        ];
+        locators.sort_by(|a, b| compare(&parse(a), &parse(b)));
+        assert_eq!(
+            locators,
+            [
+                "epubcfi(/6/2!/4/2)",
+                "epub:1:0",
+                "epubcfi(/6/4!/4/2/1:4)",
+                "epubcfi(/6/4[chap01]!/4/2/1:12)",
+                "epubcfi(/6/4!/4/10/1:3)",
+                "epubcfi(/6/6!/4/2/1:0)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use tauri::{Manager, Runtime};
+
+use crate::db::DbState;
+use crate::{book_search, books, settings};
+
+/// "auto" (the default: on for mobile builds), "on" or "off". Read when the database opens.
+pub const LOW_MEMORY_SETTING: &str = "low_memory_mode";
+
+/// SQLite page cache in low-memory mode, in KiB (SQLite's default is about 2 MiB)
+const LOW_MEMORY_CACHE_KIB: i64 = 512;
+
+/// Whether the app is running in low-memory mode, fixed for the run
+pub struct MemoryMode(pub bool);
+
+impl MemoryMode {
+    pub fn is_low(&self) -> bool {
+        self.0
+    }
+}
+
+/// Book index builds held back in low-memory mode until `build_indexes_now`
+#[derive(Default)]
+pub struct DeferredIndexes(pub Mutex<BTreeSet<i64>>);
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryModeName {
+    Standard,
+    Low,
+}
+
+pub fn low_memory_enabled(conn: &Connection) -> Result<bool> {
+    Ok(match settings::get(conn, LOW_MEMORY_SETTING)?.as_deref() {
+        Some("on") => true,
+        Some("off") => false,
+        _ => cfg!(mobile),
+    })
+}
+
+/// Shrink the connection's page cache when low-memory mode is on
+pub fn apply(conn: &Connection) -> Result<()> {
+    if low_memory_enabled(conn)? {
+        conn.pragma_update(None, "cache_size", -LOW_MEMORY_CACHE_KIB)?;
+    }
+    Ok(())
+}
+
+pub fn mode_name<R: Runtime>(app: &tauri::AppHandle<R>) -> MemoryModeName {
+    if app.state::<MemoryMode>().is_low() {
+        MemoryModeName::Low
+    } else {
+        MemoryModeName::Standard
+    }
+}
+
+/// Run the book index builds deferred by low-memory mode, e.g. once the device is idle or
+/// charging. Returns the ids of the books whose builds were started.
+#[tauri::command]
+pub fn build_indexes_now(
+    app: tauri::AppHandle,
+    deferred: tauri::State<DeferredIndexes>,
+) -> Result<Vec<i64>, String> {
+    let book_ids = std::mem::take(&mut *deferred.0.lock().unwrap());
+
+    let mut started = Vec::new();
+    for book_id in book_ids {
+        let book = {
+            let state = app.state::<DbState>();
+            let conn = state.0.lock().unwrap();
+            books::get_book(&conn, book_id).map_err(|e| e.to_string())?
+        };
+        // Books removed since the build was deferred are dropped
+        if let Some(book) = book {
+            book_search::start_build(&app, book)?;
+            started.push(book_id);
+        }
+    }
+    Ok(started)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, SearchOptions, SortOrder};
+    use rusqlite::params;
+
+    fn cache_size(conn: &Connection) -> i64 {
+        conn.pragma_query_value(None, "cache_size", |row| row.get(0))
+            .unwrap()
+    }
+
+    /// A dictionary several times the low-memory page cache, so searches evict pages
+    fn dictionary(low_memory: bool) -> Connection {
+        let conn = db::init_db(None).unwrap();
+        if low_memory {
+            settings::set(&conn, LOW_MEMORY_SETTING, "on").unwrap();
+            apply(&conn).unwrap();
+        }
+        let tx = conn.unchecked_transaction().unwrap();
+        for i in 0..4000 {
+            tx.execute(
+                "INSERT INTO dictionary (word, definition, pos, frequency) VALUES (?, ?, ?, ?)",
+                params![
+                    format!("word{:04}", i),
+                    format!("Definition {} {}", i, "padding ".repeat(60)),
+                    if i % 3 == 0 { "noun" } else { "verb" },
+                    i % 97,
+                ],
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+        conn
+    }
+
+    #[test]
+    fn low_memory_mode_is_opt_in_on_desktop() {
+        let conn = db::init_db(None).unwrap();
+        assert_eq!(low_memory_enabled(&conn).unwrap(), cfg!(mobile));
+        settings::set(&conn, LOW_MEMORY_SETTING, "off").unwrap();
+        assert!(!low_memory_enabled(&conn).unwrap());
+
+        let standard = cache_size(&conn);
+        settings::set(&conn, LOW_MEMORY_SETTING, "on").unwrap();
+        apply(&conn).unwrap();
+        assert_eq!(cache_size(&conn), -LOW_MEMORY_CACHE_KIB);
+        assert_ne!(standard, -LOW_MEMORY_CACHE_KIB);
+    }
+
+    #[test]
+    fn searches_match_with_low_memory_forced_on() {
+        let standard = dictionary(false);
+        let low = dictionary(true);
+        assert_eq!(cache_size(&low), -LOW_MEMORY_CACHE_KIB);
+
+        let searches = [
+            ("word0042", SearchOptions::default()),
+            ("word0042s", SearchOptions::default()),
+            ("word12", SearchOptions::default()),
+            (
+                "word3",
+                SearchOptions {
+                    pos: Some("noun".into()),
+                    sort: SortOrder::Frequency,
+                    ..Default::default()
+                },
+            ),
+            (
+                "word",
+                SearchOptions {
+                    min_length: Some(8),
+                    sort: SortOrder::Length,
+                    ..Default::default()
+                },
+            ),
+            ("missing", SearchOptions::default()),
+        ];
+        for (term, options) in searches {
+            let run = |conn: &Connection| {
+                let (results, exact) = db::search(conn, term, &options).unwrap();
+                let words: Vec<String> = results.into_iter().map(|e| e.word).collect();
+                (words, exact)
+            };
+            let expected = run(&standard);
+            assert_eq!(
+                expected.0.is_empty(),
+                term == "missing",
+                "searching {:?}",
+                term
+            );
+            assert_eq!(run(&low), expected, "searching {:?}", term);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::{Manager, Runtime};
+
+use crate::db::DbState;
+use crate::encryption::{self, EncryptionStatus};
+use crate::onboarding::{self, Population};
+use crate::read_only::{ReadOnly, Reason};
+use crate::repair::{self, BundledResource};
+
+/// Version of the layout created by `init_db`
+pub const SCHEMA_VERSION: i64 = 1;
+
+/// Origin given to rows carried over from the original single-table database, where
+/// bundled and user-added words weren't told apart
+const LEGACY_SOURCE: &str = "bundled-or-unknown";
+
+const LEGACY_MIGRATION_KEY: &str = "legacy_migration";
+
+/// A database from before schema versioning, already copied aside
+pub struct LegacyDatabase {
+    snapshot: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LegacyMigration {
+    migrated_at: i64,
+    entries: i64,
+    snapshot: String,
+}
+
+#[derive(Serialize)]
+pub struct DbStatus {
+    schema_version: Option<i64>,
+    legacy_migration: Option<LegacyMigration>,
+    encryption: EncryptionStatus,
+    /// Why the database is open read-only, if it is
+    read_only: Option<Reason>,
+    bundled_resource: BundledResource,
+    /// Where the first-run dictionary choice stands, for deciding whether to show onboarding
+    dictionary_population: Population,
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+/// First free `dictionary.legacy.db`, `dictionary.legacy-1.db`, ... next to the database
+fn snapshot_path(db_path: &Path) -> PathBuf {
+    let dir = db_path.parent().unwrap_or(Path::new("."));
+    let mut path = dir.join("dictionary.legacy.db");
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("dictionary.legacy-{}.db", n));
+        n += 1;
+    }
+    path
+}
+
+/// Spot a database written by the original release (a dictionary table but no `meta`) and
+/// copy it aside before anything touches its schema. Call before any table is created.
+pub fn detect_legacy(conn: &Connection) -> Result<Option<LegacyDatabase>> {
+    let Some(db_path) = conn.path().filter(|p| !p.is_empty()).map(PathBuf::from) else {
+        return Ok(None);
+    };
+    if table_exists(conn, "meta")? || !table_exists(conn, "dictionary")? {
+        return Ok(None);
+    }
+
+    let snapshot = snapshot_path(&db_path);
+    conn.execute("VACUUM INTO ?", params![snapshot.to_string_lossy()])?;
+    Ok(Some(LegacyDatabase { snapshot }))
+}
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn set_meta(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES (?, ?)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+pub fn get_meta(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM meta WHERE key = ?",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Map legacy rows into the v1 layout. The tables and columns already exist by now; what's
+/// left is marking where the old rows came from and recording the outcome.
+fn migrate_legacy(conn: &Connection, legacy: &LegacyDatabase) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let entries = tx.execute(
+        "UPDATE dictionary SET source = ?, word = lower(word)",
+        params![LEGACY_SOURCE],
+    )?;
+
+    let outcome = LegacyMigration {
+        migrated_at: tx.query_row("SELECT unixepoch()", [], |row| row.get(0))?,
+        entries: entries as i64,
+        snapshot: legacy.snapshot.to_string_lossy().into_owned(),
+    };
+    set_meta(
+        &tx,
+        LEGACY_MIGRATION_KEY,
+        &serde_json::to_string(&outcome).unwrap_or_default(),
+    )?;
+    set_meta(&tx, "schema_version", &SCHEMA_VERSION.to_string())?;
+    tx.commit()?;
+
+    println!(
+        "Migrated {} entries from the legacy database; original kept at {}",
+        outcome.entries, outcome.snapshot
+    );
+    Ok(())
+}
+
+/// Record the schema version, migrating a legacy database first. Call after every table
+/// has been created.
+pub fn finish(conn: &Connection, legacy: Option<LegacyDatabase>) -> Result<()> {
+    match legacy {
+        Some(legacy) => migrate_legacy(conn, &legacy),
+        None => {
+            if get_meta(conn, "schema_version")?.is_none() {
+                set_meta(conn, "schema_version", &SCHEMA_VERSION.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_db_status<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: tauri::State<DbState>,
+) -> Result<DbStatus, String> {
+    let conn = state.0.lock().unwrap();
+    let schema_version = get_meta(&conn, "schema_version")
+        .map_err(|e| e.to_string())?
+        .and_then(|v| v.parse().ok());
+    let legacy_migration = get_meta(&conn, LEGACY_MIGRATION_KEY)
+        .map_err(|e| e.to_string())?
+        .and_then(|json| serde_json::from_str(&json).ok());
+    let bundled_resource = repair::bundled_status(&app, &conn).map_err(|e| e.to_string())?;
+    let dictionary_population = onboarding::population(&conn).map_err(|e| e.to_string())?;
+
+    Ok(DbStatus {
+        schema_version,
+        legacy_migration,
+        encryption: encryption::status(&app, &state),
+        read_only: app.state::<ReadOnly>().0,
+        bundled_resource,
+        dictionary_population,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::fs;
+
+    fn entries(conn: &Connection) -> Vec<(i64, String, String)> {
+        let mut stmt = conn
+            .prepare("SELECT id, word, definition FROM dictionary ORDER BY id")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        rows.collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn legacy_database_is_migrated_without_losing_entries() {
+        let dir = std::env::temp_dir().join(format!("open-read-legacy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dictionary.db");
+        let _ = fs::remove_file(&path);
+
+        let original = {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(include_str!("../tests/fixtures/legacy/dictionary_v0.sql"))
+                .unwrap();
+            entries(&conn)
+        };
+
+        let conn = db::prepare(Connection::open(&path).unwrap()).unwrap();
+        let migrated = entries(&conn);
+        assert_eq!(migrated.len(), original.len());
+        for ((id, word, definition), (new_id, new_word, new_definition)) in
+            original.iter().zip(&migrated)
+        {
+            assert_eq!(new_id, id);
+            assert_eq!(new_word, &word.to_lowercase());
+            assert_eq!(new_definition, definition);
+        }
+        let sources: Vec<String> = conn
+            .prepare("SELECT DISTINCT source FROM dictionary")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(sources, [LEGACY_SOURCE]);
+
+        assert_eq!(
+            get_meta(&conn, "schema_version").unwrap(),
+            Some(SCHEMA_VERSION.to_string())
+        );
+        let outcome: LegacyMigration =
+            serde_json::from_str(&get_meta(&conn, LEGACY_MIGRATION_KEY).unwrap().unwrap()).unwrap();
+        assert_eq!(outcome.entries, original.len() as i64);
+
+        // The untouched original is kept beside the database
+        let snapshot = PathBuf::from(&outcome.snapshot);
+        assert_eq!(snapshot, dir.join("dictionary.legacy.db"));
+        let kept = Connection::open(&snapshot).unwrap();
+        assert_eq!(entries(&kept), original);
+        assert!(!table_exists(&kept, "meta").unwrap());
+        drop((conn, kept));
+
+        // Opening again finds a versioned database and leaves it alone
+        let conn = db::prepare(Connection::open(&path).unwrap()).unwrap();
+        assert_eq!(entries(&conn), migrated);
+        assert!(!dir.join("dictionary.legacy-1.db").exists());
+        drop(conn);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_databases_are_not_legacy() {
+        let conn = db::init_db(None).unwrap();
+        assert_eq!(
+            get_meta(&conn, "schema_version").unwrap(),
+            Some(SCHEMA_VERSION.to_string())
+        );
+        assert_eq!(get_meta(&conn, LEGACY_MIGRATION_KEY).unwrap(), None);
+    }
+}
@@ -0,0 +1,139 @@
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+
+use crate::db::{self, DbState};
+
+const DEFAULT_LIMIT: i64 = 50;
+
+/// Source of entries the user added themselves
+pub const USER_SOURCE: &str = "user";
+
+#[derive(Serialize)]
+pub struct MissedWord {
+    word: String,
+    /// Lookups that came back empty
+    count: i64,
+    last_tried_at: i64,
+}
+
+/// Words the active profile looked up that the dictionary never had anything for. A word
+/// the fallback found a close match for is left out, as it's more likely a typo or an
+/// inflection than a missing entry, and so is a word that has an entry by now.
+fn missed(conn: &Connection, limit: i64, min_count: i64) -> Result<Vec<MissedWord>> {
+    let mut stmt = conn.prepare(
+        "SELECT lower(h.word), COUNT(*), MAX(h.looked_up_at) FROM lookup_history h
+         WHERE h.profile_id = (SELECT id FROM temp.current_profile)
+         GROUP BY lower(h.word)
+         HAVING SUM(h.found AND h.fallback_found IS NOT 1) = 0
+            AND SUM(h.fallback_found IS 1) = 0
+            AND COUNT(*) >= ?1
+            AND NOT EXISTS (SELECT 1 FROM dictionary d WHERE d.word = lower(h.word))
+         ORDER BY COUNT(*) DESC, MAX(h.looked_up_at) DESC
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![min_count, limit], |row| {
+        Ok(MissedWord {
+            word: row.get(0)?,
+            count: row.get(1)?,
+            last_tried_at: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[tauri::command]
+pub fn get_missed_words(
+    limit: Option<i64>,
+    min_count: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<MissedWord>, String> {
+    let conn = state.user_data()?;
+    missed(
+        &conn,
+        limit.unwrap_or(DEFAULT_LIMIT),
+        min_count.unwrap_or(1).max(1),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Add the user's own entry for a missed word. Having an entry takes the word off the
+/// missed list; its lookup history stays as it was.
+#[tauri::command]
+pub fn add_missing_word(
+    word: &str,
+    definition: &str,
+    state: tauri::State<DbState>,
+) -> Result<i64, String> {
+    let word = db::normalize(word);
+    let definition = definition.trim();
+    if word.is_empty() || definition.is_empty() {
+        return Err("A word and a definition are both needed".to_string());
+    }
+
+    let conn = state.user_data()?;
+    conn.execute(
+        "INSERT INTO dictionary (word, definition, source) VALUES (?, ?, ?)",
+        params![word, definition, USER_SOURCE],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_lookups::SessionLookups;
+    use crate::source_stats::SourceCounters;
+
+    #[test]
+    fn searches_that_find_nothing_are_missed() {
+        let conn = db::init_db(None).unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('whale', 'A large sea mammal.')",
+            [],
+        )
+        .unwrap();
+        let state = DbState::new(conn, false);
+        let session = SessionLookups::default();
+        let counters = SourceCounters::default();
+        let search = |word: &str| {
+            db::search_from("main", word, None, &state, &session, &counters).unwrap();
+        };
+
+        search("whale");
+        search("Cetology");
+        search("cetology");
+        search("  ");
+
+        let conn = state.user_data().unwrap();
+        let missed = missed(&conn, DEFAULT_LIMIT, 1).unwrap();
+        let words: Vec<_> = missed.iter().map(|m| (m.word.as_str(), m.count)).collect();
+        assert_eq!(words, [("cetology", 2)]);
+    }
+
+    #[test]
+    fn searches_while_locked_leave_no_history() {
+        let conn = db::init_db(None).unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('whale', 'A large sea mammal.')",
+            [],
+        )
+        .unwrap();
+        let state = DbState::new(conn, true);
+        db::search_from(
+            "main",
+            "cetology",
+            None,
+            &state,
+            &SessionLookups::default(),
+            &SourceCounters::default(),
+        )
+        .unwrap();
+
+        let conn = state.0.lock().unwrap();
+        let lookups: i64 = conn
+            .query_row("SELECT COUNT(*) FROM lookup_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(lookups, 0);
+    }
+}
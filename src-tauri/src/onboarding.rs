@@ -0,0 +1,234 @@
+use rusqlite::{Connection, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::db::{self, DbState};
+use crate::{migrations, repair, source_reports};
+
+/// Where the first-run dictionary choice stands, kept in `meta` so that an empty dictionary
+/// on a later launch isn't mistaken for a new install
+const POPULATION_KEY: &str = "dictionary_population";
+
+/// Source of the entries a downloaded pack adds next to the bundled dictionary
+const PACK_SOURCE: &str = "pack";
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Population {
+    /// New database; the onboarding choice hasn't been made
+    Pending,
+    /// The user chose to populate later
+    Deferred,
+    Populated,
+}
+
+impl Population {
+    fn as_str(self) -> &'static str {
+        match self {
+            Population::Pending => "pending",
+            Population::Deferred => "deferred",
+            Population::Populated => "populated",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(Population::Pending),
+            "deferred" => Some(Population::Deferred),
+            "populated" => Some(Population::Populated),
+            _ => None,
+        }
+    }
+}
+
+/// The dictionaries offered on first launch
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DictionaryChoice {
+    /// The small programming dictionary shipped with the app, available offline
+    Bundled,
+    /// The full dictionary pack, downloaded in place of the bundled one
+    Download,
+    /// The bundled dictionary with the downloaded pack added alongside
+    Both,
+    /// Nothing for now; search reports an empty dictionary until a choice is made
+    Later,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PopulationOutcome {
+    population: Population,
+    entries: i64,
+}
+
+pub fn has_entries(conn: &Connection) -> Result<bool> {
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM dictionary)", [], |row| {
+        row.get(0)
+    })
+}
+
+pub fn population(conn: &Connection) -> Result<Population> {
+    Ok(migrations::get_meta(conn, POPULATION_KEY)?
+        .and_then(|value| Population::parse(&value))
+        .unwrap_or(Population::Pending))
+}
+
+fn set_population(conn: &Connection, population: Population) -> Result<()> {
+    migrations::set_meta(conn, POPULATION_KEY, population.as_str())
+}
+
+/// Record where a database stands the first time this version opens it: one that already
+/// has entries came from an earlier release and counts as populated, anything else waits
+/// for the onboarding choice. Once recorded, the state only changes through
+/// `populate_initial_dictionary`, so an emptied dictionary is never refilled behind the
+/// user's back.
+pub fn settle(conn: &Connection) -> Result<()> {
+    if migrations::get_meta(conn, POPULATION_KEY)?.is_some() {
+        return Ok(());
+    }
+    let population = if has_entries(conn)? {
+        Population::Populated
+    } else {
+        Population::Pending
+    };
+    set_population(conn, population)
+}
+
+/// Load the bundled dictionary straight away, for connections with no onboarding of their
+/// own such as the stand-in used while an encrypted database is locked
+pub fn populate_bundled(conn: &Connection, app: &tauri::AppHandle) -> Result<()> {
+    db::load_dictionary_data(conn, Some(app))?;
+    set_population(conn, Population::Populated)
+}
+
+/// Onboarding only fills a dictionary that's pending or was put off
+fn check_unpopulated(conn: &Connection) -> Result<(), String> {
+    if population(conn).map_err(|e| e.to_string())? == Population::Populated {
+        return Err(
+            "The dictionary is already populated; use repair_dictionary to replace it".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Record the outcome of the onboarding choice
+fn record(conn: &Connection, population: Population) -> Result<PopulationOutcome, String> {
+    set_population(conn, population).map_err(|e| e.to_string())?;
+    Ok(PopulationOutcome {
+        population,
+        entries: conn
+            .query_row("SELECT COUNT(*) FROM dictionary", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?,
+    })
+}
+
+fn populate(app: &tauri::AppHandle, choice: DictionaryChoice) -> Result<PopulationOutcome, String> {
+    let state = app.state::<DbState>();
+    check_unpopulated(&state.0.lock().unwrap())?;
+
+    // Each download finishes before the connection is taken, so a failed one leaves the
+    // dictionary untouched and searches keep running meanwhile
+    let population = match choice {
+        DictionaryChoice::Later => Population::Deferred,
+        DictionaryChoice::Bundled => {
+            let conn = state.0.lock().unwrap();
+            db::load_dictionary_data(&conn, Some(app)).map_err(|e| e.to_string())?;
+            Population::Populated
+        }
+        DictionaryChoice::Download => {
+            let (json, _) = repair::download_pack(app)?;
+            db::replace_bundled(&state.0.lock().unwrap(), &json)?;
+            Population::Populated
+        }
+        DictionaryChoice::Both => {
+            let (json, _) = repair::download_pack(app)?;
+            let conn = state.0.lock().unwrap();
+            // The pack first, so one that doesn't parse stops things before anything loads
+            db::add_pack(&conn, &json, PACK_SOURCE)?;
+            db::load_dictionary_data(&conn, Some(app)).map_err(|e| e.to_string())?;
+            Population::Populated
+        }
+    };
+
+    let outcome = record(&state.0.lock().unwrap(), population)?;
+    if population == Population::Populated {
+        let _ = app.emit("dictionary-populated", outcome.clone());
+        source_reports::analyze_all_in_background(app);
+    }
+    Ok(outcome)
+}
+
+/// Fill the dictionary of a new install with the user's onboarding choice, or record that
+/// they'll do it later. Works from either of those states; a populated dictionary is
+/// replaced through `repair_dictionary` instead.
+#[tauri::command]
+pub async fn populate_initial_dictionary(
+    choice: DictionaryChoice,
+    app: tauri::AppHandle,
+) -> Result<PopulationOutcome, String> {
+    tauri::async_runtime::spawn_blocking(move || populate(&app, choice))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SearchOptions;
+
+    #[test]
+    fn populating_later_leaves_an_empty_dictionary_that_can_still_be_filled() {
+        let conn = db::init_db(None).unwrap();
+        assert!(population(&conn).unwrap() == Population::Pending);
+
+        check_unpopulated(&conn).unwrap();
+        let outcome = record(&conn, Population::Deferred).unwrap();
+        assert!(outcome.population == Population::Deferred);
+        assert_eq!(outcome.entries, 0);
+
+        // Later launches keep the choice rather than treating the database as new
+        settle(&conn).unwrap();
+        assert!(population(&conn).unwrap() == Population::Deferred);
+        assert!(!has_entries(&conn).unwrap());
+        let (results, _) = db::search(&conn, "algorithm", &SearchOptions::default()).unwrap();
+        assert!(results.is_empty());
+
+        // The choice can be made again from settings
+        check_unpopulated(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('algorithm', 'A procedure.')",
+            [],
+        )
+        .unwrap();
+        let outcome = record(&conn, Population::Populated).unwrap();
+        assert_eq!(outcome.entries, 1);
+        assert_eq!(
+            check_unpopulated(&conn).err().as_deref(),
+            Some("The dictionary is already populated; use repair_dictionary to replace it")
+        );
+    }
+
+    #[test]
+    fn settling_counts_existing_entries_as_populated_once() {
+        let conn = db::init_db(None).unwrap();
+        migrations::set_meta(&conn, POPULATION_KEY, "unknown").unwrap();
+        // An unreadable value is treated as pending
+        assert!(population(&conn).unwrap() == Population::Pending);
+
+        let conn = db::init_db(None).unwrap();
+        conn.execute("DELETE FROM meta WHERE key = ?", [POPULATION_KEY])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('cache', 'A store.')",
+            [],
+        )
+        .unwrap();
+        settle(&conn).unwrap();
+        assert!(population(&conn).unwrap() == Population::Populated);
+
+        // Emptied afterwards, it isn't mistaken for a new install
+        conn.execute("DELETE FROM dictionary", []).unwrap();
+        settle(&conn).unwrap();
+        assert!(population(&conn).unwrap() == Population::Populated);
+    }
+}
@@ -0,0 +1,435 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, Manager, Runtime};
+use url::Url;
+
+use crate::books::{self, Book};
+use crate::db::DbState;
+use crate::{analytics, settings};
+
+const EPUB_MIME: &str = "application/epub+zip";
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+pub const USER_AGENT: &str = concat!("open_read/", env!("CARGO_PKG_VERSION"));
+
+/// Emit a progress event roughly every this many bytes
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+#[derive(Serialize, Default)]
+pub struct OpdsFeed {
+    title: Option<String>,
+    entries: Vec<OpdsEntry>,
+    next: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct OpdsEntry {
+    id: Option<String>,
+    title: String,
+    author: Option<String>,
+    summary: Option<String>,
+    /// Link to a sub-catalog, for navigation feeds
+    navigation: Option<String>,
+    acquisitions: Vec<OpdsAcquisition>,
+}
+
+#[derive(Serialize)]
+pub struct OpdsAcquisition {
+    href: String,
+    mime_type: Option<String>,
+    /// Only EPUB acquisitions can be downloaded into the library
+    supported: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct DownloadProgress {
+    url: String,
+    received: u64,
+    total: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Title,
+    AuthorName,
+    Id,
+    Summary,
+}
+
+struct Link {
+    rel: Option<String>,
+    href: Option<String>,
+    mime_type: Option<String>,
+}
+
+fn parse_link(tag: &BytesStart) -> Link {
+    let mut link = Link {
+        rel: None,
+        href: None,
+        mime_type: None,
+    };
+    for attr in tag.attributes().flatten() {
+        let value = attr
+            .unescape_value()
+            .map(|v| v.into_owned())
+            .unwrap_or_default();
+        match attr.key.local_name().as_ref() {
+            b"rel" => link.rel = Some(value),
+            b"href" => link.href = Some(value),
+            b"type" => link.mime_type = Some(value),
+            _ => {}
+        }
+    }
+    link
+}
+
+fn is_epub(mime_type: Option<&str>, href: &str) -> bool {
+    let declared = mime_type
+        .and_then(|mime| mime.split(';').next())
+        .is_some_and(|mime| mime.trim() == EPUB_MIME);
+    declared || href.to_lowercase().ends_with(".epub")
+}
+
+fn apply_link(link: Link, base: &Url, feed: &mut OpdsFeed, entry: Option<&mut OpdsEntry>) {
+    let Some(href) = link.href.and_then(|h| base.join(&h).ok()).map(String::from) else {
+        return;
+    };
+    let rel = link.rel.unwrap_or_default();
+
+    match entry {
+        Some(entry) => {
+            if rel.starts_with(ACQUISITION_REL) {
+                entry.acquisitions.push(OpdsAcquisition {
+                    supported: is_epub(link.mime_type.as_deref(), &href),
+                    href,
+                    mime_type: link.mime_type,
+                });
+            } else if link
+                .mime_type
+                .as_deref()
+                .is_some_and(|t| t.contains("application/atom+xml"))
+                && entry.navigation.is_none()
+            {
+                entry.navigation = Some(href);
+            }
+        }
+        None => {
+            if rel == "next" {
+                feed.next = Some(href);
+            }
+        }
+    }
+}
+
+/// Parse an OPDS 1.x Atom feed, resolving every link against `base_url`
+pub fn parse_feed(xml: &str, base_url: &str) -> Result<OpdsFeed, String> {
+    let base = Url::parse(base_url).map_err(|e| e.to_string())?;
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut feed = OpdsFeed::default();
+    let mut entry: Option<OpdsEntry> = None;
+    let mut field: Option<Field> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(tag)) => {
+                match tag.local_name().as_ref() {
+                    b"entry" => entry = Some(OpdsEntry::default()),
+                    b"title" => field = Some(Field::Title),
+                    b"name" => field = Some(Field::AuthorName),
+                    b"id" => field = Some(Field::Id),
+                    b"summary" | b"content" => field = Some(Field::Summary),
+                    b"link" => apply_link(parse_link(&tag), &base, &mut feed, entry.as_mut()),
+                    _ => {}
+                }
+                text.clear();
+            }
+            Ok(Event::Empty(tag)) if tag.local_name().as_ref() == b"link" => {
+                apply_link(parse_link(&tag), &base, &mut feed, entry.as_mut());
+            }
+            Ok(Event::Text(t)) if field.is_some() => {
+                text.push_str(&t.unescape().map_err(|e| e.to_string())?);
+            }
+            Ok(Event::CData(t)) if field.is_some() => {
+                text.push_str(&String::from_utf8_lossy(&t));
+            }
+            Ok(Event::End(tag)) => {
+                let name = tag.local_name();
+                if name.as_ref() == b"entry" {
+                    if let Some(done) = entry.take() {
+                        feed.entries.push(done);
+                    }
+                } else if let Some(current) = field.take() {
+                    let value = text.trim().to_string();
+                    match (current, entry.as_mut()) {
+                        (Field::Title, Some(e)) => e.title = value,
+                        (Field::Title, None) => feed.title = Some(value),
+                        (Field::AuthorName, Some(e)) if e.author.is_none() => {
+                            e.author = Some(value)
+                        }
+                        (Field::Id, Some(e)) => e.id = Some(value),
+                        (Field::Summary, Some(e)) if e.summary.is_none() => e.summary = Some(value),
+                        _ => {}
+                    }
+                }
+                text.clear();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(format!(
+                    "Invalid OPDS feed at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(feed)
+}
+
+pub fn ensure_online<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<(), String> {
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+    if settings::online_enabled(&conn) {
+        Ok(())
+    } else {
+        Err("Online features are turned off in settings".to_string())
+    }
+}
+
+/// Directory managed by the app for downloaded books
+pub fn books_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("books");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// Pick a file name from the URL that doesn't clash with an existing download
+fn target_path(dir: &Path, url: &Url) -> PathBuf {
+    let stem: String = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .map(|name| name.trim_end_matches(".epub"))
+        .filter(|name| !name.is_empty())
+        .unwrap_or("book")
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut path = dir.join(format!("{}.epub", stem));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.epub", stem, n));
+        n += 1;
+    }
+    path
+}
+
+fn download(
+    app: &tauri::AppHandle,
+    acquisition_url: &str,
+    title: Option<String>,
+    author: Option<String>,
+) -> Result<Book, String> {
+    let url = Url::parse(acquisition_url).map_err(|e| e.to_string())?;
+    let response = ureq::get(url.as_str())
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|e| e.to_string())?;
+
+    let content_type = response.header("Content-Type").map(str::to_string);
+    if !is_epub(content_type.as_deref(), url.path()) {
+        return Err(format!(
+            "This download is {}, but only EPUB books can be added to the library",
+            content_type.as_deref().unwrap_or("an unknown file type")
+        ));
+    }
+
+    let total = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok());
+
+    let path = target_path(&books_dir(app)?, &url);
+    let partial = path.with_extension("epub.part");
+    let mut file = File::create(&partial).map_err(|e| e.to_string())?;
+    let mut reader = response.into_reader();
+    let mut buffer = [0u8; 64 * 1024];
+    let mut received = 0u64;
+    let mut last_emitted = 0u64;
+
+    let copied = loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) => break Err(e.to_string()),
+        };
+        if let Err(e) = file.write_all(&buffer[..n]) {
+            break Err(e.to_string());
+        }
+        received += n as u64;
+        if received - last_emitted >= PROGRESS_STEP {
+            last_emitted = received;
+            let _ = app.emit(
+                "opds-download-progress",
+                DownloadProgress {
+                    url: acquisition_url.to_string(),
+                    received,
+                    total,
+                },
+            );
+        }
+    };
+
+    // Only a fully written file is moved into the library
+    if let Err(e) = copied.and_then(|_| file.sync_all().map_err(|e| e.to_string())) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    drop(file);
+    fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+
+    let _ = app.emit(
+        "opds-download-progress",
+        DownloadProgress {
+            url: acquisition_url.to_string(),
+            received,
+            total: Some(received),
+        },
+    );
+
+    let state = app.state::<DbState>();
+    let conn = state.user_data()?;
+    let book = books::insert_book(&conn, &path.to_string_lossy(), title, author, None)
+        .map_err(|e| e.to_string())?;
+    analytics::record_event(
+        &conn,
+        "opds_download",
+        serde_json::json!({ "bytes": received }),
+    );
+    Ok(book)
+}
+
+#[tauri::command]
+pub async fn browse_opds<R: Runtime>(
+    url: String,
+    app: tauri::AppHandle<R>,
+) -> Result<OpdsFeed, String> {
+    ensure_online(&app)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let body = ureq::get(&url)
+            .set("User-Agent", USER_AGENT)
+            .set("Accept", "application/atom+xml, application/xml;q=0.9")
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())?;
+        parse_feed(&body, &url)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Download an EPUB acquisition link into the managed books directory and add it to the
+/// library under the feed entry's title and author; without them the file name is used
+#[tauri::command]
+pub async fn download_opds_entry(
+    acquisition_url: String,
+    title: Option<String>,
+    author: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<Book, String> {
+    ensure_online(&app)?;
+
+    tauri::async_runtime::spawn_blocking(move || download(&app, &acquisition_url, title, author))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://standardebooks.org/feeds/opds/new-releases";
+
+    #[test]
+    fn acquisition_feed_lists_entries_and_next_page() {
+        let feed =
+            parse_feed(include_str!("../tests/fixtures/opds/acquisition.xml"), BASE).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Newest Standard Ebooks"));
+        assert_eq!(
+            feed.next.as_deref(),
+            Some("https://standardebooks.org/feeds/opds/new-releases?page=2")
+        );
+        assert_eq!(feed.entries.len(), 2);
+
+        let persuasion = &feed.entries[0];
+        assert_eq!(persuasion.title, "Persuasion");
+        // The first author is the one shown
+        assert_eq!(persuasion.author.as_deref(), Some("Jane Austen"));
+        assert_eq!(
+            persuasion.summary.as_deref(),
+            Some("A story of & second chances.")
+        );
+        // Images aren't acquisitions; relative links are resolved against the feed
+        assert_eq!(persuasion.acquisitions.len(), 2);
+        assert_eq!(
+            persuasion.acquisitions[0].href,
+            "https://standardebooks.org/ebooks/jane-austen/persuasion/downloads/jane-austen_persuasion.epub"
+        );
+        assert!(persuasion.acquisitions[0].supported);
+        assert!(!persuasion.acquisitions[1].supported);
+
+        let pride = &feed.entries[1];
+        assert_eq!(pride.title, "Pride & Prejudice");
+        assert_eq!(pride.summary.as_deref(), Some("First published in 1813."));
+        // A MIME type with parameters is still EPUB
+        assert!(pride.acquisitions[0].supported);
+    }
+
+    #[test]
+    fn navigation_feed_links_sub_catalogs() {
+        let feed = parse_feed(
+            include_str!("../tests/fixtures/opds/navigation.xml"),
+            "https://example.org/opds/root.xml",
+        )
+        .unwrap();
+        assert!(feed.next.is_none());
+        assert_eq!(feed.entries.len(), 1);
+        assert_eq!(
+            feed.entries[0].navigation.as_deref(),
+            Some("https://example.org/opds/popular.xml")
+        );
+        assert!(feed.entries[0].acquisitions.is_empty());
+    }
+
+    #[test]
+    fn malformed_feeds_are_rejected() {
+        assert!(parse_feed("<feed><entry></feed>", BASE).is_err());
+        assert!(parse_feed("<feed/>", "not a url").is_err());
+    }
+
+    #[test]
+    fn only_epub_downloads_are_supported() {
+        assert!(is_epub(Some("application/epub+zip"), "/book"));
+        assert!(is_epub(None, "/Book.EPUB"));
+        assert!(!is_epub(Some("application/pdf"), "/book.pdf"));
+    }
+}
@@ -0,0 +1,451 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use tauri::{Emitter, Manager, Runtime};
+
+use crate::db::{self, DbState};
+use crate::{opds, settings, source_reports};
+
+/// Where the list of available pack releases comes from: a URL, fetched only while online
+/// features are on, or the path of a catalog file on disk
+pub const CATALOG_SETTING: &str = "pack_catalog_url";
+const DEFAULT_CATALOG_URL: &str =
+    "https://github.com/HarshalPatel1972/open_read/releases/latest/download/packs.json";
+
+/// One release in the catalog. `id` is the dictionary source the pack installs as, so the
+/// bundled dictionary is updated through the entry with id "bundled".
+#[derive(Deserialize)]
+struct CatalogPack {
+    id: String,
+    version: String,
+    /// URL or file path of the pack's dictionary JSON
+    url: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    changelog: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Catalog {
+    packs: Vec<CatalogPack>,
+}
+
+#[derive(Serialize)]
+pub struct PackUpdate {
+    id: String,
+    /// `None` for a pack installed before versions were recorded
+    installed_version: Option<String>,
+    version: String,
+    /// Download size in bytes, when the catalog gives one
+    size: Option<u64>,
+    changelog: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum UpdateStage {
+    Downloading,
+    Importing,
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+struct UpdateProgress {
+    id: String,
+    stage: UpdateStage,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UpdateReport {
+    id: String,
+    previous_version: Option<String>,
+    version: String,
+    /// Entries of the old release, all removed
+    removed: i64,
+    /// Entries of the new release
+    imported: usize,
+    /// Entry tags moved onto the new release's entries
+    tags_kept: usize,
+}
+
+/// Installed release of each dictionary source, for sources whose file named one
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS source_versions (
+            source TEXT PRIMARY KEY,
+            version TEXT NOT NULL,
+            installed_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Store the release a source's content came from, or forget the old one when the new
+/// content doesn't say
+pub fn record_version(conn: &Connection, source: &str, version: Option<&str>) -> Result<()> {
+    match version {
+        Some(version) => conn.execute(
+            "INSERT INTO source_versions (source, version, installed_at)
+             VALUES (?, ?, unixepoch())
+             ON CONFLICT(source) DO UPDATE SET
+                version = excluded.version, installed_at = excluded.installed_at",
+            params![source, version],
+        )?,
+        None => conn.execute(
+            "DELETE FROM source_versions WHERE source = ?",
+            params![source],
+        )?,
+    };
+    Ok(())
+}
+
+fn installed_version(conn: &Connection, source: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT version FROM source_versions WHERE source = ?")?;
+    let mut rows = stmt.query_map(params![source], |row| row.get(0))?;
+    rows.next().transpose()
+}
+
+/// Whether entries are installed under `source`. The fallback words count as the bundled
+/// dictionary, which they stand in for.
+fn is_installed(conn: &Connection, source: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM dictionary
+                       WHERE source = ?1 OR (?1 = 'bundled' AND source = 'fallback'))",
+        params![source],
+        |row| row.get(0),
+    )
+}
+
+/// Whether `candidate` is a later release than `installed`. Dotted numbers ("1.10.2")
+/// compare part by part; anything else counts as newer whenever it differs.
+fn is_newer(candidate: &str, installed: &str) -> bool {
+    let parts = |v: &str| {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|p| p.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match (parts(candidate), parts(installed)) {
+        (Ok(candidate), Ok(installed)) => candidate > installed,
+        _ => candidate != installed,
+    }
+}
+
+/// Read a catalog or pack from a URL, when online features are on, or from a file path
+fn fetch<R: Runtime>(app: &tauri::AppHandle<R>, location: &str) -> Result<String, String> {
+    if !(location.starts_with("http://") || location.starts_with("https://")) {
+        return fs::read_to_string(location).map_err(|e| format!("{}: {}", location, e));
+    }
+    opds::ensure_online(app)?;
+    let response = ureq::get(location)
+        .set("User-Agent", opds::USER_AGENT)
+        .call()
+        .map_err(|e| e.to_string())?;
+    let mut body = String::new();
+    response
+        .into_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| e.to_string())?;
+    Ok(body)
+}
+
+fn load_catalog<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<Vec<CatalogPack>, String> {
+    let location = {
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        settings::get(&conn, CATALOG_SETTING)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_else(|| DEFAULT_CATALOG_URL.to_string())
+    };
+    let catalog: Catalog = serde_json::from_str(&fetch(app, &location)?)
+        .map_err(|e| format!("The pack catalog doesn't parse: {}", e))?;
+    Ok(catalog.packs)
+}
+
+fn check<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<Vec<PackUpdate>, String> {
+    let catalog = load_catalog(app)?;
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+
+    let mut updates = Vec::new();
+    for pack in catalog {
+        if !is_installed(&conn, &pack.id).map_err(|e| e.to_string())? {
+            continue;
+        }
+        let installed = installed_version(&conn, &pack.id).map_err(|e| e.to_string())?;
+        if installed
+            .as_deref()
+            .is_none_or(|installed| is_newer(&pack.version, installed))
+        {
+            updates.push(PackUpdate {
+                id: pack.id,
+                installed_version: installed,
+                version: pack.version,
+                size: pack.size,
+                changelog: pack.changelog,
+            });
+        }
+    }
+    Ok(updates)
+}
+
+fn update<R: Runtime>(app: &tauri::AppHandle<R>, id: &str) -> Result<UpdateReport, String> {
+    let emit = |stage: UpdateStage| {
+        let _ = app.emit(
+            "pack-update-progress",
+            UpdateProgress {
+                id: id.to_string(),
+                stage,
+                error: None,
+            },
+        );
+    };
+
+    let pack = load_catalog(app)?
+        .into_iter()
+        .find(|pack| pack.id == id)
+        .ok_or_else(|| format!("The pack catalog has no pack named {}", id))?;
+
+    // The download finishes before the connection is taken, so the installed pack keeps
+    // answering searches meanwhile and is untouched if the download fails
+    emit(UpdateStage::Downloading);
+    let json = fetch(app, &pack.url)?;
+
+    emit(UpdateStage::Importing);
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+    let previous_version = installed_version(&conn, id).map_err(|e| e.to_string())?;
+    let removed: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM dictionary
+             WHERE source = ?1 OR (?1 = 'bundled' AND source = 'fallback')",
+            params![id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let (imported, tags_kept) = db::swap_source(&conn, &json, id)?;
+    // A file that doesn't name its release is recorded as the one the catalog offered
+    if installed_version(&conn, id)
+        .map_err(|e| e.to_string())?
+        .is_none()
+    {
+        record_version(&conn, id, Some(&pack.version)).map_err(|e| e.to_string())?;
+    }
+
+    Ok(UpdateReport {
+        id: id.to_string(),
+        previous_version,
+        version: pack.version,
+        removed,
+        imported,
+        tags_kept,
+    })
+}
+
+/// Installed packs, the bundled dictionary included, with a later release in the catalog
+#[tauri::command]
+pub async fn check_pack_updates<R: Runtime>(
+    app: tauri::AppHandle<R>,
+) -> Result<Vec<PackUpdate>, String> {
+    tauri::async_runtime::spawn_blocking(move || check(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Download the catalog's release of a pack and swap it in for the installed one, keeping
+/// the tags on its entries. Reports "pack-update-progress" events; a failed download or
+/// import leaves the installed pack as it was. The new release is analyzed afterwards.
+#[tauri::command]
+pub async fn update_pack<R: Runtime>(
+    id: String,
+    app: tauri::AppHandle<R>,
+) -> Result<UpdateReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let outcome = update(&app, &id);
+        let (stage, error) = match &outcome {
+            Ok(_) => {
+                source_reports::analyze_in_background(&app, &id);
+                (UpdateStage::Completed, None)
+            }
+            Err(e) => (UpdateStage::Failed, Some(e.clone())),
+        };
+        let _ = app.emit("pack-update-progress", UpdateProgress { id, stage, error });
+        outcome
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::senses;
+
+    const V1_0: &str = include_str!("../tests/fixtures/packs/wordnet-1.0.json");
+    const V1_1: &str = include_str!("../tests/fixtures/packs/wordnet-1.1.json");
+
+    fn id_of(conn: &Connection, word: &str, definition: &str) -> i64 {
+        conn.query_row(
+            "SELECT id FROM dictionary WHERE word = ? AND definition = ?",
+            params![word, definition],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn tagged(conn: &Connection, tag: &str) -> Vec<(String, String)> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT d.word, d.source FROM entry_tags t JOIN dictionary d ON d.id = t.entry_id
+                 WHERE t.tag = ? ORDER BY d.word, d.id",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map(params![tag], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.collect::<Result<_>>().unwrap()
+    }
+
+    /// The 1.0 pack with a user's tags, sense order and known words on it
+    fn installed() -> Connection {
+        let conn = db::init_db(None).unwrap();
+        db::swap_source(&conn, V1_0, "wordnet").unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition, source) VALUES ('whale', 'Mine.', 'user')",
+            [],
+        )
+        .unwrap();
+        let mammal = id_of(&conn, "whale", "A large marine mammal.");
+        let hunt = id_of(&conn, "whale", "To hunt whales.");
+        let mine = id_of(&conn, "whale", "Mine.");
+        for (entry, tag) in [
+            (mammal, "nautical"),
+            (id_of(&conn, "harpoon", "A barbed spear."), "weapon"),
+            (id_of(&conn, "cetology", "The study of whales."), "science"),
+            (mine, "mine"),
+        ] {
+            conn.execute(
+                "INSERT INTO entry_tags (entry_id, tag) VALUES (?, ?)",
+                params![entry, tag],
+            )
+            .unwrap();
+        }
+        senses::reorder(&conn, "whale", &[mine, hunt, mammal]).unwrap();
+        conn.execute(
+            "INSERT INTO known_words (profile_id, word, marked_at) VALUES (1, 'harpoon', 0)",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn a_swap_keeps_user_data_keyed_by_word() {
+        let conn = installed();
+        let (imported, tags_kept) = db::swap_source(&conn, V1_1, "wordnet").unwrap();
+        assert_eq!(imported, 5);
+        // "nautical" lands on each of the three new "whale" entries
+        assert_eq!(tags_kept, 4);
+
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            count("SELECT COUNT(*) FROM dictionary WHERE source = 'wordnet'"),
+            5
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM dictionary WHERE source LIKE '%staging'"),
+            0
+        );
+        assert_eq!(
+            installed_version(&conn, "wordnet").unwrap().as_deref(),
+            Some("1.1")
+        );
+
+        let wordnet = |word: &str| (word.to_string(), "wordnet".to_string());
+        assert_eq!(
+            tagged(&conn, "nautical"),
+            [wordnet("whale"), wordnet("whale"), wordnet("whale")]
+        );
+        assert_eq!(tagged(&conn, "weapon"), [wordnet("harpoon")]);
+        // Its word is gone from the new release, so the tag goes with it
+        assert!(tagged(&conn, "science").is_empty());
+        // Other sources aren't touched
+        assert_eq!(
+            tagged(&conn, "mine"),
+            [("whale".to_string(), "user".to_string())]
+        );
+
+        // The user's order comes first; the new sense goes after it
+        let mut stmt = conn
+            .prepare(
+                "SELECT definition FROM dictionary WHERE word = 'whale'
+                 ORDER BY sense_order IS NULL, sense_order",
+            )
+            .unwrap();
+        let order: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            order,
+            [
+                "Mine.",
+                "To hunt whales.",
+                "A large marine mammal.",
+                "To beat soundly."
+            ]
+        );
+
+        assert_eq!(
+            count("SELECT COUNT(*) FROM known_words WHERE word = 'harpoon'"),
+            1
+        );
+    }
+
+    #[test]
+    fn a_failed_swap_leaves_the_installed_pack() {
+        let conn = installed();
+        let broken = r#"{"version": "1.1", "words": [{"word": "whale"}]}"#;
+        assert!(db::swap_source(&conn, broken, "wordnet").is_err());
+
+        let entries: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM dictionary WHERE source = 'wordnet'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(entries, 4);
+        assert_eq!(
+            installed_version(&conn, "wordnet").unwrap().as_deref(),
+            Some("1.0")
+        );
+        assert_eq!(tagged(&conn, "science").len(), 1);
+    }
+
+    #[test]
+    fn versions_compare_part_by_part() {
+        for (candidate, installed, newer) in [
+            ("1.10", "1.9", true),
+            ("v2.0.1", "2.0", true),
+            ("1.0", "1.0", false),
+            ("1.0", "1.1", false),
+            ("2024-06", "2024-05", true),
+            ("2024-05", "2024-05", false),
+        ] {
+            assert_eq!(
+                is_newer(candidate, installed),
+                newer,
+                "{} against {}",
+                candidate,
+                installed
+            );
+        }
+    }
+}
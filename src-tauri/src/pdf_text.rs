@@ -0,0 +1,201 @@
+use lopdf::Document;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tauri::{Manager, Runtime};
+
+use crate::books::{self, Book};
+use crate::db::DbState;
+use crate::read_only;
+
+/// Most pages extracted by one call, so a 900-page book is never done in one go
+pub const MAX_PAGES_PER_CALL: u32 = 50;
+
+/// Pages with fewer visible characters than this are treated as having no text layer
+const MIN_PAGE_CHARS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextQuality {
+    Good,
+    /// Text exists but is mostly symbols or undecodable glyphs
+    Garbled,
+    /// Scanned page with no extractable text
+    NoTextLayer,
+}
+
+impl TextQuality {
+    fn as_str(self) -> &'static str {
+        match self {
+            TextQuality::Good => "good",
+            TextQuality::Garbled => "garbled",
+            TextQuality::NoTextLayer => "no_text_layer",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "good" => TextQuality::Good,
+            "garbled" => TextQuality::Garbled,
+            _ => TextQuality::NoTextLayer,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PageRange {
+    /// First page, 1-based
+    pub first: u32,
+    /// Last page, inclusive
+    pub last: u32,
+}
+
+#[derive(Serialize)]
+pub struct PageText {
+    pub page: u32,
+    pub quality: TextQuality,
+    /// Only present for pages whose text is usable
+    pub text: Option<String>,
+}
+
+/// Judge whether extracted text is worth showing for lookups
+pub fn assess_text(text: &str) -> TextQuality {
+    let visible: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if visible.len() < MIN_PAGE_CHARS {
+        return TextQuality::NoTextLayer;
+    }
+
+    let letters = visible.iter().filter(|c| c.is_alphabetic()).count();
+    let undecodable = visible
+        .iter()
+        .filter(|&&c| c == '\u{fffd}' || c.is_control())
+        .count();
+
+    if letters * 2 < visible.len() || undecodable * 20 > visible.len() {
+        TextQuality::Garbled
+    } else {
+        TextQuality::Good
+    }
+}
+
+/// Create the per-page text cache, keyed by content hash and page number
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pdf_page_text (
+            content_hash TEXT NOT NULL,
+            page INTEGER NOT NULL,
+            quality TEXT NOT NULL,
+            text TEXT,
+            PRIMARY KEY (content_hash, page)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn cached_page(conn: &Connection, hash: &str, page: u32) -> Result<Option<PageText>> {
+    conn.query_row(
+        "SELECT quality, text FROM pdf_page_text WHERE content_hash = ? AND page = ?",
+        params![hash, page],
+        |row| {
+            Ok(PageText {
+                page,
+                quality: TextQuality::parse(&row.get::<_, String>(0)?),
+                text: row.get(1)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Text of pages `first..=last` of a PDF book, extracting and caching any not seen before.
+///
+/// Blocking: callers on the command path should run this off the main thread.
+pub fn page_texts<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    book: &Book,
+    range: &PageRange,
+) -> Result<Vec<PageText>, String> {
+    if book.format != "pdf" {
+        return Err(format!("{} is not a PDF", book.title));
+    }
+    if range.first == 0 || range.last < range.first {
+        return Err("Page ranges start at 1 and must not be reversed".to_string());
+    }
+    if range.last - range.first + 1 > MAX_PAGES_PER_CALL {
+        return Err(format!(
+            "At most {} pages can be extracted at once",
+            MAX_PAGES_PER_CALL
+        ));
+    }
+
+    let bytes = fs::read(&book.path).map_err(|e| e.to_string())?;
+    let hash = books::content_hash(&bytes);
+    let state = app.state::<DbState>();
+
+    let mut pages = Vec::new();
+    let mut missing = Vec::new();
+    {
+        let conn = state.0.lock().unwrap();
+        for page in range.first..=range.last {
+            match cached_page(&conn, &hash, page).map_err(|e| e.to_string())? {
+                Some(cached) => pages.push(cached),
+                None => missing.push(page),
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        // Parse without holding the database lock; this is the slow part
+        let document = Document::load_mem(&bytes).map_err(|e| e.to_string())?;
+        let page_count = document.get_pages().len() as u32;
+
+        let mut extracted = Vec::new();
+        for page in missing.into_iter().filter(|&p| p <= page_count) {
+            let raw = document.extract_text(&[page]).unwrap_or_default();
+            let quality = assess_text(&raw);
+            let text = (quality == TextQuality::Good).then(|| raw.trim().to_string());
+            extracted.push(PageText {
+                page,
+                quality,
+                text,
+            });
+        }
+
+        let conn = state.0.lock().unwrap();
+        // A read-only database extracts them again next time
+        for page in &extracted {
+            if read_only::is_read_only(&conn) {
+                break;
+            }
+            conn.execute(
+                "INSERT OR REPLACE INTO pdf_page_text (content_hash, page, quality, text)
+                 VALUES (?, ?, ?, ?)",
+                params![hash, page.page, page.quality.as_str(), page.text],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        pages.extend(extracted);
+        pages.sort_by_key(|p| p.page);
+    }
+
+    Ok(pages)
+}
+
+#[tauri::command]
+pub async fn extract_pdf_text<R: Runtime>(
+    book_id: i64,
+    page_range: PageRange,
+    app: tauri::AppHandle<R>,
+) -> Result<Vec<PageText>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let book = {
+            let state = app.state::<DbState>();
+            let conn = state.0.lock().unwrap();
+            books::load_book(&conn, book_id)?
+        };
+        page_texts(&app, &book, &page_range)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,220 @@
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::{read_only, settings};
+
+/// What a command can do, from least to most harmful
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    /// Reads the dictionary. Usage records such as lookup history and analytics, which every
+    /// read leaves, don't count as changes.
+    ReadOnly,
+    /// Reads or changes personal data: the library, reading progress, tags
+    UserData,
+    /// Deletes or replaces data: removing books or sources, replacing the dictionary
+    Destructive,
+}
+
+/// Ways in other than the app's own frontend, which is trusted with every tier
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryPoint {
+    /// The local automation socket
+    Automation,
+    /// Deep links, files opened with the app, launch arguments and shared content
+    Incoming,
+}
+
+const ENTRY_POINTS: [EntryPoint; 2] = [EntryPoint::Automation, EntryPoint::Incoming];
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PolicyError {
+    PermissionDenied {
+        entry_point: EntryPoint,
+        tier: Tier,
+        /// The setting that allows the tier for this entry point
+        setting: &'static str,
+    },
+    /// The database is open read-only, so nothing above `ReadOnly` is allowed whatever the
+    /// settings say
+    ReadOnlyMode { entry_point: EntryPoint, tier: Tier },
+}
+
+/// The effective policy of one entry point, for diagnostics
+#[derive(Serialize)]
+pub struct EntryPolicy {
+    entry_point: EntryPoint,
+    allowed: Vec<Tier>,
+}
+
+impl EntryPoint {
+    /// The setting that turns a tier on for this entry point, with its default. Read-only
+    /// commands need none; opening a book with the app adds it to the library, as it always
+    /// has, and everything else is off until allowed.
+    fn setting(self, tier: Tier) -> Option<(&'static str, bool)> {
+        match (self, tier) {
+            (_, Tier::ReadOnly) => None,
+            (EntryPoint::Automation, Tier::UserData) => Some(("automation_allow_user_data", false)),
+            (EntryPoint::Automation, Tier::Destructive) => {
+                Some(("automation_allow_destructive", false))
+            }
+            (EntryPoint::Incoming, Tier::UserData) => Some(("incoming_allow_user_data", true)),
+            (EntryPoint::Incoming, Tier::Destructive) => {
+                Some(("incoming_allow_destructive", false))
+            }
+        }
+    }
+}
+
+/// Allow a command of `tier` coming in through `entry_point`, or say which setting would
+pub fn check(conn: &Connection, entry_point: EntryPoint, tier: Tier) -> Result<(), PolicyError> {
+    if tier > Tier::ReadOnly && read_only::is_read_only(conn) {
+        return Err(PolicyError::ReadOnlyMode { entry_point, tier });
+    }
+    match entry_point.setting(tier) {
+        Some((setting, default)) if !settings::get_bool(conn, setting, default) => {
+            Err(PolicyError::PermissionDenied {
+                entry_point,
+                tier,
+                setting,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn effective(conn: &Connection) -> Vec<EntryPolicy> {
+    ENTRY_POINTS
+        .into_iter()
+        .map(|entry_point| EntryPolicy {
+            entry_point,
+            allowed: [Tier::ReadOnly, Tier::UserData, Tier::Destructive]
+                .into_iter()
+                .filter(|&tier| check(conn, entry_point, tier).is_ok())
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// Everything a check could touch, to show that a denied one changes nothing
+    fn contents(conn: &Connection) -> (i64, Vec<(String, String)>) {
+        let mut stmt = conn
+            .prepare("SELECT key, value FROM settings ORDER BY key")
+            .unwrap();
+        let settings = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        let changes = conn
+            .query_row("SELECT total_changes()", [], |row| row.get(0))
+            .unwrap();
+        (changes, settings)
+    }
+
+    #[test]
+    fn denied_tiers_name_their_setting() {
+        use EntryPoint::*;
+        use Tier::*;
+        let conn = db::init_db(None).unwrap();
+        for (entry_point, tier, denied_by) in [
+            (Automation, ReadOnly, None),
+            (Automation, UserData, Some("automation_allow_user_data")),
+            (
+                Automation,
+                Destructive,
+                Some("automation_allow_destructive"),
+            ),
+            (Incoming, ReadOnly, None),
+            (Incoming, UserData, None),
+            (Incoming, Destructive, Some("incoming_allow_destructive")),
+        ] {
+            let before = contents(&conn);
+            let outcome = check(&conn, entry_point, tier);
+            match denied_by {
+                None => assert!(outcome.is_ok(), "{:?} {:?}", entry_point, tier),
+                Some(expected) => match outcome {
+                    Err(PolicyError::PermissionDenied {
+                        entry_point: denied_entry,
+                        tier: denied_tier,
+                        setting,
+                    }) => {
+                        assert_eq!((denied_entry, denied_tier), (entry_point, tier));
+                        assert_eq!(setting, expected);
+                    }
+                    _ => panic!("{:?} {:?} should be denied", entry_point, tier),
+                },
+            }
+            assert_eq!(contents(&conn), before);
+        }
+    }
+
+    #[test]
+    fn settings_open_and_close_tiers() {
+        let conn = db::init_db(None).unwrap();
+        settings::set(&conn, "automation_allow_destructive", "true").unwrap();
+        settings::set(&conn, "incoming_allow_user_data", "false").unwrap();
+
+        assert!(check(&conn, EntryPoint::Automation, Tier::Destructive).is_ok());
+        // Allowing one tier doesn't allow the ones below it
+        assert!(check(&conn, EntryPoint::Automation, Tier::UserData).is_err());
+        assert!(check(&conn, EntryPoint::Incoming, Tier::UserData).is_err());
+
+        let allowed: Vec<_> = effective(&conn)
+            .into_iter()
+            .map(|policy| (policy.entry_point, policy.allowed))
+            .collect();
+        assert_eq!(
+            allowed,
+            [
+                (
+                    EntryPoint::Automation,
+                    vec![Tier::ReadOnly, Tier::Destructive]
+                ),
+                (EntryPoint::Incoming, vec![Tier::ReadOnly]),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_only_databases_allow_only_reads() {
+        let dir =
+            std::env::temp_dir().join(format!("open-read-permissions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dictionary.db");
+        {
+            let conn = db::prepare(Connection::open(&path).unwrap()).unwrap();
+            for setting in [
+                "automation_allow_user_data",
+                "automation_allow_destructive",
+                "incoming_allow_user_data",
+                "incoming_allow_destructive",
+            ] {
+                settings::set(&conn, setting, "true").unwrap();
+            }
+        }
+        let conn =
+            Connection::open_with_flags(&path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+
+        for entry_point in ENTRY_POINTS {
+            assert!(check(&conn, entry_point, Tier::ReadOnly).is_ok());
+            for tier in [Tier::UserData, Tier::Destructive] {
+                assert!(matches!(
+                    check(&conn, entry_point, tier),
+                    Err(PolicyError::ReadOnlyMode { entry_point: e, tier: t })
+                        if e == entry_point && t == tier
+                ));
+            }
+        }
+
+        drop(conn);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
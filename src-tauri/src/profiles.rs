@@ -0,0 +1,231 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tauri::{Emitter, Manager};
+
+use crate::db::DbState;
+use crate::session_lookups::SessionLookups;
+use crate::{backup, read_only, settings};
+
+pub const CURRENT_PROFILE_SETTING: &str = "current_profile";
+
+/// Per-profile tables; deleting a profile clears its rows from each
+const PROFILE_TABLES: [&str; 10] = [
+    "lookup_history",
+    "reading_sessions",
+    "reading_queue",
+    "book_progress",
+    "weekly_summaries",
+    "known_words",
+    "vocab_size_snapshots",
+    "book_glossary",
+    "review_cards",
+    "review_sessions",
+];
+
+/// The active profile, mirrored from the connection's `temp.current_profile` table that
+/// queries on per-profile tables filter by
+#[derive(Default)]
+pub struct CurrentProfile(pub AtomicI64);
+
+#[derive(Serialize, Clone)]
+pub struct Profile {
+    id: i64,
+    name: String,
+    created_at: i64,
+    current: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct ProfileDeleted {
+    id: i64,
+}
+
+/// Create the profiles table. The first profile gets id 1, which rows from before profiles
+/// existed default to.
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS profiles (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "INSERT INTO profiles (id, name, created_at)
+         SELECT 1, 'Default', unixepoch() WHERE NOT EXISTS (SELECT 1 FROM profiles)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn set_current(conn: &Connection, id: i64) -> Result<()> {
+    conn.execute("DELETE FROM temp.current_profile", [])?;
+    conn.execute(
+        "INSERT INTO temp.current_profile (id) VALUES (?)",
+        params![id],
+    )?;
+    // A read-only database keeps the profile it was saved with
+    if read_only::is_read_only(conn) {
+        return Ok(());
+    }
+    settings::set(conn, CURRENT_PROFILE_SETTING, &id.to_string())
+}
+
+/// Select the saved profile for this connection, falling back to the oldest one if the
+/// saved profile is gone. Returns the active profile id.
+pub fn activate(conn: &Connection) -> Result<i64> {
+    let saved = settings::get(conn, CURRENT_PROFILE_SETTING)?.and_then(|v| v.parse::<i64>().ok());
+    let id = conn.query_row(
+        "SELECT id FROM profiles ORDER BY id IS NOT ?, id LIMIT 1",
+        params![saved],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "CREATE TEMP TABLE IF NOT EXISTS current_profile (id INTEGER NOT NULL)",
+        [],
+    )?;
+    set_current(conn, id)?;
+    Ok(id)
+}
+
+pub fn current_id(conn: &Connection) -> Result<i64> {
+    conn.query_row("SELECT id FROM temp.current_profile", [], |row| row.get(0))
+}
+
+fn get_profile(conn: &Connection, id: i64) -> Result<Option<Profile>> {
+    conn.query_row(
+        "SELECT id, name, created_at, id = (SELECT id FROM temp.current_profile)
+         FROM profiles WHERE id = ?",
+        params![id],
+        |row| {
+            Ok(Profile {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                created_at: row.get(2)?,
+                current: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+#[tauri::command]
+pub fn list_profiles(state: tauri::State<DbState>) -> Result<Vec<Profile>, String> {
+    let conn = state.user_data()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, name, created_at, id = (SELECT id FROM temp.current_profile)
+             FROM profiles ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(Profile {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                created_at: row.get(2)?,
+                current: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut profiles = Vec::new();
+    for row in rows {
+        profiles.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub fn create_profile(name: &str, state: tauri::State<DbState>) -> Result<Profile, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name can't be empty".to_string());
+    }
+
+    let conn = state.user_data()?;
+    let inserted = conn
+        .execute(
+            "INSERT OR IGNORE INTO profiles (name, created_at) VALUES (?, unixepoch())",
+            params![name],
+        )
+        .map_err(|e| e.to_string())?;
+    if inserted == 0 {
+        return Err(format!("A profile named \"{}\" already exists", name));
+    }
+
+    get_profile(&conn, conn.last_insert_rowid())
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Profile was not created".to_string())
+}
+
+/// Make `id` the active profile. Per-run caches are cleared and "profile-switched" is
+/// emitted so every window reloads its user data.
+#[tauri::command]
+pub fn switch_profile(
+    id: i64,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<Profile, String> {
+    let conn = state.user_data()?;
+    if get_profile(&conn, id).map_err(|e| e.to_string())?.is_none() {
+        return Err(format!("Profile {} not found", id));
+    }
+
+    set_current(&conn, id).map_err(|e| e.to_string())?;
+    app.state::<CurrentProfile>().0.store(id, Ordering::SeqCst);
+    app.state::<SessionLookups>().0.lock().unwrap().clear();
+
+    let profile = get_profile(&conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Profile {} not found", id))?;
+    let _ = app.emit("profile-switched", profile.clone());
+    Ok(profile)
+}
+
+/// Delete a profile and everything recorded under it. `confirm_name` must repeat the
+/// profile's name; the active profile can't be deleted.
+#[tauri::command]
+pub fn delete_profile(
+    id: i64,
+    confirm_name: &str,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+    let profile = get_profile(&conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Profile {} not found", id))?;
+
+    if profile.current {
+        return Err("Switch to another profile before deleting this one".to_string());
+    }
+    if !profile.name.eq_ignore_ascii_case(confirm_name.trim()) {
+        return Err(format!(
+            "Type the profile name \"{}\" to confirm deleting it",
+            profile.name
+        ));
+    }
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    for table in PROFILE_TABLES {
+        tx.execute(
+            &format!("DELETE FROM {} WHERE profile_id = ?", table),
+            params![id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute("DELETE FROM profiles WHERE id = ?", params![id])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let _ = app.emit("profile-deleted", ProfileDeleted { id });
+    backup::schedule(&app);
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use rusqlite::{Connection, ErrorCode};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::settings;
+
+/// Longest an interactive search may run, in milliseconds, before it's stopped; 0 turns
+/// the limit off. Imports and index builds don't run under it.
+pub const BUDGET_SETTING: &str = "query_budget_ms";
+const DEFAULT_BUDGET_MS: f64 = 250.0;
+/// SQLite VM steps between looks at the clock
+const STEPS_PER_CHECK: i32 = 1000;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueryError {
+    /// The search ran past its budget and was stopped
+    QueryTimeout {
+        budget_ms: u64,
+        suggestion: &'static str,
+    },
+    Database {
+        message: String,
+    },
+}
+
+impl From<rusqlite::Error> for QueryError {
+    fn from(e: rusqlite::Error) -> Self {
+        QueryError::Database {
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<String> for QueryError {
+    fn from(message: String) -> Self {
+        QueryError::Database { message }
+    }
+}
+
+/// Run the statements of an interactive search, interrupting them once they've taken
+/// longer than the budget setting. The connection is left as it was either way.
+pub fn run<T>(
+    conn: &Connection,
+    query: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+) -> Result<T, QueryError> {
+    let budget_ms = settings::get_f64(conn, BUDGET_SETTING, DEFAULT_BUDGET_MS).max(0.0) as u64;
+    if budget_ms == 0 {
+        return query(conn).map_err(QueryError::from);
+    }
+
+    let budget = Duration::from_millis(budget_ms);
+    let started = Instant::now();
+    conn.progress_handler(STEPS_PER_CHECK, Some(move || started.elapsed() > budget));
+    let result = query(conn);
+    conn.progress_handler(0, None::<fn() -> bool>);
+
+    result.map_err(|e| match e.sqlite_error_code() {
+        Some(ErrorCode::OperationInterrupted) => QueryError::QueryTimeout {
+            budget_ms,
+            suggestion: "Narrow the search: type more of the word or add filters",
+        },
+        _ => QueryError::from(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// Counts far enough to run for minutes unless stopped
+    const SLOW: &str = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n)
+                        SELECT COUNT(*) FROM (SELECT i FROM n LIMIT ?)";
+
+    fn count(conn: &Connection, to: i64) -> rusqlite::Result<i64> {
+        conn.query_row(SLOW, [to], |row| row.get(0))
+    }
+
+    #[test]
+    fn a_slow_query_is_stopped_within_its_budget() {
+        let conn = db::init_db(None).unwrap();
+        settings::set(&conn, BUDGET_SETTING, "50").unwrap();
+
+        let started = Instant::now();
+        let outcome = run(&conn, |conn| count(conn, 1_000_000_000_000));
+        let elapsed = started.elapsed();
+        assert!(
+            matches!(outcome, Err(QueryError::QueryTimeout { budget_ms: 50, .. })),
+            "{:?}",
+            outcome
+        );
+        assert!(elapsed >= Duration::from_millis(50), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+
+        // The connection is still usable, and no longer under the budget outside `run`
+        assert_eq!(count(&conn, 10).unwrap(), 10);
+        settings::set(&conn, "after_timeout", "yes").unwrap();
+        assert_eq!(
+            settings::get(&conn, "after_timeout").unwrap().as_deref(),
+            Some("yes")
+        );
+        assert_eq!(run(&conn, |conn| count(conn, 1000)).unwrap(), 1000);
+    }
+
+    #[test]
+    fn budgets_leave_other_queries_alone() {
+        let conn = db::init_db(None).unwrap();
+        assert_eq!(run(&conn, |conn| count(conn, 5000)).unwrap(), 5000);
+
+        // Other errors come back as they are
+        let outcome = run(&conn, |conn| {
+            conn.query_row("SELECT * FROM no_such_table", [], |row| {
+                row.get::<_, i64>(0)
+            })
+        });
+        assert!(
+            matches!(&outcome, Err(QueryError::Database { message }) if message.contains("no_such_table")),
+            "{:?}",
+            outcome
+        );
+
+        // A query stopped under a tiny budget runs to the end with the limit off
+        settings::set(&conn, BUDGET_SETTING, "1").unwrap();
+        assert!(matches!(
+            run(&conn, |conn| count(conn, 5_000_000)),
+            Err(QueryError::QueryTimeout { budget_ms: 1, .. })
+        ));
+        settings::set(&conn, BUDGET_SETTING, "0").unwrap();
+        assert_eq!(
+            run(&conn, |conn| count(conn, 5_000_000)).unwrap(),
+            5_000_000
+        );
+    }
+}
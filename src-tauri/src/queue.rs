@@ -0,0 +1,169 @@
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+
+use crate::books::{self, Book, BOOK_COLUMNS, BOOK_SOURCE};
+use crate::db::{self, DbState};
+
+/// When "true", books reaching the finished threshold leave the queue automatically
+pub const AUTO_REMOVE_SETTING: &str = "queue_auto_remove_finished";
+
+#[derive(Serialize)]
+pub struct QueuedBook {
+    position: i64,
+    book: Book,
+}
+
+const QUEUE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS reading_queue (
+    profile_id INTEGER NOT NULL REFERENCES profiles(id) ON DELETE CASCADE,
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (profile_id, book_id)
+)";
+
+/// Create the "want to read" queue table, one queue per profile
+pub fn create_table(conn: &Connection) -> Result<()> {
+    if db::column_exists(conn, "reading_queue", "book_id")?
+        && !db::column_exists(conn, "reading_queue", "profile_id")?
+    {
+        // The queue was keyed by book alone before profiles; it becomes the Default profile's
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "ALTER TABLE reading_queue RENAME TO reading_queue_legacy",
+            [],
+        )?;
+        tx.execute(QUEUE_SCHEMA, [])?;
+        tx.execute(
+            "INSERT INTO reading_queue (profile_id, book_id, position)
+             SELECT 1, book_id, position FROM reading_queue_legacy",
+            [],
+        )?;
+        tx.execute("DROP TABLE reading_queue_legacy", [])?;
+        return tx.commit();
+    }
+
+    conn.execute(QUEUE_SCHEMA, [])?;
+    Ok(())
+}
+
+/// The active profile's queue, in order
+fn queued_ids(conn: &Connection) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT book_id FROM reading_queue
+         WHERE profile_id = (SELECT id FROM temp.current_profile)
+         ORDER BY position",
+    )?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Rewrite the active profile's queue as 0..n in the given order
+fn write_order(conn: &Connection, ids: &[i64]) -> Result<()> {
+    conn.execute(
+        "DELETE FROM reading_queue WHERE profile_id = (SELECT id FROM temp.current_profile)",
+        [],
+    )?;
+    let mut stmt = conn.prepare(
+        "INSERT INTO reading_queue (profile_id, book_id, position)
+         VALUES ((SELECT id FROM temp.current_profile), ?, ?)",
+    )?;
+    for (position, id) in ids.iter().enumerate() {
+        stmt.execute(params![id, position as i64])?;
+    }
+    Ok(())
+}
+
+/// Move (or insert) a book to `position`, clamped to the queue bounds.
+///
+/// The read-modify-write runs in one transaction so positions stay dense.
+fn place(conn: &Connection, book_id: i64, position: Option<i64>) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+
+    let mut ids = queued_ids(&tx)?;
+    ids.retain(|&id| id != book_id);
+    let index = position
+        .map(|p| p.clamp(0, ids.len() as i64) as usize)
+        .unwrap_or(ids.len());
+    ids.insert(index, book_id);
+
+    write_order(&tx, &ids)?;
+    tx.commit()
+}
+
+/// Remove a book from the active profile's queue and close the gap it leaves
+pub fn remove_from_queue(conn: &Connection, book_id: i64) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+
+    let mut ids = queued_ids(&tx)?;
+    let before = ids.len();
+    ids.retain(|&id| id != book_id);
+    if ids.len() != before {
+        write_order(&tx, &ids)?;
+    }
+
+    tx.commit()
+}
+
+#[tauri::command]
+pub fn queue_book(
+    book_id: i64,
+    position: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+    if books::get_book(&conn, book_id)
+        .map_err(|e| e.to_string())?
+        .is_none()
+    {
+        return Err(format!("Book {} not found", book_id));
+    }
+    place(&conn, book_id, position).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn dequeue_book(book_id: i64, state: tauri::State<DbState>) -> Result<(), String> {
+    let conn = state.user_data()?;
+    remove_from_queue(&conn, book_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn reorder_queue(
+    book_id: i64,
+    new_position: i64,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+    let queued = queued_ids(&conn).map_err(|e| e.to_string())?;
+    if !queued.contains(&book_id) {
+        return Err(format!("Book {} is not in the reading queue", book_id));
+    }
+    place(&conn, book_id, Some(new_position)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_reading_queue(state: tauri::State<DbState>) -> Result<Vec<QueuedBook>, String> {
+    let conn = state.user_data()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT q.position, {} FROM {}
+             JOIN reading_queue q ON q.book_id = b.id
+                AND q.profile_id = (SELECT id FROM temp.current_profile)
+             ORDER BY q.position",
+            BOOK_COLUMNS, BOOK_SOURCE
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(QueuedBook {
+                position: row.get(0)?,
+                book: books::book_from_row_at(row, 1)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut queue = Vec::new();
+    for row in rows {
+        queue.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(queue)
+}
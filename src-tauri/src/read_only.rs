@@ -0,0 +1,701 @@
+use rusqlite::{Connection, DatabaseName, OpenFlags, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use tauri::ipc::Invoke;
+use tauri::{Manager, Runtime};
+
+use crate::{db, memory, onboarding, profiles, settings};
+
+/// Open the database read-only from the next start, e.g. for a shared or kiosk install
+pub const SETTING: &str = "read_only_mode";
+/// Launch argument with the same effect for one run
+const FLAG: &str = "--read-only";
+/// Launch argument ignoring the setting for one run, so it can be turned off again
+const WRITABLE_FLAG: &str = "--writable";
+
+/// Commands that only read, the only ones run in read-only mode. Anything not listed,
+/// including commands added later, is rejected before it runs, so a new writer can't slip
+/// through. Searching, browsing, exporting, speech and the per-window state kept in memory
+/// still work; the usage records reads leave, like lookup history, are skipped instead.
+/// `unlock` opens an encrypted database read-only too.
+const READ_COMMANDS: &[&str] = &[
+    "search_dictionary",
+    "get_source_stats",
+    "get_source_report",
+    "get_attributions",
+    "get_senses",
+    "get_missed_words",
+    "get_session_lookups",
+    "set_window_context",
+    "clear_session_lookups",
+    "define_selection",
+    "open_shared",
+    "take_incoming",
+    "get_entry_details",
+    "select_entry_ids",
+    "get_entry_tags",
+    "find_word_in_library",
+    "get_hyphenation",
+    "get_word_family",
+    "get_etymology_chain",
+    "get_unknown_words",
+    "generate_quiz",
+    "get_setting",
+    "list_profiles",
+    "list_books",
+    "get_book_tags",
+    "export_library",
+    "browse_opds",
+    "get_text_book_outline",
+    "get_book_content",
+    "speak_text",
+    "pause_tts",
+    "resume_tts",
+    "stop_tts",
+    "get_epub_structure",
+    "get_epub_resource",
+    "extract_pdf_text",
+    "cancel_book_index",
+    "index_status",
+    "cancel_index_rebuild",
+    "search_books",
+    "search_book_in_order",
+    "get_book_vocabulary",
+    "get_book_glossary",
+    "get_book_progress",
+    "get_review_sessions",
+    "get_reading_queue",
+    "get_analytics_summary",
+    "generate_weekly_summary",
+    "get_summary_history",
+    "check_words",
+    "get_vocab_growth",
+    "get_diagnostics",
+    "get_db_status",
+    "health_check",
+    "check_pack_updates",
+    "unlock",
+];
+
+/// Why the database was opened read-only
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// Started with `--read-only`
+    Flag,
+    /// The `read_only_mode` setting is on
+    Setting,
+    /// The database file can't be opened for writing, e.g. on a read-only mount
+    ReadOnlyFilesystem,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReadOnlyError {
+    ReadOnlyMode { command: String, reason: Reason },
+}
+
+/// Whether this run is read-only, and why
+pub struct ReadOnly(pub Option<Reason>);
+
+pub fn open_file(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+}
+
+/// Decide at startup whether to open the database read-only. Only an existing file can be;
+/// without one there's nothing to protect and the app starts as usual.
+pub fn detect(app: &tauri::AppHandle) -> Option<Reason> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == FLAG) {
+        return Some(Reason::Flag);
+    }
+    let path = db::database_path(app).filter(|path| path.exists())?;
+    if fs::OpenOptions::new().write(true).open(&path).is_err() {
+        return Some(Reason::ReadOnlyFilesystem);
+    }
+    if args.iter().any(|arg| arg == WRITABLE_FLAG) {
+        return None;
+    }
+    let conn = open_file(&path).ok()?;
+    settings::get_bool(&conn, SETTING, false).then_some(Reason::Setting)
+}
+
+/// Open the database without write access. The file isn't migrated, so one left by an
+/// older version is read as it is. Without a file, e.g. `--read-only` on first run, the
+/// app runs on an in-memory database holding the bundled dictionary.
+pub fn open(app: &tauri::AppHandle) -> Result<Connection> {
+    let Some(path) = db::database_path(app).filter(|path| path.exists()) else {
+        let stand_in = db::prepare(Connection::open_in_memory()?)?;
+        onboarding::populate_bundled(&stand_in, app)?;
+        return Ok(stand_in);
+    };
+    prepare(open_file(&path)?)
+}
+
+/// Set up a connection opened with `open_file`, in place of `db::prepare`, which migrates
+pub fn prepare(conn: Connection) -> Result<Connection> {
+    conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    // The current profile lives in a temp table, which stays writable
+    profiles::activate(&conn)?;
+    memory::apply(&conn)?;
+    Ok(conn)
+}
+
+/// Whether writes to the connection's database would fail, for skipping the ones that
+/// only record usage
+pub fn is_read_only(conn: &Connection) -> bool {
+    conn.is_readonly(DatabaseName::Main).unwrap_or(false)
+}
+
+/// Answer any command not in `READ_COMMANDS` with `ReadOnlyError` while read-only, handing
+/// every other invoke back to run as usual
+pub fn intercept<R: Runtime>(invoke: Invoke<R>) -> Option<Invoke<R>> {
+    let webview = invoke.message.webview();
+    let reason = webview.try_state::<ReadOnly>().and_then(|mode| mode.0);
+    let command = invoke.message.command();
+    match reason {
+        Some(reason) if !READ_COMMANDS.contains(&command) => {
+            let command = command.to_string();
+            invoke
+                .resolver
+                .reject(ReadOnlyError::ReadOnlyMode { command, reason });
+            None
+        }
+        _ => Some(invoke),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BackupJob;
+    use crate::book_search::BookIndexJobs;
+    use crate::db::{DbState, SearchEntry};
+    use crate::health::HealthCache;
+    use crate::hyphenation::Hyphenators;
+    use crate::incoming::IncomingQueue;
+    use crate::indexes::{IndexRebuild, StaleIndexes};
+    use crate::memory::{DeferredIndexes, MemoryMode};
+    use crate::profiles::CurrentProfile;
+    use crate::session_lookups::SessionLookups;
+    use crate::source_reports::ReportJobs;
+    use crate::source_stats::SourceCounters;
+    use crate::tts::TtsQueue;
+    use crate::window_context::WindowContexts;
+    use crate::{
+        analytics, book_search, book_words, books, bulk_edit, coverage, diagnostics, difficulty,
+        encryption, epub, etymology, examples, export, glossary, health, hyphenation, incoming,
+        indexes, licenses, migrations, missed, opds, pack_updates, pdf_text, queue, review,
+        selection, senses, session_lookups, sessions, source_reports, source_stats, summary,
+        text_book, tts, vocabulary, window_context, word_family,
+    };
+    use serde_json::json;
+    use tauri::ipc::{CallbackFn, InvokeBody, InvokeResponseBody};
+    use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+    use tauri::webview::{InvokeRequest, WebviewWindow, WebviewWindowBuilder};
+
+    fn call(
+        webview: &WebviewWindow<MockRuntime>,
+        cmd: &str,
+        args: serde_json::Value,
+    ) -> Result<InvokeResponseBody, serde_json::Value> {
+        let request = InvokeRequest {
+            cmd: cmd.into(),
+            callback: CallbackFn(0),
+            error: CallbackFn(1),
+            url: "tauri://localhost".parse().unwrap(),
+            body: InvokeBody::Json(args),
+            headers: Default::default(),
+            invoke_key: tauri::test::INVOKE_KEY.to_string(),
+        };
+        tauri::test::get_ipc_response(webview, request)
+    }
+
+    fn invoke(webview: &WebviewWindow<MockRuntime>, cmd: &str) -> Result<(), serde_json::Value> {
+        call(
+            webview,
+            cmd,
+            serde_json::json!({ "key": "theme", "value": "dark" }),
+        )
+        .map(drop)
+    }
+
+    /// A window on an app running `intercept` the way `run` does, over the settings commands
+    fn window(mode: Option<Reason>) -> WebviewWindow<MockRuntime> {
+        let commands: fn(Invoke<MockRuntime>) -> bool =
+            tauri::generate_handler![settings::get_setting, settings::set_setting];
+        let app = mock_builder()
+            .invoke_handler(move |invoke| match intercept(invoke) {
+                Some(invoke) => commands(invoke),
+                None => true,
+            })
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        app.manage(DbState::new(db::init_db(None).unwrap(), false));
+        app.manage(ReadOnly(mode));
+        WebviewWindowBuilder::new(&app, "main", Default::default())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn read_commands_are_real_commands() {
+        // The names in `run`'s `generate_handler!`, so a renamed command can't linger here
+        let lib = include_str!("lib.rs");
+        let start = lib.find("generate_handler![").unwrap();
+        let end = start + lib[start..].find("];").unwrap();
+        let defined: Vec<&str> = lib[start..end]
+            .trim_start_matches("generate_handler![")
+            .split(',')
+            .map(|name| name.trim().rsplit("::").next().unwrap())
+            .filter(|name| !name.is_empty())
+            .collect();
+        assert!(defined.contains(&"search_dictionary"));
+
+        for (i, command) in READ_COMMANDS.iter().enumerate() {
+            assert!(defined.contains(command), "{} isn't a command", command);
+            assert!(
+                !READ_COMMANDS[..i].contains(command),
+                "{} is listed twice",
+                command
+            );
+        }
+        for writer in ["set_setting", "add_book", "set_word_known", "store_summary"] {
+            assert!(!READ_COMMANDS.contains(&writer), "{} writes", writer);
+        }
+    }
+
+    #[test]
+    fn only_reads_run_while_read_only() {
+        let webview = window(Some(Reason::Flag));
+        assert_eq!(invoke(&webview, "get_setting"), Ok(()));
+        let rejected = |command: &str| serde_json::json!({ "kind": "read_only_mode", "command": command, "reason": "flag" });
+        assert_eq!(
+            invoke(&webview, "set_setting"),
+            Err(rejected("set_setting"))
+        );
+        // Unlisted means rejected, whether or not the command exists
+        assert_eq!(
+            invoke(&webview, "a_new_writer"),
+            Err(rejected("a_new_writer"))
+        );
+
+        let conn = webview.state::<DbState>();
+        let conn = conn.0.lock().unwrap();
+        assert_eq!(settings::get(&conn, "theme").unwrap(), None);
+    }
+
+    #[test]
+    fn everything_runs_while_writable() {
+        let webview = window(None);
+        assert_eq!(invoke(&webview, "set_setting"), Ok(()));
+
+        let conn = webview.state::<DbState>();
+        let conn = conn.0.lock().unwrap();
+        assert_eq!(
+            settings::get(&conn, "theme").unwrap().as_deref(),
+            Some("dark")
+        );
+    }
+
+    #[test]
+    fn reads_skip_their_usage_writes() {
+        let dir = std::env::temp_dir().join(format!("open-read-read-only-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dictionary.db");
+        drop(db::prepare(Connection::open(&path).unwrap()).unwrap());
+
+        let conn = prepare(open_file(&path).unwrap()).unwrap();
+        assert!(is_read_only(&conn));
+        let counters = SourceCounters::default();
+        let hit = SearchEntry {
+            word: "walden".to_string(),
+            definition: "A pond.".to_string(),
+            pos: None,
+            difficulty: None,
+            source: "wordnet".to_string(),
+            truncated: false,
+        };
+        counters.record(&[hit]);
+        assert!(counters.flush(&conn).is_ok());
+
+        let app = tauri::test::mock_app();
+        app.manage(DbState::new(conn, false));
+        assert!(vocabulary::get_vocab_growth(None, app.state()).is_ok());
+        assert!(review::get_review_sessions(None, app.state()).is_ok());
+
+        drop(app);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A database file with a book of each format, dictionary entries and the usage data
+    /// the read commands show, written while writable
+    fn library(dir: &Path) -> std::path::PathBuf {
+        use std::io::Write;
+        use zip::write::FileOptions;
+
+        let text = dir.join("pride.txt");
+        fs::write(
+            &text,
+            include_str!("../tests/fixtures/gutenberg/pride_and_prejudice_excerpt.txt"),
+        )
+        .unwrap();
+        let epub = dir.join("walden.epub");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&epub).unwrap());
+        for (name, content) in [
+            (
+                "META-INF/container.xml",
+                include_str!("../tests/fixtures/epub/container.xml"),
+            ),
+            (
+                "OEBPS/content.opf",
+                include_str!("../tests/fixtures/epub/epub2/content.opf"),
+            ),
+            (
+                "OEBPS/toc.ncx",
+                include_str!("../tests/fixtures/epub/epub2/toc.ncx"),
+            ),
+            (
+                "OEBPS/cover.xhtml",
+                include_str!("../tests/fixtures/epub/chapter.xhtml"),
+            ),
+            (
+                "OEBPS/text/chapter 1.xhtml",
+                include_str!("../tests/fixtures/epub/chapter.xhtml"),
+            ),
+            (
+                "OEBPS/text/chapter2.xhtml",
+                include_str!("../tests/fixtures/epub/chapter.xhtml"),
+            ),
+        ] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        // Never parsed: its one page is already in the text cache
+        let pdf = dir.join("scan.pdf");
+        fs::write(&pdf, b"%PDF-1.4\n").unwrap();
+        let catalog = dir.join("catalog.json");
+        fs::write(
+            &catalog,
+            r#"{ "packs": [{ "id": "wordnet", "version": "1.1", "url": "wordnet-1.1.json" }] }"#,
+        )
+        .unwrap();
+
+        let path = dir.join("dictionary.db");
+        let app = tauri::test::mock_app();
+        app.manage(DbState::new(
+            db::prepare(Connection::open(&path).unwrap()).unwrap(),
+            false,
+        ));
+        {
+            let conn = app.state::<DbState>();
+            let conn = conn.0.lock().unwrap();
+            conn.execute_batch(
+                "INSERT INTO dictionary (word, definition, source, difficulty) VALUES
+                     ('truth', 'The quality of being true.', 'wordnet', 3),
+                     ('fortune', 'A large amount of money.', 'wordnet', 4),
+                     ('possession', 'The state of owning something.', 'wordnet', 5),
+                     ('acknowledged', 'Accepted as true.', 'wordnet', 5);",
+            )
+            .unwrap();
+            for book in [&text, &epub, &pdf] {
+                books::insert_book(&conn, &book.to_string_lossy(), None, None, None).unwrap();
+            }
+            conn.execute(
+                "INSERT INTO book_search (text, book_id, locator)
+                 VALUES ('It is a truth universally acknowledged', 1, 'txt:0')",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO book_search_state (book_id, content_hash, chunks, indexed_at)
+                 VALUES (1, '', 1, unixepoch())",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO pdf_page_text (content_hash, page, quality, text)
+                 VALUES (?, 1, 'good', 'Walden Pond.')",
+                [books::content_hash(b"%PDF-1.4\n")],
+            )
+            .unwrap();
+        }
+        settings::set_setting(settings::ONLINE_SETTING, "false", app.state()).unwrap();
+        settings::set_setting(
+            pack_updates::CATALOG_SETTING,
+            &catalog.to_string_lossy(),
+            app.state(),
+        )
+        .unwrap();
+        vocabulary::set_word_known("truth", true, app.state()).unwrap();
+        review::add_review_card("fortune", app.state()).unwrap();
+        books::set_book_tags(1, vec!["classics".to_string()], app.state()).unwrap();
+        queue::queue_book(2, None, app.state()).unwrap();
+        glossary::generate_book_glossary(1, None, app.state()).unwrap();
+        summary::store_summary(None, app.state()).unwrap();
+        path
+    }
+
+    #[test]
+    fn read_commands_run_on_a_read_only_database() {
+        let dir =
+            std::env::temp_dir().join(format!("open-read-read-only-all-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = library(&dir);
+
+        // Every command in `READ_COMMANDS`, and writers from the modules they sit beside
+        let commands: fn(Invoke<MockRuntime>) -> bool = tauri::generate_handler![
+            db::search_dictionary,
+            source_stats::get_source_stats,
+            source_reports::get_source_report,
+            licenses::get_attributions,
+            senses::get_senses,
+            missed::get_missed_words,
+            session_lookups::get_session_lookups,
+            window_context::set_window_context,
+            session_lookups::clear_session_lookups,
+            selection::define_selection,
+            incoming::open_shared,
+            incoming::take_incoming,
+            examples::get_entry_details,
+            bulk_edit::select_entry_ids,
+            bulk_edit::get_entry_tags,
+            examples::find_word_in_library,
+            hyphenation::get_hyphenation,
+            word_family::get_word_family,
+            etymology::get_etymology_chain,
+            difficulty::get_unknown_words,
+            difficulty::generate_quiz,
+            settings::get_setting,
+            profiles::list_profiles,
+            books::list_books,
+            books::get_book_tags,
+            export::export_library,
+            opds::browse_opds,
+            text_book::get_text_book_outline,
+            text_book::get_book_content,
+            tts::speak_text,
+            tts::pause_tts,
+            tts::resume_tts,
+            tts::stop_tts,
+            epub::get_epub_structure,
+            epub::get_epub_resource,
+            pdf_text::extract_pdf_text,
+            book_search::cancel_book_index,
+            indexes::index_status,
+            indexes::cancel_index_rebuild,
+            book_search::search_books,
+            book_search::search_book_in_order,
+            book_words::get_book_vocabulary,
+            glossary::get_book_glossary,
+            sessions::get_book_progress,
+            review::get_review_sessions,
+            queue::get_reading_queue,
+            analytics::get_analytics_summary,
+            summary::generate_weekly_summary,
+            summary::get_summary_history,
+            coverage::check_words,
+            vocabulary::get_vocab_growth,
+            diagnostics::get_diagnostics,
+            migrations::get_db_status,
+            health::health_check,
+            pack_updates::check_pack_updates,
+            encryption::unlock,
+            books::add_book,
+            vocabulary::set_word_known,
+            bulk_edit::bulk_update_entries,
+            pack_updates::update_pack,
+            summary::store_summary
+        ];
+        let app = mock_builder()
+            .invoke_handler(move |invoke| match intercept(invoke) {
+                Some(invoke) => commands(invoke),
+                None => true,
+            })
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let conn = prepare(open_file(&path).unwrap()).unwrap();
+        let profile = profiles::current_id(&conn).unwrap();
+        app.manage(DbState::new(conn, false));
+        app.manage(CurrentProfile(profile.into()));
+        app.manage(BookIndexJobs::default());
+        app.manage(SessionLookups::default());
+        app.manage(WindowContexts::default());
+        app.manage(Hyphenators::default());
+        app.manage(BackupJob::default());
+        app.manage(IncomingQueue::default());
+        app.manage(MemoryMode(false));
+        app.manage(DeferredIndexes::default());
+        app.manage(HealthCache::default());
+        app.manage(SourceCounters::default());
+        app.manage(ReportJobs::default());
+        app.manage(StaleIndexes::default());
+        app.manage(IndexRebuild::default());
+        app.manage(TtsQueue::default());
+        app.manage(ReadOnly(Some(Reason::Flag)));
+        let webview = WebviewWindowBuilder::new(&app, "main", Default::default())
+            .build()
+            .unwrap();
+
+        let export = dir.join("library.csv").to_string_lossy().into_owned();
+        let reads = [
+            ("search_dictionary", json!({ "word": "fortune" })),
+            ("get_source_stats", json!({})),
+            ("get_source_report", json!({ "sourceId": "wordnet" })),
+            ("get_attributions", json!({})),
+            ("get_senses", json!({ "word": "truth" })),
+            ("get_missed_words", json!({})),
+            ("get_session_lookups", json!({})),
+            ("set_window_context", json!({ "bookId": 1 })),
+            ("clear_session_lookups", json!({})),
+            (
+                "define_selection",
+                json!({ "selection": "fortune", "context": "a good fortune", "bookId": 1 }),
+            ),
+            ("open_shared", json!({ "payloads": ["possession"] })),
+            ("take_incoming", json!({})),
+            ("get_entry_details", json!({ "word": "truth" })),
+            ("select_entry_ids", json!({ "word": "truth" })),
+            ("get_entry_tags", json!({ "entryId": 1 })),
+            ("find_word_in_library", json!({ "word": "fortune" })),
+            ("get_hyphenation", json!({ "word": "acknowledged" })),
+            ("get_word_family", json!({ "word": "truth" })),
+            ("get_etymology_chain", json!({ "word": "truth" })),
+            ("get_unknown_words", json!({})),
+            ("generate_quiz", json!({})),
+            ("get_setting", json!({ "key": "theme" })),
+            ("list_profiles", json!({})),
+            ("list_books", json!({})),
+            ("get_book_tags", json!({ "bookId": 1 })),
+            ("export_library", json!({ "path": export, "format": "csv" })),
+            ("get_text_book_outline", json!({ "bookId": 1 })),
+            ("get_book_content", json!({ "bookId": 1, "chapter": 0 })),
+            ("pause_tts", json!({})),
+            ("resume_tts", json!({})),
+            ("stop_tts", json!({})),
+            ("get_epub_structure", json!({ "bookId": 2 })),
+            (
+                "get_epub_resource",
+                json!({ "bookId": 2, "href": "OEBPS/text/chapter2.xhtml" }),
+            ),
+            (
+                "extract_pdf_text",
+                json!({ "bookId": 3, "pageRange": { "first": 1, "last": 1 } }),
+            ),
+            ("cancel_book_index", json!({ "bookId": 1 })),
+            ("index_status", json!({})),
+            ("cancel_index_rebuild", json!({})),
+            ("search_books", json!({ "query": "truth" })),
+            (
+                "search_book_in_order",
+                json!({ "bookId": 1, "query": "truth" }),
+            ),
+            ("get_book_vocabulary", json!({ "bookId": 1 })),
+            ("get_book_glossary", json!({ "bookId": 1 })),
+            ("get_book_progress", json!({ "bookId": 1 })),
+            ("get_review_sessions", json!({})),
+            ("get_reading_queue", json!({})),
+            ("get_analytics_summary", json!({ "period": "week" })),
+            ("generate_weekly_summary", json!({ "weekOffset": 0 })),
+            ("get_summary_history", json!({})),
+            ("check_words", json!({ "words": ["truth", "whale"] })),
+            ("get_vocab_growth", json!({})),
+            ("get_diagnostics", json!({})),
+            ("get_db_status", json!({})),
+            ("health_check", json!({})),
+            ("check_pack_updates", json!({})),
+        ];
+        // Refused for reasons of their own, which have nothing to do with the database
+        let refused = [
+            ("speak_text", json!({ "bookId": 1, "textChunks": [] })),
+            (
+                "browse_opds",
+                json!({ "url": "https://standardebooks.org/feeds/opds" }),
+            ),
+            ("unlock", json!({ "passphrase": "secret" })),
+        ];
+        for command in READ_COMMANDS {
+            assert!(
+                reads
+                    .iter()
+                    .chain(&refused)
+                    .any(|(read, _)| read == command),
+                "{} isn't tried",
+                command
+            );
+        }
+
+        let changes = || -> i64 {
+            let state = webview.state::<DbState>();
+            let conn = state.0.lock().unwrap();
+            conn.query_row("SELECT total_changes()", [], |row| row.get(0))
+                .unwrap()
+        };
+        for (command, args) in reads {
+            if let Err(error) = call(&webview, command, args) {
+                panic!("{} failed: {}", command, error);
+            }
+        }
+        for (command, args) in refused {
+            let error = call(&webview, command, args).err().unwrap().to_string();
+            assert!(
+                !error.contains("read_only_mode") && !error.contains("readonly"),
+                "{}: {}",
+                command,
+                error
+            );
+        }
+
+        let searched = call(&webview, "search_dictionary", json!({ "word": "fortune" }))
+            .unwrap()
+            .deserialize::<serde_json::Value>()
+            .unwrap();
+        assert!(searched.to_string().contains("A large amount of money."));
+        let page = call(
+            &webview,
+            "extract_pdf_text",
+            json!({ "bookId": 3, "pageRange": { "first": 1, "last": 1 } }),
+        )
+        .unwrap()
+        .deserialize::<serde_json::Value>()
+        .unwrap();
+        assert_eq!(page[0]["text"], "Walden Pond.");
+        assert!(fs::metadata(&export).is_ok());
+
+        let before = changes();
+        let writers = [
+            (
+                "add_book",
+                json!({ "path": dir.join("emma.txt").to_string_lossy() }),
+            ),
+            (
+                "set_word_known",
+                json!({ "word": "fortune", "known": true }),
+            ),
+            (
+                "bulk_update_entries",
+                json!({ "entryIds": [1], "changes": { "addTags": ["austen"] } }),
+            ),
+            ("update_pack", json!({ "id": "wordnet" })),
+            ("store_summary", json!({ "weekOffset": 0 })),
+        ];
+        for (command, args) in writers {
+            assert_eq!(
+                call(&webview, command, args).map(drop),
+                Err(json!({ "kind": "read_only_mode", "command": command, "reason": "flag" }))
+            );
+        }
+        assert_eq!(changes(), before);
+
+        drop(webview);
+        drop(app);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
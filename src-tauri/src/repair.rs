@@ -0,0 +1,181 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use tauri::{Emitter, Manager, Runtime};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::db::{self, DbState};
+use crate::{migrations, opds, settings, source_reports};
+
+/// Where `repair_dictionary` downloads the dictionary pack from, overridable via settings
+pub const PACK_URL_SETTING: &str = "dictionary_pack_url";
+const DEFAULT_PACK_URL: &str =
+    "https://github.com/HarshalPatel1972/open_read/releases/latest/download/dictionary.json";
+
+const REPAIR_KEY: &str = "dictionary_repair";
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RepairSource {
+    /// A dictionary JSON on disk; without a path the user picks one in a file dialog
+    File {
+        path: Option<String>,
+    },
+    Download,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DictionaryRepair {
+    repaired_at: i64,
+    entries: usize,
+    /// The file path or URL the dictionary came from
+    origin: String,
+}
+
+#[derive(Serialize)]
+pub struct BundledResource {
+    found: bool,
+    path: Option<String>,
+    /// Fallback words in use because the bundled dictionary never loaded
+    fallback_entries: i64,
+    repair: Option<DictionaryRepair>,
+}
+
+impl BundledResource {
+    /// The package shipped without its dictionary and none was repaired in its place
+    pub fn is_missing(&self) -> bool {
+        !self.found && self.repair.is_none()
+    }
+
+    pub fn fallback_entries(&self) -> i64 {
+        self.fallback_entries
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct BundledMissing {
+    path: Option<String>,
+}
+
+pub fn bundled_status<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    conn: &Connection,
+) -> rusqlite::Result<BundledResource> {
+    let path = db::bundled_dictionary_path(app);
+    let fallback_entries = conn.query_row(
+        "SELECT COUNT(*) FROM dictionary WHERE source = 'fallback'",
+        [],
+        |row| row.get(0),
+    )?;
+    let repair =
+        migrations::get_meta(conn, REPAIR_KEY)?.and_then(|json| serde_json::from_str(&json).ok());
+
+    Ok(BundledResource {
+        found: path.as_ref().is_some_and(|p| p.is_file()),
+        path: path.map(|p| p.to_string_lossy().into_owned()),
+        fallback_entries,
+        repair,
+    })
+}
+
+/// Warn at startup when the package shipped without its dictionary and it hasn't been
+/// repaired since, so a broken install doesn't pass for a tiny dictionary
+pub fn check_bundled(app: &tauri::AppHandle) {
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+    let Ok(status) = bundled_status(app, &conn) else {
+        return;
+    };
+    if status.is_missing() {
+        eprintln!(
+            "Bundled dictionary not found at {}",
+            status.path.as_deref().unwrap_or("(no resource directory)")
+        );
+        let _ = app.emit(
+            "bundled-dictionary-missing",
+            BundledMissing { path: status.path },
+        );
+    }
+}
+
+fn pick_file(app: &tauri::AppHandle) -> Result<String, String> {
+    let picked = app
+        .dialog()
+        .file()
+        .add_filter("Dictionary", &["json"])
+        .blocking_pick_file()
+        .ok_or_else(|| "No dictionary file was chosen".to_string())?;
+    let path = picked.into_path().map_err(|e| e.to_string())?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Fetch the dictionary pack, returning its JSON and the URL it came from
+pub fn download_pack(app: &tauri::AppHandle) -> Result<(String, String), String> {
+    opds::ensure_online(app)?;
+    let url = {
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        settings::get(&conn, PACK_URL_SETTING)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_else(|| DEFAULT_PACK_URL.to_string())
+    };
+
+    let response = ureq::get(&url)
+        .set("User-Agent", opds::USER_AGENT)
+        .call()
+        .map_err(|e| e.to_string())?;
+    let mut json = String::new();
+    response
+        .into_reader()
+        .read_to_string(&mut json)
+        .map_err(|e| e.to_string())?;
+    Ok((json, url))
+}
+
+fn repair(app: &tauri::AppHandle, source: RepairSource) -> Result<DictionaryRepair, String> {
+    let (json, origin) = match source {
+        RepairSource::File { path } => {
+            let path = match path {
+                Some(path) => path,
+                None => pick_file(app)?,
+            };
+            (fs::read_to_string(&path).map_err(|e| e.to_string())?, path)
+        }
+        RepairSource::Download => download_pack(app)?,
+    };
+
+    let state = app.state::<DbState>();
+    let conn = state.0.lock().unwrap();
+    let entries = db::replace_bundled(&conn, &json)?;
+
+    let repair = DictionaryRepair {
+        repaired_at: conn
+            .query_row("SELECT unixepoch()", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?,
+        entries,
+        origin,
+    };
+    migrations::set_meta(
+        &conn,
+        REPAIR_KEY,
+        &serde_json::to_string(&repair).unwrap_or_default(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let _ = app.emit("dictionary-repaired", repair.clone());
+    source_reports::analyze_in_background(app, "bundled");
+    Ok(repair)
+}
+
+/// Replace a missing or broken bundled dictionary from a file or a download. The data goes
+/// through the same import as the bundled file and is stored under the "bundled" source.
+#[tauri::command]
+pub async fn repair_dictionary(
+    source: RepairSource,
+    app: tauri::AppHandle,
+) -> Result<DictionaryRepair, String> {
+    tauri::async_runtime::spawn_blocking(move || repair(&app, source))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,562 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::analytics;
+use crate::db::{self, DbState};
+use crate::read_only;
+
+const DEFAULT_LIMIT: i64 = 20;
+const START_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+/// Ease lost on each lapse
+const LAPSE_PENALTY: f64 = 0.2;
+/// A lapsed card comes back this soon, within the same sitting if it's long enough
+const RELEARN_SECONDS: i64 = 600;
+
+#[derive(Serialize)]
+pub struct ReviewCard {
+    word: String,
+    definition: Option<String>,
+    due_at: i64,
+    reps: i64,
+    lapses: i64,
+}
+
+#[derive(Serialize)]
+pub struct ReviewSession {
+    id: i64,
+    /// When the time box runs out, if there is one
+    expires_at: Option<i64>,
+    cards: Vec<ReviewCard>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewAnswer {
+    word: String,
+    correct: bool,
+    /// Time the user took to answer, measured by the frontend
+    elapsed_ms: i64,
+}
+
+/// Where a card stands after an answer
+#[derive(Serialize)]
+pub struct Scheduled {
+    word: String,
+    due_at: i64,
+    interval_days: f64,
+    lapsed: bool,
+}
+
+#[derive(Serialize)]
+pub struct ReviewSummary {
+    session_id: i64,
+    started_at: i64,
+    ended_at: i64,
+    /// Closed because its time box ran out or it was left open, rather than ended
+    auto_closed: bool,
+    cards_seen: i64,
+    answers: i64,
+    /// Share of answers that were correct; `None` without answers
+    accuracy: Option<f64>,
+    average_response_ms: Option<f64>,
+    lapsed: Vec<String>,
+}
+
+/// The active profile's review cards with their schedule, the review sessions and the
+/// answers given in them. Answers are kept per session so a summary can be rebuilt; the
+/// schedule only ever moves on a submitted answer.
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS review_cards (
+            profile_id INTEGER NOT NULL,
+            word TEXT NOT NULL COLLATE NOCASE,
+            due_at INTEGER NOT NULL,
+            interval_days REAL NOT NULL DEFAULT 0,
+            ease REAL NOT NULL DEFAULT 2.5,
+            reps INTEGER NOT NULL DEFAULT 0,
+            lapses INTEGER NOT NULL DEFAULT 0,
+            added_at INTEGER NOT NULL,
+            PRIMARY KEY (profile_id, word)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS review_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            profile_id INTEGER NOT NULL,
+            started_at INTEGER NOT NULL,
+            expires_at INTEGER,
+            ended_at INTEGER,
+            auto_closed INTEGER NOT NULL DEFAULT 0,
+            cards TEXT NOT NULL,
+            cards_seen INTEGER,
+            answers INTEGER,
+            correct INTEGER,
+            average_response_ms REAL,
+            lapsed TEXT
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS review_answers (
+            session_id INTEGER NOT NULL REFERENCES review_sessions(id) ON DELETE CASCADE,
+            word TEXT NOT NULL COLLATE NOCASE,
+            correct INTEGER NOT NULL,
+            elapsed_ms INTEGER NOT NULL,
+            answered_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_review_answers_session ON review_answers(session_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Write a session's summary from its answers and mark it ended. Unanswered cards are
+/// simply not counted.
+fn close(conn: &Connection, session_id: i64, auto_closed: bool) -> Result<()> {
+    conn.execute(
+        "UPDATE review_sessions SET
+            ended_at = CASE WHEN ?2 AND expires_at < unixepoch() THEN expires_at
+                            ELSE unixepoch() END,
+            auto_closed = ?2,
+            cards_seen = (SELECT COUNT(DISTINCT word) FROM review_answers WHERE session_id = ?1),
+            answers = (SELECT COUNT(*) FROM review_answers WHERE session_id = ?1),
+            correct = (SELECT COALESCE(SUM(correct), 0) FROM review_answers
+                       WHERE session_id = ?1),
+            average_response_ms = (SELECT AVG(elapsed_ms) FROM review_answers
+                                   WHERE session_id = ?1),
+            lapsed = (SELECT json_group_array(word) FROM
+                          (SELECT DISTINCT word FROM review_answers
+                           WHERE session_id = ?1 AND correct = 0 ORDER BY word))
+         WHERE id = ?1 AND ended_at IS NULL",
+        params![session_id, auto_closed],
+    )?;
+    Ok(())
+}
+
+/// Close the sessions whose time box has run out. With `all`, close every open session,
+/// in any profile, as on exit. A read-only database keeps them open until the next
+/// writable run.
+fn close_stale(conn: &Connection, all: bool) -> Result<()> {
+    if read_only::is_read_only(conn) {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id FROM review_sessions
+         WHERE ended_at IS NULL AND (?1 OR expires_at <= unixepoch())",
+    )?;
+    let ids = stmt
+        .query_map(params![all], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<_>>>()?;
+    for id in ids {
+        close(conn, id, true)?;
+    }
+    Ok(())
+}
+
+/// Auto-close every session left open. Called on exit.
+pub fn close_all_open(conn: &Connection) -> Result<()> {
+    close_stale(conn, true)
+}
+
+fn summary(conn: &Connection, session_id: i64) -> Result<Option<ReviewSummary>> {
+    conn.query_row(
+        "SELECT id, started_at, ended_at, auto_closed, cards_seen, answers, correct,
+                average_response_ms, lapsed
+         FROM review_sessions
+         WHERE id = ? AND ended_at IS NOT NULL
+           AND profile_id = (SELECT id FROM temp.current_profile)",
+        params![session_id],
+        |row| {
+            let answers: i64 = row.get(5)?;
+            let correct: i64 = row.get(6)?;
+            let lapsed: String = row.get(8)?;
+            Ok(ReviewSummary {
+                session_id: row.get(0)?,
+                started_at: row.get(1)?,
+                ended_at: row.get(2)?,
+                auto_closed: row.get(3)?,
+                cards_seen: row.get(4)?,
+                answers,
+                accuracy: (answers > 0).then(|| correct as f64 / answers as f64),
+                average_response_ms: row.get(7)?,
+                lapsed: serde_json::from_str(&lapsed).unwrap_or_default(),
+            })
+        },
+    )
+    .optional()
+}
+
+fn due_cards(conn: &Connection, limit: i64) -> Result<Vec<ReviewCard>> {
+    let mut stmt = conn.prepare(
+        "SELECT word, due_at, reps, lapses FROM review_cards
+         WHERE profile_id = (SELECT id FROM temp.current_profile) AND due_at <= unixepoch()
+         ORDER BY due_at, word LIMIT ?",
+    )?;
+    let cards = stmt
+        .query_map(params![limit], |row| {
+            Ok(ReviewCard {
+                word: row.get(0)?,
+                definition: None,
+                due_at: row.get(1)?,
+                reps: row.get(2)?,
+                lapses: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    cards
+        .into_iter()
+        .map(|card| {
+            Ok(ReviewCard {
+                definition: db::lookup(conn, &card.word)?.into_iter().next(),
+                ..card
+            })
+        })
+        .collect()
+}
+
+/// Move a card's schedule on by one answer. A correct answer grows the interval by the
+/// card's ease (a new or relearning card goes to one day); a wrong one is a lapse, which
+/// lowers the ease and brings the card back in a few minutes.
+fn schedule(conn: &Connection, word: &str, correct: bool) -> Result<Scheduled> {
+    let (interval, ease): (f64, f64) = conn.query_row(
+        "SELECT interval_days, ease FROM review_cards
+         WHERE profile_id = (SELECT id FROM temp.current_profile) AND word = ?",
+        params![word],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let (interval, ease, delay) = if correct {
+        let interval = if interval < 1.0 { 1.0 } else { interval * ease };
+        (interval, ease, (interval * 86400.0).round() as i64)
+    } else {
+        (0.0, (ease - LAPSE_PENALTY).max(MIN_EASE), RELEARN_SECONDS)
+    };
+    let due_at = conn.query_row(
+        "UPDATE review_cards SET
+            due_at = unixepoch() + ?2, interval_days = ?3, ease = ?4,
+            reps = reps + ?5, lapses = lapses + ?6
+         WHERE profile_id = (SELECT id FROM temp.current_profile) AND word = ?1
+         RETURNING due_at",
+        params![word, delay, interval, ease, correct, !correct],
+        |row| row.get(0),
+    )?;
+    Ok(Scheduled {
+        word: word.to_string(),
+        due_at,
+        interval_days: interval,
+        lapsed: !correct,
+    })
+}
+
+/// Add a word to the active profile's review cards, due right away. Returns false if it
+/// was already there.
+#[tauri::command]
+pub fn add_review_card(word: &str, state: tauri::State<DbState>) -> Result<bool, String> {
+    let word = db::normalize(word);
+    if word.is_empty() {
+        return Err("The word can't be empty".to_string());
+    }
+    let conn = state.user_data()?;
+    let added = conn
+        .execute(
+            "INSERT OR IGNORE INTO review_cards (profile_id, word, due_at, ease, added_at)
+             VALUES ((SELECT id FROM temp.current_profile), ?, unixepoch(), ?, unixepoch())",
+            params![word, START_EASE],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(added > 0)
+}
+
+/// Start a review of up to `limit` due cards, ended by `end_review_session` or, with a
+/// time box, closed on its own once the time is up. Starting closes any session the
+/// active profile left open.
+#[tauri::command]
+pub fn start_review_session(
+    limit: Option<i64>,
+    time_box_minutes: Option<u32>,
+    state: tauri::State<DbState>,
+) -> Result<ReviewSession, String> {
+    let conn = state.user_data()?;
+    close_stale(&conn, false).map_err(|e| e.to_string())?;
+    let open: Vec<i64> = conn
+        .prepare(
+            "SELECT id FROM review_sessions
+             WHERE ended_at IS NULL AND profile_id = (SELECT id FROM temp.current_profile)",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<_>>>()
+        })
+        .map_err(|e| e.to_string())?;
+    for id in open {
+        close(&conn, id, true).map_err(|e| e.to_string())?;
+    }
+
+    let cards =
+        due_cards(&conn, limit.unwrap_or(DEFAULT_LIMIT).max(1)).map_err(|e| e.to_string())?;
+    let words: Vec<&str> = cards.iter().map(|c| c.word.as_str()).collect();
+    let (id, expires_at) = conn
+        .query_row(
+            "INSERT INTO review_sessions (profile_id, started_at, expires_at, cards)
+             VALUES ((SELECT id FROM temp.current_profile), unixepoch(),
+                     unixepoch() + ?1 * 60, ?2)
+             RETURNING id, expires_at",
+            params![
+                time_box_minutes,
+                serde_json::to_string(&words).unwrap_or_default()
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    Ok(ReviewSession {
+        id,
+        expires_at,
+        cards,
+    })
+}
+
+/// Record an answer in an open session and reschedule its card. Only cards the session
+/// was started with can be answered; a session past its time box is closed instead.
+#[tauri::command]
+pub fn submit_review(
+    session_id: i64,
+    answer: ReviewAnswer,
+    state: tauri::State<DbState>,
+) -> Result<Scheduled, String> {
+    let conn = state.user_data()?;
+    close_stale(&conn, false).map_err(|e| e.to_string())?;
+    let word = db::normalize(&answer.word);
+
+    let open: Option<bool> = conn
+        .query_row(
+            "SELECT ended_at IS NULL, EXISTS(SELECT 1 FROM json_each(cards) WHERE value = ?2)
+             FROM review_sessions
+             WHERE id = ?1 AND profile_id = (SELECT id FROM temp.current_profile)",
+            params![session_id, word],
+            |row| Ok(row.get::<_, bool>(0)? && row.get::<_, bool>(1)?),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match open {
+        None => return Err(format!("Review session {} not found", session_id)),
+        Some(false) => {
+            return Err(format!(
+                "Review session {} has ended or doesn't include \"{}\"",
+                session_id, word
+            ))
+        }
+        Some(true) => {}
+    }
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO review_answers (session_id, word, correct, elapsed_ms, answered_at)
+         VALUES (?, ?, ?, ?, unixepoch())",
+        params![session_id, word, answer.correct, answer.elapsed_ms.max(0)],
+    )
+    .map_err(|e| e.to_string())?;
+    let scheduled = schedule(&tx, &word, answer.correct).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(scheduled)
+}
+
+/// End a session and return its summary; a session that already closed on its own
+/// returns the summary it closed with
+#[tauri::command]
+pub fn end_review_session(
+    session_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<ReviewSummary, String> {
+    let conn = state.user_data()?;
+    close_stale(&conn, false).map_err(|e| e.to_string())?;
+    let owned: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM review_sessions
+                           WHERE id = ? AND profile_id = (SELECT id FROM temp.current_profile))",
+            params![session_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !owned {
+        return Err(format!("Review session {} not found", session_id));
+    }
+    close(&conn, session_id, false).map_err(|e| e.to_string())?;
+
+    let summary = summary(&conn, session_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Review session {} not found", session_id))?;
+    analytics::record_event(
+        &conn,
+        "review_session_ended",
+        serde_json::json!({
+            "cards_seen": summary.cards_seen,
+            "answers": summary.answers,
+            "lapses": summary.lapsed.len(),
+        }),
+    );
+    Ok(summary)
+}
+
+/// Summaries of the active profile's finished sessions, most recent first
+#[tauri::command]
+pub fn get_review_sessions(
+    limit: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<ReviewSummary>, String> {
+    let conn = state.user_data()?;
+    close_stale(&conn, false).map_err(|e| e.to_string())?;
+    let ids: Vec<i64> = conn
+        .prepare(
+            "SELECT id FROM review_sessions
+             WHERE profile_id = (SELECT id FROM temp.current_profile) AND ended_at IS NOT NULL
+             ORDER BY ended_at DESC LIMIT ?",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![limit.unwrap_or(-1)], |row| row.get(0))?
+                .collect::<Result<Vec<_>>>()
+        })
+        .map_err(|e| e.to_string())?;
+    let mut summaries = Vec::new();
+    for id in ids {
+        summaries.extend(summary(&conn, id).map_err(|e| e.to_string())?);
+    }
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::Manager;
+
+    fn app() -> tauri::App<tauri::test::MockRuntime> {
+        let app = tauri::test::mock_app();
+        let conn = db::init_db(None).unwrap();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('whale', 'A large sea mammal.')",
+            [],
+        )
+        .unwrap();
+        app.manage(DbState::new(conn, false));
+        for word in ["whale", "harpoon", "cetology"] {
+            assert!(add_review_card(word, app.state()).unwrap());
+        }
+        app
+    }
+
+    fn answer(word: &str, correct: bool, elapsed_ms: i64) -> ReviewAnswer {
+        ReviewAnswer {
+            word: word.to_string(),
+            correct,
+            elapsed_ms,
+        }
+    }
+
+    /// (due in seconds from now, interval days, ease, reps, lapses)
+    fn card(app: &tauri::App<tauri::test::MockRuntime>, word: &str) -> (i64, f64, f64, i64, i64) {
+        let state = app.state::<DbState>();
+        let conn = state.0.lock().unwrap();
+        conn.query_row(
+            "SELECT due_at - unixepoch(), interval_days, ease, reps, lapses
+             FROM review_cards WHERE word = ?",
+            params![word],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )
+        .unwrap()
+    }
+
+    fn assert_due_in(due: i64, seconds: i64) {
+        assert!(
+            (due - seconds).abs() <= 5,
+            "due in {}s, not {}s",
+            due,
+            seconds
+        );
+    }
+
+    #[test]
+    fn a_session_with_a_lapse() {
+        let app = app();
+        let session = start_review_session(Some(10), None, app.state()).unwrap();
+        let words: Vec<_> = session.cards.iter().map(|c| c.word.as_str()).collect();
+        assert_eq!(words, ["cetology", "harpoon", "whale"]);
+        assert_eq!(
+            session.cards[2].definition.as_deref(),
+            Some("A large sea mammal.")
+        );
+        assert_eq!(session.expires_at, None);
+
+        let whale = submit_review(session.id, answer("whale", true, 1200), app.state()).unwrap();
+        assert!(!whale.lapsed);
+        let lapse = submit_review(session.id, answer("Harpoon", false, 3000), app.state()).unwrap();
+        assert!(lapse.lapsed);
+        assert_due_in(card(&app, "harpoon").0, RELEARN_SECONDS);
+        // Relearned within the same sitting
+        submit_review(session.id, answer("harpoon", true, 1800), app.state()).unwrap();
+
+        let summary = end_review_session(session.id, app.state()).unwrap();
+        assert!(!summary.auto_closed);
+        assert_eq!(summary.cards_seen, 2);
+        assert_eq!(summary.answers, 3);
+        assert_eq!(summary.accuracy, Some(2.0 / 3.0));
+        assert_eq!(summary.average_response_ms, Some(2000.0));
+        assert_eq!(summary.lapsed, ["harpoon"]);
+
+        let (due, interval, ease, reps, lapses) = card(&app, "whale");
+        assert_due_in(due, 86400);
+        assert_eq!((interval, ease, reps, lapses), (1.0, START_EASE, 1, 0));
+        let (due, interval, ease, reps, lapses) = card(&app, "harpoon");
+        assert_due_in(due, 86400);
+        assert_eq!((interval, reps, lapses), (1.0, 1, 1));
+        assert!((ease - (START_EASE - LAPSE_PENALTY)).abs() < 1e-9);
+        // Never answered, so still due as it was
+        let (due, interval, ease, reps, lapses) = card(&app, "cetology");
+        assert!(due <= 0);
+        assert_eq!((interval, ease, reps, lapses), (0.0, START_EASE, 0, 0));
+
+        // Ending twice returns the same summary; the session takes no more answers
+        assert_eq!(
+            end_review_session(session.id, app.state()).unwrap().answers,
+            3
+        );
+        assert!(submit_review(session.id, answer("cetology", true, 900), app.state()).is_err());
+    }
+
+    #[test]
+    fn an_abandoned_session_closes_on_the_next_start() {
+        let app = app();
+        let first = start_review_session(Some(2), None, app.state()).unwrap();
+        assert_eq!(first.cards.len(), 2);
+        submit_review(first.id, answer("cetology", false, 500), app.state()).unwrap();
+        // Not part of this session
+        assert!(submit_review(first.id, answer("whale", true, 500), app.state()).is_err());
+
+        let second = start_review_session(None, Some(5), app.state()).unwrap();
+        let words: Vec<_> = second.cards.iter().map(|c| c.word.as_str()).collect();
+        assert_eq!(words, ["harpoon", "whale"]);
+        assert!(second.expires_at.is_some());
+
+        let summaries = get_review_sessions(None, app.state()).unwrap();
+        assert_eq!(summaries.len(), 1);
+        let abandoned = &summaries[0];
+        assert_eq!(abandoned.session_id, first.id);
+        assert!(abandoned.auto_closed);
+        assert_eq!((abandoned.cards_seen, abandoned.answers), (1, 1));
+        assert_eq!(abandoned.lapsed, ["cetology"]);
+
+        // Only the submitted answer moved a schedule
+        assert_eq!(card(&app, "cetology").4, 1);
+        assert_eq!(card(&app, "harpoon").3, 0);
+    }
+}
@@ -0,0 +1,287 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::Runtime;
+
+use crate::db::{self, DbState};
+use crate::window_context::WindowContexts;
+use crate::{analytics, history, snippet};
+
+/// Selections longer than this are treated as "more than a sentence"
+const MAX_SELECTION_WORDS: usize = 12;
+const MAX_SELECTION_CHARS: usize = 200;
+const MAX_CONTEXT_CHARS: usize = 300;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind")]
+pub enum SelectionError {
+    /// Nothing was left after trimming punctuation and whitespace
+    Empty,
+    /// The selection spans more than a sentence; the frontend should ask the user to select less
+    TooLong {
+        max_words: usize,
+    },
+    Database {
+        message: String,
+    },
+}
+
+/// The database is locked, so there's no history to record the lookup in
+impl From<String> for SelectionError {
+    fn from(message: String) -> Self {
+        SelectionError::Database { message }
+    }
+}
+
+impl From<rusqlite::Error> for SelectionError {
+    fn from(e: rusqlite::Error) -> Self {
+        SelectionError::Database {
+            message: e.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LookupMode {
+    /// A single word, looked up like a normal search
+    Word,
+    /// The whole selection matched a dictionary headword
+    Phrase,
+    /// No phrase entry, so each token was looked up separately
+    Tokens,
+}
+
+#[derive(Serialize, Clone)]
+pub struct TokenResult {
+    token: String,
+    definitions: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SelectionResult {
+    selection: String,
+    mode: LookupMode,
+    definitions: Vec<String>,
+    tokens: Vec<TokenResult>,
+    context: Option<String>,
+}
+
+/// Join words hyphenated across line breaks, collapse whitespace, and trim surrounding punctuation
+pub fn clean_selection(raw: &str) -> String {
+    let joined = raw.replace("-\r\n", "").replace("-\n", "");
+    let collapsed = joined.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Split a cleaned selection into unique tokens, keeping their first-seen order
+pub fn tokenize(cleaned: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for token in cleaned
+        .split(' ')
+        .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|t| !t.is_empty())
+    {
+        if !tokens.iter().any(|existing| existing == token) {
+            tokens.push(token.to_string());
+        }
+    }
+    tokens
+}
+
+/// True if the text contains a sentence break followed by a capital letter. Full stops of
+/// abbreviations and initials ("Dr. Who", "e.g. this") aren't breaks.
+fn spans_sentences(text: &str) -> bool {
+    let text = text.trim();
+    snippet::sentence_ends(text)
+        .into_iter()
+        .any(|end| text[end..].trim_start().starts_with(char::is_uppercase))
+}
+
+/// Split a paragraph into sentences at terminators followed by whitespace
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((_, c)) = chars.next() {
+        if matches!(c, '.' | '!' | '?') {
+            if let Some(&(next, n)) = chars.peek() {
+                if n.is_whitespace() {
+                    sentences.push(&text[start..next]);
+                    start = next;
+                }
+            }
+        }
+    }
+    sentences.push(&text[start..]);
+
+    sentences
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// The sentence of `context` that contains the selection, whitespace-collapsed and capped in length
+pub fn context_sentence(context: &str, cleaned: &str) -> Option<String> {
+    split_sentences(context)
+        .into_iter()
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|s| s.to_lowercase().contains(cleaned))
+        .map(|s| s.chars().take(MAX_CONTEXT_CHARS).collect())
+}
+
+/// Define a selection in the calling window, attributed to `book_id` or else to the book
+/// the window's context names
+#[tauri::command]
+pub fn define_selection<R: Runtime>(
+    selection: String,
+    context: String,
+    book_id: Option<i64>,
+    window: tauri::Window<R>,
+    contexts: tauri::State<WindowContexts>,
+    state: tauri::State<DbState>,
+) -> Result<SelectionResult, SelectionError> {
+    let book_id = book_id.or_else(|| contexts.book_id(window.label()));
+    let conn = state.user_data()?;
+    define(&conn, &selection, &context, book_id)
+}
+
+/// Define a selection of up to a sentence, shared by the reader and text shared from
+/// other apps
+pub fn define(
+    conn: &Connection,
+    selection: &str,
+    context: &str,
+    book_id: Option<i64>,
+) -> Result<SelectionResult, SelectionError> {
+    let cleaned = clean_selection(selection);
+    if cleaned.is_empty() {
+        return Err(SelectionError::Empty);
+    }
+
+    let tokens = tokenize(&cleaned);
+    if tokens.len() > MAX_SELECTION_WORDS
+        || cleaned.chars().count() > MAX_SELECTION_CHARS
+        || spans_sentences(selection)
+    {
+        return Err(SelectionError::TooLong {
+            max_words: MAX_SELECTION_WORDS,
+        });
+    }
+
+    let sentence = context_sentence(context, &cleaned);
+
+    let result = if tokens.len() == 1 {
+        let (definitions, fallback_found) = db::lookup_matched(conn, &tokens[0])?;
+        history::record_lookup(
+            conn,
+            &tokens[0],
+            !definitions.is_empty(),
+            fallback_found,
+            book_id,
+            sentence.as_deref(),
+        )?;
+
+        SelectionResult {
+            selection: cleaned,
+            mode: LookupMode::Word,
+            definitions,
+            tokens: Vec::new(),
+            context: sentence,
+        }
+    } else {
+        let definitions = db::lookup_exact(conn, &cleaned)?;
+
+        let mut token_results = Vec::new();
+        let mut token_fallbacks = Vec::new();
+        for token in &tokens {
+            let (definitions, fallback_found) = db::lookup_matched(conn, token)?;
+            token_results.push(TokenResult {
+                token: token.clone(),
+                definitions,
+            });
+            token_fallbacks.push(fallback_found);
+        }
+
+        let mode = if definitions.is_empty() {
+            LookupMode::Tokens
+        } else {
+            LookupMode::Phrase
+        };
+
+        // A phrase hit is one lookup; otherwise each word counts on its own
+        if mode == LookupMode::Phrase {
+            history::record_lookup(conn, &cleaned, true, false, book_id, sentence.as_deref())?;
+        } else {
+            for (token, &fallback_found) in token_results.iter().zip(&token_fallbacks) {
+                history::record_lookup(
+                    conn,
+                    &token.token,
+                    !token.definitions.is_empty(),
+                    fallback_found,
+                    book_id,
+                    sentence.as_deref(),
+                )?;
+            }
+        }
+
+        SelectionResult {
+            selection: cleaned,
+            mode,
+            definitions,
+            tokens: token_results,
+            context: sentence,
+        }
+    };
+
+    analytics::record_event(
+        conn,
+        "define_selection",
+        serde_json::json!({
+            "mode": result.mode,
+            "tokens": tokens.len(),
+            "found": !result.definitions.is_empty()
+                || result.tokens.iter().any(|t| !t.definitions.is_empty()),
+        }),
+    );
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbreviations_are_not_sentence_breaks() {
+        assert!(!spans_sentences("Dr. Who"));
+        assert!(!spans_sentences("e.g. this"));
+        assert!(!spans_sentences("J. R. R. Tolkien"));
+        assert!(!spans_sentences("the end."));
+    }
+
+    #[test]
+    fn sentence_breaks_are_found() {
+        assert!(spans_sentences("It rained. Then it stopped."));
+        assert!(spans_sentences("Really? Yes"));
+    }
+
+    #[test]
+    fn selections_are_cleaned_and_tokenized() {
+        let cleaned = clean_selection("  \"Hyphen-\nated   Words,\" ");
+        assert_eq!(cleaned, "hyphenated words");
+        assert_eq!(tokenize("the cat, the hat"), vec!["the", "cat", "hat"]);
+    }
+
+    #[test]
+    fn context_sentence_is_the_one_holding_the_selection() {
+        let context = "First one here. The   second holds the word. Third.";
+        assert_eq!(
+            context_sentence(context, "word").as_deref(),
+            Some("The second holds the word.")
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::db::{self, DbState};
+
+/// Display order of a word's senses: the user's order, then imported order
+pub const SENSE_ORDER: &str = "sense_order IS NULL, sense_order, id";
+
+/// A headword's senses are its dictionary rows. `sense_order` holds the user's order and
+/// `sense_orders` records which words the user has ordered, by definition text, so the
+/// order survives the bundled rows being replaced.
+pub fn create_table(conn: &Connection) -> Result<()> {
+    db::add_column_if_missing(conn, "dictionary", "sense_order", "INTEGER")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sense_orders (
+            word TEXT PRIMARY KEY COLLATE NOCASE,
+            definitions TEXT NOT NULL,
+            ordered_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct Sense {
+    id: i64,
+    definition: String,
+    pos: Option<String>,
+    source: String,
+}
+
+#[derive(Serialize)]
+pub struct WordSenses {
+    word: String,
+    senses: Vec<Sense>,
+    /// Whether the order was set by the user rather than the import
+    user_ordered: bool,
+}
+
+fn senses_of(conn: &Connection, word: &str) -> Result<WordSenses> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, definition, pos, source FROM dictionary
+         WHERE word = ? COLLATE NOCASE ORDER BY {}",
+        SENSE_ORDER
+    ))?;
+    let senses = stmt
+        .query_map(params![word], |row| {
+            Ok(Sense {
+                id: row.get(0)?,
+                definition: row.get(1)?,
+                pos: row.get(2)?,
+                source: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    let user_ordered = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sense_orders WHERE word = ?)",
+        params![word],
+        |row| row.get(0),
+    )?;
+
+    Ok(WordSenses {
+        word: word.to_string(),
+        senses,
+        user_ordered,
+    })
+}
+
+/// Put the word's senses in the given order. `sense_ids` must be exactly the word's senses,
+/// each once.
+pub fn reorder(conn: &Connection, word: &str, sense_ids: &[i64]) -> Result<WordSenses, String> {
+    let current = senses_of(conn, word).map_err(|e| e.to_string())?;
+    if current.senses.is_empty() {
+        return Err(format!("No senses found for '{}'", word));
+    }
+
+    let known: HashSet<i64> = current.senses.iter().map(|s| s.id).collect();
+    let given: HashSet<i64> = sense_ids.iter().copied().collect();
+    if given.len() != sense_ids.len() {
+        return Err("Each sense can only appear once in the new order".to_string());
+    }
+    if given != known {
+        let mut unknown: Vec<i64> = given.difference(&known).copied().collect();
+        let mut missing: Vec<i64> = known.difference(&given).copied().collect();
+        unknown.sort_unstable();
+        missing.sort_unstable();
+        return Err(format!(
+            "The new order doesn't match the senses of '{}' (not its senses: {:?}, missing: {:?})",
+            word, unknown, missing
+        ));
+    }
+
+    let definitions: Vec<&str> = sense_ids
+        .iter()
+        .filter_map(|id| current.senses.iter().find(|s| s.id == *id))
+        .map(|s| s.definition.as_str())
+        .collect();
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    for (position, id) in sense_ids.iter().enumerate() {
+        tx.execute(
+            "UPDATE dictionary SET sense_order = ? WHERE id = ?",
+            params![position as i64, id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "INSERT INTO sense_orders (word, definitions) VALUES (?, ?)
+         ON CONFLICT(word) DO UPDATE SET
+            definitions = excluded.definitions, ordered_at = unixepoch()",
+        params![
+            word,
+            serde_json::to_string(&definitions).map_err(|e| e.to_string())?
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    senses_of(conn, word).map_err(|e| e.to_string())
+}
+
+/// Restore the user's order after the bundled rows were replaced. Senses are matched by
+/// definition text; ones the user never ordered go after the rest.
+pub fn reapply(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE dictionary SET sense_order = (
+            SELECT j.key
+            FROM sense_orders o, json_each(o.definitions) j
+            WHERE o.word = dictionary.word AND j.value = dictionary.definition
+            ORDER BY j.key
+            LIMIT 1
+         )
+         WHERE word IN (SELECT word FROM sense_orders)",
+        [],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_senses(word: &str, state: tauri::State<DbState>) -> Result<WordSenses, String> {
+    let conn = state.0.lock().unwrap();
+    senses_of(&conn, &db::normalize(word)).map_err(|e| e.to_string())
+}
+
+/// Set the display order of a headword's senses, e.g. to put a rare sense below the
+/// common ones. Fails without changing anything unless `sense_ids` are exactly its senses.
+#[tauri::command]
+pub fn reorder_senses(
+    word: &str,
+    sense_ids: Vec<i64>,
+    state: tauri::State<DbState>,
+) -> Result<WordSenses, String> {
+    let conn = state.user_data()?;
+    reorder(&conn, &db::normalize(word), &sense_ids)
+}
+
+/// Move one sense to the top of its word, keeping the others in their current order
+#[tauri::command]
+pub fn promote_sense(sense_id: i64, state: tauri::State<DbState>) -> Result<WordSenses, String> {
+    let conn = state.user_data()?;
+    let word: String = conn
+        .query_row(
+            "SELECT word FROM dictionary WHERE id = ?",
+            params![sense_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Sense {} not found", sense_id))?;
+
+    let current = senses_of(&conn, &word).map_err(|e| e.to_string())?;
+    let sense_ids: Vec<i64> = std::iter::once(sense_id)
+        .chain(
+            current
+                .senses
+                .iter()
+                .map(|s| s.id)
+                .filter(|id| *id != sense_id),
+        )
+        .collect();
+    reorder(&conn, &word, &sense_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary() -> (Connection, Vec<i64>) {
+        let conn = db::init_db(None).unwrap();
+        let mut ids = Vec::new();
+        for definition in ["To move quickly.", "A score in cricket.", "To operate."] {
+            conn.execute(
+                "INSERT INTO dictionary (word, definition) VALUES ('run', ?)",
+                params![definition],
+            )
+            .unwrap();
+            ids.push(conn.last_insert_rowid());
+        }
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('walk', 'To move on foot.')",
+            [],
+        )
+        .unwrap();
+        ids.push(conn.last_insert_rowid());
+        (conn, ids)
+    }
+
+    fn order(senses: &WordSenses) -> Vec<i64> {
+        senses.senses.iter().map(|s| s.id).collect()
+    }
+
+    #[test]
+    fn reorder_sets_the_display_order() {
+        let (conn, ids) = dictionary();
+        let (a, b, c) = (ids[0], ids[1], ids[2]);
+        assert_eq!(order(&senses_of(&conn, "run").unwrap()), [a, b, c]);
+        assert!(!senses_of(&conn, "run").unwrap().user_ordered);
+
+        let reordered = reorder(&conn, "run", &[c, a, b]).unwrap();
+        assert_eq!(order(&reordered), [c, a, b]);
+        assert!(reordered.user_ordered);
+        assert_eq!(db::lookup_exact(&conn, "RUN").unwrap()[0], "To operate.");
+    }
+
+    #[test]
+    fn mismatched_ids_are_rejected_without_changes() {
+        let (conn, ids) = dictionary();
+        let (a, b, c, walk) = (ids[0], ids[1], ids[2], ids[3]);
+        let cases: [(&[i64], String); 4] = [
+            (
+                &[a, b],
+                format!(
+                    "The new order doesn't match the senses of 'run' (not its senses: [], missing: [{}])",
+                    c
+                ),
+            ),
+            (
+                &[a, b, c, walk],
+                format!(
+                    "The new order doesn't match the senses of 'run' (not its senses: [{}], missing: [])",
+                    walk
+                ),
+            ),
+            (
+                &[a, b, walk],
+                format!(
+                    "The new order doesn't match the senses of 'run' (not its senses: [{}], missing: [{}])",
+                    walk, c
+                ),
+            ),
+            (
+                &[a, a, b, c],
+                "Each sense can only appear once in the new order".to_string(),
+            ),
+        ];
+        for (sense_ids, expected) in cases {
+            assert_eq!(reorder(&conn, "run", sense_ids).err(), Some(expected));
+        }
+        assert_eq!(
+            reorder(&conn, "nothing", &[]).err().as_deref(),
+            Some("No senses found for 'nothing'")
+        );
+
+        let unchanged = senses_of(&conn, "run").unwrap();
+        assert_eq!(order(&unchanged), [a, b, c]);
+        assert!(!unchanged.user_ordered);
+    }
+
+    #[test]
+    fn order_survives_the_rows_being_replaced() {
+        let (conn, ids) = dictionary();
+        reorder(&conn, "run", &[ids[2], ids[0], ids[1]]).unwrap();
+
+        // A pack update replaces the rows and adds a sense the user never ordered
+        conn.execute("DELETE FROM dictionary WHERE word = 'run'", [])
+            .unwrap();
+        for definition in [
+            "A score in cricket.",
+            "A continuous period.",
+            "To operate.",
+            "To move quickly.",
+        ] {
+            conn.execute(
+                "INSERT INTO dictionary (word, definition) VALUES ('run', ?)",
+                params![definition],
+            )
+            .unwrap();
+        }
+        reapply(&conn).unwrap();
+
+        assert_eq!(
+            db::lookup_exact(&conn, "run").unwrap(),
+            [
+                "To operate.",
+                "To move quickly.",
+                "A score in cricket.",
+                "A continuous period.",
+            ]
+        );
+    }
+}
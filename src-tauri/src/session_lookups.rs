@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Runtime;
+
+/// Lookups kept per window for the current run; older ones fall off the front
+const CAPACITY: usize = 50;
+
+#[derive(Serialize, Clone)]
+pub struct SessionLookup {
+    word: String,
+    definition: String,
+    looked_up_at: u64,
+}
+
+/// Words defined since the app started, by the label of the window that looked them up,
+/// so the reader and the lookup popup each keep their own. Guarded by its own lock so
+/// reading it never waits on the database.
+#[derive(Default)]
+pub struct SessionLookups(pub Mutex<HashMap<String, VecDeque<SessionLookup>>>);
+
+impl SessionLookups {
+    /// Remember a successful lookup in a window, folding an immediate repeat into the
+    /// previous entry
+    pub fn push(&self, label: &str, word: &str, definition: &str) {
+        let looked_up_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut windows = self.0.lock().unwrap();
+        let lookups = windows.entry(label.to_string()).or_default();
+        if let Some(last) = lookups.back_mut() {
+            if last.word.eq_ignore_ascii_case(word) {
+                last.looked_up_at = looked_up_at;
+                return;
+            }
+        }
+        if lookups.len() == CAPACITY {
+            lookups.pop_front();
+        }
+        lookups.push_back(SessionLookup {
+            word: word.to_string(),
+            definition: definition.to_string(),
+            looked_up_at,
+        });
+    }
+}
+
+/// The calling window's lookups, most recent first
+#[tauri::command]
+pub fn get_session_lookups<R: Runtime>(
+    window: tauri::Window<R>,
+    lookups: tauri::State<SessionLookups>,
+) -> Vec<SessionLookup> {
+    lookups
+        .0
+        .lock()
+        .unwrap()
+        .get(window.label())
+        .map(|lookups| lookups.iter().rev().cloned().collect())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn clear_session_lookups<R: Runtime>(
+    window: tauri::Window<R>,
+    lookups: tauri::State<SessionLookups>,
+) {
+    lookups.0.lock().unwrap().remove(window.label());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, DbState};
+    use crate::source_stats::SourceCounters;
+
+    fn words(lookups: &SessionLookups, label: &str) -> Vec<String> {
+        lookups.0.lock().unwrap()[label]
+            .iter()
+            .rev()
+            .map(|lookup| lookup.word.clone())
+            .collect()
+    }
+
+    #[test]
+    fn two_windows_interleaving_searches_keep_their_own_lookups() {
+        let conn = db::init_db(None).unwrap();
+        for word in ["whale", "harpoon", "cetology", "ambergris"] {
+            conn.execute(
+                "INSERT INTO dictionary (word, definition) VALUES (?, 'Of whaling.')",
+                [word],
+            )
+            .unwrap();
+        }
+        let state = DbState::new(conn, false);
+        let lookups = SessionLookups::default();
+        let counters = SourceCounters::default();
+
+        for (label, word) in [
+            ("main", "whale"),
+            ("popup", "harpoon"),
+            ("main", "cetology"),
+            // A repeat in one window isn't one in the other
+            ("popup", "Harpoon"),
+            ("popup", "whale"),
+            ("main", "Cetology"),
+            // Not an exact match, so nothing is kept
+            ("main", "amber"),
+            ("popup", "ambergris"),
+        ] {
+            db::search_from(label, word, None, &state, &lookups, &counters).unwrap();
+        }
+
+        assert_eq!(words(&lookups, "main"), ["cetology", "whale"]);
+        assert_eq!(words(&lookups, "popup"), ["ambergris", "whale", "harpoon"]);
+    }
+
+    #[test]
+    fn each_window_is_capped_on_its_own() {
+        let lookups = SessionLookups::default();
+        for i in 0..CAPACITY + 10 {
+            lookups.push("main", &format!("word{}", i), "");
+        }
+        lookups.push("popup", "whale", "");
+
+        let main = words(&lookups, "main");
+        assert_eq!(main.len(), CAPACITY);
+        assert_eq!(main[0], format!("word{}", CAPACITY + 9));
+        assert_eq!(main[CAPACITY - 1], "word10");
+        assert_eq!(words(&lookups, "popup"), ["whale"]);
+    }
+}
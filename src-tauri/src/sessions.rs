@@ -0,0 +1,250 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::analytics;
+use crate::books::{self, BOOK_SOURCE};
+use crate::db::{self, DbState};
+
+/// Number of recent sessions included in a progress record
+const RECENT_SESSIONS: i64 = 10;
+
+/// Progress a session ends at: its profile's current position in the book
+const END_PROGRESS: &str = "COALESCE((SELECT bp.progress FROM book_progress bp
+    WHERE bp.book_id = reading_sessions.book_id
+      AND bp.profile_id = reading_sessions.profile_id), 0.0)";
+
+#[derive(Serialize)]
+pub struct ReadingSession {
+    id: i64,
+    started_at: i64,
+    ended_at: Option<i64>,
+    start_progress: f64,
+    end_progress: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct BookProgress {
+    book_id: i64,
+    progress: f64,
+    locator: Option<String>,
+    finished: bool,
+    finished_at: Option<i64>,
+    total_reading_seconds: i64,
+    session_count: i64,
+    last_read_at: Option<i64>,
+    recent_sessions: Vec<ReadingSession>,
+}
+
+#[derive(Serialize, Clone)]
+struct BookFinished {
+    book_id: i64,
+    finished_at: i64,
+}
+
+/// Create the reading sessions table
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS reading_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER,
+            start_progress REAL NOT NULL,
+            end_progress REAL,
+            profile_id INTEGER NOT NULL DEFAULT 1
+        )",
+        [],
+    )?;
+
+    // Databases from before profiles; their sessions belong to the Default profile
+    db::add_column_if_missing(
+        conn,
+        "reading_sessions",
+        "profile_id",
+        "INTEGER NOT NULL DEFAULT 1",
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_sessions_book ON reading_sessions(book_id, started_at)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Close any sessions the active profile left open for a book (e.g. the app was closed
+/// mid-session)
+fn close_open_sessions(conn: &Connection, book_id: i64) -> Result<()> {
+    conn.execute(
+        &format!(
+            "UPDATE reading_sessions
+             SET ended_at = unixepoch(), end_progress = {}
+             WHERE book_id = ? AND ended_at IS NULL
+               AND profile_id = (SELECT id FROM temp.current_profile)",
+            END_PROGRESS
+        ),
+        params![book_id],
+    )?;
+    Ok(())
+}
+
+/// End every session left open, in any profile, at the current time. Called on exit.
+pub fn close_all_open(conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            "UPDATE reading_sessions SET ended_at = unixepoch(), end_progress = {}
+             WHERE ended_at IS NULL",
+            END_PROGRESS
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Mark the book finished if its progress is past the threshold when a session ends.
+///
+/// Returns the new `finished_at` only the first time, so the event fires once per read-through.
+fn detect_finished(conn: &Connection, book_id: i64) -> Result<Option<i64>> {
+    let threshold = books::finished_threshold(conn);
+    conn.query_row(
+        "UPDATE book_progress SET finished_at = unixepoch()
+         WHERE book_id = ? AND profile_id = (SELECT id FROM temp.current_profile)
+           AND finished_at IS NULL AND progress >= ?
+         RETURNING finished_at",
+        params![book_id, threshold],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+#[tauri::command]
+pub fn start_reading_session(book_id: i64, state: tauri::State<DbState>) -> Result<i64, String> {
+    let conn = state.user_data()?;
+    close_open_sessions(&conn, book_id).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        &format!(
+            "INSERT INTO reading_sessions (profile_id, book_id, started_at, start_progress)
+             SELECT (SELECT id FROM temp.current_profile), b.id, unixepoch(),
+                    COALESCE(p.progress, 0.0)
+             FROM {} WHERE b.id = ?",
+            BOOK_SOURCE
+        ),
+        params![book_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    if conn.changes() == 0 {
+        return Err(format!("Book {} not found", book_id));
+    }
+
+    let session_id = conn.last_insert_rowid();
+    books::touch_book(&conn, book_id).map_err(|e| e.to_string())?;
+
+    Ok(session_id)
+}
+
+#[tauri::command]
+pub fn end_reading_session(
+    session_id: i64,
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<(), String> {
+    let conn = state.user_data()?;
+
+    let book_id: Option<i64> = conn
+        .query_row(
+            &format!(
+                "UPDATE reading_sessions
+                 SET ended_at = unixepoch(), end_progress = {}
+                 WHERE id = ? AND ended_at IS NULL
+                   AND profile_id = (SELECT id FROM temp.current_profile)
+                 RETURNING book_id",
+                END_PROGRESS
+            ),
+            params![session_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let Some(book_id) = book_id else {
+        return Ok(());
+    };
+
+    if let Some(finished_at) = detect_finished(&conn, book_id).map_err(|e| e.to_string())? {
+        analytics::record_event(
+            &conn,
+            "book_finished",
+            serde_json::json!({ "book_id": book_id }),
+        );
+        let _ = app.emit(
+            "book-finished",
+            BookFinished {
+                book_id,
+                finished_at,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+fn progress_for(conn: &Connection, book_id: i64) -> Result<Option<BookProgress>> {
+    let Some(book) = books::get_book(conn, book_id)? else {
+        return Ok(None);
+    };
+
+    let (total_reading_seconds, session_count): (i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(ended_at - started_at), 0), COUNT(*)
+         FROM reading_sessions
+         WHERE book_id = ? AND profile_id = (SELECT id FROM temp.current_profile)
+           AND ended_at IS NOT NULL",
+        params![book_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, started_at, ended_at, start_progress, end_progress
+         FROM reading_sessions
+         WHERE book_id = ? AND profile_id = (SELECT id FROM temp.current_profile)
+         ORDER BY started_at DESC LIMIT ?",
+    )?;
+    let recent_sessions = stmt
+        .query_map(params![book_id, RECENT_SESSIONS], |row| {
+            Ok(ReadingSession {
+                id: row.get(0)?,
+                started_at: row.get(1)?,
+                ended_at: row.get(2)?,
+                start_progress: row.get(3)?,
+                end_progress: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(BookProgress {
+        book_id,
+        progress: book.progress,
+        locator: book.locator,
+        finished: book.finished_at.is_some(),
+        finished_at: book.finished_at,
+        total_reading_seconds,
+        session_count,
+        last_read_at: recent_sessions
+            .first()
+            .map(|s| s.ended_at.unwrap_or(s.started_at)),
+        recent_sessions,
+    }))
+}
+
+#[tauri::command]
+pub fn get_book_progress(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<BookProgress, String> {
+    let conn = state.user_data()?;
+    progress_for(&conn, book_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Book {} not found", book_id))
+}
@@ -0,0 +1,69 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+use crate::db::DbState;
+
+/// When "false", every feature that would touch the network refuses to run
+pub const ONLINE_SETTING: &str = "online_enabled";
+
+/// Create the key/value settings table
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn get(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn set(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?, ?)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// Read a boolean setting, treating a missing or unreadable value as `default`
+pub fn get_bool(conn: &Connection, key: &str, default: bool) -> bool {
+    match get(conn, key) {
+        Ok(Some(value)) => matches!(value.as_str(), "true" | "1"),
+        _ => default,
+    }
+}
+
+/// Read a numeric setting, treating a missing or unparsable value as `default`
+pub fn get_f64(conn: &Connection, key: &str, default: f64) -> f64 {
+    get(conn, key)
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+pub fn online_enabled(conn: &Connection) -> bool {
+    get_bool(conn, ONLINE_SETTING, true)
+}
+
+#[tauri::command]
+pub fn get_setting(key: &str, state: tauri::State<DbState>) -> Result<Option<String>, String> {
+    let conn = state.user_data()?;
+    get(&conn, key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_setting(key: &str, value: &str, state: tauri::State<DbState>) -> Result<(), String> {
+    let conn = state.user_data()?;
+    set(&conn, key, value).map_err(|e| e.to_string())
+}
@@ -0,0 +1,138 @@
+use rusqlite::{Connection, Result};
+use std::sync::mpsc;
+use std::time::Duration;
+use tauri::{Manager, Runtime};
+
+use crate::db::DbState;
+use crate::read_only;
+use crate::review;
+use crate::sessions;
+use crate::source_stats::SourceCounters;
+use crate::tts;
+
+/// Longest the app waits for the shutdown sequence before exiting regardless
+const SHUTDOWN_BUDGET: Duration = Duration::from_secs(3);
+
+/// Save what's only in memory, end open reading and review sessions and close the database.
+/// Each step runs even if an earlier one failed.
+fn shut_down<R: Runtime>(app: &tauri::AppHandle<R>) {
+    // Speech runs in child processes that would otherwise outlive the app
+    tts::stop(app);
+
+    let state = app.state::<DbState>();
+    let mut conn = state.0.lock().unwrap();
+    let report = |step: &str, result: Result<()>| {
+        if let Err(e) = result {
+            eprintln!("Shutdown: failed to {}: {}", step, e);
+        }
+    };
+
+    // A read-only database has nothing to save and no sessions that could have opened
+    if !read_only::is_read_only(&conn) {
+        report(
+            "save source statistics",
+            app.state::<SourceCounters>().flush(&conn),
+        );
+        report("close reading sessions", sessions::close_all_open(&conn));
+        report("close review sessions", review::close_all_open(&conn));
+        // A no-op unless the database is in WAL mode
+        report(
+            "checkpoint the database",
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())),
+        );
+    }
+
+    // Anything still running after this finds an empty in-memory database
+    report(
+        "close the database",
+        Connection::open_in_memory().and_then(|stand_in| {
+            std::mem::replace(&mut *conn, stand_in)
+                .close()
+                .map_err(|(_, e)| e)
+        }),
+    );
+}
+
+/// Run the shutdown sequence on exit. It runs on its own thread so that a command stuck
+/// holding the connection can delay exit by at most `SHUTDOWN_BUDGET`.
+pub fn run(app: &tauri::AppHandle) {
+    let (done, finished) = mpsc::channel();
+    let app = app.clone();
+    std::thread::spawn(move || {
+        shut_down(&app);
+        let _ = done.send(());
+    });
+
+    if finished.recv_timeout(SHUTDOWN_BUDGET).is_err() {
+        eprintln!(
+            "Shutdown didn't finish within {} seconds; exiting anyway",
+            SHUTDOWN_BUDGET.as_secs()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, SearchEntry};
+    use crate::tts::TtsQueue;
+
+    #[test]
+    fn pending_writes_are_there_on_the_next_open() {
+        let dir = std::env::temp_dir().join(format!("open-read-shutdown-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dictionary.db");
+
+        let conn = db::prepare(Connection::open(&path).unwrap()).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             INSERT INTO books (id, path, title, format, added_at)
+             VALUES (1, '/books/walden.txt', 'Walden', 'txt', 0);
+             INSERT INTO reading_sessions (book_id, started_at, start_progress)
+             VALUES (1, unixepoch() - 60, 0.2);
+             INSERT INTO review_sessions (profile_id, started_at, cards)
+             VALUES (1, unixepoch() - 60, '[]');",
+        )
+        .unwrap();
+
+        let app = tauri::test::mock_app();
+        app.manage(DbState::new(conn, false));
+        app.manage(TtsQueue::default());
+        let counters = SourceCounters::default();
+        let hit = SearchEntry {
+            word: "walden".to_string(),
+            definition: "A pond.".to_string(),
+            pos: None,
+            difficulty: None,
+            source: "wordnet".to_string(),
+            truncated: false,
+        };
+        // Queued just before exit, below the threshold that would flush it
+        counters.record(&[hit]);
+        app.manage(counters);
+
+        shut_down(app.handle());
+
+        let reopened = Connection::open(&path).unwrap();
+        let hits: i64 = reopened
+            .query_row(
+                "SELECT hits FROM source_stats WHERE source = 'wordnet'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
+        let open: i64 = reopened
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM reading_sessions WHERE ended_at IS NULL)
+                      + (SELECT COUNT(*) FROM review_sessions WHERE ended_at IS NULL)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(open, 0);
+
+        drop(reopened);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use serde::Serialize;
+
+/// Longest definition shown in search results, in characters; 0 shows definitions whole
+pub const SNIPPET_LENGTH_SETTING: &str = "snippet_length";
+pub const DEFAULT_SNIPPET_LENGTH: f64 = 240.0;
+
+/// A sentence boundary earlier than this fraction of the limit makes too short a snippet,
+/// so the cut falls back to a word boundary
+const MIN_SENTENCE_FRACTION: usize = 3;
+
+/// Abbreviations whose full stop doesn't end a sentence, lowercase and without the final "."
+const ABBREVIATIONS: &[&str] = &[
+    "e.g", "i.e", "etc", "cf", "vs", "viz", "approx", "esp", "incl", "lit", "orig", "fig", "mr",
+    "mrs", "ms", "dr", "st", "jr", "sr", "ca",
+];
+/// Of those, the ones that also end sentences when a capital follows ("colours, etc. The")
+const SENTENCE_FINAL_ABBREVIATIONS: &[&str] = &["etc"];
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    /// The text was cut; the whole definition comes from `get_entry_details`
+    pub truncated: bool,
+}
+
+/// The word directly before the full stop at byte `dot`, lowercase
+fn word_before(text: &str, dot: usize) -> String {
+    text[..dot]
+        .rsplit(|c: char| c.is_whitespace() || matches!(c, '(' | '"' | '\'' | '“' | '‘'))
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+fn is_abbreviation(text: &str, dot: usize, next: Option<char>) -> bool {
+    let word = word_before(text, dot);
+    // Initials and dotted abbreviations: "J. R. R.", "U.S.", "a.m."
+    let initial = word.chars().count() == 1 && word.chars().all(char::is_alphabetic);
+    let dotted = word.contains('.');
+    if SENTENCE_FINAL_ABBREVIATIONS.contains(&word.as_str()) {
+        return !next.is_some_and(char::is_uppercase);
+    }
+    initial || dotted || ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Byte offsets just past each sentence end in `text`: a terminator plus any closing
+/// quotes or brackets, followed by whitespace or the end. Full stops of abbreviations and
+/// terminators inside parentheses don't count.
+pub fn sentence_ends(text: &str) -> Vec<usize> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut ends = Vec::new();
+    let mut depth = 0usize;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let (at, c) = chars[i];
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            '.' | '!' | '?' if depth == 0 => {
+                // Take the whole "?!" or "..." run and trailing closers: `said "no."`
+                let mut j = i + 1;
+                while j < chars.len() && matches!(chars[j].1, '.' | '!' | '?') {
+                    j += 1;
+                }
+                while j < chars.len() && matches!(chars[j].1, '"' | '\'' | '”' | '’' | ')' | ']')
+                {
+                    j += 1;
+                }
+                let end = chars.get(j).map_or(text.len(), |&(offset, _)| offset);
+                let followed_by_space = chars.get(j).is_none_or(|&(_, n)| n.is_whitespace());
+                let next_word = chars[j..]
+                    .iter()
+                    .map(|&(_, n)| n)
+                    .find(|n| !n.is_whitespace() && !matches!(n, '"' | '\'' | '“' | '‘' | '('));
+
+                if followed_by_space && !(c == '.' && is_abbreviation(text, at, next_word)) {
+                    ends.push(end);
+                }
+                i = j;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    ends
+}
+
+/// Shorten `text` to at most `max_chars` characters, cutting at the last sentence end that
+/// fits, else at a word boundary with an ellipsis. Text that fits is returned whole.
+pub fn snippet(text: &str, max_chars: usize) -> Snippet {
+    let text = text.trim();
+    if max_chars == 0 || text.chars().count() <= max_chars {
+        return Snippet {
+            text: text.to_string(),
+            truncated: false,
+        };
+    }
+
+    // Byte offset of the character just past the limit
+    let limit = text
+        .char_indices()
+        .nth(max_chars)
+        .map_or(text.len(), |(at, _)| at);
+    let min_sentence = text
+        .char_indices()
+        .nth(max_chars / MIN_SENTENCE_FRACTION)
+        .map_or(0, |(at, _)| at);
+
+    if let Some(&end) = sentence_ends(text)
+        .iter()
+        .rev()
+        .find(|&&end| end <= limit && end >= min_sentence)
+    {
+        return Snippet {
+            text: text[..end].trim_end().to_string(),
+            truncated: true,
+        };
+    }
+
+    // A space within the limit leaves room for the ellipsis; without one, cut mid-word
+    let cut = text[..limit]
+        .rfind(char::is_whitespace)
+        .filter(|&at| at > 0)
+        .unwrap_or_else(|| {
+            text.char_indices()
+                .nth(max_chars.saturating_sub(1))
+                .map_or(text.len(), |(at, _)| at)
+        });
+    let kept = text[..cut].trim_end_matches(|c: char| {
+        c.is_whitespace() || matches!(c, ',' | ';' | ':' | '-' | '–' | '—' | '(')
+    });
+    Snippet {
+        text: format!("{}…", kept),
+        truncated: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ends(text: &str) -> Vec<&str> {
+        let mut start = 0;
+        sentence_ends(text)
+            .into_iter()
+            .map(|end| {
+                let sentence = text[start..end].trim();
+                start = end;
+                sentence
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sentences_end_outside_quotes_and_parentheses() {
+        assert_eq!(
+            ends(r#"He said "stop." Then he left."#),
+            [r#"He said "stop.""#, "Then he left."]
+        );
+        assert_eq!(
+            ends("A unit of length (see also: metre. Rare.) used in surveying. It varies."),
+            [
+                "A unit of length (see also: metre. Rare.) used in surveying.",
+                "It varies."
+            ]
+        );
+        assert_eq!(
+            ends("Really?! Yes... Quite."),
+            ["Really?!", "Yes...", "Quite."]
+        );
+        assert_eq!(ends("‘Hush.’ She listened."), ["‘Hush.’", "She listened."]);
+        assert_eq!(ends("Version 2.5 is out"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn abbreviations_do_not_end_sentences() {
+        assert_eq!(
+            ends("A fruit, e.g. an apple (cf. pear) or Dr. Smith's plum. Sweet."),
+            [
+                "A fruit, e.g. an apple (cf. pear) or Dr. Smith's plum.",
+                "Sweet."
+            ]
+        );
+        assert_eq!(
+            ends("Named by J. R. R. Tolkien in the U.S. edition. Later dropped."),
+            [
+                "Named by J. R. R. Tolkien in the U.S. edition.",
+                "Later dropped."
+            ]
+        );
+        // "etc." ends a sentence only when a capital follows
+        assert_eq!(
+            ends("Reds, blues, etc. and greens. Colours, etc. The end."),
+            ["Reds, blues, etc. and greens.", "Colours, etc.", "The end."]
+        );
+    }
+
+    #[test]
+    fn snippets_cut_at_sentences_then_words() {
+        let definition = r#"A "bank" (from Italian banca, "bench") is an institution that accepts deposits. It also lends money, e.g. mortgages. Banks are regulated."#;
+
+        let cut = snippet(definition, 100);
+        assert_eq!(
+            cut.text,
+            r#"A "bank" (from Italian banca, "bench") is an institution that accepts deposits."#
+        );
+        assert!(cut.truncated);
+
+        // No sentence end late enough: cut at a word, drop the dangling comma, mark it
+        let cut = snippet(definition, 30);
+        assert_eq!(cut.text, r#"A "bank" (from Italian banca…"#);
+        assert!(cut.text.chars().count() <= 30);
+
+        let cut = snippet(
+            "(parenthetical (nested) aside) and more words follow here",
+            16,
+        );
+        assert_eq!(cut.text, "(parenthetical…");
+
+        let whole = snippet(definition, 0);
+        assert_eq!(whole.text, definition);
+        assert!(!whole.truncated);
+        assert!(!snippet("  Short.  ", 240).truncated);
+    }
+
+    #[test]
+    fn snippets_never_split_characters() {
+        let cut = snippet("Ünïcödé façade naïveté rôle", 10);
+        assert_eq!(cut.text, "Ünïcödé…");
+        let cut = snippet("Donaudampfschifffahrtsgesellschaft", 8);
+        assert_eq!(cut.text, "Donauda…");
+        assert_eq!(cut.text.chars().count(), 8);
+    }
+}
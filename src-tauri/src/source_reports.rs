@@ -0,0 +1,268 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use tauri::{Emitter, Manager, Runtime};
+
+use crate::db::DbState;
+
+/// Definitions shorter than this, in characters, are probably stubs
+const SHORT_DEFINITION_CHARS: i64 = 10;
+/// Definitions longer than this are probably whole articles or import mistakes
+const LONG_DEFINITION_CHARS: i64 = 2000;
+/// Size of the reference list of common English words coverage is measured against
+const REFERENCE_WORDS: i64 = 5000;
+const STEPS: usize = 3;
+
+/// Quality figures for one dictionary source, kept until the next analysis
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SourceReport {
+    source: String,
+    entries: i64,
+    /// Share of entries, from 0 to 1, with a part of speech
+    with_pos: f64,
+    /// Share of entries with usage labels
+    with_labels: f64,
+    /// Share of entries with a frequency
+    with_frequency: f64,
+    /// In characters
+    average_definition_length: f64,
+    short_definitions: i64,
+    long_definitions: i64,
+    /// Share of entries whose headword another entry of the source already has
+    duplicate_headwords: f64,
+    /// Share of the most frequent English words the source defines, measured against the
+    /// frequencies of every installed source; `None` when no source has frequencies
+    reference_coverage: Option<f64>,
+    reference_size: i64,
+    analyzed_at: i64,
+}
+
+/// The figures shown in the sources list
+#[derive(Serialize)]
+pub struct ReportSummary {
+    with_pos: f64,
+    average_definition_length: f64,
+    duplicate_headwords: f64,
+    reference_coverage: Option<f64>,
+    analyzed_at: i64,
+}
+
+impl SourceReport {
+    pub fn summary(&self) -> ReportSummary {
+        ReportSummary {
+            with_pos: self.with_pos,
+            average_definition_length: self.average_definition_length,
+            duplicate_headwords: self.duplicate_headwords,
+            reference_coverage: self.reference_coverage,
+            analyzed_at: self.analyzed_at,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct ReportProgress {
+    source: String,
+    step: usize,
+    total: usize,
+    done: bool,
+    error: Option<String>,
+}
+
+/// Sources being analyzed, so the same one isn't analyzed twice at once
+#[derive(Default)]
+pub struct ReportJobs(Mutex<HashSet<String>>);
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS source_reports (
+            source TEXT PRIMARY KEY,
+            report TEXT NOT NULL,
+            analyzed_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn load(conn: &Connection, source: &str) -> Result<Option<SourceReport>> {
+    let report: Option<String> = conn
+        .query_row(
+            "SELECT report FROM source_reports WHERE source = ?",
+            params![source],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(report.and_then(|json| serde_json::from_str(&json).ok()))
+}
+
+/// Compute a source's report. The connection is taken for each step in turn rather than
+/// for the whole analysis, so searches can run in between.
+fn analyze<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    source: &str,
+    progress: impl Fn(usize),
+) -> Result<SourceReport, String> {
+    let state = app.state::<DbState>();
+
+    progress(0);
+    let mut report = state
+        .0
+        .lock()
+        .unwrap()
+        .query_row(
+            "SELECT COUNT(*),
+                    COALESCE(AVG(pos IS NOT NULL AND trim(pos) != ''), 0),
+                    COALESCE(AVG(labels IS NOT NULL), 0),
+                    COALESCE(AVG(frequency IS NOT NULL), 0),
+                    COALESCE(AVG(length(definition)), 0),
+                    COALESCE(SUM(length(trim(definition)) < ?2), 0),
+                    COALESCE(SUM(length(definition) > ?3), 0)
+             FROM dictionary WHERE source = ?1",
+            params![source, SHORT_DEFINITION_CHARS, LONG_DEFINITION_CHARS],
+            |row| {
+                Ok(SourceReport {
+                    source: source.to_string(),
+                    entries: row.get(0)?,
+                    with_pos: row.get(1)?,
+                    with_labels: row.get(2)?,
+                    with_frequency: row.get(3)?,
+                    average_definition_length: row.get(4)?,
+                    short_definitions: row.get(5)?,
+                    long_definitions: row.get(6)?,
+                    duplicate_headwords: 0.0,
+                    reference_coverage: None,
+                    reference_size: 0,
+                    analyzed_at: 0,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    if report.entries == 0 {
+        return Err(format!("There is no dictionary source named {}", source));
+    }
+
+    progress(1);
+    let headwords: i64 = state
+        .0
+        .lock()
+        .unwrap()
+        .query_row(
+            "SELECT COUNT(DISTINCT word) FROM dictionary WHERE source = ?",
+            params![source],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    progress(2);
+    let conn = state.0.lock().unwrap();
+    let (reference_size, covered): (i64, i64) = conn
+        .query_row(
+            "WITH reference AS (
+                 SELECT word FROM dictionary
+                 WHERE frequency IS NOT NULL AND language = 'en' COLLATE NOCASE
+                 GROUP BY word ORDER BY MAX(frequency) DESC LIMIT ?2)
+             SELECT COUNT(*), COALESCE(SUM(EXISTS(SELECT 1 FROM dictionary d
+                                                  WHERE d.word = reference.word
+                                                    AND d.source = ?1)), 0)
+             FROM reference",
+            params![source, REFERENCE_WORDS],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    report.duplicate_headwords = (report.entries - headwords) as f64 / report.entries as f64;
+    report.reference_coverage =
+        (reference_size > 0).then(|| covered as f64 / reference_size as f64);
+    report.reference_size = reference_size;
+    report.analyzed_at = conn
+        .query_row("SELECT unixepoch()", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO source_reports (source, report, analyzed_at) VALUES (?, ?, ?)",
+        params![
+            source,
+            serde_json::to_string(&report).unwrap_or_default(),
+            report.analyzed_at
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Analyze a source on a background thread, reporting "source-report-progress" events.
+/// Returns false if the source is already being analyzed.
+pub fn analyze_in_background<R: Runtime>(app: &tauri::AppHandle<R>, source: &str) -> bool {
+    if !app
+        .state::<ReportJobs>()
+        .0
+        .lock()
+        .unwrap()
+        .insert(source.to_string())
+    {
+        return false;
+    }
+
+    let app = app.clone();
+    let source = source.to_string();
+    std::thread::spawn(move || {
+        let emit = |step: usize, done: bool, error: Option<String>| {
+            let _ = app.emit(
+                "source-report-progress",
+                ReportProgress {
+                    source: source.clone(),
+                    step,
+                    total: STEPS,
+                    done,
+                    error,
+                },
+            );
+        };
+        let outcome = analyze(&app, &source, |step| emit(step, false, None));
+        emit(STEPS, true, outcome.err());
+        app.state::<ReportJobs>().0.lock().unwrap().remove(&source);
+    });
+    true
+}
+
+/// Analyze every installed source, as after the dictionary is first filled. The sources are
+/// listed on the background thread too, so this can be called with the connection held.
+pub fn analyze_all_in_background(app: &tauri::AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let state = app.state::<DbState>();
+        let sources: Result<Vec<String>> = state
+            .0
+            .lock()
+            .unwrap()
+            .prepare("SELECT DISTINCT source FROM dictionary")
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect());
+        match sources {
+            Ok(sources) => {
+                for source in sources {
+                    analyze_in_background(&app, &source);
+                }
+            }
+            Err(e) => eprintln!("Failed to list sources to analyze: {}", e),
+        }
+    });
+}
+
+/// Compute a source's quality report in the background, replacing the previous one; run
+/// it again after editing the source. Progress and completion come as
+/// "source-report-progress" events. Returns false if an analysis of the source is already
+/// running.
+#[tauri::command]
+pub fn analyze_source(source_id: &str, app: tauri::AppHandle) -> bool {
+    analyze_in_background(&app, source_id)
+}
+
+/// The last report computed for a source, if any
+#[tauri::command]
+pub fn get_source_report(
+    source_id: &str,
+    state: tauri::State<DbState>,
+) -> Result<Option<SourceReport>, String> {
+    let conn = state.0.lock().unwrap();
+    load(&conn, source_id).map_err(|e| e.to_string())
+}
@@ -0,0 +1,169 @@
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+use crate::db::{DbState, SearchEntry};
+use crate::read_only;
+use crate::source_reports::{self, ReportSummary};
+
+/// Searches counted in memory before the counters are written out
+const FLUSH_THRESHOLD: u32 = 50;
+/// Counters are also written out this often while the app runs, if anything changed
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS source_stats (
+            source TEXT PRIMARY KEY,
+            hits INTEGER NOT NULL DEFAULT 0,
+            top_hits INTEGER NOT NULL DEFAULT 0,
+            last_hit_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct Counter {
+    hits: i64,
+    top_hits: i64,
+    last_hit_at: i64,
+}
+
+#[derive(Default)]
+struct Pending {
+    searches: u32,
+    sources: HashMap<String, Counter>,
+}
+
+/// Per-source counts from searches since the last flush, kept in memory so a search never
+/// has to write them
+#[derive(Default)]
+pub struct SourceCounters(Mutex<Pending>);
+
+impl SourceCounters {
+    /// Count the sources of a search's results. Returns true once enough searches have
+    /// piled up that the counters should be flushed.
+    pub fn record(&self, results: &[SearchEntry]) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let mut pending = self.0.lock().unwrap();
+        for (rank, entry) in results.iter().enumerate() {
+            let counter = pending.sources.entry(entry.source.clone()).or_default();
+            counter.hits += 1;
+            if rank == 0 {
+                counter.top_hits += 1;
+            }
+            counter.last_hit_at = now;
+        }
+        pending.searches += 1;
+        pending.searches >= FLUSH_THRESHOLD
+    }
+
+    /// Add the pending counts to `source_stats` and reset them. Writes nothing when no
+    /// search has run since the last flush, nor on a read-only database, where the counts
+    /// are dropped.
+    pub fn flush(&self, conn: &Connection) -> Result<()> {
+        let pending = std::mem::take(&mut *self.0.lock().unwrap());
+        if pending.sources.is_empty() || read_only::is_read_only(conn) {
+            return Ok(());
+        }
+
+        let tx = conn.unchecked_transaction()?;
+        for (source, counter) in &pending.sources {
+            tx.execute(
+                "INSERT INTO source_stats (source, hits, top_hits, last_hit_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(source) DO UPDATE SET
+                    hits = hits + excluded.hits,
+                    top_hits = top_hits + excluded.top_hits,
+                    last_hit_at = max(COALESCE(last_hit_at, 0), excluded.last_hit_at)",
+                params![source, counter.hits, counter.top_hits, counter.last_hit_at],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+/// Flush the counters through the app's connection
+pub fn flush(app: &tauri::AppHandle) {
+    let state = app.state::<DbState>();
+    // While locked the counts wait in memory for the real database
+    let Ok(conn) = state.user_data() else {
+        return;
+    };
+    if let Err(e) = app.state::<SourceCounters>().flush(&conn) {
+        eprintln!("Failed to save source statistics: {}", e);
+    }
+}
+
+/// Flush the counters every few minutes for the life of the app
+pub fn start_flush_timer(app: &tauri::AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(FLUSH_INTERVAL);
+        flush(&app);
+    });
+}
+
+#[derive(Serialize)]
+pub struct SourceStats {
+    source: String,
+    entries: i64,
+    /// Times an entry from the source was among the returned results
+    hits: i64,
+    /// Times it was the first result
+    top_hits: i64,
+    last_hit_at: Option<i64>,
+    /// Figures from the source's last quality report, if it's been analyzed
+    report: Option<ReportSummary>,
+}
+
+/// How often each installed source shows up in search results, fewest hits first, so
+/// packs that never help stand out, with a summary of each one's quality report
+#[tauri::command]
+pub fn get_source_stats(
+    state: tauri::State<DbState>,
+    counters: tauri::State<SourceCounters>,
+) -> Result<Vec<SourceStats>, String> {
+    let conn = state.0.lock().unwrap();
+    counters.flush(&conn).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT d.source, d.entries, COALESCE(s.hits, 0), COALESCE(s.top_hits, 0),
+                    s.last_hit_at
+             FROM (SELECT source, COUNT(*) AS entries FROM dictionary GROUP BY source) d
+             LEFT JOIN source_stats s ON s.source = d.source
+             ORDER BY 3, 4, d.source",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(SourceStats {
+                source: row.get(0)?,
+                entries: row.get(1)?,
+                hits: row.get(2)?,
+                top_hits: row.get(3)?,
+                last_hit_at: row.get(4)?,
+                report: None,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    let mut sources = rows
+        .collect::<Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    for stats in &mut sources {
+        stats.report = source_reports::load(&conn, &stats.source)
+            .map_err(|e| e.to_string())?
+            .map(|report| report.summary());
+    }
+    Ok(sources)
+}
@@ -0,0 +1,328 @@
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbState;
+
+/// Local calendar day of a unix timestamp, the same day boundaries the analytics use
+const LOCAL_DAY: &str = "date(ts, 'unixepoch', 'localtime')";
+
+/// Reading time in one book over the week
+#[derive(Serialize, Deserialize)]
+pub struct BookReading {
+    book_id: i64,
+    title: String,
+    seconds: i64,
+    sessions: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WeeklySummary {
+    /// Local dates of the Monday and Sunday the week runs from and to
+    week_start: String,
+    week_end: String,
+    lookups: i64,
+    distinct_words: i64,
+    /// Words looked up this week for the first time ever
+    new_words: i64,
+    /// Lookups the dictionary had no entry for
+    not_found: i64,
+    reading: Vec<BookReading>,
+    reading_seconds: i64,
+    /// Review sessions ended this week, the cards answered in them and the share of
+    /// answers that were correct. Absent from reports stored before reviews existed.
+    #[serde(default)]
+    review_sessions: i64,
+    #[serde(default)]
+    reviewed_cards: i64,
+    #[serde(default)]
+    review_accuracy: Option<f64>,
+    /// Days this week with a lookup or a reading session
+    active_days: i64,
+    /// Days in a row with activity, counted back from the week's last day (or today, for
+    /// the current week). A day without activity yet doesn't break the streak until it's over.
+    streak_days: i64,
+    generated_at: i64,
+}
+
+/// Create the stored reports table. Reports are kept whole, so they outlive the history
+/// they were built from.
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS weekly_summaries (
+            profile_id INTEGER NOT NULL,
+            week_start TEXT NOT NULL,
+            generated_at INTEGER NOT NULL,
+            report TEXT NOT NULL,
+            PRIMARY KEY (profile_id, week_start)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Local days on which the active profile looked something up or finished a reading session
+fn activity_days() -> String {
+    format!(
+        "SELECT {day} AS day FROM (SELECT looked_up_at AS ts FROM lookup_history
+             WHERE profile_id = (SELECT id FROM temp.current_profile))
+         UNION
+         SELECT {day} AS day FROM (SELECT started_at AS ts FROM reading_sessions
+             WHERE profile_id = (SELECT id FROM temp.current_profile) AND ended_at IS NOT NULL)",
+        day = LOCAL_DAY
+    )
+}
+
+/// Count back from `last` while each day has activity, allowing `last` itself to be empty
+fn streak(conn: &Connection, last: &str) -> Result<i64> {
+    // Days as day numbers so consecutive ones differ by one
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT unixepoch(day) / 86400 AS n FROM ({}) WHERE day <= ? ORDER BY n DESC",
+        activity_days()
+    ))?;
+    let days = stmt
+        .query_map(params![last], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<_>>>()?;
+    let last: i64 = conn.query_row("SELECT unixepoch(?) / 86400", params![last], |row| {
+        row.get(0)
+    })?;
+
+    let mut expected = last;
+    let mut count = 0;
+    for day in days {
+        if day == expected || (count == 0 && day == last - 1) {
+            count += 1;
+            expected = day - 1;
+        } else {
+            break;
+        }
+    }
+    Ok(count)
+}
+
+/// Aggregate the active profile's activity for a Monday-to-Sunday local week, `week_offset`
+/// weeks back from the current one
+pub fn generate(conn: &Connection, week_offset: u32) -> Result<WeeklySummary> {
+    // 'weekday 0' moves to the coming Sunday (or stays on one); the Monday before is 6 days back
+    let (week_start, week_end, today): (String, String, String) = conn.query_row(
+        "SELECT date(sunday, '-6 days'), sunday, date('now', 'localtime')
+         FROM (SELECT date('now', 'localtime', 'weekday 0', ?) AS sunday)",
+        params![format!("-{} days", 7 * week_offset)],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    // Bounds as unix timestamps of local midnights; 'utc' reads the date as local time
+    let (from, to): (i64, i64) = conn.query_row(
+        "SELECT unixepoch(?1, 'utc'), unixepoch(?2, '+1 day', 'utc')",
+        params![week_start, week_end],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let (lookups, distinct_words, not_found): (i64, i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COUNT(DISTINCT lower(word)), COALESCE(SUM(found = 0), 0)
+         FROM lookup_history
+         WHERE profile_id = (SELECT id FROM temp.current_profile)
+           AND looked_up_at >= ? AND looked_up_at < ?",
+        params![from, to],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let new_words: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM (
+             SELECT MIN(looked_up_at) AS first FROM lookup_history
+             WHERE profile_id = (SELECT id FROM temp.current_profile)
+             GROUP BY lower(word))
+         WHERE first >= ? AND first < ?",
+        params![from, to],
+        |row| row.get(0),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT s.book_id, b.title, SUM(s.ended_at - s.started_at), COUNT(*)
+         FROM reading_sessions s JOIN books b ON b.id = s.book_id
+         WHERE s.profile_id = (SELECT id FROM temp.current_profile)
+           AND s.ended_at IS NOT NULL AND s.started_at >= ? AND s.started_at < ?
+         GROUP BY s.book_id ORDER BY 3 DESC",
+    )?;
+    let reading = stmt
+        .query_map(params![from, to], |row| {
+            Ok(BookReading {
+                book_id: row.get(0)?,
+                title: row.get(1)?,
+                seconds: row.get(2)?,
+                sessions: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let (review_sessions, reviewed_cards, answers, correct): (i64, i64, i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), COALESCE(SUM(cards_seen), 0), COALESCE(SUM(answers), 0),
+                    COALESCE(SUM(correct), 0)
+             FROM review_sessions
+             WHERE profile_id = (SELECT id FROM temp.current_profile)
+               AND ended_at >= ? AND ended_at < ?",
+            params![from, to],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+    let active_days: i64 = conn.query_row(
+        &format!(
+            "SELECT COUNT(DISTINCT day) FROM ({}) WHERE day BETWEEN ? AND ?",
+            activity_days()
+        ),
+        params![week_start, week_end],
+        |row| row.get(0),
+    )?;
+
+    let last_day = if week_offset == 0 { &today } else { &week_end };
+
+    Ok(WeeklySummary {
+        streak_days: streak(conn, last_day)?,
+        reading_seconds: reading.iter().map(|r| r.seconds).sum(),
+        week_start,
+        week_end,
+        lookups,
+        distinct_words,
+        new_words,
+        not_found,
+        reading,
+        review_sessions,
+        reviewed_cards,
+        review_accuracy: (answers > 0).then(|| correct as f64 / answers as f64),
+        active_days,
+        generated_at: conn.query_row("SELECT unixepoch()", [], |row| row.get(0))?,
+    })
+}
+
+/// Generate a week's report and keep it, replacing an earlier report for the same week
+pub fn store(conn: &Connection, week_offset: u32) -> Result<WeeklySummary> {
+    let summary = generate(conn, week_offset)?;
+    conn.execute(
+        "INSERT INTO weekly_summaries (profile_id, week_start, generated_at, report)
+         VALUES ((SELECT id FROM temp.current_profile), ?, ?, ?)
+         ON CONFLICT(profile_id, week_start) DO UPDATE
+         SET generated_at = excluded.generated_at, report = excluded.report",
+        params![
+            summary.week_start,
+            summary.generated_at,
+            serde_json::to_string(&summary).unwrap_or_default()
+        ],
+    )?;
+    Ok(summary)
+}
+
+fn stored(conn: &Connection, limit: i64) -> Result<Vec<WeeklySummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT report FROM weekly_summaries
+         WHERE profile_id = (SELECT id FROM temp.current_profile)
+         ORDER BY week_start DESC LIMIT ?",
+    )?;
+    let reports = stmt
+        .query_map(params![limit], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>>>()?;
+    // A report that no longer parses (say, from a later version) is left out
+    Ok(reports
+        .iter()
+        .filter_map(|report| serde_json::from_str(report).ok())
+        .collect())
+}
+
+/// The summary of the current week (0), last week (1) and so on, computed fresh
+#[tauri::command]
+pub fn generate_weekly_summary(
+    week_offset: u32,
+    state: tauri::State<DbState>,
+) -> Result<WeeklySummary, String> {
+    let conn = state.user_data()?;
+    generate(&conn, week_offset).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn store_summary(
+    week_offset: Option<u32>,
+    state: tauri::State<DbState>,
+) -> Result<WeeklySummary, String> {
+    let conn = state.user_data()?;
+    store(&conn, week_offset.unwrap_or(0)).map_err(|e| e.to_string())
+}
+
+/// Stored reports, most recent week first
+#[tauri::command]
+pub fn get_summary_history(
+    limit: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<WeeklySummary>, String> {
+    let conn = state.user_data()?;
+    stored(&conn, limit.unwrap_or(-1)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// A database holding the fixture week, placed as last week
+    fn last_week() -> Connection {
+        let conn = db::init_db(None).unwrap();
+        conn.execute_batch(
+            "CREATE TEMP TABLE week AS SELECT unixepoch(
+                 date('now', 'localtime', 'weekday 0', '-7 days', '-6 days'), 'utc') AS monday",
+        )
+        .unwrap();
+        conn.execute_batch(include_str!("../tests/fixtures/summary/week.sql"))
+            .unwrap();
+        conn
+    }
+
+    #[test]
+    fn a_synthetic_week_adds_up() {
+        let conn = last_week();
+        let summary = generate(&conn, 1).unwrap();
+
+        let (monday, sunday): (String, String) = conn
+            .query_row(
+                "SELECT date(monday, 'unixepoch', 'localtime'),
+                        date(monday, 'unixepoch', 'localtime', '+6 days')
+                 FROM temp.week",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(summary.week_start, monday);
+        assert_eq!(summary.week_end, sunday);
+
+        assert_eq!(summary.lookups, 5);
+        assert_eq!(summary.distinct_words, 4);
+        assert_eq!(summary.new_words, 3);
+        assert_eq!(summary.not_found, 1);
+
+        let reading: Vec<_> = summary
+            .reading
+            .iter()
+            .map(|r| (r.book_id, r.title.as_str(), r.seconds, r.sessions))
+            .collect();
+        assert_eq!(reading, [(1, "Moby-Dick", 5400, 2), (2, "Walden", 1200, 1)]);
+        assert_eq!(summary.reading_seconds, 6600);
+
+        assert_eq!(summary.review_sessions, 2);
+        assert_eq!(summary.reviewed_cards, 15);
+        assert_eq!(summary.review_accuracy, Some(13.0 / 17.0));
+
+        // Every day but Friday, which also ends the streak counted back from Sunday
+        assert_eq!(summary.active_days, 6);
+        assert_eq!(summary.streak_days, 2);
+    }
+
+    #[test]
+    fn stored_reports_outlive_the_history() {
+        let conn = last_week();
+        store(&conn, 1).unwrap();
+        conn.execute_batch("DELETE FROM lookup_history; DELETE FROM reading_sessions;")
+            .unwrap();
+
+        let reports = stored(&conn, 10).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].lookups, 5);
+        assert_eq!(reports[0].reading_seconds, 6600);
+        assert_eq!(generate(&conn, 1).unwrap().lookups, 0);
+    }
+}
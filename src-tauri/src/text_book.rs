@@ -0,0 +1,316 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::books::{self, Book};
+use crate::db::DbState;
+use crate::read_only;
+
+/// Fewer detected headings than this means the book gets no outline at all
+const MIN_CHAPTERS: usize = 2;
+
+/// Headings closer together than this are a table of contents, not chapters
+const MIN_CHAPTER_BYTES: usize = 500;
+
+const MAX_CAPS_HEADING_CHARS: usize = 50;
+const MAX_NUMBERED_HEADING_CHARS: usize = 80;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Chapter {
+    pub index: usize,
+    pub title: String,
+    /// Byte offset of the heading line in the decoded text
+    pub start: usize,
+    /// Byte offset where the next chapter (or the book body) ends
+    pub end: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HeadingKind {
+    /// "CHAPTER I", "Chapter 12. The Storm"
+    Chapter,
+    /// "IV." or "IV. THE RED-HEADED LEAGUE" on its own between blank lines
+    Roman,
+    /// A short all-caps line between blank lines
+    Caps,
+}
+
+struct Line<'a> {
+    offset: usize,
+    text: &'a str,
+}
+
+fn is_roman(token: &str) -> bool {
+    !token.is_empty()
+        && token.len() <= 8
+        && token
+            .chars()
+            .all(|c| matches!(c, 'I' | 'V' | 'X' | 'L' | 'C' | 'D' | 'M'))
+}
+
+fn heading_kind(text: &str, blank_before: bool, blank_after: bool) -> Option<HeadingKind> {
+    if !blank_before || text.is_empty() {
+        return None;
+    }
+
+    let first = text.split_whitespace().next().unwrap_or("");
+    let chars = text.chars().count();
+
+    if first.eq_ignore_ascii_case("chapter")
+        && chars <= MAX_NUMBERED_HEADING_CHARS
+        && text.split_whitespace().nth(1).is_some()
+    {
+        return Some(HeadingKind::Chapter);
+    }
+
+    if !blank_after {
+        return None;
+    }
+
+    if is_roman(first.trim_end_matches('.')) && chars <= MAX_NUMBERED_HEADING_CHARS {
+        return Some(HeadingKind::Roman);
+    }
+
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    if chars <= MAX_CAPS_HEADING_CHARS
+        && letters >= 3
+        && !text.chars().any(|c| c.is_lowercase())
+        && !text.starts_with("***")
+    {
+        return Some(HeadingKind::Caps);
+    }
+
+    None
+}
+
+/// The part of a Project Gutenberg file between its START and END markers
+fn body_span(lines: &[Line], len: usize) -> (usize, usize) {
+    let start = lines
+        .iter()
+        .position(|l| l.text.starts_with("*** START OF"))
+        .map(|i| lines.get(i + 1).map(|l| l.offset).unwrap_or(len))
+        .unwrap_or(0);
+    let end = lines
+        .iter()
+        .find(|l| l.text.starts_with("*** END OF"))
+        .map(|l| l.offset)
+        .unwrap_or(len);
+    (start, end.max(start))
+}
+
+/// Detect chapter boundaries in a plain-text book.
+///
+/// Deliberately conservative: only the strongest kind of heading found is used, headings
+/// that sit too close together (a table of contents) are dropped, and a book with fewer
+/// than `MIN_CHAPTERS` headings gets an empty outline rather than a guessed one.
+pub fn detect_chapters(text: &str) -> Vec<Chapter> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for raw in text.split_inclusive('\n') {
+        lines.push(Line {
+            offset,
+            text: raw.trim(),
+        });
+        offset += raw.len();
+    }
+
+    let (body_start, body_end) = body_span(&lines, text.len());
+
+    let mut candidates: Vec<(HeadingKind, usize)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.offset < body_start || line.offset >= body_end {
+            continue;
+        }
+        let blank_before = i == 0 || lines[i - 1].text.is_empty();
+        let blank_after = lines.get(i + 1).is_none_or(|l| l.text.is_empty());
+        if let Some(kind) = heading_kind(line.text, blank_before, blank_after) {
+            candidates.push((kind, i));
+        }
+    }
+
+    for kind in [HeadingKind::Chapter, HeadingKind::Roman, HeadingKind::Caps] {
+        let starts: Vec<usize> = candidates
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, i)| *i)
+            .collect();
+
+        let mut kept: Vec<usize> = Vec::new();
+        for (n, &line_index) in starts.iter().enumerate() {
+            let here = lines[line_index].offset;
+            let next = starts
+                .get(n + 1)
+                .map(|&j| lines[j].offset)
+                .unwrap_or(body_end);
+            if next - here >= MIN_CHAPTER_BYTES {
+                kept.push(line_index);
+            }
+        }
+
+        if kept.len() >= MIN_CHAPTERS {
+            return kept
+                .iter()
+                .enumerate()
+                .map(|(index, &line_index)| Chapter {
+                    index,
+                    title: lines[line_index].text.to_string(),
+                    start: lines[line_index].offset,
+                    end: kept
+                        .get(index + 1)
+                        .map(|&j| lines[j].offset)
+                        .unwrap_or(body_end),
+                })
+                .collect();
+        }
+    }
+
+    Vec::new()
+}
+
+/// Create the outline cache, keyed by content hash so edits to a file invalidate it
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_outline (
+            content_hash TEXT PRIMARY KEY,
+            outline TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn is_text_book(book: &Book) -> bool {
+    matches!(book.format.as_str(), "txt" | "md")
+}
+
+/// Read a text book from disk, returning its decoded text and content hash
+pub fn read_text(book: &Book) -> Result<(String, String), String> {
+    if !is_text_book(book) {
+        return Err(format!("{} is not a plain-text book", book.title));
+    }
+    let bytes = fs::read(&book.path).map_err(|e| e.to_string())?;
+    let hash = books::content_hash(&bytes);
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), hash))
+}
+
+/// Cached outline for the text, detecting and storing it on first use
+pub fn outline_for(conn: &Connection, text: &str, hash: &str) -> Result<Vec<Chapter>, String> {
+    let cached: Option<String> = conn
+        .query_row(
+            "SELECT outline FROM book_outline WHERE content_hash = ?",
+            params![hash],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    if let Some(json) = cached {
+        if let Ok(outline) = serde_json::from_str(&json) {
+            return Ok(outline);
+        }
+    }
+
+    let outline = detect_chapters(text);
+    if read_only::is_read_only(conn) {
+        return Ok(outline);
+    }
+    let json = serde_json::to_string(&outline).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO book_outline (content_hash, outline, created_at)
+         VALUES (?, ?, unixepoch())",
+        params![hash, json],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(outline)
+}
+
+#[tauri::command]
+pub fn get_text_book_outline(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<Vec<Chapter>, String> {
+    let book = {
+        let conn = state.user_data()?;
+        books::load_book(&conn, book_id)?
+    };
+    // Read and hash the file without holding the connection
+    let (text, hash) = read_text(&book)?;
+    let conn = state.user_data()?;
+    outline_for(&conn, &text, &hash)
+}
+
+/// Text of a plain-text book, or just one chapter of it when `chapter` is given
+#[tauri::command]
+pub fn get_book_content(
+    book_id: i64,
+    chapter: Option<usize>,
+    state: tauri::State<DbState>,
+) -> Result<String, String> {
+    let book = {
+        let conn = state.user_data()?;
+        books::load_book(&conn, book_id)?
+    };
+    let (text, hash) = read_text(&book)?;
+
+    let Some(index) = chapter else {
+        return Ok(text);
+    };
+
+    let outline = {
+        let conn = state.user_data()?;
+        outline_for(&conn, &text, &hash)?
+    };
+    let chapter = outline
+        .get(index)
+        .ok_or_else(|| format!("{} has no chapter {}", book.title, index))?;
+    text.get(chapter.start..chapter.end)
+        .map(str::to_string)
+        .ok_or_else(|| format!("Outline for {} is out of date", book.title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(text: &str) -> Vec<String> {
+        detect_chapters(text).into_iter().map(|c| c.title).collect()
+    }
+
+    #[test]
+    fn numbered_chapters_skip_the_table_of_contents() {
+        let text = include_str!("../tests/fixtures/gutenberg/pride_and_prejudice_excerpt.txt");
+        let chapters = detect_chapters(text);
+        assert_eq!(
+            chapters
+                .iter()
+                .map(|c| c.title.as_str())
+                .collect::<Vec<_>>(),
+            ["Chapter 1", "Chapter 2", "Chapter 3"]
+        );
+        assert!(text[chapters[0].start..chapters[0].end].contains("truth universally"));
+        // The last chapter stops at the Gutenberg footer
+        assert!(!text[chapters[2].start..chapters[2].end].contains("*** END"));
+        assert!(chapters.windows(2).all(|w| w[0].end == w[1].start));
+    }
+
+    #[test]
+    fn roman_headings_too_close_together_are_dropped() {
+        let text = include_str!("../tests/fixtures/gutenberg/sherlock_holmes_excerpt.txt");
+        let chapters = detect_chapters(text);
+        assert_eq!(chapters.len(), 2);
+        assert!(text[chapters[0].start..chapters[0].end].contains("Irene Adler"));
+        assert_eq!(chapters[1].title, "II. THE RED-HEADED LEAGUE");
+    }
+
+    #[test]
+    fn unstructured_text_gets_no_outline() {
+        let paragraph = "The chapter of accidents is the longest chapter in the book.\n\n";
+        assert!(titles(&paragraph.repeat(40)).is_empty());
+
+        // A single heading isn't an outline
+        let one = format!("CHAPTER I\n\n{}", paragraph.repeat(40));
+        assert!(titles(&one).is_empty());
+    }
+}
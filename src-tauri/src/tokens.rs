@@ -0,0 +1,312 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::collections::HashSet;
+use std::fs;
+
+use crate::books;
+use crate::db::DbState;
+use crate::settings;
+
+/// Language whose stopwords apply when a book or lookup doesn't say
+pub const LANGUAGE_SETTING: &str = "analysis_language";
+const DEFAULT_LANGUAGE: &str = "en";
+/// Shorter tokens are left out of word counts and reports
+pub const MIN_LENGTH_SETTING: &str = "min_token_length";
+const DEFAULT_MIN_LENGTH: usize = 2;
+
+/// Stopwords shipped with the app, by language code
+const EMBEDDED_STOPWORDS: [(&str, &str); 4] = [
+    (
+        "en",
+        "a about above after again against all am an and any are as at be because been before
+         being below between both but by can could did do does doing down during each few for
+         from further had has have having he her here hers herself him himself his how i if in
+         into is it its itself just me more most my myself no nor not now of off on once only or
+         other our ours ourselves out over own same she should so some such than that the their
+         theirs them themselves then there these they this those through to too under until up
+         very was we were what when where which while who whom why will with would you your
+         yours yourself yourselves",
+    ),
+    (
+        "fr",
+        "au aux avec ce ces dans de des du elle en et eux il ils je la le les leur lui ma mais me
+         même mes moi mon ne nos notre nous on ou par pas pour qu que qui sa se ses son sur ta te
+         tes toi ton tu un une vos votre vous c d j l à m n s t y été étée étées étés étant suis
+         es est sommes êtes sont serai sera serons seront était étaient fut ai as avons avez ont
+         aurai aura avait avaient eu cette cet ceux celle celui aussi comme donc plus très tout",
+    ),
+    (
+        "de",
+        "aber alle allem allen aller alles als also am an ander andere anderem anderen anderer
+         auch auf aus bei bin bis bist da damit dann das dass dasselbe dazu dein deine dem den
+         denn der des desselben dessen dich die dies diese dieselbe diesem diesen dieser dieses
+         dir doch dort du durch ein eine einem einen einer eines er es etwas euch euer für hat
+         hatte hatten hier hin hinter ich ihm ihn ihnen ihr ihre im in ist jede jedem jeden jeder
+         jetzt kann kein keine mich mit muss nach nicht nichts noch nun nur ob oder ohne sehr
+         sein seine sich sie sind so solche soll sondern um und uns unser unter viel vom von vor
+         war waren warst was weg weil weiter welche wenn werde werden wie wieder will wir wird
+         wo wollen zu zum zur zwar zwischen",
+    ),
+    (
+        "es",
+        "a al algo algunas algunos ante antes como con contra cual cuando de del desde donde
+         durante e el ella ellas ellos en entre era erais eran eres es esa esas ese eso esos esta
+         estaba estado estas este esto estos fue fueron ha habéis había han has hay la las le les
+         lo los me mi mis mucho muy más nada ni no nos nosotros o os otra otras otro otros para
+         pero poco por porque que quien se sea ser si sin sobre son su sus también te tiene
+         tengo ti tu tus tú un una uno unos vosotros y ya yo él",
+    ),
+];
+
+/// Splits text into the words analysis features count, so that their numbers agree.
+/// Apostrophes stay inside words ("don't", "l'homme") and hyphenated compounds stay whole
+/// ("well-known"); possessive 's is dropped, tokens are lowercased, and stopwords, numbers
+/// and tokens below the minimum length are left out.
+pub struct Tokenizer {
+    stopwords: HashSet<String>,
+    min_length: usize,
+    /// Fingerprint of the stopwords and minimum length, stored with cached counts so a
+    /// change in either invalidates them
+    pub fingerprint: String,
+}
+
+fn primary_language(language: &str) -> String {
+    language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(language)
+        .trim()
+        .to_lowercase()
+}
+
+fn parse_stopwords(text: &str) -> HashSet<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace)
+        .map(|word| word.replace('’', "'").to_lowercase())
+        .collect()
+}
+
+/// Stopwords loaded from the user's files, replacing the embedded list of their language
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS custom_stopwords (
+            language TEXT PRIMARY KEY,
+            words TEXT NOT NULL,
+            loaded_from TEXT NOT NULL,
+            loaded_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+impl Tokenizer {
+    /// The tokenizer for a language code such as "en" or "pt-BR"; `None` means the
+    /// analysis language setting. Languages without a list have no stopwords.
+    pub fn for_language(conn: &Connection, language: Option<&str>) -> Result<Tokenizer> {
+        let language = match language.filter(|l| !l.trim().is_empty()) {
+            Some(language) => primary_language(language),
+            None => settings::get(conn, LANGUAGE_SETTING)?
+                .map(|l| primary_language(&l))
+                .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string()),
+        };
+        let custom: Option<String> = conn
+            .query_row(
+                "SELECT words FROM custom_stopwords WHERE language = ?",
+                params![language],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let stopwords = match custom {
+            Some(words) => serde_json::from_str(&words).unwrap_or_default(),
+            None => EMBEDDED_STOPWORDS
+                .iter()
+                .find(|(code, _)| *code == language)
+                .map(|(_, words)| parse_stopwords(words))
+                .unwrap_or_default(),
+        };
+        let min_length = settings::get_f64(conn, MIN_LENGTH_SETTING, DEFAULT_MIN_LENGTH as f64)
+            .max(1.0) as usize;
+
+        let mut sorted: Vec<&String> = stopwords.iter().collect();
+        sorted.sort();
+        let fingerprint =
+            books::content_hash(format!("{}\n{}\n{:?}", language, min_length, sorted).as_bytes());
+        Ok(Tokenizer {
+            stopwords,
+            min_length,
+            fingerprint,
+        })
+    }
+
+    /// Whether a single word would be counted
+    pub fn keeps(&self, word: &str) -> bool {
+        word.chars().count() >= self.min_length
+            && word.chars().any(char::is_alphabetic)
+            && !self.stopwords.contains(word)
+    }
+
+    /// The counted words of the text, in order, repeats included
+    pub fn tokens<'a>(&'a self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        text.split(|c: char| !(c.is_alphanumeric() || matches!(c, '\'' | '’' | '-')))
+            .map(|token| {
+                let token = token
+                    .trim_matches(|c| matches!(c, '\'' | '’' | '-'))
+                    .replace('’', "'")
+                    .to_lowercase();
+                match token.strip_suffix("'s") {
+                    Some(stem) => stem.to_string(),
+                    None => token,
+                }
+            })
+            .filter(|token| self.keeps(token))
+    }
+
+    /// Stopwords as a JSON array, for filtering in SQL with `json_each`
+    pub fn stopwords_json(&self) -> String {
+        serde_json::to_string(&self.stopwords).unwrap_or_default()
+    }
+
+    pub fn min_length(&self) -> usize {
+        self.min_length
+    }
+}
+
+/// Replace a language's stopwords with those in a file (whitespace-separated, `#` starts a
+/// comment), or go back to the embedded list when `path` is empty. Returns how many
+/// stopwords the language now has. Cached word counts built with the old list are rebuilt
+/// on next use.
+#[tauri::command]
+pub fn set_custom_stopwords(
+    lang: &str,
+    path: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<usize, String> {
+    let language = primary_language(lang);
+    if language.is_empty() {
+        return Err("A language code is required".to_string());
+    }
+    let conn = state.0.lock().unwrap();
+
+    match path.filter(|p| !p.trim().is_empty()) {
+        Some(path) => {
+            let words = parse_stopwords(&fs::read_to_string(&path).map_err(|e| e.to_string())?);
+            conn.execute(
+                "INSERT INTO custom_stopwords (language, words, loaded_from, loaded_at)
+                 VALUES (?, ?, ?, unixepoch())
+                 ON CONFLICT(language) DO UPDATE SET words = excluded.words,
+                    loaded_from = excluded.loaded_from, loaded_at = excluded.loaded_at",
+                params![
+                    language,
+                    serde_json::to_string(&words).unwrap_or_default(),
+                    path
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        None => {
+            conn.execute(
+                "DELETE FROM custom_stopwords WHERE language = ?",
+                params![language],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let tokenizer = Tokenizer::for_language(&conn, Some(&language)).map_err(|e| e.to_string())?;
+    Ok(tokenizer.stopwords.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn tokens(conn: &Connection, language: &str, text: &str) -> Vec<String> {
+        let tokenizer = Tokenizer::for_language(conn, Some(language)).unwrap();
+        tokenizer.tokens(text).collect()
+    }
+
+    #[test]
+    fn tokenizes_samples() {
+        let conn = db::init_db(None).unwrap();
+        for (language, text, expected) in [
+            // Apostrophes, straight and curly; possessives lose their 's
+            (
+                "en",
+                "Don't stop—the whale's tail, Ishmael’s ship",
+                &["don't", "stop", "whale", "tail", "ishmael", "ship"][..],
+            ),
+            // Quotes around a word aren't part of it
+            (
+                "en",
+                "'Tis the 'whale' o’ the sea'",
+                &["tis", "whale", "sea"],
+            ),
+            // Hyphenated compounds stay whole; dashes between words don't count
+            (
+                "en",
+                "A well-known, state-of-the-art harpoon -- sharp -ish",
+                &["well-known", "state-of-the-art", "harpoon", "sharp", "ish"],
+            ),
+            // Numbers are left out, words with digits in them aren't
+            ("en", "In 1851, the 3rd edition", &["3rd", "edition"]),
+            (
+                "fr",
+                "L'homme et la mer : c'est l'été à Paris",
+                &["l'homme", "mer", "c'est", "l'été", "paris"],
+            ),
+            // Regional variants use their language's list
+            ("fr-CA", "Le chat est là", &["chat", "là"]),
+            (
+                "de",
+                "Die Straße ist über-füllt, aber schön",
+                &["straße", "über-füllt", "schön"],
+            ),
+            // No list for Portuguese: only the minimum length applies
+            ("pt-BR", "O gato e o rato", &["gato", "rato"]),
+        ] {
+            assert_eq!(tokens(&conn, language, text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn settings_and_custom_lists_apply() {
+        let conn = db::init_db(None).unwrap();
+        let fingerprint = |language| {
+            Tokenizer::for_language(&conn, language)
+                .unwrap()
+                .fingerprint
+        };
+        let english = fingerprint(Some("en"));
+        assert_eq!(fingerprint(None), english);
+        assert_ne!(fingerprint(Some("fr")), english);
+
+        settings::set(&conn, MIN_LENGTH_SETTING, "4").unwrap();
+        assert_eq!(tokens(&conn, "en", "The big whale swam"), ["whale", "swam"]);
+        assert_ne!(fingerprint(Some("en")), english);
+        settings::set(&conn, MIN_LENGTH_SETTING, "2").unwrap();
+        assert_eq!(fingerprint(Some("en")), english);
+
+        let custom = parse_stopwords("# Whaling terms\nwhale  Harpoon # inline\n\nship’s");
+        assert_eq!(
+            custom,
+            ["whale", "harpoon", "ship's"]
+                .into_iter()
+                .map(String::from)
+                .collect()
+        );
+        conn.execute(
+            "INSERT INTO custom_stopwords (language, words, loaded_from, loaded_at)
+             VALUES ('en', ?, 'whaling.txt', 0)",
+            params![serde_json::to_string(&custom).unwrap()],
+        )
+        .unwrap();
+        // The custom list replaces the embedded one
+        assert_eq!(
+            tokens(&conn, "en", "The whale and the harpoon"),
+            ["the", "and", "the"]
+        );
+        assert_ne!(fingerprint(Some("en")), english);
+    }
+}
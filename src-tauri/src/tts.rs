@@ -0,0 +1,345 @@
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tauri::{Emitter, Manager, Runtime};
+
+use crate::db::DbState;
+use crate::settings;
+
+/// Speaking rate in words per minute
+pub const RATE_SETTING: &str = "tts_rate";
+const DEFAULT_RATE: f64 = 180.0;
+/// Engine-specific voice name; the engine's default voice when unset
+pub const VOICE_SETTING: &str = "tts_voice";
+
+/// How often a speaking chunk checks for pause and stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TtsError {
+    /// No speech engine was found on this system
+    Unavailable,
+    NothingToSpeak,
+}
+
+/// The speech programs the platform offers, each reading the text from stdin
+#[derive(Clone, Copy)]
+enum Engine {
+    /// macOS
+    Say,
+    /// Linux and other Unix systems
+    EspeakNg,
+    Espeak,
+    /// Windows, through System.Speech
+    PowerShell,
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            let candidate = dir.join(program);
+            candidate.is_file() || candidate.with_extension("exe").is_file()
+        })
+    })
+}
+
+impl Engine {
+    fn detect() -> Option<Engine> {
+        if cfg!(windows) {
+            return on_path("powershell").then_some(Engine::PowerShell);
+        }
+        [
+            ("say", Engine::Say),
+            ("espeak-ng", Engine::EspeakNg),
+            ("espeak", Engine::Espeak),
+        ]
+        .into_iter()
+        .find(|(program, _)| on_path(program))
+        .map(|(_, engine)| engine)
+    }
+
+    fn command(self, rate: f64, voice: Option<&str>) -> Command {
+        match self {
+            Engine::Say => {
+                let mut command = Command::new("say");
+                command.arg("-r").arg(format!("{:.0}", rate));
+                if let Some(voice) = voice {
+                    command.arg("-v").arg(voice);
+                }
+                command
+            }
+            Engine::EspeakNg | Engine::Espeak => {
+                let program = match self {
+                    Engine::EspeakNg => "espeak-ng",
+                    _ => "espeak",
+                };
+                let mut command = Command::new(program);
+                command.arg("--stdin").arg("-s").arg(format!("{:.0}", rate));
+                if let Some(voice) = voice {
+                    command.arg("-v").arg(voice);
+                }
+                command
+            }
+            Engine::PowerShell => {
+                // System.Speech rates run from -10 to 10 around roughly 180 words per minute
+                let rate = ((rate - DEFAULT_RATE) / 20.0).round().clamp(-10.0, 10.0);
+                let select = voice
+                    .map(|v| format!("$s.SelectVoice('{}');", v.replace('\'', "''")))
+                    .unwrap_or_default();
+                let mut command = Command::new("powershell");
+                command
+                    .args(["-NoProfile", "-NonInteractive", "-Command"])
+                    .arg(format!(
+                        "Add-Type -AssemblyName System.Speech;
+                     $s = New-Object System.Speech.Synthesis.SpeechSynthesizer;
+                     {} $s.Rate = {}; $s.Speak([Console]::In.ReadToEnd())",
+                        select, rate
+                    ));
+                command
+            }
+        }
+    }
+
+    /// Start speaking `text` in the background
+    fn speak(self, text: &str, rate: f64, voice: Option<&str>) -> std::io::Result<Child> {
+        let mut child = self
+            .command(rate, voice)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        Ok(child)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PlayState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// Shared between the commands and the thread speaking a queue
+struct Control {
+    state: Mutex<PlayState>,
+    changed: Condvar,
+}
+
+impl Control {
+    fn set(&self, state: PlayState) {
+        let mut current = self.state.lock().unwrap();
+        // A stopped queue stays stopped
+        if *current != PlayState::Stopped {
+            *current = state;
+        }
+        self.changed.notify_all();
+    }
+
+    fn get(&self) -> PlayState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Block while paused, returning the state that ended the wait
+    fn wait_unpaused(&self) -> PlayState {
+        let state = self.state.lock().unwrap();
+        *self
+            .changed
+            .wait_while(state, |s| *s == PlayState::Paused)
+            .unwrap()
+    }
+}
+
+struct Playback {
+    book_id: i64,
+    control: Arc<Control>,
+}
+
+/// The queue being read aloud, if any. Only one book speaks at a time.
+#[derive(Default)]
+pub struct TtsQueue(Mutex<Option<Playback>>);
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum TtsStatus {
+    /// The chunk at `chunk` started; the frontend highlights it and saves the position
+    Speaking,
+    Paused,
+    Finished,
+    Stopped,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+struct TtsProgress {
+    book_id: i64,
+    chunk: usize,
+    total: usize,
+    status: TtsStatus,
+    error: Option<String>,
+}
+
+/// Speak one chunk to the end, or until paused or stopped. Returns the state that ended it.
+fn speak_chunk(
+    engine: Engine,
+    text: &str,
+    rate: f64,
+    voice: Option<&str>,
+    control: &Control,
+) -> Result<PlayState, String> {
+    let mut child = engine.speak(text, rate, voice).map_err(|e| e.to_string())?;
+    loop {
+        if child.try_wait().map_err(|e| e.to_string())?.is_some() {
+            return Ok(PlayState::Playing);
+        }
+        let state = control.get();
+        if state != PlayState::Playing {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(state);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Speak the chunks in order, reporting progress to the window that asked. A paused chunk
+/// starts over from its beginning on resume.
+fn run_queue<R: Runtime>(
+    window: &tauri::Window<R>,
+    book_id: i64,
+    chunks: Vec<String>,
+    engine: Engine,
+    rate: f64,
+    voice: Option<String>,
+    control: &Control,
+) {
+    let total = chunks.len();
+    let emit = |chunk: usize, status: TtsStatus, error: Option<String>| {
+        let _ = window.emit_to(
+            window.label(),
+            "tts-progress",
+            TtsProgress {
+                book_id,
+                chunk,
+                total,
+                status,
+                error,
+            },
+        );
+    };
+
+    let mut index = 0;
+    while index < total {
+        if control.wait_unpaused() == PlayState::Stopped {
+            emit(index, TtsStatus::Stopped, None);
+            return;
+        }
+        emit(index, TtsStatus::Speaking, None);
+        match speak_chunk(engine, &chunks[index], rate, voice.as_deref(), control) {
+            Ok(PlayState::Playing) => index += 1,
+            Ok(PlayState::Paused) => emit(index, TtsStatus::Paused, None),
+            Ok(PlayState::Stopped) => {
+                emit(index, TtsStatus::Stopped, None);
+                return;
+            }
+            Err(e) => {
+                emit(index, TtsStatus::Failed, Some(e));
+                return;
+            }
+        }
+    }
+    emit(total, TtsStatus::Finished, None);
+}
+
+/// Stop whatever is being read aloud. Called by `stop_tts`, before a new queue starts,
+/// and on exit.
+pub fn stop<R: Runtime>(app: &tauri::AppHandle<R>) {
+    if let Some(playback) = app.state::<TtsQueue>().0.lock().unwrap().take() {
+        playback.control.set(PlayState::Stopped);
+    }
+}
+
+/// Read the chunks of a passage aloud, one after another, replacing anything already
+/// being read. Each chunk sends a "tts-progress" event as it starts; rate and voice come
+/// from settings.
+#[tauri::command]
+pub fn speak_text<R: Runtime>(
+    book_id: i64,
+    text_chunks: Vec<String>,
+    app: tauri::AppHandle<R>,
+    window: tauri::Window<R>,
+    state: tauri::State<DbState>,
+) -> Result<(), TtsError> {
+    let chunks: Vec<String> = text_chunks
+        .into_iter()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    if chunks.is_empty() {
+        return Err(TtsError::NothingToSpeak);
+    }
+    let engine = Engine::detect().ok_or(TtsError::Unavailable)?;
+    let (rate, voice) = {
+        let conn = state.0.lock().unwrap();
+        (
+            settings::get_f64(&conn, RATE_SETTING, DEFAULT_RATE).clamp(80.0, 450.0),
+            settings::get(&conn, VOICE_SETTING)
+                .ok()
+                .flatten()
+                .filter(|v| !v.trim().is_empty()),
+        )
+    };
+
+    stop(&app);
+    let control = Arc::new(Control {
+        state: Mutex::new(PlayState::Playing),
+        changed: Condvar::new(),
+    });
+    *app.state::<TtsQueue>().0.lock().unwrap() = Some(Playback {
+        book_id,
+        control: control.clone(),
+    });
+
+    std::thread::spawn(move || {
+        run_queue(&window, book_id, chunks, engine, rate, voice, &control);
+        // Clear the slot unless a newer queue has taken it
+        let queue = window.state::<TtsQueue>();
+        let mut current = queue.0.lock().unwrap();
+        if current
+            .as_ref()
+            .is_some_and(|p| Arc::ptr_eq(&p.control, &control))
+        {
+            *current = None;
+        }
+    });
+    Ok(())
+}
+
+/// The book being read aloud, if any
+#[tauri::command]
+pub fn pause_tts(queue: tauri::State<TtsQueue>) -> Option<i64> {
+    let current = queue.0.lock().unwrap();
+    current.as_ref().map(|playback| {
+        playback.control.set(PlayState::Paused);
+        playback.book_id
+    })
+}
+
+#[tauri::command]
+pub fn resume_tts(queue: tauri::State<TtsQueue>) -> Option<i64> {
+    let current = queue.0.lock().unwrap();
+    current.as_ref().map(|playback| {
+        playback.control.set(PlayState::Playing);
+        playback.book_id
+    })
+}
+
+#[tauri::command]
+pub fn stop_tts<R: Runtime>(app: tauri::AppHandle<R>) {
+    stop(&app);
+}
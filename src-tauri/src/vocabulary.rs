@@ -0,0 +1,291 @@
+use rusqlite::{params, Connection, Result};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::db::{self, DbState};
+use crate::read_only;
+
+/// Local calendar day a word was marked known on
+const LOCAL_DAY: &str = "date(marked_at, 'unixepoch', 'localtime')";
+
+/// One point of the vocabulary growth chart
+#[derive(Serialize)]
+pub struct VocabPoint {
+    /// Local date
+    day: String,
+    known: i64,
+    /// Reconstructed from when words were marked rather than taken on the day
+    backfilled: bool,
+}
+
+/// Words the user marks as known, and a daily count of them per profile
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS known_words (
+            profile_id INTEGER NOT NULL,
+            word TEXT NOT NULL COLLATE NOCASE,
+            marked_at INTEGER NOT NULL,
+            PRIMARY KEY (profile_id, word)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS vocab_size_snapshots (
+            profile_id INTEGER NOT NULL,
+            day TEXT NOT NULL,
+            known INTEGER NOT NULL,
+            backfilled INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (profile_id, day)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Reconstruct one point per day on which words were marked, for a profile with no
+/// snapshots yet, so the chart of an existing user doesn't start empty. Today is left to
+/// the regular snapshot.
+fn backfill(conn: &Connection) -> Result<()> {
+    let has_snapshots: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM vocab_size_snapshots
+                       WHERE profile_id = (SELECT id FROM temp.current_profile))",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_snapshots {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {day} AS day, COUNT(*) FROM known_words
+         WHERE profile_id = (SELECT id FROM temp.current_profile)
+           AND {day} < date('now', 'localtime')
+         GROUP BY day ORDER BY day",
+        day = LOCAL_DAY
+    ))?;
+    let days = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut known = 0;
+    for (day, marked) in days {
+        known += marked;
+        conn.execute(
+            "INSERT OR IGNORE INTO vocab_size_snapshots (profile_id, day, known, backfilled)
+             VALUES ((SELECT id FROM temp.current_profile), ?, ?, 1)",
+            params![day, known],
+        )?;
+    }
+    Ok(())
+}
+
+/// Record today's count for the active profile if it hasn't been yet, backfilling first
+/// on the profile's first snapshot. Later calls on the same day change nothing; marking a
+/// word updates the day's count instead. Skipped on a read-only database.
+pub fn snapshot_if_due(conn: &Connection) -> Result<()> {
+    if read_only::is_read_only(conn) {
+        return Ok(());
+    }
+    let taken: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM vocab_size_snapshots
+                       WHERE profile_id = (SELECT id FROM temp.current_profile)
+                         AND day = date('now', 'localtime'))",
+        [],
+        |row| row.get(0),
+    )?;
+    if taken {
+        return Ok(());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    backfill(&tx)?;
+    record_today(&tx)?;
+    tx.commit()
+}
+
+/// Write today's count, replacing one taken earlier today, so each day keeps the count it
+/// ended with
+fn record_today(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "INSERT INTO vocab_size_snapshots (profile_id, day, known)
+         SELECT (SELECT id FROM temp.current_profile), date('now', 'localtime'), COUNT(*)
+         FROM known_words WHERE profile_id = (SELECT id FROM temp.current_profile)
+         ON CONFLICT(profile_id, day) DO UPDATE SET known = excluded.known",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Take the day's snapshot at startup; commands that touch the vocabulary take it too, so
+/// a window left open past midnight still gets one
+pub fn snapshot_at_startup(app: &tauri::AppHandle) {
+    let state = app.state::<DbState>();
+    let Ok(conn) = state.user_data() else {
+        return;
+    };
+    if let Err(e) = snapshot_if_due(&conn) {
+        eprintln!("Failed to take the vocabulary snapshot: {}", e);
+    }
+}
+
+/// Mark a word as known, or unmark it, for the active profile. Returns the count of known
+/// words, which becomes today's point on the growth chart.
+#[tauri::command]
+pub fn set_word_known(
+    word: &str,
+    known: bool,
+    state: tauri::State<DbState>,
+) -> Result<i64, String> {
+    let word = db::normalize(word);
+    if word.is_empty() {
+        return Err("The word can't be empty".to_string());
+    }
+    let conn = state.user_data()?;
+    snapshot_if_due(&conn).map_err(|e| e.to_string())?;
+
+    if known {
+        conn.execute(
+            "INSERT OR IGNORE INTO known_words (profile_id, word, marked_at)
+             VALUES ((SELECT id FROM temp.current_profile), ?, unixepoch())",
+            params![word],
+        )
+    } else {
+        conn.execute(
+            "DELETE FROM known_words
+             WHERE profile_id = (SELECT id FROM temp.current_profile) AND word = ?",
+            params![word],
+        )
+    }
+    .map_err(|e| e.to_string())?;
+    record_today(&conn).map_err(|e| e.to_string())?;
+
+    conn.query_row(
+        "SELECT known FROM vocab_size_snapshots
+         WHERE profile_id = (SELECT id FROM temp.current_profile)
+           AND day = date('now', 'localtime')",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Count of known words at the end of each recorded day within the last `days` days (all
+/// of them when unset), oldest first. The last point is today's count so far.
+#[tauri::command]
+pub fn get_vocab_growth(
+    days: Option<u32>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<VocabPoint>, String> {
+    let conn = state.user_data()?;
+    snapshot_if_due(&conn).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT day, known, backfilled FROM vocab_size_snapshots
+             WHERE profile_id = (SELECT id FROM temp.current_profile)
+               AND (?1 IS NULL OR day >= date('now', 'localtime', '-' || ?1 || ' days'))
+             ORDER BY day",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![days], |row| {
+            Ok(VocabPoint {
+                day: row.get(0)?,
+                known: row.get(1)?,
+                backfilled: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_>>().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mark words known at local noon `days_ago` days back, for the given profile
+    fn mark(conn: &Connection, history: &[(&str, i64, i64)]) {
+        for (word, days_ago, profile_id) in history {
+            conn.execute(
+                "INSERT INTO known_words (profile_id, word, marked_at)
+                 VALUES (?1, ?2, unixepoch(date('now', 'localtime', -?3 || ' days'),
+                                           '+12 hours', 'utc'))",
+                params![profile_id, word, days_ago],
+            )
+            .unwrap();
+        }
+    }
+
+    fn points(conn: &Connection) -> Vec<(i64, i64, bool)> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT julianday(date('now', 'localtime')) - julianday(day), known, backfilled
+                 FROM vocab_size_snapshots WHERE profile_id = 1 ORDER BY day",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, f64>(0)? as i64, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        rows.collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn backfill_reconstructs_past_days() {
+        type Case = (
+            &'static [(&'static str, i64, i64)],
+            &'static [(i64, i64, bool)],
+        );
+        let cases: &[Case] = &[
+            // Nothing marked yet: just today's empty point
+            (&[], &[(0, 0, false)]),
+            // Only today: nothing to backfill
+            (&[("whale", 0, 1)], &[(0, 1, false)]),
+            (
+                &[
+                    ("whale", 10, 1),
+                    ("harpoon", 10, 1),
+                    ("cetology", 3, 1),
+                    ("ambergris", 0, 1),
+                    // Another profile's words count for it alone
+                    ("queequeg", 5, 2),
+                    ("ishmael", 10, 2),
+                ],
+                &[(10, 2, true), (3, 3, true), (0, 4, false)],
+            ),
+            // Days without marks have no point; the count carries over
+            (
+                &[("whale", 400, 1), ("harpoon", 30, 1), ("cetology", 1, 1)],
+                &[(400, 1, true), (30, 2, true), (1, 3, true), (0, 3, false)],
+            ),
+        ];
+        for (history, expected) in cases {
+            let conn = db::init_db(None).unwrap();
+            mark(&conn, history);
+            snapshot_if_due(&conn).unwrap();
+            assert_eq!(points(&conn), *expected, "{:?}", history);
+        }
+    }
+
+    #[test]
+    fn snapshots_are_taken_once_a_day() {
+        let conn = db::init_db(None).unwrap();
+        mark(&conn, &[("whale", 4, 1), ("harpoon", 0, 1)]);
+        snapshot_if_due(&conn).unwrap();
+        let first = points(&conn);
+
+        // Marked meanwhile without going through `set_word_known`: neither a second
+        // snapshot nor a second backfill picks it up
+        mark(&conn, &[("cetology", 2, 1)]);
+        snapshot_if_due(&conn).unwrap();
+        assert_eq!(points(&conn), first);
+        assert_eq!(first, [(4, 1, true), (0, 2, false)]);
+
+        // Recording the day again replaces its count rather than adding a point
+        record_today(&conn).unwrap();
+        assert_eq!(points(&conn), [(4, 1, true), (0, 3, false)]);
+    }
+}
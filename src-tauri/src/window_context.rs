@@ -0,0 +1,104 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{Manager, Runtime};
+
+use crate::books;
+use crate::db::DbState;
+use crate::session_lookups::SessionLookups;
+
+/// What a window is showing, for defaults of the commands it calls
+#[derive(Serialize, Default, Clone, Copy)]
+pub struct WindowContext {
+    /// The book open in the window; lookups from it are attributed to this book unless
+    /// they name one
+    book_id: Option<i64>,
+}
+
+/// Contexts by webview window label, so the main reader and the lookup popup don't
+/// overwrite each other's
+#[derive(Default)]
+pub struct WindowContexts(Mutex<HashMap<String, WindowContext>>);
+
+impl WindowContexts {
+    pub fn book_id(&self, label: &str) -> Option<i64> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(label)
+            .and_then(|context| context.book_id)
+    }
+}
+
+/// Drop what was kept for a window once it's closed
+pub fn forget<R: Runtime>(app: &tauri::AppHandle<R>, label: &str) {
+    app.state::<WindowContexts>()
+        .0
+        .lock()
+        .unwrap()
+        .remove(label);
+    app.state::<SessionLookups>()
+        .0
+        .lock()
+        .unwrap()
+        .remove(label);
+}
+
+/// Set the book the calling window is showing, or clear it with `None`
+#[tauri::command]
+pub fn set_window_context<R: Runtime>(
+    book_id: Option<i64>,
+    window: tauri::Window<R>,
+    contexts: tauri::State<WindowContexts>,
+    state: tauri::State<DbState>,
+) -> Result<WindowContext, String> {
+    if let Some(book_id) = book_id {
+        let conn = state.0.lock().unwrap();
+        books::load_book(&conn, book_id)?;
+    }
+    let context = WindowContext { book_id };
+    contexts
+        .0
+        .lock()
+        .unwrap()
+        .insert(window.label().to_string(), context);
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_window_keeps_its_own_book_and_lookups() {
+        let app = tauri::test::mock_app();
+        app.manage(WindowContexts::default());
+        app.manage(SessionLookups::default());
+        let contexts = app.state::<WindowContexts>();
+        let lookups = app.state::<SessionLookups>();
+
+        let set = |label: &str, book_id| {
+            contexts
+                .0
+                .lock()
+                .unwrap()
+                .insert(label.to_string(), WindowContext { book_id });
+        };
+        set("main", Some(1));
+        set("popup", Some(2));
+        lookups.push("main", "whale", "A large sea mammal.");
+        lookups.push("popup", "harpoon", "A barbed spear.");
+        set("main", Some(3));
+        assert_eq!(contexts.book_id("main"), Some(3));
+        assert_eq!(contexts.book_id("popup"), Some(2));
+        assert_eq!(contexts.book_id("settings"), None);
+
+        // Closing the popup drops only what it kept
+        forget(app.handle(), "popup");
+        assert_eq!(contexts.book_id("popup"), None);
+        assert_eq!(contexts.book_id("main"), Some(3));
+        let kept = lookups.0.lock().unwrap();
+        assert!(!kept.contains_key("popup"));
+        assert_eq!(kept["main"].len(), 1);
+    }
+}
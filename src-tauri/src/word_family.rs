@@ -0,0 +1,422 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::db::{self, DbState};
+
+/// Roots shorter than this never get derived or prefixed relatives
+const MIN_ROOT_CHARS: usize = 3;
+/// Roots at least this long are related on word shape alone; shorter ones ("ear", "ion")
+/// also need the relative's definition to mention the root, so "early" and "union" stay out
+const MIN_UNCHECKED_ROOT_CHARS: usize = 5;
+
+const DERIVATION_SUFFIXES: &[&str] = &[
+    "er", "ers", "ness", "ly", "ment", "able", "ful", "less", "ist", "ism", "ity", "ation", "ion",
+    "al",
+];
+const PREFIXES: &[&str] = &[
+    "re", "un", "dis", "mis", "pre", "over", "under", "out", "non",
+];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    /// The headword the rest of the family is built from
+    Root,
+    /// "runs", "running"
+    Inflection,
+    /// "runner"
+    Derivation,
+    /// "rerun"
+    Prefixed,
+}
+
+#[derive(Serialize)]
+pub struct FamilyMember {
+    word: String,
+    definitions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct FamilyGroup {
+    relation: Relation,
+    members: Vec<FamilyMember>,
+}
+
+#[derive(Serialize)]
+pub struct WordFamily {
+    word: String,
+    root: String,
+    groups: Vec<FamilyGroup>,
+}
+
+/// Known irregular forms ("ran" -> "run"), filled by dictionary packs
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS word_forms (
+            form TEXT NOT NULL COLLATE NOCASE,
+            lemma TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (form, lemma)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_word_forms_lemma ON word_forms(lemma)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/// Ends consonant-vowel-consonant, the shape that doubles its last letter ("run" -> "running")
+fn ends_cvc(chars: &[char]) -> bool {
+    matches!(chars, [.., a, b, c] if !is_vowel(*a) && is_vowel(*b) && !is_vowel(*c) && !matches!(c, 'w' | 'x' | 'y'))
+}
+
+/// Spellings of `root` plus `suffix` under the usual rules: "make" -> "making",
+/// "run" -> "runner", "happy" -> "happiness"
+fn with_suffix(root: &str, suffix: &str) -> Vec<String> {
+    let chars: Vec<char> = root.chars().collect();
+    let Some(&last) = chars.last() else {
+        return Vec::new();
+    };
+    let vowel_suffix = suffix.chars().next().is_some_and(is_vowel);
+    let before_last = chars.len().checked_sub(2).map(|i| chars[i]);
+    let head: String = chars[..chars.len() - 1].iter().collect();
+
+    let mut forms = Vec::new();
+    if vowel_suffix && last == 'e' && before_last != Some('e') {
+        forms.push(format!("{}{}", head, suffix));
+    } else if last == 'y' && before_last.is_some_and(|c| !is_vowel(c)) && !suffix.starts_with('i') {
+        forms.push(format!("{}i{}", head, suffix));
+    } else if vowel_suffix && ends_cvc(&chars) {
+        forms.push(format!("{}{}{}", root, last, suffix));
+        // Longer words vary: "visited" but "admitted"
+        if chars.len() > 4 {
+            forms.push(format!("{}{}", root, suffix));
+        }
+    } else {
+        forms.push(format!("{}{}", root, suffix));
+    }
+    forms
+}
+
+/// Regular inflections of a headword: plural or third person, past, and present participle
+fn inflections(root: &str) -> Vec<String> {
+    let sibilant = ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|end| root.ends_with(end));
+    let consonant_y = root
+        .strip_suffix('y')
+        .filter(|head| head.chars().last().is_some_and(|c| !is_vowel(c)));
+
+    let mut forms = vec![match consonant_y {
+        Some(head) => format!("{}ies", head),
+        None if sibilant => format!("{}es", root),
+        None => format!("{}s", root),
+    }];
+    forms.extend(with_suffix(root, "ed"));
+    forms.extend(with_suffix(root, "ing"));
+    forms
+}
+
+fn definitions_of(conn: &Connection, word: &str) -> Result<Vec<String>> {
+    db::lookup_exact(conn, word)
+}
+
+/// True if `definitions` use `root` or one of its inflections as a word
+fn mentions(definitions: &[String], root: &str) -> bool {
+    let mut forms: HashSet<String> = inflections(root).into_iter().collect();
+    forms.insert(root.to_string());
+    definitions.iter().any(|definition| {
+        definition
+            .to_lowercase()
+            .split(|c: char| !c.is_alphabetic())
+            .any(|token| forms.contains(token))
+    })
+}
+
+/// Whether a derived or prefixed word really belongs to `root`
+fn related(root: &str, definitions: &[String]) -> bool {
+    let len = root.chars().count();
+    len >= MIN_UNCHECKED_ROOT_CHARS || (len >= MIN_ROOT_CHARS && mentions(definitions, root))
+}
+
+/// Headword the word is an inflection of, if any
+fn lemma_of(conn: &Connection, word: &str) -> Result<Option<String>> {
+    let listed: Option<String> = conn
+        .query_row(
+            "SELECT lemma FROM word_forms WHERE form = ? LIMIT 1",
+            params![word],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if listed.is_some() {
+        return Ok(listed);
+    }
+
+    let stem = db::stem(word);
+    for candidate in [format!("{}e", stem), stem.clone()] {
+        if candidate != word
+            && inflections(&candidate).iter().any(|f| f == word)
+            && !definitions_of(conn, &candidate)?.is_empty()
+        {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Headword the word is derived from by a suffix or prefix, if any
+fn root_of(conn: &Connection, word: &str) -> Result<Option<String>> {
+    let definitions = definitions_of(conn, word)?;
+
+    for suffix in DERIVATION_SUFFIXES {
+        let Some(base) = word.strip_suffix(suffix) else {
+            continue;
+        };
+        let mut candidates = vec![base.to_string(), format!("{}e", base)];
+        if let Some(head) = base.strip_suffix('i') {
+            candidates.push(format!("{}y", head));
+        }
+        if let Some(undoubled) = base.get(..base.len().saturating_sub(1)) {
+            candidates.push(undoubled.to_string());
+        }
+        for candidate in candidates {
+            if with_suffix(&candidate, suffix).iter().any(|f| f == word)
+                && related(&candidate, &definitions)
+                && !definitions_of(conn, &candidate)?.is_empty()
+            {
+                return Ok(Some(candidate));
+            }
+        }
+    }
+
+    for prefix in PREFIXES {
+        if let Some(rest) = word.strip_prefix(prefix) {
+            if related(rest, &definitions) && !definitions_of(conn, rest)?.is_empty() {
+                return Ok(Some(rest.to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn push_member(
+    conn: &Connection,
+    seen: &mut HashSet<String>,
+    group: &mut Vec<FamilyMember>,
+    word: String,
+) -> Result<()> {
+    if seen.contains(&word) {
+        return Ok(());
+    }
+    let definitions = definitions_of(conn, &word)?;
+    if !definitions.is_empty() {
+        seen.insert(word.clone());
+        group.push(FamilyMember { word, definitions });
+    }
+    Ok(())
+}
+
+/// Related headwords of a word, grouped by how they're related to the family's root
+pub fn family_of(conn: &Connection, word: &str) -> Result<WordFamily> {
+    let word = db::normalize(word);
+    let lemma = lemma_of(conn, &word)?.unwrap_or_else(|| word.clone());
+    let root = root_of(conn, &lemma)?.unwrap_or_else(|| lemma.clone());
+
+    let mut seen = HashSet::from([word.clone()]);
+    let mut roots = Vec::new();
+    push_member(conn, &mut seen, &mut roots, root.clone())?;
+
+    let mut inflected = Vec::new();
+    let mut stmt = conn.prepare("SELECT form FROM word_forms WHERE lemma = ?")?;
+    let listed: Vec<String> = stmt
+        .query_map(params![root], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    for form in listed.into_iter().chain(inflections(&root)) {
+        push_member(conn, &mut seen, &mut inflected, form)?;
+    }
+
+    let mut derived = Vec::new();
+    for suffix in DERIVATION_SUFFIXES {
+        for form in with_suffix(&root, suffix) {
+            if seen.contains(&form) {
+                continue;
+            }
+            let definitions = definitions_of(conn, &form)?;
+            if !definitions.is_empty() && related(&root, &definitions) {
+                seen.insert(form.clone());
+                derived.push(FamilyMember {
+                    word: form,
+                    definitions,
+                });
+            }
+        }
+    }
+
+    let mut prefixed = Vec::new();
+    for prefix in PREFIXES {
+        let form = format!("{}{}", prefix, root);
+        if seen.contains(&form) {
+            continue;
+        }
+        let definitions = definitions_of(conn, &form)?;
+        if !definitions.is_empty() && related(&root, &definitions) {
+            seen.insert(form.clone());
+            prefixed.push(FamilyMember {
+                word: form,
+                definitions,
+            });
+        }
+    }
+
+    let groups = [
+        (Relation::Root, roots),
+        (Relation::Inflection, inflected),
+        (Relation::Derivation, derived),
+        (Relation::Prefixed, prefixed),
+    ]
+    .into_iter()
+    .filter(|(_, members)| !members.is_empty())
+    .map(|(relation, members)| FamilyGroup { relation, members })
+    .collect();
+
+    Ok(WordFamily { word, root, groups })
+}
+
+#[tauri::command]
+pub fn get_word_family(word: &str, state: tauri::State<DbState>) -> Result<WordFamily, String> {
+    let conn = state.0.lock().unwrap();
+    family_of(&conn, word).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary(entries: &[(&str, &str)]) -> Connection {
+        let conn = db::init_db(None).unwrap();
+        for (word, definition) in entries {
+            conn.execute(
+                "INSERT INTO dictionary (word, definition) VALUES (?, ?)",
+                params![word, definition],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn members(family: &WordFamily, relation: Relation) -> Vec<&str> {
+        family
+            .groups
+            .iter()
+            .filter(|group| group.relation == relation)
+            .flat_map(|group| group.members.iter().map(|m| m.word.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn groups_a_family_around_its_root() {
+        let conn = dictionary(&[
+            ("run", "To move quickly on foot."),
+            ("runs", "Plural of run."),
+            ("running", "The action of moving quickly on foot."),
+            ("runner", "A person who runs."),
+            ("rerun", "To run again."),
+        ]);
+        for word in ["running", "runner", "rerun", "RUN"] {
+            let family = family_of(&conn, word).unwrap();
+            assert_eq!(family.root, "run", "root of {}", word);
+        }
+
+        let family = family_of(&conn, "runner").unwrap();
+        assert_eq!(family.word, "runner");
+        assert_eq!(members(&family, Relation::Root), ["run"]);
+        assert_eq!(members(&family, Relation::Inflection), ["runs", "running"]);
+        // The word asked about isn't repeated in its own family
+        assert!(members(&family, Relation::Derivation).is_empty());
+        assert_eq!(members(&family, Relation::Prefixed), ["rerun"]);
+    }
+
+    #[test]
+    fn short_roots_need_their_relatives_to_mention_them() {
+        let conn = dictionary(&[
+            ("ear", "The organ of hearing."),
+            ("earn", "To receive money in return for work."),
+            ("early", "Near the beginning of a period of time."),
+            ("earful", "A lot of talk poured into someone's ear."),
+            ("ion", "An atom with an electric charge."),
+            ("union", "The act of joining together."),
+        ]);
+
+        let family = family_of(&conn, "ear").unwrap();
+        assert_eq!(members(&family, Relation::Derivation), ["earful"]);
+        assert!(members(&family, Relation::Inflection).is_empty());
+        assert!(members(&family, Relation::Prefixed).is_empty());
+
+        for word in ["earn", "early"] {
+            assert_eq!(family_of(&conn, word).unwrap().root, word);
+        }
+        assert_eq!(family_of(&conn, "union").unwrap().root, "union");
+        assert_eq!(family_of(&conn, "earful").unwrap().root, "ear");
+    }
+
+    #[test]
+    fn long_roots_are_related_on_shape_alone() {
+        let conn = dictionary(&[
+            ("kind", "Friendly and caring."),
+            ("kindness", "The quality of being friendly and caring."),
+            ("happy", "Feeling pleasure."),
+            ("happiness", "The state of feeling pleasure."),
+            ("unhappy", "Not feeling pleasure."),
+        ]);
+        // "kind" is too short to relate on shape, and "kindness" doesn't mention it
+        assert_eq!(family_of(&conn, "kindness").unwrap().root, "kindness");
+
+        let family = family_of(&conn, "happiness").unwrap();
+        assert_eq!(family.root, "happy");
+        assert_eq!(members(&family, Relation::Root), ["happy"]);
+        assert_eq!(members(&family, Relation::Prefixed), ["unhappy"]);
+    }
+
+    #[test]
+    fn irregular_forms_come_from_word_forms() {
+        let conn = dictionary(&[
+            ("make", "To create."),
+            ("making", "The process of creating."),
+            ("run", "To move quickly on foot."),
+            ("ran", "Past tense of run."),
+        ]);
+        conn.execute(
+            "INSERT INTO word_forms (form, lemma) VALUES ('ran', 'run')",
+            [],
+        )
+        .unwrap();
+
+        let family = family_of(&conn, "ran").unwrap();
+        assert_eq!(family.root, "run");
+        assert_eq!(members(&family, Relation::Root), ["run"]);
+        assert!(members(&family, Relation::Inflection).is_empty());
+        assert_eq!(
+            members(&family_of(&conn, "run").unwrap(), Relation::Inflection),
+            ["ran"]
+        );
+        assert_eq!(family_of(&conn, "making").unwrap().root, "make");
+    }
+
+    #[test]
+    fn suffixes_follow_spelling_rules() {
+        assert_eq!(with_suffix("make", "ing"), ["making"]);
+        assert_eq!(with_suffix("see", "ing"), ["seeing"]);
+        assert_eq!(with_suffix("happy", "ness"), ["happiness"]);
+        assert_eq!(with_suffix("run", "er"), ["runner"]);
+        // Both doublings are offered for longer words
+        assert_eq!(with_suffix("admit", "ed"), ["admitted", "admited"]);
+        assert_eq!(inflections("box"), ["boxes", "boxed", "boxing"]);
+        assert_eq!(inflections("carry"), ["carries", "carried", "carrying"]);
+    }
+}
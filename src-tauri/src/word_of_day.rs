@@ -0,0 +1,220 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::db::DbState;
+use crate::settings;
+use crate::tokens;
+
+/// Local hour (0-23) the word of the day is due at
+pub const HOUR_SETTING: &str = "word_of_day_hour";
+const DEFAULT_HOUR: f64 = 8.0;
+/// Words shorter than this rarely make an interesting word of the day
+const MIN_WORD_LENGTH: i64 = 4;
+
+/// The next word-of-the-day notification, for the frontend (or the notification plugin) to
+/// schedule
+#[derive(Serialize, Clone)]
+pub struct NotificationPayload {
+    /// Local date the word is for
+    date: String,
+    /// When to show it; at or before now means it's due already
+    scheduled_at: i64,
+    word: String,
+    definition: String,
+    pos: Option<String>,
+    language: String,
+}
+
+/// One word per local day, chosen the first time the day is asked for so it stays the
+/// same however often it's read, and when its notification was shown (or made unneeded by
+/// opening the app)
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS word_of_day (
+            day TEXT PRIMARY KEY,
+            word TEXT NOT NULL,
+            definition TEXT NOT NULL,
+            pos TEXT,
+            language TEXT NOT NULL,
+            shown_at INTEGER
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn preferred_hour(conn: &Connection) -> i64 {
+    settings::get_f64(conn, HOUR_SETTING, DEFAULT_HOUR).clamp(0.0, 23.0) as i64
+}
+
+/// Unix time of the preferred hour on a local date
+fn scheduled_at(conn: &Connection, day: &str) -> Result<i64> {
+    conn.query_row(
+        "SELECT unixepoch(?, printf('+%d hours', ?), 'utc')",
+        params![day, preferred_hour(conn)],
+        |row| row.get(0),
+    )
+}
+
+/// The day's word, picking one on first use: an entry in the default language that hasn't
+/// been a word of the day before, at an offset derived from the date. Only the local
+/// dictionary is used.
+fn word_for(conn: &Connection, day: &str) -> Result<Option<NotificationPayload>> {
+    let language = settings::get(conn, tokens::LANGUAGE_SETTING)?
+        .filter(|l| !l.trim().is_empty())
+        .unwrap_or_else(|| "en".to_string());
+    conn.execute(
+        "INSERT OR IGNORE INTO word_of_day (day, word, definition, pos, language)
+         SELECT ?1, word, definition, pos, language FROM (
+             SELECT d.word, d.definition, d.pos, d.language,
+                    ROW_NUMBER() OVER (ORDER BY d.id) - 1 AS n,
+                    COUNT(*) OVER () AS total
+             FROM dictionary d
+             WHERE d.language = ?2 COLLATE NOCASE AND length(d.word) >= ?3
+               AND d.word NOT LIKE '% %'
+               AND NOT EXISTS (SELECT 1 FROM word_of_day w WHERE w.word = d.word))
+         WHERE n = (unixepoch(?1) / 86400 * 7919) % total",
+        params![day, language, MIN_WORD_LENGTH],
+    )?;
+    conn.query_row(
+        "SELECT word, definition, pos, language FROM word_of_day WHERE day = ?",
+        params![day],
+        |row| {
+            Ok(NotificationPayload {
+                date: day.to_string(),
+                scheduled_at: 0,
+                word: row.get(0)?,
+                definition: row.get(1)?,
+                pos: row.get(2)?,
+                language: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Today's notification if it hasn't been shown, otherwise tomorrow's
+fn next_payload(conn: &Connection) -> Result<Option<NotificationPayload>> {
+    let (today, tomorrow): (String, String) = conn.query_row(
+        "SELECT date('now', 'localtime'), date('now', 'localtime', '+1 day')",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let shown_today: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM word_of_day WHERE day = ? AND shown_at IS NOT NULL)",
+        params![today],
+        |row| row.get(0),
+    )?;
+    let day = if shown_today { tomorrow } else { today };
+
+    let scheduled_at = scheduled_at(conn, &day)?;
+    Ok(word_for(conn, &day)?.map(|payload| NotificationPayload {
+        scheduled_at,
+        ..payload
+    }))
+}
+
+/// Mark a day's notification shown. Returns false if it already was.
+fn mark_shown(conn: &Connection, day: &str) -> Result<bool> {
+    word_for(conn, day)?;
+    let marked = conn.execute(
+        "UPDATE word_of_day SET shown_at = unixepoch() WHERE day = ? AND shown_at IS NULL",
+        params![day],
+    )?;
+    Ok(marked > 0)
+}
+
+/// Opening the app before the day's notification is due counts as seeing it, so that day
+/// goes without one
+fn skip_if_early(conn: &Connection) -> Result<()> {
+    let (today, now): (String, i64) =
+        conn.query_row("SELECT date('now', 'localtime'), unixepoch()", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    if scheduled_at(conn, &today)? > now {
+        mark_shown(conn, &today)?;
+    }
+    Ok(())
+}
+
+pub fn note_app_opened(app: &tauri::AppHandle) {
+    let state = app.state::<DbState>();
+    let Ok(conn) = state.user_data() else {
+        return;
+    };
+    if let Err(e) = skip_if_early(&conn) {
+        eprintln!(
+            "Failed to note the app opening for the word of the day: {}",
+            e
+        );
+    }
+}
+
+/// Schedule the next notification through the notification plugin, replacing the one
+/// scheduled before. One that's due already is shown right away.
+#[cfg(feature = "notifications")]
+pub fn schedule_notification(app: &tauri::AppHandle) {
+    use tauri_plugin_notification::{NotificationExt, Schedule};
+
+    /// The same id each time, so a new schedule replaces the pending one
+    const NOTIFICATION_ID: i32 = 1;
+
+    let payload = {
+        let state = app.state::<DbState>();
+        let Ok(conn) = state.user_data() else {
+            return;
+        };
+        next_payload(&conn).and_then(|payload| {
+            let now = conn.query_row("SELECT unixepoch()", [], |row| row.get::<_, i64>(0))?;
+            Ok((payload, now))
+        })
+    };
+    let (payload, now) = match payload {
+        Ok((Some(payload), now)) => (payload, now),
+        Ok((None, _)) => return,
+        Err(e) => {
+            eprintln!("Failed to prepare the word of the day: {}", e);
+            return;
+        }
+    };
+
+    let mut builder = app
+        .notification()
+        .builder()
+        .id(NOTIFICATION_ID)
+        .title(&payload.word)
+        .body(&payload.definition);
+    if payload.scheduled_at > now {
+        let Ok(date) = time::OffsetDateTime::from_unix_timestamp(payload.scheduled_at) else {
+            return;
+        };
+        builder = builder.schedule(Schedule::At {
+            date,
+            repeating: false,
+            allow_while_idle: true,
+        });
+    }
+    if let Err(e) = builder.show() {
+        eprintln!("Failed to schedule the word of the day: {}", e);
+    }
+}
+
+/// The word of the day to notify about next, with when to show it: today's if it hasn't
+/// been shown (due now if the preferred hour has passed), otherwise tomorrow's. `None`
+/// when the dictionary has no entries in the default language.
+#[tauri::command]
+pub fn get_next_notification_payload(
+    state: tauri::State<DbState>,
+) -> Result<Option<NotificationPayload>, String> {
+    let conn = state.user_data()?;
+    next_payload(&conn).map_err(|e| e.to_string())
+}
+
+/// Record that a day's notification was shown, so the next payload moves on to the
+/// following day. Returns false if it had been already.
+#[tauri::command]
+pub fn mark_notification_shown(date: &str, state: tauri::State<DbState>) -> Result<bool, String> {
+    let conn = state.user_data()?;
+    mark_shown(&conn, date).map_err(|e| e.to_string())
+}
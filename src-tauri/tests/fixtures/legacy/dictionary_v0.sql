@@ -0,0 +1,15 @@
+-- Database as written by the first release: one dictionary table, no meta table
+CREATE TABLE dictionary (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    word TEXT NOT NULL COLLATE NOCASE,
+    definition TEXT NOT NULL
+);
+CREATE INDEX idx_word ON dictionary(word COLLATE NOCASE);
+
+INSERT INTO dictionary (word, definition) VALUES
+    ('algorithm', 'A step-by-step procedure for solving a problem.'),
+    ('Compiler', 'A program that translates source code into machine code.'),
+    ('debug', 'To find and remove errors from a program.'),
+    ('debug', 'The process of removing errors; also used as a noun.'),
+    ('naïve', 'Showing a lack of experience, wisdom, or judgement.'),
+    ('quote', 'A passage "repeated" from another''s work.');
@@ -0,0 +1,44 @@
+-- A synthetic week of activity for the active profile (id 1). Times are given as
+-- (day, hour) from `temp.week.monday`, the local midnight starting the week; day 0 is
+-- Monday and day 6 Sunday.
+INSERT INTO books (id, path, title, format, added_at) VALUES
+    (1, '/books/moby-dick.epub', 'Moby-Dick', 'epub', 0),
+    (2, '/books/walden.txt', 'Walden', 'txt', 0);
+
+INSERT INTO lookup_history (word, found, looked_up_at, profile_id)
+SELECT column1, column2, monday + column3 * 86400 + column4 * 3600, column5
+FROM temp.week, (VALUES
+    -- The Friday before: "whale" isn't new this week
+    ('whale', 1, -3, 12, 1),
+    ('whale', 1, 0, 12, 1),
+    ('harpoon', 1, 0, 13, 1),
+    ('Harpoon', 1, 1, 12, 1),
+    ('cetology', 0, 2, 12, 1),
+    ('leviathan', 1, 6, 20, 1),
+    -- Another profile's lookup
+    ('queequeg', 1, 3, 12, 2),
+    -- The following Monday
+    ('ishmael', 1, 7, 12, 1));
+
+INSERT INTO reading_sessions (book_id, started_at, ended_at, start_progress, end_progress)
+SELECT column1, monday + column2 * 86400 + column3 * 3600,
+       monday + column2 * 86400 + column3 * 3600 + column4, 0.0, 0.1
+FROM temp.week, (VALUES
+    (1, 0, 14, 1800),
+    (1, 3, 12, 3600),
+    (2, 5, 12, 1200));
+
+-- Still open, so it doesn't count
+INSERT INTO reading_sessions (book_id, started_at, start_progress)
+SELECT 2, monday + 6 * 86400 + 12 * 3600, 0.1 FROM temp.week;
+
+INSERT INTO review_sessions (profile_id, started_at, ended_at, cards, cards_seen, answers, correct)
+SELECT 1, monday + column1 * 86400 + 12 * 3600, monday + column1 * 86400 + 12 * 3600 + 600,
+       '[]', column2, column3, column4
+FROM temp.week, (VALUES
+    (1, 10, 12, 9),
+    (5, 5, 5, 4));
+
+-- Abandoned without ending
+INSERT INTO review_sessions (profile_id, started_at, cards)
+SELECT 1, monday + 4 * 86400, '[]' FROM temp.week;
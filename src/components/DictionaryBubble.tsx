@@ -67,8 +67,9 @@ export const DictionaryBubble: React.FC<DictionaryBubbleProps> = ({ word, positi
                 try {
                     const { invoke } = await import('@tauri-apps/api/core');
                     if (typeof invoke === 'function') {
-                        const results: string[] = await invoke('search_dictionary', { word: cleanWord });
-                        if (results && results.length > 0) {
+                        const response: { results: { definition: string }[] } = await invoke('search_dictionary', { word: cleanWord });
+                        const results = response?.results?.map((entry) => entry.definition) ?? [];
+                        if (results.length > 0) {
                             setDefinitions(results);
                             setSource('local');
                             foundLocal = true;